@@ -1,97 +1,152 @@
 use clap::Clap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::io;
+use gargamel::remote::{Computer, Readiness};
+use gargamel::plan::{AcquisitionPlan, Method};
+use gargamel::profile::Profile;
+use gargamel::event_timeline::EventFilter;
+use gargamel::memory_acquirer::LinuxMemoryTool;
+use gargamel::container::{EncryptionKey, DecryptionKey, validate_key};
 
 #[derive(Clap, Clone)]
 #[clap(version = "1.0", author = "LIFARS LLC")]
 pub struct Opts {
     #[clap(
-    short = "c",
+    short = 'c',
     long = "computer",
     default_value = "127.0.0.1",
-    help = "Remote computer address/name."
+    about = "Remote computer address/name."
     )]
     pub computer: String,
 
     #[clap(
-    short = "u",
+    short = 'u',
     long = "user",
-    help = "Remote user name"
+    about = "Remote user name"
     )]
     pub user: String,
 
     #[clap(
-    short = "d",
+    short = 'd',
     long = "domain",
-    help = "Optional: Remote Windows domain"
+    about = "Optional: Remote Windows domain"
     )]
     pub domain: Option<String>,
 
     #[clap(
-    short = "p",
+    short = 'p',
     long = "password",
-    help = "Optional: Remote user password. Skipping this option will prompt a possibility to put a password in hidden way.\
+    about = "Optional: Remote user password. Skipping this option will prompt a possibility to put a password in hidden way.\
      To specify an empty password use `-p \"\"`"
     )]
     pub password: Option<String>,
 
     #[clap(
-    short = "o",
+    short = 'o',
     long = "output",
     default_value = "evidence-output",
-    help = "Name of local directory to store the evidence"
+    about = "Name of local directory to store the evidence"
     )]
     pub local_store_directory: String,
 
     #[clap(
-    short = "r",
+    short = 'r',
     long = "remote-storage",
-    help = "Name of remote directory to be used as a temporary storage. (Windows targets only)",
+    about = "Name of remote directory to be used as a temporary storage. (Windows targets only)",
     default_value = "C:\\Users\\Public"
     )]
     pub remote_store_directory: String,
 
     #[clap(
-    short = "e",
+    short = 'e',
     long = "commands",
-    help = "Optional: File with custom commands to execute on remote computer"
+    about = "Optional: File with custom commands to execute on remote computer"
     )]
     pub custom_command_path: Option<String>,
 
     #[clap(
-    short = "s",
+    short = 's',
     long = "search",
-    help = "Optional: File with files names to be searched on remote computer. \
+    about = "Optional: File with files names to be searched on remote computer. \
     File names supports also `*` and `?` wildcards on file names (but not yet parent directories)."
     )]
     pub search_files_path: Option<String>,
 
     #[clap(
     long = "no-evidence-search",
-    help = "Disables acquisition of evidence that can be usually downloaded quickly (like ipconfig, firewall status etc..)"
+    about = "Disables acquisition of evidence that can be usually downloaded quickly (like ipconfig, firewall status etc..)"
     )]
     pub disable_evidence_download: bool,
 
     #[clap(
     long = "no-registry-search",
-    help = "Disables target registry acquisition."
+    about = "Disables target registry acquisition."
     )]
     pub disable_registry_download: bool,
 
+    #[clap(
+    long = "hives",
+    about = "Optional: Acquire the registry as binary hives using `reg save` instead of exporting it to text: \
+    SYSTEM, SOFTWARE, SAM, SECURITY, DEFAULT and NTUSER.DAT & UsrClass.dat of every user profile. \
+    Use `gargamel hive` to dump the keys of an acquired hive to JSON."
+    )]
+    pub registry_hives: bool,
+
     #[clap(
     long = "no-events-search",
-    help = "Disables Windows event logs acquisition."
+    about = "Disables Windows event logs acquisition."
     )]
     pub disable_event_download: bool,
 
     #[clap(
-    short = "a",
+    long = "event-channels",
+    about = "Optional: Comma separated Windows event log channels to acquire instead of those of the profile, \
+    e.g. `Security,Microsoft-Windows-PowerShell/Operational`. The built-in profile acquires System, Application, \
+    Security, Sysmon, PowerShell, Task Scheduler, RDP and Windows Defender logs. \
+    Channels missing on the target are reported and skipped."
+    )]
+    pub event_channels: Option<String>,
+
+    #[clap(
+    long = "events-since",
+    about = "Optional: Acquire only events created in the last given number of days from each event log channel."
+    )]
+    pub events_since: Option<u64>,
+
+    #[clap(
+    long = "no-events-timeline",
+    about = "Disables parsing of the acquired event logs into JSON lines."
+    )]
+    pub disable_events_timeline: bool,
+
+    #[clap(
+    long = "timeline-events",
+    parse(try_from_str = EventFilter::parse),
+    about = "Optional: Comma separated IDs of the events written into the JSON lines parsed from the acquired event logs, \
+    each optionally prefixed by its channel like Security:4624, or `all`. Defaults to high-value events like logons (4624, 4625), process creation (4688), new services (7045), \
+    PowerShell script blocks (4104), cleared logs (1102, 104), RDP sessions and Defender detections."
+    )]
+    pub timeline_events: Option<EventFilter>,
+
+    #[clap(
+    long = "timeline",
+    about = "Optional: After the acquisition, merge event logs, registry key last write times of binary hives, \
+    times of downloaded files on the target and Linux logs into a sorted timeline.csv and a mactime bodyfile \
+    timeline.body, for the whole case and for every host."
+    )]
+    pub timeline: bool,
+
+    #[clap(
+    short = 'a',
     long = "all",
-    help = "Acquire evidence from Windows machine using all supported methods (PsExec, PsRemote, WMI, RDP)."
+    about = "Acquire evidence from Windows machine using all supported methods (PsExec, PsRemote, WMI, RDP)."
     )]
     pub all: bool,
 
     #[clap(
     long = "wmi",
-    help = "Acquire evidence from Windows machine using WMI. \
+    about = "Acquire evidence from Windows machine using WMI. \
     Requires WMImplant.ps1 in the current directory or in the path and PowerShell 3.0+ on the host machine. \
     Note: It is necessary to disable Windows Defender real-time protection (other AVs not tested)."
     )]
@@ -99,25 +154,26 @@ pub struct Opts {
 
     #[clap(
     long = "rdp",
-    help = "Acquire evidence from Windows machine using RDP. Requires SharpRDP.exe in the current directory or in the path."
+    about = "Acquire evidence from Windows machine using RDP. Requires SharpRDP.exe in the current directory or in the path."
     )]
     pub rdp: bool,
 
     #[clap(
     long = "psexec",
-    help = "Acquire evidence from Windows machine using PsExec. Requires both PsExec64.exe and paexec.exe in the current directory or in the path."
+    about = "Acquire evidence from Windows machine using PsExec. Requires both PsExec64.exe and paexec.exe in the current directory or in the path."
     )]
     pub psexec: bool,
 
     #[clap(
     long = "psrem",
-    help = "Acquire evidence from Windows machine using PowerShell. Requires both PsExec64.exe and paexec.exe in the current directory or in the path."
+    about = "Acquire evidence from Windows machine using PowerShell. Requires both PsExec64.exe and paexec.exe in the current directory or in the path."
     )]
     pub psrem: bool,
 
     #[clap(
     long = "ssh",
-    help = "Acquire evidence from Linux machine using SSH. Requires both plink.exe and pscp.exe in the current directory or in the path."
+    about = "Acquire evidence from Linux machine using SSH. \
+    Host key of the target must be present in the known_hosts file (see --known-hosts)."
     )]
     pub ssh: bool,
 
@@ -126,39 +182,301 @@ pub struct Opts {
     // pub local: bool,
 
     #[clap(
-    short = "m",
+    short = 'm',
     long = "mem-image",
-    help = "Optional: Memory dump of a target Windows machine."
+    about = "Optional: Memory dump of a target Windows machine using winpmem, \
+    or of a Linux machine using the tool set by --memory-tool over SSH."
     )]
     pub image_memory: bool,
 
+    #[clap(long = "memory-tool", default_value = "avml", about =
+    "Optional: Name/path of the static tool uploaded to image memory of Linux targets, like AVML \
+    or a LiME kernel module built for the kernel of the target. (Linux target only)")]
+    pub memory_tool: String,
+
+    #[clap(long = "memory-tool-command", default_value = "{tool} {output}", about =
+    "Optional: Command running the memory tool on Linux targets, {tool} is replaced by the uploaded tool \
+    and {output} by the image path. For LiME use `insmod {tool} \"path={output} format=lime\"`. (Linux target only)")]
+    pub memory_tool_command: String,
+
+    #[clap(long = "memory-stream", about =
+    "Optional: Stream the memory image of Linux targets over the SSH channel instead of writing it \
+    to the target first. The memory tool must be able to write the image to /dev/stdout. (Linux target only)")]
+    pub memory_stream: bool,
+
     #[clap(
     long = "timeout",
-    help = "Optional: Timeout in seconds for long running operations.\
+    about = "Optional: Timeout in seconds for long running operations.\
     This option is a workaround for a bug in WMImplant.ps1 amd SharpRDP.exe where finishing of a long running operation cannot sometimes properly close the connection leaving the Gargamel in seemingly frozen state or executing the next operation with the previous one unfinished on target site.\
     Increasing this timeout may solve issues when acquiring registry or memory image from target machine.",
     default_value = "300",
     )]
     pub timeout: u64,
 
-    #[clap(long = "key", help = "Optional: Name/path of SSH private key file. (Linux target only)")]
+    #[clap(long = "key", about = "Optional: Name/path of SSH private key file. (Linux target only)")]
     pub ssh_key: Option<String>,
 
-    #[clap(long = "nla", help = "Optional: Use network level authentication when using RDP. (Windows targets only)")]
+    #[clap(long = "known-hosts", about = "Optional: Name/path of OpenSSH known_hosts file used to verify host key of the target. \
+    Defaults to ~/.ssh/known_hosts. (Linux target only)")]
+    pub known_hosts: Option<String>,
+
+    #[clap(long = "operator", about = "Optional: Name of the operator recorded in the evidence manifest. \
+    Defaults to the name of the current user.")]
+    pub operator: Option<String>,
+
+    #[clap(long = "nla", about = "Optional: Use network level authentication when using RDP. (Windows targets only)")]
     pub nla: bool,
 
-    #[clap(long = "no-7z", help = "Optional: Disable 7zip compression for registry & memory images.\
+    #[clap(long = "no-7z", about = "Optional: Disable 7zip compression for registry & memory images.\
     This will significantly decrease the running time, but WMI and RDP connections will probably not work properly.
     (Windows targets only)")]
     pub no_compression: bool,
 
-    #[clap(long = "redownload", help =
+    #[clap(long = "redownload", about =
     "Optional: Download and DELETE specified file from target computer. \
     Use this in case of previous failed partially completed operation. \
     For just downloading a file (without deleting it) please use a `search` switch. \
     If you specify a 7zip chunk (.7z.[chunk-number], e.g. .7z.004), then it will also automatically \
     try to download subsequent chunks. \
     Use also with --psexec --psrem, --rdp, --wmi, --all")]
-    pub re_download: Option<String>
+    pub re_download: Option<String>,
+
+    #[clap(long = "inventory", about =
+    "Optional: CSV or TOML file with the list of target hosts. \
+    Each host may specify its address, os, username, domain, methods and key_file. \
+    Missing values are taken from the command line. \
+    Evidence of each host is stored in its own subdirectory of the output directory.")]
+    pub inventory: Option<String>,
+
+    #[clap(long = "jobs", default_value = "4", about =
+    "Optional: Maximal number of hosts from the inventory acquired in parallel.")]
+    pub jobs: usize,
+
+    #[clap(long = "host-timeout", about =
+    "Optional: Time budget in seconds for the acquisition of one host from the inventory. \
+    A host exceeding its budget is reported as failed and Gargamel continues with the next host. \
+    The host stops after its current operation, before finishing the case Gargamel waits for it at most --timeout seconds.")]
+    pub host_timeout: Option<u64>,
+
+    #[clap(long = "profile", about =
+    "Optional: TOML or YAML file with the acquisition profile listing the acquired artifacts. \
+    Defaults to the built-in profile, see profiles/default.toml.")]
+    pub profile: Option<String>,
+
+    #[clap(long = "ioc", about =
+    "Optional: File with indicators of compromise, one per line: IP addresses, domains, MD5/SHA1/SHA256 hashes, \
+    process names or registry keys, optionally prefixed by their type like `domain:evil.com`. After the acquisition, \
+    the reports, registry exports, logs and downloaded files are scanned and matches are written to ioc-findings.json.")]
+    pub ioc: Option<String>,
+
+    #[clap(long = "yara", about =
+    "Optional: YARA rule file, or a directory with .yar and .yara rule files, to scan the files downloaded \
+    by --search with, including files inside downloaded 7zip and zip archives. \
+    Matching rules are recorded in the manifest and in ioc-findings.json.")]
+    pub yara: Option<String>,
+
+    #[clap(long = "resume", about =
+    "Optional: Continue an interrupted acquisition into the same output directory. \
+    Steps completed according to the journal.json are skipped and interrupted downloads of registry, \
+    event logs or memory images, including split 7zip archives, continue from the last transferred part.")]
+    pub resume: bool,
+
+    #[clap(long = "cleanup-only", about =
+    "Optional: Only delete the files Gargamel left on the target computer, as recorded in the cleanup.json \
+    of the output directory, without acquiring any evidence. Use together with the connection methods.")]
+    pub cleanup_only: bool,
+
+    #[clap(long = "poll-max-interval", default_value = "10", about =
+    "Optional: Maximal interval in seconds between two checks whether a remote file is ready. \
+    The interval starts at 250 ms and doubles after each check.")]
+    pub poll_max_interval: u64,
+
+    #[clap(long = "poll-timeout", default_value = "300", about =
+    "Optional: Time in seconds after which Gargamel stops waiting for a remote file to become ready or released.")]
+    pub poll_timeout: u64,
+
+    #[clap(long = "encrypt-to", about =
+    "Optional: age public key (age1...) to encrypt the evidence with. After the acquisition, even a failed or interrupted one, all files of the output directory \
+    including the manifest are moved into an encrypted container evidence-<time>.tar.age. Use `gargamel unpack` to decrypt it. \
+    Until then the acquired files are unencrypted in the output directory, so keep it on an encrypted volume.")]
+    pub encrypt_to: Option<String>,
+
+    #[clap(long = "encrypt-passphrase", about =
+    "Optional: Passphrase to encrypt the evidence with, instead of an age public key. \
+    To be prompted for the passphrase use `--encrypt-passphrase \"\"`")]
+    pub encrypt_passphrase: Option<String>,
 }
 
+pub const UNPACK_COMMAND: &str = "unpack";
+
+// Options of `gargamel unpack`, parsed separately as the acquisition options require the remote user.
+#[derive(Clap, Clone)]
+#[clap(name = "gargamel unpack", bin_name = "gargamel unpack", about = "Decrypts an evidence container and verifies the evidence against the manifest inside.")]
+pub struct UnpackOpts {
+    #[clap(about = "Encrypted evidence container (.tar.age)")]
+    pub container: String,
+
+    #[clap(
+    short = 'o',
+    long = "output",
+    default_value = "evidence-unpacked",
+    about = "Name of local directory to unpack the evidence into"
+    )]
+    pub output: String,
+
+    #[clap(long = "identity", about =
+    "Optional: File with the age identity (AGE-SECRET-KEY-1...) matching the public key the evidence was encrypted to. \
+    Skipping this option will prompt for the passphrase.")]
+    pub identity: Option<String>,
+
+    #[clap(long = "passphrase", about = "Optional: Passphrase the evidence was encrypted with.")]
+    pub passphrase: Option<String>,
+}
+
+pub const REPORT_COMMAND: &str = "report";
+
+// Options of `gargamel report`, parsed separately like `gargamel unpack`.
+#[derive(Clap, Clone)]
+#[clap(name = "gargamel report", bin_name = "gargamel report", about = "Correlates processes and network connections \
+of all hosts acquired into an output directory and writes an HTML and CSV summary.")]
+pub struct ReportOpts {
+    #[clap(about = "Output directory of a previous acquisition")]
+    pub directory: String,
+
+    #[clap(
+    short = 'o',
+    long = "output",
+    default_value = "evidence-report",
+    about = "Name of local directory to write the report into"
+    )]
+    pub output: String,
+
+    #[clap(long = "rare-threshold", default_value = "1", about =
+    "Optional: Processes running on at most this number of hosts are reported as rare.")]
+    pub rare_threshold: usize,
+}
+
+pub const HIVE_COMMAND: &str = "hive";
+
+// Options of `gargamel hive`, parsed separately like `gargamel unpack`.
+#[derive(Clap, Clone)]
+#[clap(name = "gargamel hive", bin_name = "gargamel hive", about = "Dumps keys of binary registry hives \
+acquired with --hives, with their last write times and values, to JSON lines.")]
+pub struct HiveOpts {
+    #[clap(required = true, about = "Registry hive files")]
+    pub hives: Vec<String>,
+
+    #[clap(
+    short = 'o',
+    long = "output",
+    about = "Optional: Local directory to write the <hive>.jsonl dumps into. Defaults to the directory of each hive."
+    )]
+    pub output: Option<String>,
+}
+
+impl UnpackOpts {
+    pub fn decryption_key(&self, read_passphrase: impl FnOnce() -> String) -> DecryptionKey {
+        match (&self.identity, &self.passphrase) {
+            (Some(identity), _) => DecryptionKey::IdentityFile(PathBuf::from(identity)),
+            (None, Some(passphrase)) => DecryptionKey::Passphrase(passphrase.clone()),
+            (None, None) => DecryptionKey::Passphrase(read_passphrase()),
+        }
+    }
+}
+
+impl Opts {
+    pub fn encryption_key(&self, read_passphrase: impl FnOnce() -> String) -> io::Result<Option<EncryptionKey>> {
+        let key = match (&self.encrypt_to, &self.encrypt_passphrase) {
+            (Some(recipient), _) => EncryptionKey::Recipient(recipient.clone()),
+            (None, Some(passphrase)) if passphrase.is_empty() => EncryptionKey::Passphrase(read_passphrase()),
+            (None, Some(passphrase)) => EncryptionKey::Passphrase(passphrase.clone()),
+            (None, None) => return Ok(None),
+        };
+        validate_key(&key)?;
+        Ok(Some(key))
+    }
+
+    pub fn acquisition_plan(&self, local_store_directory: &Path, profile: &Profile) -> AcquisitionPlan {
+        let methods = if self.all {
+            Method::windows()
+        } else {
+            let mut methods = vec![];
+            if self.psexec {
+                methods.push(Method::PsExec);
+            }
+            if self.wmi {
+                methods.push(Method::Wmi);
+            }
+            if self.psrem {
+                methods.push(Method::PsRemote);
+            }
+            if self.ssh {
+                methods.push(Method::Ssh);
+            }
+            if self.rdp {
+                methods.push(Method::Rdp);
+            }
+            methods
+        };
+        AcquisitionPlan {
+            methods,
+            remote_temp_storage: PathBuf::from(&self.remote_store_directory),
+            key_file: self.ssh_key.as_ref().map(PathBuf::from),
+            known_hosts: self.known_hosts.as_ref().map(PathBuf::from),
+            nla: self.nla,
+            no_compression: self.no_compression,
+            timeout: Duration::from_secs(self.timeout),
+            readiness: Readiness {
+                max_delay: Duration::from_secs(self.poll_max_interval),
+                timeout: Duration::from_secs(self.poll_timeout),
+                ..Readiness::default()
+            },
+            profile: self.event_profile(profile),
+            evidence: !self.disable_evidence_download,
+            events: !self.disable_event_download,
+            events_timeline: if self.disable_events_timeline {
+                None
+            } else {
+                Some(self.timeline_events.clone().unwrap_or_else(EventFilter::high_value))
+            },
+            registry: !self.disable_registry_download,
+            registry_hives: self.registry_hives,
+            custom_commands: self.custom_command_path.as_ref().map(PathBuf::from),
+            search_files: self.search_files_path.as_ref().map(PathBuf::from),
+            re_download: self.re_download.as_ref().map(PathBuf::from),
+            memory_image: self.image_memory,
+            linux_memory_tool: LinuxMemoryTool {
+                path: PathBuf::from(&self.memory_tool),
+                command: self.memory_tool_command.clone(),
+                stream: self.memory_stream,
+            },
+            ..AcquisitionPlan::new(Computer::from(self.clone()), local_store_directory)
+        }
+    }
+
+    fn event_profile(&self, profile: &Profile) -> Profile {
+        let mut profile = profile.clone();
+        if let Some(channels) = &self.event_channels {
+            let channels: Vec<String> = channels.split(',')
+                .map(|it| it.trim().to_string())
+                .filter(|it| !it.is_empty())
+                .collect();
+            profile = profile.with_event_channels(&channels);
+        }
+        if let Some(days) = self.events_since {
+            profile = profile.with_events_since(days);
+        }
+        profile
+    }
+}
+
+impl From<Opts> for Computer {
+    fn from(opts: Opts) -> Self {
+        Computer {
+            address: opts.computer,
+            username: opts.user,
+            domain: opts.domain,
+            password: opts.password,
+        }
+    }
+}
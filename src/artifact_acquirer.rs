@@ -0,0 +1,179 @@
+use std::path::Path;
+use std::time::Duration;
+use std::io;
+use std::io::ErrorKind;
+use crate::remote::{Connector, Command, Compression, HostPlatform, download_verified};
+use crate::process_runner::CommandOutcome;
+use crate::large_evidence_acquirer::LargeEvidenceAcquirer;
+use crate::profile::Artifact;
+use crate::manifest::{Provenance, record_file};
+use crate::journal::{StepKey, skipped_step, start_step, complete_step};
+use crate::parsers::{Parser, write_parsed};
+use crate::plan::is_cancelled;
+
+pub(crate) struct ArtifactAcquirer<'a> {
+    pub(crate) store_directory: &'a Path,
+    pub(crate) connector: &'a dyn Connector,
+    pub(crate) compress_timeout: Option<Duration>,
+    pub(crate) compression: Compression,
+}
+
+impl<'a> ArtifactAcquirer<'a> {
+    pub(crate) fn acquire_all(&self, artifacts: &[Artifact]) -> Vec<io::Result<CommandOutcome>> {
+        let mut outcomes = vec![];
+        let installed = match self.detect_programs(artifacts) {
+            Ok(installed) => installed,
+            Err(err) => {
+                error!("Cannot detect programs installed on {}. Cause: {}", self.connector.computer().address, err);
+                outcomes.push(Err(err));
+                None
+            }
+        };
+        for artifact in artifacts {
+            if is_cancelled(self.connector.computer()) {
+                debug!("Acquisition from {} is cancelled, skipping remaining artifacts", self.connector.computer().address);
+                break;
+            }
+            if let Some(program) = &artifact.requires {
+                let is_installed = installed.as_ref()
+                    .map(|installed| installed.contains(program))
+                    .unwrap_or(false);
+                if !is_installed {
+                    info!("{}: {} not found, skipping {}", self.connector.connect_method_name(), program, artifact.name);
+                    continue;
+                }
+            }
+            outcomes.push(self.acquire(artifact));
+        }
+        outcomes
+    }
+
+    fn detect_programs(&self, artifacts: &[Artifact]) -> io::Result<Option<Vec<String>>> {
+        let programs: Vec<&str> = artifacts.iter()
+            .filter_map(|artifact| artifact.requires.as_deref())
+            .collect();
+        if programs.is_empty() {
+            return Ok(None);
+        }
+        if self.connector.remote_platform() != HostPlatform::Linux {
+            warn!("Required programs can be detected only on Linux targets");
+            return Ok(None);
+        }
+        let detection = format!(
+            "PATH=$PATH:/sbin:/usr/sbin; for p in {}; do command -v $p >/dev/null 2>&1 && echo $p; done; true",
+            programs.join(" ")
+        );
+        let outcome = self.connector.connect_and_run_command(
+            Command::new(
+                vec![detection],
+                None,
+                "program-detection",
+                false,
+            ),
+            None,
+        )?;
+        Ok(Some(outcome.stdout.lines().map(|line| line.trim().to_string()).collect()))
+    }
+
+    pub(crate) fn acquire(&self, artifact: &Artifact) -> io::Result<CommandOutcome> {
+        if artifact.large && artifact.file.is_none() {
+            // the channel and the query of an event log export are data and must stay single arguments
+            let quoted = |args: Vec<String>| if artifact.channel.is_some() {
+                args.iter().map(|arg| self.connector.quote_argument(arg)).collect()
+            } else {
+                args
+            };
+            let lea = LargeEvidenceAcquirer {
+                store_directory: self.store_directory,
+                connector: self.connector,
+                compress_timeout: self.compress_timeout,
+                compression: if artifact.compress { self.compression } else { Compression::No },
+                report_extension: &artifact.extension,
+                switches: quoted(artifact.switches()),
+                elevated: artifact.elevated,
+                command_timeout: artifact.timeout(),
+            };
+            return lea.run(&quoted(artifact.command_line()), &artifact.name);
+        }
+        let key = StepKey::new(self.connector.computer(), self.connector.connect_method_name(), &artifact.name);
+        if let Some(outcome) = skipped_step(&key) {
+            return Ok(outcome);
+        }
+        start_step(&key, &artifact.command_line(), None);
+        let outcome = match &artifact.file {
+            Some(file) => self.download(artifact, Path::new(file)),
+            None => self.run(artifact),
+        };
+        if let Ok(outcome) = &outcome {
+            if outcome.is_success() {
+                complete_step(&key, outcome.report_path.as_deref());
+            }
+        }
+        outcome
+    }
+
+    fn run(&self, artifact: &Artifact) -> io::Result<CommandOutcome> {
+        let command = artifact.command_line();
+        if command.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Empty command to acquire {}", artifact.name),
+            ));
+        }
+        let remote_connection = Command::new(
+            command.clone(),
+            Some(self.store_directory),
+            &artifact.name,
+            artifact.elevated,
+        );
+
+        info!("{}: Checking {}",
+              self.connector.connect_method_name(),
+              artifact.name.replace("-", " ")
+        );
+
+        let outcome = self.connector.connect_and_run_command(remote_connection, artifact.timeout());
+        match &outcome {
+            Ok(outcome) => {
+                if let (Some(parser), Some(report_path), true) = (artifact.parser, &outcome.report_path, outcome.is_success()) {
+                    self.parse(parser, report_path, &command);
+                }
+                if !outcome.is_success() {
+                    warn!(
+                        "Command {:?} finished with exit code {:?}{}",
+                        command,
+                        outcome.exit_code,
+                        if outcome.timed_out { " (timed out)" } else { "" }
+                    )
+                }
+            }
+            Err(err) => { error!("Error running command {:?}. Cause: {}", command, err) }
+        }
+        outcome
+    }
+
+    fn parse(&self, parser: Parser, report_path: &Path, command: &[String]) {
+        match write_parsed(parser, report_path) {
+            Ok(parsed_path) => {
+                let provenance = Provenance::new(self.connector.computer(), self.connector.connect_method_name())
+                    .command(command);
+                record_file(&parsed_path, &provenance);
+            }
+            Err(err) => warn!("Cannot parse {} due to {}", report_path.display(), err),
+        }
+    }
+
+    fn download(&self, artifact: &Artifact, file: &Path) -> io::Result<CommandOutcome> {
+        info!("{}: Downloading {}", self.connector.connect_method_name(), artifact.name);
+        let provenance = Provenance::new(self.connector.computer(), self.connector.connect_method_name())
+            .source(file);
+        download_verified(self.connector, file, self.store_directory)?;
+        let local_path = self.store_directory.join(file.file_name().unwrap_or(file.as_os_str()));
+        record_file(&local_path, &provenance);
+        Ok(CommandOutcome {
+            exit_code: Some(0),
+            report_path: Some(local_path),
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use crate::ioc;
+use crate::ioc::Ioc;
+use crate::timeline;
+use crate::yara_scanner;
+
+// Analyses of the whole case. Downloaded files are scanned by the YARA rules while they are acquired,
+// the IOC matching and the timeline run once the acquisition from all hosts finished.
+#[derive(Clone, Debug, Default)]
+pub struct CaseAnalysis {
+    pub iocs: Option<Vec<Ioc>>,
+    pub yara_rules: Option<PathBuf>,
+    pub timeline: bool,
+}
+
+impl CaseAnalysis {
+    // Call before running the acquisition plans, so their downloaded files are scanned.
+    pub fn start(&self, case_directory: &Path) -> io::Result<()> {
+        if let Some(rules_path) = &self.yara_rules {
+            yara_scanner::start_yara_scanning(rules_path, case_directory)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self, case_directory: &Path) -> io::Result<()> {
+        if self.iocs.is_some() || self.yara_rules.is_some() {
+            let mut findings = yara_scanner::take_yara_findings();
+            if let Some(iocs) = &self.iocs {
+                findings.extend(ioc::scan_case(case_directory, iocs)?);
+            }
+            let findings_path = ioc::write_findings(case_directory, &findings)?;
+            info!("Found {} IOC and YARA rule matches, see {}", findings.len(), findings_path.display());
+        }
+        if self.timeline {
+            let count = timeline::write_case_timeline(case_directory)?;
+            info!("Timeline of {} entries is written to {}", count, case_directory.join(timeline::TIMELINE_CSV).display());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::case_analysis::CaseAnalysis;
+    use crate::ioc::{load_iocs, IOC_FINDINGS_JSON};
+    use crate::timeline::TIMELINE_CSV;
+    use std::fs;
+
+    #[test]
+    fn test_finish_writes_findings_and_timeline() {
+        let case_directory = std::env::temp_dir().join(format!("gargamel-analysis-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(case_directory.join("10.0.0.1")).unwrap();
+        fs::write(case_directory.join("10.0.0.1").join("netstat.txt"), "TCP 10.0.0.1:49712 185.220.101.4:443 ESTABLISHED").unwrap();
+        let ioc_path = std::env::temp_dir().join(format!("gargamel-iocs-{}.txt", uuid::Uuid::new_v4()));
+        fs::write(&ioc_path, "185.220.101.4\n").unwrap();
+        let analysis = CaseAnalysis {
+            iocs: Some(load_iocs(&ioc_path).unwrap()),
+            yara_rules: None,
+            timeline: true,
+        };
+
+        analysis.start(&case_directory).unwrap();
+        analysis.finish(&case_directory).unwrap();
+
+        let findings = fs::read_to_string(case_directory.join(IOC_FINDINGS_JSON)).unwrap();
+        assert!(findings.contains("185.220.101.4"));
+        assert!(findings.contains("netstat.txt"));
+        assert!(case_directory.join(TIMELINE_CSV).is_file());
+    }
+}
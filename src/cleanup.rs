@@ -0,0 +1,308 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::io;
+use std::io::{BufReader, ErrorKind};
+use std::fs::File;
+use serde::{Serialize, Deserialize};
+use chrono::Utc;
+use crate::remote::{Computer, RemoteFileCopier, RemoteFileState, path_to_part};
+use crate::manifest::timestamp;
+
+pub const CLEANUP_JSON: &str = "cleanup.json";
+
+static CLEANUP: Mutex<Option<CleanupRegistry>> = Mutex::new(None);
+
+// A file created on a target which must be deleted once the acquisition is over.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RemoteArtifact {
+    pub computer: String,
+    pub path: String,
+    pub registered: String,
+    // kept on the target on purpose, e.g. the only good copy of a failed or corrupted transfer
+    #[serde(default)]
+    pub retained: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CleanupRegistry {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    pub artifacts: Vec<RemoteArtifact>,
+}
+
+impl CleanupRegistry {
+    fn open(case_directory: &Path) -> io::Result<CleanupRegistry> {
+        let path = case_directory.join(CLEANUP_JSON);
+        let existing = if path.exists() {
+            serde_json::from_reader(BufReader::new(File::open(&path)?))?
+        } else {
+            CleanupRegistry::default()
+        };
+        Ok(CleanupRegistry { path: Some(path), ..existing })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            None => return Ok(()),
+            Some(path) => path,
+        };
+        let temp = path.with_file_name(format!("{}.tmp", CLEANUP_JSON));
+        {
+            let mut file = File::create(&temp)?;
+            serde_json::to_writer_pretty(&mut file, self)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&temp, path)
+    }
+}
+
+fn update<F>(update: F) where F: FnOnce(&mut CleanupRegistry) {
+    let mut registry = CLEANUP.lock().unwrap();
+    let registry = registry.get_or_insert_with(CleanupRegistry::default);
+    update(registry);
+    if let Err(err) = registry.save() {
+        error!("Cannot save cleanup registry due to {}", err);
+    }
+}
+
+// Continues with the registry of the case directory, so files left by previous runs are not forgotten.
+pub fn start_cleanup_registry(case_directory: &Path) -> io::Result<()> {
+    let registry = CleanupRegistry::open(case_directory)?;
+    if !registry.artifacts.is_empty() {
+        warn!("{} remote files left by previous runs are still registered for cleanup", registry.artifacts.len());
+    }
+    registry.save()?;
+    *CLEANUP.lock().unwrap() = Some(registry);
+    Ok(())
+}
+
+pub fn register_remote_file(computer: &Computer, path: &Path) {
+    let artifact = RemoteArtifact {
+        computer: computer.address.clone(),
+        path: path.to_string_lossy().to_string(),
+        registered: timestamp(Utc::now()),
+        retained: false,
+    };
+    update(|registry| {
+        match registry.artifacts.iter_mut().find(|it| it.computer == artifact.computer && it.path == artifact.path) {
+            // created again, so the retained copy is overwritten
+            Some(registered) => registered.retained = false,
+            None => {
+                trace!("Registering remote file {} for cleanup", artifact.path);
+                registry.artifacts.push(artifact);
+            }
+        }
+    })
+}
+
+// The registered file is not deleted by the cleanup, but stays listed in the registry,
+// so it can be transferred again using --redownload, which deletes it afterwards.
+pub fn retain_remote_file(computer: &Computer, path: &Path) {
+    let path = path.to_string_lossy();
+    let registered = CLEANUP.lock().unwrap().as_ref()
+        .map(|registry| registry.artifacts.iter().any(|it| it.computer == computer.address && it.path == path))
+        .unwrap_or(false);
+    if registered {
+        update(|registry| {
+            for artifact in registry.artifacts.iter_mut().filter(|it| it.computer == computer.address && it.path == path) {
+                debug!("Keeping remote file {} on {}", artifact.path, artifact.computer);
+                artifact.retained = true;
+            }
+        })
+    }
+}
+
+pub fn unregister_remote_file(computer: &Computer, path: &Path) {
+    let path = path.to_string_lossy();
+    let registered = CLEANUP.lock().unwrap().as_ref()
+        .map(|registry| registry.artifacts.iter().any(|it| it.computer == computer.address && it.path == path))
+        .unwrap_or(false);
+    if registered {
+        update(|registry| registry.artifacts.retain(|it| it.computer != computer.address || it.path != path))
+    }
+}
+
+fn remote_files(computer: &Computer, retained: bool) -> Vec<PathBuf> {
+    CLEANUP.lock().unwrap().as_ref()
+        .map(|registry| registry.artifacts.iter()
+            .filter(|it| it.computer == computer.address && it.retained == retained)
+            .map(|it| PathBuf::from(&it.path))
+            .collect())
+        .unwrap_or_default()
+}
+
+// Files to be deleted by the cleanup.
+pub fn registered_remote_files(computer: &Computer) -> Vec<PathBuf> {
+    remote_files(computer, false)
+}
+
+pub fn retained_remote_files(computer: &Computer) -> Vec<PathBuf> {
+    remote_files(computer, true)
+}
+
+fn delete(copiers: &[&dyn RemoteFileCopier], path: &Path) -> io::Result<()> {
+    let mut last_error = io::Error::other("No method to delete remote files");
+    for copier in copiers {
+        match copier.delete_remote_file(path) {
+            Ok(_) => return Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => last_error = err,
+        }
+    }
+    Err(last_error)
+}
+
+// 7zip parts not reached by the interrupted download were never registered, so they are
+// found by probing the following parts, which is possible only for some methods.
+fn delete_following_parts(copiers: &[&dyn RemoteFileCopier], part: &Path) {
+    let number = match part.extension().and_then(|it| it.to_string_lossy().parse::<usize>().ok()) {
+        None => return,
+        Some(number) => number,
+    };
+    let archive = part.with_extension("");
+    for copier in copiers {
+        let mut next = number + 1;
+        while let Ok(RemoteFileState::Present { .. }) = copier.remote_file_state(&path_to_part(&archive, next)) {
+            if let Err(err) = copier.delete_remote_file(&path_to_part(&archive, next)) {
+                warn!("Cannot delete remote file {} due to {}", path_to_part(&archive, next).display(), err);
+                break;
+            }
+            next += 1;
+        }
+    }
+}
+
+// Deletes all files registered for the computer trying the given methods in turn, retained files are kept.
+// Returns the number of files which are still left on the computer.
+pub fn clean_up(computer: &Computer, copiers: &[&dyn RemoteFileCopier]) -> usize {
+    for file in retained_remote_files(computer) {
+        warn!("Remote file {} is kept on {}, use --redownload to transfer it", file.display(), computer.address);
+    }
+    let files = registered_remote_files(computer);
+    if files.is_empty() {
+        return 0;
+    }
+    info!("Deleting {} remote files from {}", files.len(), computer.address);
+    let mut left = 0;
+    for file in files {
+        match delete(copiers, &file) {
+            Ok(_) => {
+                unregister_remote_file(computer, &file);
+                delete_following_parts(copiers, &file);
+            }
+            Err(err) => {
+                warn!("Cannot delete remote file {} from {} due to {}", file.display(), computer.address, err);
+                left += 1;
+            }
+        }
+    }
+    left
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cleanup::{register_remote_file, registered_remote_files, unregister_remote_file, retain_remote_file, retained_remote_files, clean_up};
+    use crate::remote::{Computer, MockTarget, MockCopier, RemoteFileCopier};
+    use std::path::{Path, PathBuf};
+
+    // the registry is global, so every test uses its own computer
+    fn computer(address: &str) -> Computer {
+        Computer {
+            address: address.to_string(),
+            username: "Jano".to_string(),
+            domain: None,
+            password: None,
+        }
+    }
+
+    #[test]
+    fn test_file_is_registered_once() {
+        let other = computer("10.0.0.12");
+        let computer = computer("10.0.0.11");
+        register_remote_file(&computer, Path::new("/tmp/winpmem.exe"));
+        register_remote_file(&computer, Path::new("/tmp/winpmem.exe"));
+        register_remote_file(&computer, Path::new("/tmp/deleted.txt"));
+        unregister_remote_file(&computer, Path::new("/tmp/deleted.txt"));
+        assert_eq!(vec![PathBuf::from("/tmp/winpmem.exe")], registered_remote_files(&computer));
+        assert!(registered_remote_files(&other).is_empty());
+    }
+
+    #[test]
+    fn test_clean_up_deletes_registered_files_and_following_parts() {
+        let computer = computer("10.0.0.14");
+        let target = MockTarget::new();
+        let copier = MockCopier { computer: computer.clone(), target: target.clone() };
+        for file in &["winpmem.exe", "image.aff4.7z.002", "image.aff4.7z.003", "kept.txt"] {
+            target.add_file(&Path::new("/tmp").join(file), b"content");
+        }
+        register_remote_file(&computer, Path::new("/tmp/winpmem.exe"));
+        register_remote_file(&computer, Path::new("/tmp/image.aff4.7z.002"));
+
+        let left = clean_up(&computer, &[&copier as &dyn RemoteFileCopier]);
+
+        assert_eq!(0, left);
+        assert!(registered_remote_files(&computer).is_empty());
+        assert_eq!(vec![PathBuf::from("/tmp/kept.txt")], target.files());
+    }
+
+    #[test]
+    fn test_clean_up_treats_missing_file_as_deleted() {
+        let computer = computer("10.0.0.15");
+        let target = MockTarget::new();
+        let copier = MockCopier { computer: computer.clone(), target };
+        register_remote_file(&computer, Path::new("/tmp/deleted.txt"));
+
+        let left = clean_up(&computer, &[&copier as &dyn RemoteFileCopier]);
+
+        assert_eq!(0, left);
+        assert!(registered_remote_files(&computer).is_empty());
+    }
+
+    #[test]
+    fn test_clean_up_keeps_locked_file_registered() {
+        let computer = computer("10.0.0.16");
+        let target = MockTarget::new();
+        let copier = MockCopier { computer: computer.clone(), target: target.clone() };
+        target.add_file(Path::new("/tmp/memory.aff4"), b"content");
+        target.lock_file(Path::new("/tmp/memory.aff4"));
+        register_remote_file(&computer, Path::new("/tmp/memory.aff4"));
+
+        let left = clean_up(&computer, &[&copier as &dyn RemoteFileCopier]);
+
+        assert_eq!(1, left);
+        assert_eq!(vec![PathBuf::from("/tmp/memory.aff4")], registered_remote_files(&computer));
+        assert_eq!(vec![PathBuf::from("/tmp/memory.aff4")], target.files());
+    }
+
+    #[test]
+    fn test_clean_up_keeps_retained_file() {
+        let computer = computer("10.0.0.17");
+        let target = MockTarget::new();
+        let copier = MockCopier { computer: computer.clone(), target: target.clone() };
+        target.add_file(Path::new("/tmp/registry.7z"), b"content");
+        target.add_file(Path::new("/tmp/winpmem.exe"), b"content");
+        register_remote_file(&computer, Path::new("/tmp/registry.7z"));
+        register_remote_file(&computer, Path::new("/tmp/winpmem.exe"));
+        retain_remote_file(&computer, Path::new("/tmp/registry.7z"));
+
+        let left = clean_up(&computer, &[&copier as &dyn RemoteFileCopier]);
+
+        assert_eq!(0, left);
+        assert!(registered_remote_files(&computer).is_empty());
+        assert_eq!(vec![PathBuf::from("/tmp/registry.7z")], retained_remote_files(&computer));
+        assert_eq!(vec![PathBuf::from("/tmp/registry.7z")], target.files());
+    }
+
+    #[test]
+    fn test_registering_again_cancels_retaining() {
+        let computer = computer("10.0.0.18");
+        register_remote_file(&computer, Path::new("/tmp/registry.7z"));
+        retain_remote_file(&computer, Path::new("/tmp/registry.7z"));
+        retain_remote_file(&computer, Path::new("/tmp/unregistered.txt"));
+
+        register_remote_file(&computer, Path::new("/tmp/registry.7z"));
+
+        assert_eq!(vec![PathBuf::from("/tmp/registry.7z")], registered_remote_files(&computer));
+        assert!(retained_remote_files(&computer).is_empty());
+    }
+}
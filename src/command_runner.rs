@@ -3,6 +3,10 @@ use std::path::{Path, PathBuf};
 use std::fs::File;
 use crate::command_utils::parse_command;
 use std::time::Duration;
+use crate::process_runner::CommandOutcome;
+use crate::journal::{StepKey, skipped_step, start_step, complete_step};
+use crate::plan::is_cancelled;
+use std::io;
 
 pub struct CommandRunner<'a> {
     local_store_directory: &'a Path,
@@ -11,6 +15,18 @@ pub struct CommandRunner<'a> {
 }
 
 impl<'a> CommandRunner<'a> {
+    pub fn new(
+        connector: Box<dyn Connector>,
+        local_store_directory: &'a Path,
+        run_implicit: bool,
+    ) -> CommandRunner<'a> {
+        CommandRunner {
+            local_store_directory,
+            connector,
+            run_implicit,
+        }
+    }
+
     pub fn psexec(
         remote_computer: Computer,
         local_store_directory: &'a Path,
@@ -78,10 +94,11 @@ impl<'a> CommandRunner<'a> {
         remote_computer: Computer,
         local_store_directory: &'a Path,
         key_file: Option<PathBuf>,
+        known_hosts: Option<PathBuf>,
     ) -> CommandRunner<'a> {
         CommandRunner {
             local_store_directory,
-            connector: Box::new(Ssh { key_file, computer: remote_computer }),
+            connector: Box::new(Ssh::new(remote_computer, key_file, known_hosts)),
             run_implicit: false,
         }
     }
@@ -90,23 +107,28 @@ impl<'a> CommandRunner<'a> {
         &self,
         command_file: &Path,
         timeout: Option<Duration>
-    ) {
+    ) -> Vec<io::Result<CommandOutcome>> {
+        let mut outcomes = Vec::new();
         let file = match File::open(command_file) {
             Ok(file) => file,
             Err(err) => {
                 error!("{}", err);
-                return;
+                return outcomes;
             }
         };
         let reader = std::io::BufReader::new(file);
         use std::io::BufRead;
-        for one_command in reader.lines().filter_map(|item| item.ok()) {
+        for one_command in reader.lines().map_while(Result::ok) {
             if one_command.starts_with("#") {
                 continue;
             }
             if one_command.is_empty() {
                 continue;
             }
+            if is_cancelled(self.connector.computer()) {
+                debug!("Acquisition from {} is cancelled, skipping remaining commands", self.connector.computer().address);
+                break;
+            }
             debug!("Running remote command {}", one_command);
             let command = parse_command(&one_command);
 
@@ -140,19 +162,40 @@ impl<'a> CommandRunner<'a> {
                 .replace("\\", "")
                 .replace(":", "-");
             let report_filename_prefix = format!("custom-{}", command_joined);
+            let key = StepKey::new(self.connector.computer(), self.connector.connect_method_name(), &report_filename_prefix);
+            if let Some(outcome) = skipped_step(&key) {
+                outcomes.push(Ok(outcome));
+                continue;
+            }
+            start_step(&key, &command, None);
 
             let remote_connection = Command::new(
                 command,
-                Some(&self.local_store_directory),
+                Some(self.local_store_directory),
                 &report_filename_prefix,
                 elevated,
             );
-            if let Err(err) = self.connector.connect_and_run_command(
+            let outcome = self.connector.connect_and_run_command(
                 remote_connection,
                 timeout
-            ) {
-                error!("{}", err)
-            };
+            );
+            match &outcome {
+                Ok(outcome) => {
+                    if outcome.is_success() {
+                        complete_step(&key, outcome.report_path.as_deref());
+                    } else {
+                        warn!(
+                            "Command {} finished with exit code {:?}{}",
+                            one_command,
+                            outcome.exit_code,
+                            if outcome.timed_out { " (timed out)" } else { "" }
+                        )
+                    }
+                }
+                Err(err) => error!("{}", err),
+            }
+            outcomes.push(outcome);
         }
+        outcomes
     }
 }
@@ -24,7 +24,7 @@ pub fn parse_command(command: &str) -> Vec<String> {
             take_from = i + 1
         }
     }
-    if take_from < command.chars().count() - 1{
+    if take_from < command.len() {
         result.push(command[take_from..].to_string());
     }
     result
@@ -34,7 +34,6 @@ pub fn parse_command(command: &str) -> Vec<String> {
 #[cfg(test)]
 mod tests {
     use crate::command_utils::parse_command;
-    use std::ops::Deref;
 
     fn test_parse_command(expected_output: &[&str]) {
         let input = expected_output.join(" ");
@@ -45,7 +44,7 @@ mod tests {
             result.len()
         );
         for (i, expected_output_item) in expected_output.iter().enumerate() {
-            assert_eq!(expected_output_item.deref().trim(), result[i].as_str())
+            assert_eq!(expected_output_item.trim(), result[i].as_str())
         }
     }
 
@@ -83,7 +82,14 @@ mod tests {
     #[test]
     fn test_two_word_command() {
         test_parse_command(
-            &["one two"]
+            &["one", "two"]
+        );
+    }
+
+    #[test]
+    fn test_single_char_last_arg_command() {
+        test_parse_command(
+            &["one", "t"]
         );
     }
 }
@@ -0,0 +1,321 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::{BufReader, BufWriter, BufRead, ErrorKind, Read, Write};
+use std::fs::File;
+use std::str::FromStr;
+use chrono::Utc;
+use age::{Encryptor, Decryptor, x25519};
+use age::secrecy::SecretString;
+use crate::manifest::{Manifest, MANIFEST_JSON};
+use crate::cleanup::CLEANUP_JSON;
+use crate::hash::hash_file;
+
+pub const CONTAINER_EXTENSION: &str = "tar.age";
+
+// Key the evidence is encrypted with, either an age public key (`age1...`) or a passphrase.
+#[derive(Clone, Debug)]
+pub enum EncryptionKey {
+    Recipient(String),
+    Passphrase(String),
+}
+
+// Key a container is decrypted with, either a file with age identities (`AGE-SECRET-KEY-1...`) or a passphrase.
+#[derive(Clone, Debug)]
+pub enum DecryptionKey {
+    IdentityFile(PathBuf),
+    Passphrase(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Verification {
+    pub verified: usize,
+    pub failed: Vec<String>,
+}
+
+pub fn is_container(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(&format!(".{}", CONTAINER_EXTENSION))
+}
+
+fn encryption_error(cause: impl std::fmt::Display) -> io::Error {
+    io::Error::other(format!("Cannot encrypt evidence: {}", cause))
+}
+
+fn decryption_error(container: &Path, cause: impl std::fmt::Display) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("Cannot decrypt {}: {}", container.display(), cause))
+}
+
+fn encryptor(key: &EncryptionKey) -> io::Result<Encryptor> {
+    match key {
+        EncryptionKey::Recipient(recipient) => {
+            let recipient = x25519::Recipient::from_str(recipient)
+                .map_err(|err| encryption_error(format!("{} is not an age public key ({})", recipient, err)))?;
+            Encryptor::with_recipients(vec![Box::new(recipient)])
+                .ok_or_else(|| encryption_error("no recipient"))
+        }
+        EncryptionKey::Passphrase(passphrase) => Ok(Encryptor::with_user_passphrase(SecretString::new(passphrase.clone()))),
+    }
+}
+
+// Checks the key before the acquisition, so that no evidence is left unencrypted due to a mistyped key.
+pub fn validate_key(key: &EncryptionKey) -> io::Result<()> {
+    if let EncryptionKey::Passphrase(passphrase) = key {
+        if passphrase.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "Cannot encrypt evidence: the passphrase is empty"));
+        }
+    }
+    encryptor(key).map(|_| ())
+}
+
+fn read_identities(identity_file: &Path) -> io::Result<Vec<x25519::Identity>> {
+    let mut identities = vec![];
+    for line in BufReader::new(File::open(identity_file)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let identity = x25519::Identity::from_str(line).map_err(|err| io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} contains an invalid age identity ({})", identity_file.display(), err),
+        ))?;
+        identities.push(identity);
+    }
+    Ok(identities)
+}
+
+// Files left in the case directory are needed to delete files from the targets or are older containers.
+fn is_sealed(path: &Path) -> bool {
+    let name = path.file_name().map(|it| it.to_string_lossy().to_string()).unwrap_or_default();
+    !is_container(path) && !name.starts_with(CLEANUP_JSON) && !name.ends_with(".tmp")
+}
+
+fn list_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else if is_sealed(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn remove_empty_directories(directory: &Path) -> io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_empty_directories(&path)?;
+            if std::fs::read_dir(&path)?.next().is_none() {
+                std::fs::remove_dir(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_container<W: Write>(case_directory: &Path, files: &[PathBuf], key: &EncryptionKey, output: W) -> io::Result<W> {
+    let encrypted = encryptor(key)?.wrap_output(output).map_err(encryption_error)?;
+    let mut archive = tar::Builder::new(encrypted);
+    for file in files {
+        let name = file.strip_prefix(case_directory).unwrap_or(file);
+        trace!("Sealing {}", name.display());
+        archive.append_path_with_name(file, name)?;
+    }
+    archive.into_inner()?.finish()
+}
+
+// Overwrites the content with zeros before deleting the file, so the plain evidence cannot be simply undeleted.
+// File systems writing elsewhere than in place, e.g. copy-on-write ones or SSDs, can still keep the old content.
+fn wipe_file(path: &Path) -> io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let mut remaining = file.metadata()?.len();
+    let zeros = [0u8; 64 * 1024];
+    while remaining > 0 {
+        let length = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..length])?;
+        remaining -= length as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    std::fs::remove_file(path)
+}
+
+// Streams all files of the case directory, including the manifest, into an encrypted tar archive
+// `evidence-<time>.tar.age` and wipes the plain files. Returns the path of the container.
+// The files are unencrypted in the case directory until then, as the analyses of the case read them.
+pub fn seal_case(case_directory: &Path, key: &EncryptionKey) -> io::Result<PathBuf> {
+    let mut files = vec![];
+    list_files(case_directory, &mut files)?;
+    files.sort();
+    let container = case_directory.join(format!("evidence-{}.{}", Utc::now().format("%Y%m%dT%H%M%SZ"), CONTAINER_EXTENSION));
+    let temp = container.with_file_name(format!("{}.tmp", container.file_name().unwrap().to_string_lossy()));
+    info!("Encrypting {} files into {}", files.len(), container.display());
+    let output = write_container(case_directory, &files, key, BufWriter::new(File::create(&temp)?))?;
+    output.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    std::fs::rename(&temp, &container)?;
+    for file in files {
+        if let Err(err) = wipe_file(&file) {
+            warn!("Cannot delete unencrypted file {} due to {}", file.display(), err);
+        }
+    }
+    remove_empty_directories(case_directory)?;
+    Ok(container)
+}
+
+fn decrypt<R: Read + 'static>(container: &Path, input: R, key: &DecryptionKey) -> io::Result<Box<dyn Read>> {
+    let decryptor = Decryptor::new(input).map_err(|err| decryption_error(container, err))?;
+    let decrypted: Box<dyn Read> = match (decryptor, key) {
+        (Decryptor::Recipients(decryptor), DecryptionKey::IdentityFile(identity_file)) => {
+            let identities = read_identities(identity_file)?;
+            Box::new(decryptor.decrypt(identities.iter().map(|it| it as &dyn age::Identity))
+                .map_err(|err| decryption_error(container, err))?)
+        }
+        (Decryptor::Passphrase(decryptor), DecryptionKey::Passphrase(passphrase)) => {
+            Box::new(decryptor.decrypt(&SecretString::new(passphrase.clone()), None)
+                .map_err(|err| decryption_error(container, err))?)
+        }
+        (Decryptor::Recipients(_), _) => return Err(decryption_error(container, "it is encrypted to an age public key, use an identity file")),
+        (Decryptor::Passphrase(_), _) => return Err(decryption_error(container, "it is encrypted with a passphrase")),
+    };
+    Ok(decrypted)
+}
+
+// Checks the unpacked files against the SHA-256 hashes of the manifest inside the container.
+pub fn verify_unpacked(directory: &Path) -> io::Result<Verification> {
+    let manifest: Manifest = serde_json::from_reader(BufReader::new(File::open(directory.join(MANIFEST_JSON))?))?;
+    let mut verification = Verification::default();
+    for entry in manifest.entries {
+        match hash_file(&directory.join(&entry.file)) {
+            Ok(hashes) if hashes.sha256 == entry.sha256 => verification.verified += 1,
+            Ok(_) => {
+                error!("{} does not match the manifest", entry.file);
+                verification.failed.push(entry.file);
+            }
+            Err(err) => {
+                error!("Cannot verify {} due to {}", entry.file, err);
+                verification.failed.push(entry.file);
+            }
+        }
+    }
+    Ok(verification)
+}
+
+// Decrypts the container into the target directory and verifies the unpacked evidence.
+pub fn unpack(container: &Path, key: &DecryptionKey, target_directory: &Path) -> io::Result<Verification> {
+    let decrypted = decrypt(container, BufReader::new(File::open(container)?), key)?;
+    std::fs::create_dir_all(target_directory)?;
+    tar::Archive::new(decrypted).unpack(target_directory)
+        .map_err(|err| decryption_error(container, err))?;
+    verify_unpacked(target_directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::container::{seal_case, unpack, validate_key, EncryptionKey, DecryptionKey, Verification};
+    use crate::manifest::{Manifest, ManifestEntry, MANIFEST_JSON};
+    use crate::cleanup::CLEANUP_JSON;
+    use crate::hash::hash_bytes;
+    use age::secrecy::ExposeSecret;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const EVIDENCE: &[u8] = b"Windows IP Configuration";
+
+    // Case directory with one evidence file and one file changed after it was recorded in the manifest.
+    fn case_directory() -> (PathBuf, PathBuf) {
+        let directory = std::env::temp_dir().join(format!("gargamel-container-{}", uuid::Uuid::new_v4()));
+        let case = directory.join("case");
+        fs::create_dir_all(case.join("10.0.0.1")).unwrap();
+        fs::write(case.join("10.0.0.1").join("ipconfig.txt"), EVIDENCE).unwrap();
+        fs::write(case.join("tampered.txt"), b"original").unwrap();
+        fs::write(case.join(CLEANUP_JSON), b"{\"artifacts\": []}").unwrap();
+        let entry = |file: &str, content: &[u8]| ManifestEntry {
+            file: file.to_string(),
+            size: content.len() as u64,
+            sha256: hash_bytes(content).sha256,
+            md5: hash_bytes(content).md5,
+            integrity: None,
+            computer: None,
+            method: None,
+            source: None,
+            command: None,
+            operator: "Jano".to_string(),
+            started: "2020-10-10T10:10:10Z".to_string(),
+            finished: "2020-10-10T10:10:10Z".to_string(),
+            yara: vec![],
+            times: None,
+        };
+        let manifest = Manifest {
+            case_directory: case.clone(),
+            operator: "Jano".to_string(),
+            started: "2020-10-10T10:10:10Z".to_string(),
+            entries: vec![
+                entry(&format!("10.0.0.1{}ipconfig.txt", std::path::MAIN_SEPARATOR), EVIDENCE),
+                entry("tampered.txt", b"tampered"),
+            ],
+            incomplete: vec![],
+        };
+        fs::write(case.join(MANIFEST_JSON), serde_json::to_vec(&manifest).unwrap()).unwrap();
+        (directory, case)
+    }
+
+    #[test]
+    fn test_sealed_case_keeps_only_cleanup_registry() {
+        let (directory, case) = case_directory();
+
+        let container = seal_case(&case, &EncryptionKey::Passphrase("secret".to_string())).unwrap();
+
+        let mut left: Vec<_> = fs::read_dir(&case).unwrap().map(|it| it.unwrap().path()).collect();
+        left.sort();
+        assert_eq!(vec![case.join(CLEANUP_JSON), container], left);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_sealed_files_are_overwritten() {
+        let (directory, case) = case_directory();
+        let link = directory.join("ipconfig-link.txt");
+        fs::hard_link(case.join("10.0.0.1").join("ipconfig.txt"), &link).unwrap();
+
+        seal_case(&case, &EncryptionKey::Passphrase("secret".to_string())).unwrap();
+
+        assert_eq!(vec![0u8; EVIDENCE.len()], fs::read(&link).unwrap());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_sealed_case_is_unpacked_and_verified() {
+        let (directory, case) = case_directory();
+        let identity = age::x25519::Identity::generate();
+        let identity_file = directory.join("key.txt");
+        fs::write(&identity_file, format!("# operator key\n{}\n", identity.to_string().expose_secret())).unwrap();
+
+        let container = seal_case(&case, &EncryptionKey::Recipient(identity.to_public().to_string())).unwrap();
+        let verification = unpack(&container, &DecryptionKey::IdentityFile(identity_file), &directory.join("unpacked")).unwrap();
+
+        assert_eq!(Verification { verified: 1, failed: vec!["tampered.txt".to_string()] }, verification);
+        assert_eq!(EVIDENCE.to_vec(), fs::read(directory.join("unpacked").join("10.0.0.1").join("ipconfig.txt")).unwrap());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_key_cannot_unpack() {
+        let (directory, case) = case_directory();
+
+        let container = seal_case(&case, &EncryptionKey::Passphrase("secret".to_string())).unwrap();
+
+        assert!(unpack(&container, &DecryptionKey::Passphrase("guess".to_string()), &directory.join("wrong")).is_err());
+        let verification = unpack(&container, &DecryptionKey::Passphrase("secret".to_string()), &directory.join("unpacked")).unwrap();
+        assert_eq!(1, verification.verified);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_key_is_rejected() {
+        assert!(validate_key(&EncryptionKey::Recipient("age1bad".to_string())).is_err());
+        assert!(validate_key(&EncryptionKey::Passphrase(String::new())).is_err());
+        let identity = age::x25519::Identity::generate();
+        assert!(validate_key(&EncryptionKey::Recipient(identity.to_public().to_string())).is_ok());
+    }
+}
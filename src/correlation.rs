@@ -0,0 +1,428 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::{BufReader, Write};
+use std::fs::File;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use crate::manifest::{Manifest, MANIFEST_JSON};
+use crate::parsers::{ParsedReport, Parser, Process, Connection};
+
+pub const REPORT_HTML: &str = "report.html";
+pub const RARE_PROCESSES_CSV: &str = "rare-processes.csv";
+pub const SHARED_ADDRESSES_CSV: &str = "shared-external-addresses.csv";
+pub const LISTENING_PORTS_CSV: &str = "listening-ports.csv";
+
+// Processes and connections of one host, gathered from all parsed reports of all methods.
+#[derive(Clone, Debug, Default)]
+pub struct HostEvidence {
+    pub host: String,
+    pub processes: Vec<Process>,
+    pub connections: Vec<Connection>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RareProcess {
+    pub name: String,
+    pub hosts: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedAddress {
+    pub address: String,
+    pub hosts: Vec<String>,
+    pub ports: Vec<u16>,
+    pub processes: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListeningPort {
+    pub host: String,
+    pub protocol: String,
+    pub address: String,
+    pub port: u16,
+    pub pid: Option<u32>,
+    pub process_name: Option<String>,
+}
+
+#[derive(Default)]
+struct AddressContacts {
+    hosts: BTreeSet<String>,
+    ports: BTreeSet<u16>,
+    processes: BTreeSet<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CorrelationReport {
+    pub hosts: Vec<String>,
+    pub rare_processes: Vec<RareProcess>,
+    pub shared_addresses: Vec<SharedAddress>,
+    pub listening_ports: Vec<ListeningPort>,
+}
+
+fn list_parsed_reports(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_parsed_reports(&path, files)?;
+        } else if path.extension().map(|it| it == "json").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// The host of a report is taken from the manifest, or from the directory of the report
+// when the report is not recorded there.
+pub(crate) fn host_of(report: &Path, directory: &Path, manifest: Option<&Manifest>) -> String {
+    let relative = report.strip_prefix(directory).unwrap_or(report).to_string_lossy().to_string();
+    manifest
+        .and_then(|manifest| manifest.entries.iter().find(|entry| entry.file == relative))
+        .and_then(|entry| entry.computer.clone())
+        .or_else(|| report.parent()
+            .filter(|parent| *parent != directory)
+            .and_then(|parent| parent.file_name())
+            .map(|name| name.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Loads the parsed process and connection reports of all hosts acquired into the directory.
+pub fn load_evidence(directory: &Path) -> io::Result<Vec<HostEvidence>> {
+    let manifest_path = directory.join(MANIFEST_JSON);
+    let manifest: Option<Manifest> = if manifest_path.exists() {
+        Some(serde_json::from_reader(BufReader::new(File::open(&manifest_path)?))?)
+    } else {
+        None
+    };
+    let mut files = vec![];
+    list_parsed_reports(directory, &mut files)?;
+    files.sort();
+    let mut hosts: BTreeMap<String, HostEvidence> = BTreeMap::new();
+    for file in files {
+        let parsed: ParsedReport = match serde_json::from_reader(BufReader::new(File::open(&file)?)) {
+            Ok(parsed) => parsed,
+            // other JSON files like the journal
+            Err(_) => continue,
+        };
+        let host = host_of(&file, directory, manifest.as_ref());
+        let evidence = hosts.entry(host.clone()).or_insert_with(|| HostEvidence { host, ..Default::default() });
+        match parsed.parser {
+            Parser::Tasklist | Parser::PsAux => {
+                let processes: Vec<Process> = serde_json::from_value(parsed.records)?;
+                evidence.processes.extend(processes);
+            }
+            Parser::NetstatAno | Parser::NetstatNatp => {
+                let connections: Vec<Connection> = serde_json::from_value(parsed.records)?;
+                evidence.connections.extend(connections);
+            }
+            _ => {}
+        }
+    }
+    Ok(hosts.into_values().collect())
+}
+
+fn is_external(address: &str) -> bool {
+    match address.parse::<IpAddr>() {
+        Ok(IpAddr::V4(address)) => !(address.is_private() || address.is_loopback() || address.is_link_local()
+            || address.is_unspecified() || address.is_broadcast() || address.is_multicast()),
+        Ok(IpAddr::V6(address)) => {
+            let first = address.segments()[0];
+            // link-local fe80::/10 and unique local fc00::/7 addresses
+            !(address.is_loopback() || address.is_unspecified() || address.is_multicast()
+                || first & 0xffc0 == 0xfe80 || first & 0xfe00 == 0xfc00)
+        }
+        Err(_) => false,
+    }
+}
+
+fn is_listening(connection: &Connection) -> bool {
+    match &connection.state {
+        Some(state) => state == "LISTEN" || state == "LISTENING",
+        None => false,
+    }
+}
+
+// netstat -ano reports only the pid, the name is looked up in the processes of the host.
+fn process_name(evidence: &HostEvidence, connection: &Connection) -> Option<String> {
+    connection.process_name.clone().or_else(|| {
+        let pid = connection.pid?;
+        evidence.processes.iter().find(|process| process.pid == pid).map(|process| process.name.clone())
+    })
+}
+
+// Processes running on at most `rare_threshold` hosts, external addresses connected from at least two hosts
+// and ports listening on each host.
+pub fn correlate(evidence: &[HostEvidence], rare_threshold: usize) -> CorrelationReport {
+    let mut process_hosts: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut address_contacts: BTreeMap<String, AddressContacts> = BTreeMap::new();
+    let mut listening_ports = BTreeSet::new();
+    for host in evidence {
+        for process in &host.processes {
+            process_hosts.entry(process.name.to_lowercase()).or_default().insert(host.host.clone());
+        }
+        for connection in &host.connections {
+            let name = process_name(host, connection);
+            if is_listening(connection) {
+                if let Some(port) = connection.local_port {
+                    listening_ports.insert((
+                        host.host.clone(),
+                        port,
+                        connection.protocol.clone(),
+                        connection.local_address.clone(),
+                        connection.pid,
+                        name,
+                    ));
+                }
+            } else if is_external(&connection.remote_address) {
+                let contacts = address_contacts.entry(connection.remote_address.clone()).or_default();
+                contacts.hosts.insert(host.host.clone());
+                contacts.ports.extend(connection.remote_port);
+                contacts.processes.extend(name);
+            }
+        }
+    }
+    CorrelationReport {
+        hosts: evidence.iter().map(|it| it.host.clone()).collect(),
+        rare_processes: process_hosts.into_iter()
+            .filter(|(_, hosts)| hosts.len() <= rare_threshold)
+            .map(|(name, hosts)| RareProcess { name, hosts: hosts.into_iter().collect() })
+            .collect(),
+        shared_addresses: address_contacts.into_iter()
+            .filter(|(_, contacts)| contacts.hosts.len() > 1)
+            .map(|(address, contacts)| SharedAddress {
+                address,
+                hosts: contacts.hosts.into_iter().collect(),
+                ports: contacts.ports.into_iter().collect(),
+                processes: contacts.processes.into_iter().collect(),
+            })
+            .collect(),
+        listening_ports: listening_ports.into_iter()
+            .map(|(host, port, protocol, address, pid, process_name)| ListeningPort { host, protocol, address, port, pid, process_name })
+            .collect(),
+    }
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_csv(path: &Path, header: &[&str], rows: Vec<Vec<String>>) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", header.join(","))?;
+    for row in rows {
+        writeln!(file, "{}", row.iter().map(|it| csv_field(it)).collect::<Vec<_>>().join(","))?;
+    }
+    Ok(())
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|it| it.to_string()).unwrap_or_default()
+}
+
+fn joined<T: ToString>(values: &[T]) -> String {
+    values.iter().map(|it| it.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+impl CorrelationReport {
+    fn rare_process_rows(&self) -> Vec<Vec<String>> {
+        self.rare_processes.iter()
+            .map(|it| vec![it.name.clone(), it.hosts.len().to_string(), joined(&it.hosts)])
+            .collect()
+    }
+
+    fn shared_address_rows(&self) -> Vec<Vec<String>> {
+        self.shared_addresses.iter()
+            .map(|it| vec![it.address.clone(), it.hosts.len().to_string(), joined(&it.hosts), joined(&it.ports), joined(&it.processes)])
+            .collect()
+    }
+
+    fn listening_port_rows(&self) -> Vec<Vec<String>> {
+        self.listening_ports.iter()
+            .map(|it| vec![
+                it.host.clone(),
+                it.protocol.clone(),
+                it.address.clone(),
+                it.port.to_string(),
+                optional(&it.pid),
+                optional(&it.process_name),
+            ])
+            .collect()
+    }
+
+    // Writes the HTML summary and a CSV file for each table into the directory. Returns the written files.
+    pub fn write(&self, directory: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(directory)?;
+        let rare_processes = ["process", "hosts", "host names"];
+        let shared_addresses = ["address", "hosts", "host names", "ports", "processes"];
+        let listening_ports = ["host", "protocol", "address", "port", "pid", "process"];
+        write_csv(&directory.join(RARE_PROCESSES_CSV), &rare_processes, self.rare_process_rows())?;
+        write_csv(&directory.join(SHARED_ADDRESSES_CSV), &shared_addresses, self.shared_address_rows())?;
+        write_csv(&directory.join(LISTENING_PORTS_CSV), &listening_ports, self.listening_port_rows())?;
+
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>Gargamel correlation report</title>\n\
+            <style>body { font-family: sans-serif; } table { border-collapse: collapse; } \
+            th, td { border: 1px solid #999; padding: 2px 8px; text-align: left; }</style>\n</head>\n<body>\n");
+        html.push_str("<h1>Gargamel correlation report</h1>\n");
+        html.push_str(&format!("<p>Hosts: {}</p>\n", escape(&self.hosts.join(", "))));
+        html.push_str(&html_table("Rare processes", &rare_processes, &self.rare_process_rows()));
+        html.push_str(&html_table("Shared external addresses", &shared_addresses, &self.shared_address_rows()));
+        html.push_str(&html_table("Listening ports", &listening_ports, &self.listening_port_rows()));
+        html.push_str("</body>\n</html>\n");
+        std::fs::write(directory.join(REPORT_HTML), html)?;
+
+        Ok([REPORT_HTML, RARE_PROCESSES_CSV, SHARED_ADDRESSES_CSV, LISTENING_PORTS_CSV].iter()
+            .map(|it| directory.join(it))
+            .collect())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn html_table(title: &str, header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut html = format!("<h2>{} ({})</h2>\n<table>\n<tr>", escape(title), rows.len());
+    for column in header {
+        html.push_str(&format!("<th>{}</th>", escape(column)));
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for value in row {
+            html.push_str(&format!("<td>{}</td>", escape(value)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::correlation::{correlate, csv_field, host_of, is_external, HostEvidence, RareProcess, SharedAddress, ListeningPort};
+    use crate::parsers::{parse_tasklist, parse_netstat_ano, parse_ps_aux, parse_netstat_natp};
+    use std::path::Path;
+
+    fn windows(name: &str, extra_process: &str, connections: &str) -> HostEvidence {
+        let tasklist = format!("
+Image Name                     PID Session Name        Session#    Mem Usage
+========================= ======== ================ =========== ============
+svchost.exe                    848 Services                   0     21,400 K
+{}", extra_process);
+        HostEvidence {
+            host: name.to_string(),
+            processes: parse_tasklist(&tasklist),
+            connections: parse_netstat_ano(connections),
+        }
+    }
+
+    fn linux(name: &str) -> HostEvidence {
+        HostEvidence {
+            host: name.to_string(),
+            processes: parse_ps_aux("USER PID %CPU %MEM VSZ RSS TTY STAT START TIME COMMAND
+root 812 0.0 0.1 1000 100 ? Ss 10:10 0:00 /usr/sbin/sshd -D
+"),
+            connections: parse_netstat_natp("tcp 0 0 10.0.0.3:40000 198.51.100.7:22 ESTABLISHED 812/sshd\n"),
+        }
+    }
+
+    #[test]
+    fn test_rare_processes() {
+        let evidence = vec![
+            windows("10.0.0.1", "", ""),
+            windows("10.0.0.2", "evil.exe                      4444 Console                    1      1,000 K\n", ""),
+            linux("10.0.0.3"),
+        ];
+
+        let report = correlate(&evidence, 1);
+
+        assert_eq!(vec!["10.0.0.1", "10.0.0.2", "10.0.0.3"], report.hosts);
+        assert_eq!(vec![
+            RareProcess { name: "evil.exe".to_string(), hosts: vec!["10.0.0.2".to_string()] },
+            RareProcess { name: "sshd".to_string(), hosts: vec!["10.0.0.3".to_string()] },
+        ], report.rare_processes);
+        assert_eq!(3, correlate(&evidence, 2).rare_processes.len());
+    }
+
+    #[test]
+    fn test_shared_external_addresses() {
+        let evidence = vec![
+            windows("10.0.0.1", "", "
+  TCP    10.0.0.1:50000         203.0.113.9:443        ESTABLISHED     848
+  TCP    10.0.0.1:50001         10.0.0.2:445           ESTABLISHED     4
+"),
+            windows("10.0.0.2", "evil.exe                      4444 Console                    1      1,000 K\n", "
+  TCP    10.0.0.2:50001         203.0.113.9:8443       ESTABLISHED     4444
+  TCP    10.0.0.2:50002         10.0.0.1:445           ESTABLISHED     4
+"),
+            linux("10.0.0.3"),
+        ];
+
+        let report = correlate(&evidence, 1);
+
+        assert_eq!(vec![SharedAddress {
+            address: "203.0.113.9".to_string(),
+            hosts: vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()],
+            ports: vec![443, 8443],
+            processes: vec!["evil.exe".to_string(), "svchost.exe".to_string()],
+        }], report.shared_addresses);
+    }
+
+    #[test]
+    fn test_listening_ports_with_process_names() {
+        let evidence = vec![windows("10.0.0.1", "", "
+  TCP    0.0.0.0:135            0.0.0.0:0              LISTENING       848
+  TCP    0.0.0.0:445            0.0.0.0:0              LISTENING       4
+")];
+
+        let report = correlate(&evidence, 1);
+
+        assert_eq!(vec![
+            ListeningPort {
+                host: "10.0.0.1".to_string(),
+                protocol: "tcp".to_string(),
+                address: "0.0.0.0".to_string(),
+                port: 135,
+                pid: Some(848),
+                process_name: Some("svchost.exe".to_string()),
+            },
+            ListeningPort {
+                host: "10.0.0.1".to_string(),
+                protocol: "tcp".to_string(),
+                address: "0.0.0.0".to_string(),
+                port: 445,
+                pid: Some(4),
+                process_name: None,
+            },
+        ], report.listening_ports);
+    }
+
+    #[test]
+    fn test_external_addresses() {
+        assert!(is_external("203.0.113.9"));
+        assert!(is_external("2001:db8::1"));
+        assert!(!is_external("192.168.42.47"));
+        assert!(!is_external("127.0.0.1"));
+        assert!(!is_external("fe80::1"));
+        assert!(!is_external("fd00::1"));
+        assert!(!is_external("*"));
+    }
+
+    #[test]
+    fn test_csv_field_is_quoted() {
+        assert_eq!("svchost.exe", csv_field("svchost.exe"));
+        assert_eq!("\"a,b\"", csv_field("a,b"));
+        assert_eq!("\"say \"\"hi\"\"\"", csv_field("say \"hi\""));
+    }
+
+    #[test]
+    fn test_host_of_report_without_manifest() {
+        let directory = Path::new("case");
+        assert_eq!("10.0.0.1", host_of(&directory.join("10.0.0.1").join("tasklist.json"), directory, None));
+        assert_eq!("unknown", host_of(&directory.join("tasklist.json"), directory, None));
+    }
+}
@@ -0,0 +1,255 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::fs::File;
+use chrono::SecondsFormat;
+use evtx::EvtxParser;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+const TIMELINE_EXTENSION: &str = "jsonl";
+
+// Events most useful for incident response by their channel: logons, process creation, new services and
+// scheduled tasks, account changes, cleared logs, PowerShell script blocks, RDP sessions, Defender detections
+// and Sysmon. Event IDs are unique only within a provider, so the same ID of another channel is not accepted.
+pub const HIGH_VALUE_EVENTS: &[(&str, &[u32])] = &[
+    ("Security", &[
+        1102, 4624, 4625, 4648, 4672, 4688, 4697, 4698, 4702, 4720, 4722, 4724, 4728, 4732, 4756,
+        4768, 4769, 4771, 4776, 4778, 4779,
+    ]),
+    ("System", &[104, 7034, 7040, 7045]),
+    ("Windows PowerShell", &[400, 800]),
+    ("Microsoft-Windows-PowerShell/Operational", &[4103, 4104]),
+    ("Microsoft-Windows-TaskScheduler/Operational", &[106, 140, 141, 200, 201]),
+    ("Microsoft-Windows-TerminalServices-LocalSessionManager/Operational", &[21, 22, 23, 24, 25]),
+    ("Microsoft-Windows-TerminalServices-RemoteConnectionManager/Operational", &[1149]),
+    ("Microsoft-Windows-Windows Defender/Operational", &[1116, 1117, 5001, 5007]),
+    ("Microsoft-Windows-Sysmon/Operational", &[1, 3, 8, 10, 11, 13, 22]),
+];
+
+// An event ID, accepted only from the channel when it is given.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventKey {
+    pub channel: Option<String>,
+    pub event_id: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventFilter {
+    All,
+    Events(Vec<EventKey>),
+}
+
+impl EventFilter {
+    pub fn high_value() -> EventFilter {
+        EventFilter::Events(HIGH_VALUE_EVENTS.iter()
+            .flat_map(|(channel, ids)| ids.iter().map(move |id| EventKey { channel: Some(channel.to_string()), event_id: *id }))
+            .collect())
+    }
+
+    // Parses `all` or comma separated event IDs, each optionally prefixed by its channel, e.g. `Security:4624`.
+    pub fn parse(text: &str) -> io::Result<EventFilter> {
+        if text.trim().eq_ignore_ascii_case("all") {
+            return Ok(EventFilter::All);
+        }
+        text.split(',')
+            .map(|it| it.trim())
+            .filter(|it| !it.is_empty())
+            .map(|it| {
+                let (channel, id) = match it.rsplit_once(':') {
+                    None => (None, it),
+                    Some((channel, id)) => (Some(channel.trim().to_string()), id.trim()),
+                };
+                id.parse::<u32>()
+                    .map(|event_id| EventKey { channel, event_id })
+                    .map_err(|_| io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Invalid event ID {}", it),
+                    ))
+            })
+            .collect::<io::Result<Vec<EventKey>>>()
+            .map(EventFilter::Events)
+    }
+
+    pub fn accepts(&self, channel: &str, event_id: u32) -> bool {
+        match self {
+            EventFilter::All => true,
+            EventFilter::Events(keys) => keys.iter().any(|key| key.event_id == event_id
+                && key.channel.as_ref().map(|it| it.eq_ignore_ascii_case(channel)).unwrap_or(true)),
+        }
+    }
+}
+
+// One event of a timeline, with the fields of its EventData or UserData flattened into `data`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EventRecord {
+    pub timestamp: String,
+    pub event_id: u32,
+    pub record_id: u64,
+    pub provider: String,
+    pub channel: String,
+    pub computer: String,
+    pub user_sid: Option<String>,
+    pub data: Map<String, Value>,
+}
+
+// Values are numbers or strings, or objects with the value in #text when the element has attributes.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Object(object) => object.get("#text").and_then(text),
+        _ => None,
+    }
+}
+
+fn attribute(value: &Value, name: &str) -> Option<String> {
+    value.get("#attributes").and_then(|it| it.get(name)).and_then(text)
+}
+
+fn event_data(event: &Value) -> Map<String, Value> {
+    let mut data = Map::new();
+    if let Some(Value::Object(fields)) = event.get("EventData") {
+        for (name, value) in fields.iter().filter(|(name, _)| *name != "#attributes") {
+            // unnamed <Data> elements are a list in #text
+            let value = match (name.as_str(), value.get("#text")) {
+                ("Data", Some(values)) => values.clone(),
+                _ => value.clone(),
+            };
+            data.insert(name.clone(), value);
+        }
+    }
+    // UserData contains a single element with the event specific fields
+    if let Some(Value::Object(user_data)) = event.get("UserData") {
+        for fields in user_data.values().filter_map(Value::as_object) {
+            for (name, value) in fields.iter().filter(|(name, _)| *name != "#attributes") {
+                data.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    data
+}
+
+// Converts an event parsed by the evtx crate into a record, None if it has no event ID.
+pub fn event_record(record_id: u64, timestamp: &str, event: &Value) -> Option<EventRecord> {
+    let event = event.get("Event").unwrap_or(event);
+    let system = event.get("System")?;
+    let event_id = system.get("EventID").and_then(text)?.parse().ok()?;
+    let data = event_data(event);
+    let user_sid = system.get("Security")
+        .and_then(|it| attribute(it, "UserID"))
+        .or_else(|| data.get("SubjectUserSid").and_then(text));
+    Some(EventRecord {
+        timestamp: timestamp.to_string(),
+        event_id,
+        record_id,
+        provider: system.get("Provider").and_then(|it| attribute(it, "Name")).unwrap_or_default(),
+        channel: system.get("Channel").and_then(text).unwrap_or_default(),
+        computer: system.get("Computer").and_then(text).unwrap_or_default(),
+        user_sid,
+        data,
+    })
+}
+
+// Parses the events accepted by the filter, ordered by their time. Corrupted records are skipped.
+pub fn parse_events(evtx: &Path, filter: &EventFilter) -> io::Result<Vec<EventRecord>> {
+    let mut parser = EvtxParser::from_path(evtx)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, format!("Cannot parse {} due to {}", evtx.display(), err)))?;
+    let mut records = vec![];
+    for record in parser.records_json_value() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                debug!("Skipping corrupted record of {} due to {}", evtx.display(), err);
+                continue;
+            }
+        };
+        let timestamp = record.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true);
+        if let Some(event) = event_record(record.event_record_id, &timestamp, &record.data) {
+            if filter.accepts(&event.channel, event.event_id) {
+                records.push(event);
+            }
+        }
+    }
+    records.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.record_id.cmp(&b.record_id)));
+    Ok(records)
+}
+
+pub fn timeline_path(evtx: &Path) -> PathBuf {
+    evtx.with_file_name(format!("{}.{}", evtx.file_name().unwrap_or_default().to_string_lossy(), TIMELINE_EXTENSION))
+}
+
+// Writes the events of the log as JSON lines next to it. Returns the number of events.
+pub fn write_timeline(evtx: &Path, filter: &EventFilter) -> io::Result<usize> {
+    let records = parse_events(evtx, filter)?;
+    let mut writer = BufWriter::new(File::create(timeline_path(evtx))?);
+    for record in records.iter() {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(records.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_timeline::{event_record, EventFilter};
+    use serde_json::json;
+
+    #[test]
+    fn test_event_record() {
+        let logon = json!({"Event": {
+            "System": {
+                "Provider": {"#attributes": {"Name": "Microsoft-Windows-Security-Auditing"}},
+                "EventID": 4624,
+                "Channel": "Security",
+                "Computer": "WIN-LL0C19JS506",
+                "Security": null
+            },
+            "EventData": {"SubjectUserSid": "S-1-5-18", "TargetUserName": "Jano", "LogonType": 10}
+        }});
+        let service = json!({"Event": {
+            "System": {
+                "Provider": {"#attributes": {"Name": "Service Control Manager"}},
+                "EventID": {"#attributes": {"Qualifiers": "16384"}, "#text": "7045"},
+                "Channel": "System",
+                "Computer": "WIN-LL0C19JS506",
+                "Security": {"#attributes": {"UserID": "S-1-5-21-1-2-3-500"}}
+            },
+            "EventData": {"Data": {"#text": ["PSEXESVC", "%SystemRoot%\\PSEXESVC.exe"]}}
+        }});
+
+        let logon = event_record(7, "2020-10-10T10:10:10.000000Z", &logon).unwrap();
+        let service = event_record(8, "2020-10-10T10:10:11.000000Z", &service).unwrap();
+
+        assert_eq!(4624, logon.event_id);
+        assert_eq!("Microsoft-Windows-Security-Auditing", logon.provider);
+        assert_eq!(Some("S-1-5-18".to_string()), logon.user_sid);
+        assert_eq!(json!("Jano"), logon.data["TargetUserName"]);
+        assert_eq!(7045, service.event_id);
+        assert_eq!(Some("S-1-5-21-1-2-3-500".to_string()), service.user_sid);
+        assert_eq!(json!(["PSEXESVC", "%SystemRoot%\\PSEXESVC.exe"]), service.data["Data"]);
+    }
+
+    #[test]
+    fn test_high_value_events_are_keyed_by_channel() {
+        let filter = EventFilter::high_value();
+        assert!(filter.accepts("Microsoft-Windows-PowerShell/Operational", 4104));
+        assert!(filter.accepts("security", 4624));
+        assert!(filter.accepts("Microsoft-Windows-Sysmon/Operational", 1));
+        assert!(!filter.accepts("Application", 1));
+        assert!(!filter.accepts("System", 4624));
+    }
+
+    #[test]
+    fn test_event_filter_parsing() {
+        let filter = EventFilter::parse("4624, 4625, Microsoft-Windows-Sysmon/Operational:3").unwrap();
+        assert!(filter.accepts("Security", 4625));
+        assert!(!filter.accepts("Security", 4688));
+        assert!(filter.accepts("Microsoft-Windows-Sysmon/Operational", 3));
+        assert!(!filter.accepts("System", 3));
+        assert!(EventFilter::parse("all").unwrap().accepts("Security", 4688));
+        assert!(EventFilter::parse("46x").is_err());
+        assert!(EventFilter::parse("Security:x").is_err());
+    }
+}
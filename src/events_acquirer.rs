@@ -1,17 +1,26 @@
 use std::path::{Path, PathBuf};
-use crate::remote::{Computer, Connector, PsExec, PsRemote, Rdp, Wmi, Compression};
+use crate::remote::{Computer, Connector, PsExec, PsRemote, Rdp, Wmi, Compression, command_output};
 use std::time::Duration;
-use crate::large_evidence_acquirer::LargeEvidenceAcquirer;
+use crate::artifact_acquirer::ArtifactAcquirer;
+use crate::profile::{Profile, Artifact, Category};
+use crate::process_runner::CommandOutcome;
+use crate::event_timeline::{EventFilter, write_timeline, timeline_path};
+use crate::manifest::{Provenance, record_file};
+use crate::plan::is_cancelled;
+use std::io;
+use std::io::ErrorKind;
 
 pub struct EventsAcquirer<'a> {
     store_directory: &'a Path,
     connector: Box<dyn Connector>,
 
-    application_event_logs_command: Vec<String>,
-    system_event_logs_command: Vec<String>,
+    artifacts: Vec<Artifact>,
 
     compress_timeout: Option<Duration>,
     compression: Compression,
+
+    // exported logs are parsed into a timeline of the events accepted by the filter
+    timeline: Option<EventFilter>,
 }
 
 impl<'a> EventsAcquirer<'a> {
@@ -20,36 +29,35 @@ impl<'a> EventsAcquirer<'a> {
         connector: Box<dyn Connector>,
         compress_timeout: Option<Duration>,
         compression: Compression,
+        profile: &Profile,
     ) -> EventsAcquirer<'a> {
         EventsAcquirer {
             store_directory,
+            artifacts: profile.artifacts(Category::Events, connector.remote_platform()),
             connector,
-            application_event_logs_command: vec![
-                "wevtutil".to_string(),
-                "epl".to_string(),
-                "application".to_string(),
-            ],
-            system_event_logs_command: vec![
-                "wevtutil".to_string(),
-                "epl".to_string(),
-                "system".to_string(),
-            ],
             compress_timeout,
             compression,
+            timeline: Some(EventFilter::high_value()),
         }
     }
 
+    pub fn with_timeline(self, timeline: Option<EventFilter>) -> EventsAcquirer<'a> {
+        EventsAcquirer { timeline, ..self }
+    }
+
     pub fn psexec(
         store_directory: &'a Path,
         computer: Computer,
         no_7zip: bool,
-        remote_temp_storage: PathBuf
-    ) -> EventsAcquirer {
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> EventsAcquirer<'a> {
         EventsAcquirer::new(
             store_directory,
             Box::new(PsExec::psexec(computer, remote_temp_storage)),
             None,
             if no_7zip { Compression::No } else { Compression::Yes },
+            profile,
         )
     }
 
@@ -57,13 +65,15 @@ impl<'a> EventsAcquirer<'a> {
         store_directory: &'a Path,
         computer: Computer,
         _no_7zip: bool,
-        remote_temp_storage: PathBuf
-    ) -> EventsAcquirer {
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> EventsAcquirer<'a> {
         EventsAcquirer::new(
             store_directory,
             Box::new(PsRemote::new(computer, remote_temp_storage)),
             None,
             Compression::No,
+            profile,
         )
     }
 
@@ -72,13 +82,15 @@ impl<'a> EventsAcquirer<'a> {
         computer: Computer,
         compress_timeout: Duration,
         no_7zip: bool,
-        remote_temp_storage: PathBuf
-    ) -> EventsAcquirer {
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> EventsAcquirer<'a> {
         EventsAcquirer::new(
             store_directory,
             Box::new(Wmi { computer, remote_temp_storage }),
             Some(compress_timeout),
-            if no_7zip { Compression::No } else { Compression::YesSplit }
+            if no_7zip { Compression::No } else { Compression::YesSplit },
+            profile,
         )
     }
 
@@ -88,34 +100,98 @@ impl<'a> EventsAcquirer<'a> {
         compress_timeout: Duration,
         nla: bool,
         no_7zip: bool,
-        remote_temp_storage: PathBuf
-    ) -> EventsAcquirer {
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> EventsAcquirer<'a> {
         EventsAcquirer::new(
             store_directory,
             Box::new(Rdp { computer, nla, remote_temp_storage }),
             Some(compress_timeout),
-            if no_7zip { Compression::No } else { Compression::YesSplit }
+            if no_7zip { Compression::No } else { Compression::YesSplit },
+            profile,
         )
     }
 
-    pub fn acquire(&self) {
-        let lea = LargeEvidenceAcquirer{
+    pub fn acquire(&self) -> Vec<io::Result<CommandOutcome>> {
+        let acquirer = ArtifactAcquirer {
             store_directory: self.store_directory,
             connector: self.connector.as_ref(),
             compress_timeout: self.compress_timeout,
             compression: self.compression,
-            report_extension: "evtx",
-            overwrite_switch: Some("/ow:true")
         };
-        let command = &self.system_event_logs_command;
-        lea.run(
-            command,
-            "events-system"
-        );
-        let command = &self.application_event_logs_command;
-        lea.run(
-            command,
-            "events-application"
-        );
+        let channels = self.existing_channels();
+        let mut outcomes = vec![];
+        for artifact in self.artifacts.iter() {
+            if is_cancelled(self.connector.computer()) {
+                debug!("Acquisition from {} is cancelled, skipping remaining event logs", self.connector.computer().address);
+                break;
+            }
+            let channel = match &artifact.channel {
+                Some(channel) => channel,
+                None => {
+                    outcomes.extend(acquirer.acquire_all(std::slice::from_ref(artifact)));
+                    continue;
+                }
+            };
+            let exists = channels.as_ref()
+                .map(|channels| channels.iter().any(|it| it.eq_ignore_ascii_case(channel)))
+                .unwrap_or(true);
+            if !exists {
+                warn!("{}: Event log channel {} does not exist on {}", self.connector.connect_method_name(), channel, self.connector.computer().address);
+                outcomes.push(Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("Event log channel {} does not exist on {}", channel, self.connector.computer().address),
+                )));
+                continue;
+            }
+            let outcome = acquirer.acquire(artifact);
+            match &outcome {
+                Ok(outcome) if outcome.is_success() => info!("{}: Event log channel {} exported", self.connector.connect_method_name(), channel),
+                Ok(outcome) => warn!("{}: Event log channel {} not exported, exit code {:?}", self.connector.connect_method_name(), channel, outcome.exit_code),
+                Err(err) => warn!("{}: Event log channel {} not exported due to {}", self.connector.connect_method_name(), channel, err),
+            }
+            outcomes.push(outcome);
+        }
+        let exported = outcomes.iter()
+            .filter_map(|outcome| outcome.as_ref().ok())
+            .filter(|outcome| outcome.is_success())
+            .filter_map(|outcome| outcome.report_path.as_ref())
+            .filter(|path| path.extension().map(|it| it == "evtx").unwrap_or(false));
+        for evtx in exported {
+            self.write_timeline(evtx);
+        }
+        outcomes
+    }
+
+    fn write_timeline(&self, evtx: &Path) {
+        let filter = match &self.timeline {
+            None => return,
+            Some(filter) => filter,
+        };
+        match write_timeline(evtx, filter) {
+            Ok(count) => {
+                info!("{}: Parsed {} events of {}", self.connector.connect_method_name(), count, evtx.display());
+                let provenance = Provenance::new(self.connector.computer(), self.connector.connect_method_name())
+                    .source(evtx);
+                record_file(&timeline_path(evtx), &provenance);
+            }
+            Err(err) => warn!("Cannot parse {} due to {}", evtx.display(), err),
+        }
+    }
+
+    // Channels listed by `wevtutil el`, None when they cannot be listed and all channels are exported.
+    fn existing_channels(&self) -> Option<Vec<String>> {
+        if self.artifacts.iter().all(|artifact| artifact.channel.is_none()) {
+            return None;
+        }
+        let command = vec!["wevtutil".to_string(), "el".to_string()];
+        match command_output(self.connector.as_ref(), command, "event-channels") {
+            Ok(output) if !output.trim().is_empty() => Some(output.lines().map(|line| line.trim().to_string()).collect()),
+            Ok(_) => None,
+            Err(err) => {
+                warn!("Cannot list event log channels of {}. Cause: {}", self.connector.computer().address, err);
+                None
+            }
+        }
     }
 }
\ No newline at end of file
@@ -1,76 +1,59 @@
 use std::path::{Path, PathBuf};
-use crate::remote::{Computer, Connector, Command, PsExec, PsRemote, Ssh, Rdp, Wmi};
+use crate::remote::{Computer, Connector, PsExec, PsRemote, Ssh, Rdp, Wmi, Compression};
+use crate::process_runner::CommandOutcome;
+use crate::artifact_acquirer::ArtifactAcquirer;
+use crate::profile::{Profile, Artifact, Category};
+use std::io;
 
 pub struct EvidenceAcquirer<'a> {
     store_directory: &'a Path,
     connector: Box<dyn Connector>,
-
-    firewall_state_command: Option<Vec<String>>,
-    network_state_command: Option<Vec<String>>,
-    logged_users_command: Option<Vec<String>>,
-    running_processes_command: Option<Vec<String>>,
-    active_network_connections_command: Option<Vec<String>>,
+    artifacts: Vec<Artifact>,
 }
 
 impl<'a> EvidenceAcquirer<'a> {
-    fn new_standard_acquirer(
+    pub fn new(
         store_directory: &'a Path,
         remote_connector: Box<dyn Connector>,
+        profile: &Profile,
     ) -> EvidenceAcquirer<'a> {
         EvidenceAcquirer {
             store_directory,
+            artifacts: profile.artifacts(Category::Evidence, remote_connector.remote_platform()),
             connector: remote_connector,
-            firewall_state_command: Some(vec![
-                "netsh".to_string(),
-                "advfirewall".to_string(),
-                "show".to_string(),
-                "allprofiles".to_string(),
-                "state".to_string(),
-            ]),
-            network_state_command: Some(vec![
-                "ipconfig".to_string(),
-                "/all".to_string(),
-            ]),
-            logged_users_command: Some(vec![
-                "query".to_string(),
-                "user".to_string(),
-            ]),
-            running_processes_command: Some(vec![
-                "tasklist".to_string(),
-            ]),
-            active_network_connections_command: Some(vec![
-                "netstat".to_string(),
-                "-ano".to_string(),
-            ]),
         }
     }
 
     pub fn psexec(
         remote_computer: Computer,
         store_directory: &'a Path,
-        remote_temp_storage: PathBuf
+        remote_temp_storage: PathBuf,
+        profile: &Profile
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer::new_standard_acquirer(
+        EvidenceAcquirer::new(
             store_directory,
             Box::new(PsExec::paexec(remote_computer, remote_temp_storage)),
+            profile,
         )
     }
 
     pub fn psremote(
         remote_computer: Computer,
         store_directory: &'a Path,
-        remote_temp_storage: PathBuf
+        remote_temp_storage: PathBuf,
+        profile: &Profile
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer::new_standard_acquirer(
+        EvidenceAcquirer::new(
             store_directory,
             Box::new(PsRemote::new(remote_computer, remote_temp_storage)),
+            profile,
         )
     }
 
     // pub fn local(
     //     store_directory: &'a Path,
     // ) -> EvidenceAcquirer<'a> {
-    //     EvidenceAcquirer::new_standard_acquirer(
+    //     EvidenceAcquirer::new(
     //         store_directory,
     //         Box::new(Local::new()),
     //     )
@@ -79,14 +62,16 @@ impl<'a> EvidenceAcquirer<'a> {
     pub fn wmi(
         remote_computer: Computer,
         store_directory: &'a Path,
-        remote_temp_storage: PathBuf
+        remote_temp_storage: PathBuf,
+        profile: &Profile
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer::new_standard_acquirer(
+        EvidenceAcquirer::new(
             store_directory,
             Box::new(Wmi {
                 computer: remote_computer,
                 remote_temp_storage
-            })
+            }),
+            profile,
         )
     }
 
@@ -94,15 +79,17 @@ impl<'a> EvidenceAcquirer<'a> {
         remote_computer: Computer,
         store_directory: &'a Path,
         nla: bool,
-        remote_temp_storage: PathBuf
+        remote_temp_storage: PathBuf,
+        profile: &Profile
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer::new_standard_acquirer(
+        EvidenceAcquirer::new(
             store_directory,
             Box::new(Rdp {
                 nla,
                 computer: remote_computer,
                 remote_temp_storage
-            })
+            }),
+            profile,
         )
     }
 
@@ -110,121 +97,25 @@ impl<'a> EvidenceAcquirer<'a> {
         remote_computer: Computer,
         store_directory: &'a Path,
         key_file: Option<PathBuf>,
+        known_hosts: Option<PathBuf>,
+        profile: &Profile,
     ) -> EvidenceAcquirer<'a> {
-        EvidenceAcquirer {
+        EvidenceAcquirer::new(
             store_directory,
-            connector: Box::new(Ssh{ key_file, computer: remote_computer.clone() }),
-            firewall_state_command: Some(vec![
-                format!("echo {} | sudo -S iptables -L", remote_computer.password.unwrap_or_default()),
-            ]),
-            network_state_command: Some(vec![
-                "ifconfig".to_string(),
-            ]),
-            logged_users_command: Some(vec![
-                "who".to_string(),
-            ]),
-            running_processes_command: Some(vec![
-                "ps aux".to_string(),
-            ]),
-            active_network_connections_command: Some(vec![
-                "netstat -natp".to_string(),
-            ]),
-        }
-    }
-
-    fn run(
-        &self,
-        command: &[String],
-        report_filename_prefix: &str,
-    ) {
-        if command.is_empty() {
-            return;
-        }
-        let remote_connection = Command::new(
-            command.to_vec(),
-            Some(&self.store_directory),
-            report_filename_prefix,
-            false
-        );
-
-        info!("{}: Checking {}",
-              self.connector.connect_method_name(),
-              report_filename_prefix.replace("_", " ")
-        );
-
-        match self.connector.connect_and_run_command(remote_connection, None) {
-            Ok(_) => {}
-            Err(err) => { error!("Error running command {:?}. Cause: {}", command, err) }
-        }
-    }
-
-    pub fn firewall_state(&self) {
-        match &self.firewall_state_command {
-            None => {},
-            Some(command) => {
-                self.run(
-                    command,
-                    "firewall-status",
-                )
-            },
-        }
-    }
-
-    pub fn network_state(&self) {
-        match &self.network_state_command {
-            None => {},
-            Some(command) => {
-                self.run(
-                    command,
-                    "network-status",
-                )
-            },
-        }
-    }
-
-    pub fn logged_users(&self) {
-        match &self.logged_users_command {
-            None => {},
-            Some(command) => {
-                self.run(
-                    command,
-                    "logged-users",
-                )
-            },
-        }
-    }
-
-    pub fn running_processes(&self) {
-        match &self.running_processes_command {
-            None => {},
-            Some(command) => {
-                self.run(
-                    command,
-                    "running-processes",
-                )
-            },
-        }
-    }
-
-    pub fn active_network_connections(&self) {
-        match &self.active_network_connections_command {
-            None => {},
-            Some(command) => {
-                self.run(
-                    command,
-                    "active-network-connections",
-                )
-            },
-        }
+            Box::new(Ssh::new(remote_computer, key_file, known_hosts)),
+            profile,
+        )
     }
 
     pub fn run_all(
         &self,
-    ) {
-        self.firewall_state();
-        self.network_state();
-        self.active_network_connections();
-        self.running_processes();
-        self.logged_users();
+    ) -> Vec<io::Result<CommandOutcome>> {
+        let acquirer = ArtifactAcquirer {
+            store_directory: self.store_directory,
+            connector: self.connector.as_ref(),
+            compress_timeout: None,
+            compression: Compression::No,
+        };
+        acquirer.acquire_all(&self.artifacts)
     }
-}
\ No newline at end of file
+}
@@ -1,8 +1,16 @@
 use crate::remote::RemoteFileCopier;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io;
 use std::fs::File;
 use std::io::{BufReader, BufRead};
+use std::collections::HashMap;
+use std::time::SystemTime;
+use chrono::{DateTime, Utc};
+use crate::manifest::{Provenance, record_file};
+use crate::yara_scanner::scan_downloaded;
+
+// Size and modification time of local files.
+type FileStates = HashMap<PathBuf, (u64, Option<SystemTime>)>;
 
 pub fn download_files(file_list: &Path,
                       local_store_directory: &Path,
@@ -10,7 +18,7 @@ pub fn download_files(file_list: &Path,
 ) -> io::Result<()> {
     let input_file = File::open(file_list)?;
     let local_store_directory = dunce::canonicalize(local_store_directory)
-        .expect(&format!("Cannot canonicalize {}", local_store_directory.display()));
+        .unwrap_or_else(|_| panic!("Cannot canonicalize {}", local_store_directory.display()));
     for path_to_find in BufReader::new(input_file).lines() {
         if path_to_find.is_err() {
             warn!("Cannot read line in {}", file_list.display());
@@ -25,15 +33,133 @@ pub fn download_files(file_list: &Path,
         //     None => "root".to_string(),
         //     Some(filename) => filename.to_string_lossy().to_string(),
         // };
+        let before = matching_files(path_to_download, &local_store_directory);
+        let started = Utc::now();
         let download_result = downloader.copy_from_remote(
             path_to_download,
             &local_store_directory,
             // &local_store_directory.join(file_name_to_download),
         );
         match download_result {
-            Ok(_) => { debug!("Remote file {} found and downloaded", path_to_find) }
+            Ok(_) => {
+                debug!("Remote file {} found and downloaded", path_to_find);
+                let produced = produced_files(&before, matching_files(path_to_download, &local_store_directory));
+                record_downloaded(path_to_download, &local_store_directory, &produced, started, downloader);
+            }
             Err(err) => { warn!("Cannot find remote file {} due to: {}", path_to_find, err) }
         }
     }
     Ok(())
 }
+
+// Local files matching the name of the remote path, which may contain wildcards, including files in matching directories.
+fn matching_files(remote_path: &Path, local_store_directory: &Path) -> FileStates {
+    let mut states = FileStates::new();
+    let pattern = match remote_path.file_name() {
+        None => return states,
+        Some(file_name) => file_name.to_string_lossy().to_lowercase(),
+    };
+    let entries = match std::fs::read_dir(local_store_directory) {
+        Ok(entries) => entries,
+        Err(_) => return states,
+    };
+    for entry in entries.filter_map(|it| it.ok()) {
+        let file_name = entry.file_name().to_string_lossy().to_lowercase();
+        if !matches_wildcard(pattern.as_bytes(), file_name.as_bytes()) {
+            continue;
+        }
+        let mut files = vec![];
+        if let Err(err) = list_files(&entry.path(), &mut files) {
+            debug!("{}", err);
+        }
+        for file in files {
+            if let Ok(metadata) = file.metadata() {
+                states.insert(file, (metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+    states
+}
+
+// Files created or changed by the transfer, files downloaded before by other transfers are left out.
+fn produced_files(before: &FileStates, after: FileStates) -> Vec<PathBuf> {
+    let mut produced: Vec<PathBuf> = after.into_iter()
+        .filter(|(path, state)| before.get(path) != Some(state))
+        .map(|(path, _)| path)
+        .collect();
+    produced.sort();
+    produced
+}
+
+fn record_downloaded(
+    remote_path: &Path,
+    local_store_directory: &Path,
+    produced: &[PathBuf],
+    started: DateTime<Utc>,
+    downloader: &dyn RemoteFileCopier,
+) {
+    let remote_directory = remote_path.parent().unwrap_or(remote_path);
+    for local_path in produced {
+        let source = remote_directory.join(local_path.strip_prefix(local_store_directory).unwrap_or(local_path));
+        // the download does not preserve the times of the target, so they are read from the target for the timeline
+        let times = match downloader.remote_file_times(&source) {
+            Ok(times) => Some(times),
+            Err(err) => {
+                debug!("{}", err);
+                None
+            }
+        };
+        let provenance = Provenance::new(downloader.remote_computer(), downloader.method_name())
+            .source(&source)
+            .started(started)
+            .times(times);
+        record_file(local_path, &provenance);
+        scan_downloaded(local_path);
+    }
+}
+
+fn list_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            list_files(&entry?.path(), files)?;
+        }
+    } else {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn matches_wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) =>
+            matches_wildcard(&pattern[1..], name) || (!name.is_empty() && matches_wildcard(pattern, &name[1..])),
+        (Some(b'?'), Some(_)) => matches_wildcard(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches_wildcard(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_acquirer::{matching_files, produced_files};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_only_files_of_the_transfer_are_produced() {
+        let directory = std::env::temp_dir().join(format!("gargamel-files-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("old.log"), "downloaded before").unwrap();
+        fs::write(directory.join("notes.txt"), "not matching").unwrap();
+        let remote_path = Path::new("/var/log/*.log");
+        let before = matching_files(remote_path, &directory);
+
+        fs::write(directory.join("new.log"), "downloaded now").unwrap();
+        fs::write(directory.join("notes.txt"), "changed, but not matching").unwrap();
+        let produced = produced_files(&before, matching_files(remote_path, &directory));
+
+        assert_eq!(vec![directory.join("new.log")], produced);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
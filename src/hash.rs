@@ -0,0 +1,74 @@
+use std::path::Path;
+use std::io;
+use std::fs::File;
+use std::io::Read;
+use sha2::{Sha256, Digest};
+use md5::Md5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileHashes {
+    pub size: u64,
+    pub sha256: String,
+    pub md5: String,
+}
+
+pub fn hash_file(path: &Path) -> io::Result<FileHashes> {
+    hash_reader(File::open(path)?)
+}
+
+pub fn hash_bytes(content: &[u8]) -> FileHashes {
+    hash_reader(content).expect("Reading from memory cannot fail")
+}
+
+fn hash_reader<R: Read>(mut reader: R) -> io::Result<FileHashes> {
+    let mut sha256 = Sha256::new();
+    let mut md5 = Md5::new();
+    let mut size = 0u64;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        sha256.update(&buffer[..read]);
+        md5.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok(FileHashes {
+        size,
+        sha256: format!("{:x}", sha256.finalize()),
+        md5: format!("{:x}", md5.finalize()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::{hash_file, hash_bytes};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_hash_file() {
+        let path = std::env::temp_dir().join("gargamel-hash-test.txt");
+        std::fs::write(&path, "gargamel").unwrap();
+        let hashes = hash_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(8, hashes.size);
+        assert_eq!("4e4b5f47ff8dc92f3d387481bcf0c49b2f86a3353313b4f17b3ab5cdc34a58a1", hashes.sha256);
+        assert_eq!("25cd833383e5b770958dc1df8a96bfe0", hashes.md5);
+        assert_eq!(hash_bytes(b"gargamel"), hashes);
+    }
+
+    #[test]
+    fn test_hash_empty_content() {
+        let hashes = hash_bytes(b"");
+        assert_eq!(0, hashes.size);
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", hashes.sha256);
+        assert_eq!("d41d8cd98f00b204e9800998ecf8427e", hashes.md5);
+    }
+
+    #[test]
+    fn test_hash_missing_file() {
+        let path = std::env::temp_dir().join(format!("gargamel-hash-missing-{}", uuid::Uuid::new_v4()));
+        assert_eq!(ErrorKind::NotFound, hash_file(&path).unwrap_err().kind());
+    }
+}
@@ -0,0 +1,469 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::{ErrorKind, Write, BufWriter};
+use std::fs::File;
+use std::collections::HashSet;
+use chrono::{DateTime, Utc, TimeZone};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use crate::manifest::timestamp;
+
+// Offline parser of binary registry hives (regf) saved by `reg save`.
+// Only the primary hive file is read, transaction logs of a dirty hive are not applied.
+
+pub const DUMP_EXTENSION: &str = "jsonl";
+
+const BASE_BLOCK_SIZE: usize = 4096;
+const BIG_DATA_SEGMENT_SIZE: usize = 16344;
+const MAX_DEPTH: usize = 512;
+const MAX_BINARY_DUMP: usize = 1024;
+const KEY_COMPRESSED_NAME: u16 = 0x20;
+const VALUE_COMPRESSED_NAME: u16 = 0x01;
+const DATA_INLINE: u32 = 0x8000_0000;
+// 100 ns intervals between 1601-01-01 and 1970-01-01
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HiveValue {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub data: serde_json::Value,
+}
+
+// Key with its path relative to the root key of the hive, the root key itself has an empty path.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HiveKey {
+    pub path: String,
+    pub last_written: String,
+    pub values: Vec<HiveValue>,
+}
+
+struct KeyNode {
+    name: String,
+    last_written: DateTime<Utc>,
+    subkey_count: u32,
+    subkey_list: u32,
+    value_count: u32,
+    value_list: u32,
+}
+
+pub struct Hive {
+    data: Vec<u8>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn bytes_at(data: &[u8], at: usize, length: usize) -> io::Result<&[u8]> {
+    data.get(at..at + length).ok_or_else(|| invalid(format!("Hive record is truncated at {:#x}", at)))
+}
+
+fn u16_at(data: &[u8], at: usize) -> io::Result<u16> {
+    bytes_at(data, at, 2).map(|it| u16::from_le_bytes([it[0], it[1]]))
+}
+
+fn u32_at(data: &[u8], at: usize) -> io::Result<u32> {
+    bytes_at(data, at, 4).map(|it| u32::from_le_bytes([it[0], it[1], it[2], it[3]]))
+}
+
+fn u64_at(data: &[u8], at: usize) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(bytes_at(data, at, 8)?);
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|it| u16::from_le_bytes([it[0], it[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn name(bytes: &[u8], compressed: bool) -> String {
+    if compressed {
+        bytes.iter().map(|it| *it as char).collect()
+    } else {
+        utf16(bytes)
+    }
+}
+
+fn filetime(value: u64) -> DateTime<Utc> {
+    let since_epoch = value as i128 - FILETIME_UNIX_EPOCH as i128;
+    let seconds = since_epoch.div_euclid(10_000_000) as i64;
+    let nanoseconds = (since_epoch.rem_euclid(10_000_000) * 100) as u32;
+    Utc.timestamp_opt(seconds, nanoseconds).single().unwrap_or_else(|| Utc.timestamp_opt(0, 0).unwrap())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut text: String = bytes.iter().take(MAX_BINARY_DUMP).map(|it| format!("{:02x}", it)).collect();
+    if bytes.len() > MAX_BINARY_DUMP {
+        text.push_str("...");
+    }
+    text
+}
+
+fn value_data(kind: u32, data: &[u8]) -> (String, serde_json::Value) {
+    let string = || utf16(data).trim_end_matches('\0').to_string();
+    match kind {
+        0 => ("REG_NONE".to_string(), json!(hex(data))),
+        1 => ("REG_SZ".to_string(), json!(string())),
+        2 => ("REG_EXPAND_SZ".to_string(), json!(string())),
+        3 => ("REG_BINARY".to_string(), json!(hex(data))),
+        4 if data.len() >= 4 => ("REG_DWORD".to_string(), json!(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))),
+        5 if data.len() >= 4 => ("REG_DWORD_BIG_ENDIAN".to_string(), json!(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))),
+        6 => ("REG_LINK".to_string(), json!(string())),
+        7 => ("REG_MULTI_SZ".to_string(), json!(utf16(data).split('\0').filter(|it| !it.is_empty()).collect::<Vec<&str>>())),
+        8 => ("REG_RESOURCE_LIST".to_string(), json!(hex(data))),
+        9 => ("REG_FULL_RESOURCE_DESCRIPTOR".to_string(), json!(hex(data))),
+        10 => ("REG_RESOURCE_REQUIREMENTS_LIST".to_string(), json!(hex(data))),
+        11 if data.len() >= 8 => {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[..8]);
+            ("REG_QWORD".to_string(), json!(u64::from_le_bytes(bytes)))
+        }
+        _ => (format!("{:#x}", kind), json!(hex(data))),
+    }
+}
+
+impl Hive {
+    pub fn open(path: &Path) -> io::Result<Hive> {
+        Hive::parse(std::fs::read(path)?)
+    }
+
+    pub fn parse(data: Vec<u8>) -> io::Result<Hive> {
+        if data.len() < BASE_BLOCK_SIZE || !data.starts_with(b"regf") {
+            return Err(invalid("Not a registry hive, regf signature is missing".to_string()));
+        }
+        Ok(Hive { data })
+    }
+
+    // Data of the cell at the offset relative to the first hive bin, without the size field.
+    fn cell(&self, offset: u32) -> io::Result<&[u8]> {
+        let start = BASE_BLOCK_SIZE + offset as usize;
+        let size = u32_at(&self.data, start)? as i32;
+        let length = (size.unsigned_abs() as usize).max(4);
+        bytes_at(&self.data, start + 4, length - 4)
+    }
+
+    fn signed_cell(&self, offset: u32, signature: &[u8]) -> io::Result<&[u8]> {
+        let cell = self.cell(offset)?;
+        if !cell.starts_with(signature) {
+            return Err(invalid(format!(
+                "Expected {} record at {:#x}", String::from_utf8_lossy(signature), offset
+            )));
+        }
+        Ok(cell)
+    }
+
+    fn key(&self, offset: u32) -> io::Result<KeyNode> {
+        let cell = self.signed_cell(offset, b"nk")?;
+        let flags = u16_at(cell, 0x02)?;
+        let name_length = u16_at(cell, 0x48)? as usize;
+        Ok(KeyNode {
+            name: name(bytes_at(cell, 0x4C, name_length)?, flags & KEY_COMPRESSED_NAME != 0),
+            last_written: filetime(u64_at(cell, 0x04)?),
+            subkey_count: u32_at(cell, 0x14)?,
+            subkey_list: u32_at(cell, 0x1C)?,
+            value_count: u32_at(cell, 0x24)?,
+            value_list: u32_at(cell, 0x28)?,
+        })
+    }
+
+    fn subkeys(&self, list: u32, is_index: bool, subkeys: &mut Vec<u32>) -> io::Result<()> {
+        let cell = self.cell(list)?;
+        let count = u16_at(cell, 0x02)? as usize;
+        match bytes_at(cell, 0, 2)? {
+            b"li" => for index in 0..count {
+                subkeys.push(u32_at(cell, 4 + index * 4)?);
+            },
+            // fast leaf and hash leaf entries hold a name hint or hash after the offset
+            b"lf" | b"lh" => for index in 0..count {
+                subkeys.push(u32_at(cell, 4 + index * 8)?);
+            },
+            // an index root lists leaves only
+            b"ri" if !is_index => for index in 0..count {
+                self.subkeys(u32_at(cell, 4 + index * 4)?, true, subkeys)?;
+            },
+            other => return Err(invalid(format!(
+                "Unknown subkey list {} at {:#x}", String::from_utf8_lossy(other), list
+            ))),
+        }
+        Ok(())
+    }
+
+    fn value(&self, offset: u32) -> io::Result<HiveValue> {
+        let cell = self.signed_cell(offset, b"vk")?;
+        let name_length = u16_at(cell, 0x02)? as usize;
+        let size = u32_at(cell, 0x04)?;
+        let data_offset = u32_at(cell, 0x08)?;
+        let flags = u16_at(cell, 0x10)?;
+        let data = if size & DATA_INLINE != 0 {
+            // data of up to 4 bytes is stored in place of its offset
+            bytes_at(cell, 0x08, ((size & !DATA_INLINE) as usize).min(4))?.to_vec()
+        } else if size == 0 {
+            vec![]
+        } else {
+            self.data(data_offset, size as usize)?
+        };
+        let (kind, data) = value_data(u32_at(cell, 0x0C)?, &data);
+        Ok(HiveValue {
+            name: name(bytes_at(cell, 0x14, name_length)?, flags & VALUE_COMPRESSED_NAME != 0),
+            kind,
+            data,
+        })
+    }
+
+    fn data(&self, offset: u32, size: usize) -> io::Result<Vec<u8>> {
+        let cell = self.cell(offset)?;
+        if size <= BIG_DATA_SEGMENT_SIZE || !cell.starts_with(b"db") {
+            return Ok(bytes_at(cell, 0, size.min(cell.len()))?.to_vec());
+        }
+        // big data is split into segments listed by the db record
+        let segments = self.cell(u32_at(cell, 0x04)?)?;
+        let mut data = vec![];
+        for index in 0..u16_at(cell, 0x02)? as usize {
+            let segment = self.cell(u32_at(segments, index * 4)?)?;
+            data.extend_from_slice(&segment[..segment.len().min(BIG_DATA_SEGMENT_SIZE)]);
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+
+    fn values(&self, key: &KeyNode) -> io::Result<Vec<HiveValue>> {
+        let mut values = vec![];
+        if key.value_count == 0 {
+            return Ok(values);
+        }
+        let list = self.cell(key.value_list)?;
+        for index in 0..key.value_count as usize {
+            match self.value(u32_at(list, index * 4)?) {
+                Ok(value) => values.push(value),
+                Err(err) => warn!("Skipping value of {} due to {}", key.name, err),
+            }
+        }
+        Ok(values)
+    }
+
+    // Visits all keys of the hive in depth-first order, starting with the root key.
+    pub fn walk(&self, visit: &mut dyn FnMut(HiveKey) -> io::Result<()>) -> io::Result<()> {
+        let root = u32_at(&self.data, 0x24)?;
+        self.walk_key(root, None, 0, &mut HashSet::new(), visit)
+    }
+
+    // A corrupted or crafted hive may reference a key from several lists, each key is visited once.
+    fn walk_key(&self, offset: u32, parent: Option<&str>, depth: usize, visited: &mut HashSet<u32>, visit: &mut dyn FnMut(HiveKey) -> io::Result<()>) -> io::Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(invalid(format!("Keys are nested deeper than {} at {:#x}", MAX_DEPTH, offset)));
+        }
+        if !visited.insert(offset) {
+            return Err(invalid(format!("Key at {:#x} is referenced more than once", offset)));
+        }
+        let key = self.key(offset)?;
+        let path = match parent {
+            None => String::new(),
+            Some("") => key.name.clone(),
+            Some(parent) => format!("{}\\{}", parent, key.name),
+        };
+        let values = self.values(&key).unwrap_or_else(|err| {
+            warn!("Skipping values of {} due to {}", path, err);
+            vec![]
+        });
+        visit(HiveKey {
+            path: path.clone(),
+            last_written: timestamp(key.last_written),
+            values,
+        })?;
+        let mut subkeys = vec![];
+        if key.subkey_count > 0 {
+            if let Err(err) = self.subkeys(key.subkey_list, false, &mut subkeys) {
+                warn!("Skipping subkeys of {} due to {}", path, err);
+            }
+        }
+        for subkey in subkeys {
+            if let Err(err) = self.walk_key(subkey, Some(&path), depth + 1, visited, visit) {
+                warn!("Skipping subkey of {} due to {}", path, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn dump_path(hive: &Path, output_directory: Option<&Path>) -> PathBuf {
+    let file_name = format!("{}.{}", hive.file_name().unwrap_or_default().to_string_lossy(), DUMP_EXTENSION);
+    match output_directory {
+        None => hive.with_file_name(file_name),
+        Some(directory) => directory.join(file_name),
+    }
+}
+
+// Writes all keys of the hive as JSON lines, one key with its values per line. Returns the number of keys.
+pub fn dump_hive(hive: &Path, output: &Path) -> io::Result<usize> {
+    let hive = Hive::open(hive)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    let mut count = 0;
+    hive.walk(&mut |key| {
+        serde_json::to_writer(&mut writer, &key)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hive::{Hive, HiveKey, HiveValue, value_data};
+    use serde_json::json;
+    use std::io;
+    use std::io::ErrorKind;
+
+    fn cell(hive: &mut Vec<u8>, content: &[u8]) -> u32 {
+        let offset = (hive.len() - 4096) as u32;
+        let size = (content.len() + 4).div_ceil(8) * 8;
+        hive.extend_from_slice(&(-(size as i32)).to_le_bytes());
+        hive.extend_from_slice(content);
+        hive.resize(4096 + offset as usize + size, 0);
+        offset
+    }
+
+    fn key(name: &str, subkeys: u32, subkey_list: u32, values: u32, value_list: u32) -> Vec<u8> {
+        let mut nk = vec![0u8; 0x4C];
+        nk[0..2].copy_from_slice(b"nk");
+        nk[0x02..0x04].copy_from_slice(&0x20u16.to_le_bytes());
+        nk[0x04..0x0C].copy_from_slice(&132467982100000000u64.to_le_bytes());
+        nk[0x14..0x18].copy_from_slice(&subkeys.to_le_bytes());
+        nk[0x1C..0x20].copy_from_slice(&subkey_list.to_le_bytes());
+        nk[0x24..0x28].copy_from_slice(&values.to_le_bytes());
+        nk[0x28..0x2C].copy_from_slice(&value_list.to_le_bytes());
+        nk[0x48..0x4A].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        nk.extend_from_slice(name.as_bytes());
+        nk
+    }
+
+    fn value(name: &str, kind: u32, size: u32, data: u32) -> Vec<u8> {
+        let mut vk = vec![0u8; 0x14];
+        vk[0..2].copy_from_slice(b"vk");
+        vk[0x02..0x04].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        vk[0x04..0x08].copy_from_slice(&size.to_le_bytes());
+        vk[0x08..0x0C].copy_from_slice(&data.to_le_bytes());
+        vk[0x0C..0x10].copy_from_slice(&kind.to_le_bytes());
+        vk[0x10..0x12].copy_from_slice(&1u16.to_le_bytes());
+        vk.extend_from_slice(name.as_bytes());
+        vk
+    }
+
+    fn empty_hive() -> Vec<u8> {
+        let mut hive = vec![0u8; 4096];
+        hive[0..4].copy_from_slice(b"regf");
+        hive.extend_from_slice(b"hbin");
+        hive.resize(4096 + 32, 0);
+        hive
+    }
+
+    // Root key with the Run subkey holding two values. Returns the hive and the offset of the Run key.
+    fn run_key_hive() -> (Vec<u8>, u32) {
+        let mut hive = empty_hive();
+        let path: Vec<u8> = "C:\\evil.exe\0".encode_utf16().flat_map(|it| it.to_le_bytes().to_vec()).collect();
+        let path_data = cell(&mut hive, &path);
+        let updater = cell(&mut hive, &value("Updater", 1, path.len() as u32, path_data));
+        let start = cell(&mut hive, &value("Start", 4, 0x8000_0004, 2));
+        let value_list: Vec<u8> = [updater, start].iter().flat_map(|it| it.to_le_bytes().to_vec()).collect();
+        let value_list = cell(&mut hive, &value_list);
+        let run = cell(&mut hive, &key("Run", 0, 0, 2, value_list));
+        let mut subkey_list = b"lf".to_vec();
+        subkey_list.extend_from_slice(&1u16.to_le_bytes());
+        subkey_list.extend_from_slice(&run.to_le_bytes());
+        subkey_list.extend_from_slice(b"Run\0");
+        let subkey_list = cell(&mut hive, &subkey_list);
+        let root = cell(&mut hive, &key("ROOT", 1, subkey_list, 0, 0));
+        hive[0x24..0x28].copy_from_slice(&root.to_le_bytes());
+        (hive, run)
+    }
+
+    fn walk(hive: Vec<u8>) -> io::Result<Vec<HiveKey>> {
+        let hive = Hive::parse(hive)?;
+        let mut keys = vec![];
+        hive.walk(&mut |key| {
+            keys.push(key);
+            Ok(())
+        })?;
+        Ok(keys)
+    }
+
+    #[test]
+    fn test_keys_and_values_are_dumped() {
+        let (hive, _) = run_key_hive();
+
+        let keys = walk(hive).unwrap();
+
+        assert_eq!(vec![
+            HiveKey { path: "".to_string(), last_written: "2020-10-10T10:10:10Z".to_string(), values: vec![] },
+            HiveKey {
+                path: "Run".to_string(),
+                last_written: "2020-10-10T10:10:10Z".to_string(),
+                values: vec![
+                    HiveValue { name: "Updater".to_string(), kind: "REG_SZ".to_string(), data: json!("C:\\evil.exe") },
+                    HiveValue { name: "Start".to_string(), kind: "REG_DWORD".to_string(), data: json!(2) },
+                ],
+            },
+        ], keys);
+    }
+
+    #[test]
+    fn test_file_without_signature_is_rejected() {
+        assert!(Hive::parse(vec![0u8; 4096]).is_err());
+        assert!(Hive::parse(b"regf".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_truncated_hive_fails() {
+        let (mut hive, _) = run_key_hive();
+        hive.truncate(hive.len() - 40);
+
+        assert_eq!(ErrorKind::InvalidData, walk(hive).unwrap_err().kind());
+    }
+
+    #[test]
+    fn test_damaged_subkey_is_skipped() {
+        let (mut hive, run) = run_key_hive();
+        let signature = 4096 + run as usize + 4;
+        hive[signature..signature + 2].copy_from_slice(b"xx");
+
+        let keys = walk(hive).unwrap();
+
+        assert_eq!(vec![""], keys.iter().map(|it| it.path.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_value_data_kinds() {
+        let multi_sz: Vec<u8> = "a\0b\0\0".encode_utf16().flat_map(|it| it.to_le_bytes().to_vec()).collect();
+        assert_eq!(("REG_MULTI_SZ".to_string(), json!(["a", "b"])), value_data(7, &multi_sz));
+        assert_eq!(("REG_BINARY".to_string(), json!("01ff")), value_data(3, &[0x01, 0xFF]));
+        assert_eq!(("REG_QWORD".to_string(), json!(1)), value_data(11, &1u64.to_le_bytes()));
+        assert_eq!(("0x4".to_string(), json!("01")), value_data(4, &[0x01]));
+    }
+
+    #[test]
+    fn test_key_listing_itself_is_visited_once() {
+        let mut hive = empty_hive();
+        let subkey_list = cell(&mut hive, &[0u8; 12]);
+        let root = cell(&mut hive, &key("ROOT", 1, subkey_list, 0, 0));
+        let mut list = b"lf".to_vec();
+        list.extend_from_slice(&1u16.to_le_bytes());
+        list.extend_from_slice(&root.to_le_bytes());
+        list.extend_from_slice(b"ROOT");
+        let start = 4096 + subkey_list as usize + 4;
+        hive[start..start + 12].copy_from_slice(&list);
+        hive[0x24..0x28].copy_from_slice(&root.to_le_bytes());
+        let hive = Hive::parse(hive).unwrap();
+
+        let mut count = 0;
+        hive.walk(&mut |_| {
+            count += 1;
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(1, count);
+    }
+}
@@ -0,0 +1,329 @@
+use std::path::{Path, PathBuf};
+use crate::remote::{Computer, Connector, PsExec, PsRemote, Rdp, Wmi, Compression, HostPlatform, command_output};
+use std::time::Duration;
+use crate::artifact_acquirer::ArtifactAcquirer;
+use crate::profile::{Profile, Artifact, Category};
+use crate::process_runner::CommandOutcome;
+use std::io;
+
+const PROFILE_LIST: &str = "HKLM\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\ProfileList";
+const USER_HIVES_DIRECTORY: &str = "user-hives";
+
+// Acquires the registry as binary hives using `reg save`, including NTUSER.DAT and UsrClass.dat of every user.
+pub struct HiveAcquirer<'a> {
+    store_directory: &'a Path,
+    connector: Box<dyn Connector>,
+
+    artifacts: Vec<Artifact>,
+
+    compress_timeout: Option<Duration>,
+    compression: Compression,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserProfile {
+    pub sid: String,
+    pub path: String,
+    // hives of logged on users are locked, they can only be saved from HKEY_USERS
+    pub loaded: bool,
+    pub classes_loaded: bool,
+}
+
+impl UserProfile {
+    pub fn user_name(&self) -> String {
+        let name = self.path.rsplit('\\').next().filter(|it| !it.is_empty()).unwrap_or(&self.sid);
+        name.chars()
+            .map(|it| if it.is_alphanumeric() || it == '-' || it == '_' || it == '.' { it } else { '_' })
+            .collect()
+    }
+}
+
+// Parses profiles of domain and local users from `reg query <ProfileList> /s /v ProfileImagePath`
+// and marks those loaded according to `reg query HKU`. Profiles of the system accounts are skipped.
+pub fn parse_user_profiles(profile_list: &str, loaded_hives: &str) -> Vec<UserProfile> {
+    let loaded: Vec<String> = loaded_hives.lines()
+        .filter_map(|line| line.trim().rsplit('\\').next())
+        .map(|it| it.to_uppercase())
+        .collect();
+    let mut profiles = vec![];
+    let mut sid = None;
+    for line in profile_list.lines() {
+        let line = line.trim();
+        if line.to_uppercase().starts_with("HKEY_") {
+            sid = line.rsplit('\\').next().map(str::to_string);
+        } else if let (Some(current), Some(path)) = (&sid, line.strip_prefix("ProfileImagePath")) {
+            if !current.starts_with("S-1-5-21-") {
+                continue;
+            }
+            let path = path.trim_start();
+            let path = path.find(char::is_whitespace).map(|end| path[end..].trim()).unwrap_or_default();
+            profiles.push(UserProfile {
+                sid: current.clone(),
+                path: path.to_string(),
+                loaded: loaded.contains(&current.to_uppercase()),
+                classes_loaded: loaded.contains(&format!("{}_CLASSES", current.to_uppercase())),
+            });
+        }
+    }
+    profiles
+}
+
+fn user_artifact(name: String) -> Artifact {
+    Artifact {
+        name,
+        os: HostPlatform::Windows,
+        category: Category::Hives,
+        command: vec![],
+        script: None,
+        file: None,
+        channel: None,
+        query: None,
+        extension: "dat".to_string(),
+        large: false,
+        compress: true,
+        overwrite_switch: None,
+        elevated: true,
+        requires: None,
+        timeout: None,
+        parser: None,
+    }
+}
+
+// Artifacts saving the hives of a logged on user, or downloading the hive files of a user who is not logged on.
+pub fn user_hive_artifacts(profile: &UserProfile) -> Vec<Artifact> {
+    let user = profile.user_name();
+    let hives = [
+        ("ntuser", profile.loaded, format!("HKU\\{}", profile.sid), "NTUSER.DAT"),
+        ("usrclass", profile.classes_loaded, format!("HKU\\{}_Classes", profile.sid), "AppData\\Local\\Microsoft\\Windows\\UsrClass.dat"),
+    ];
+    hives.iter()
+        .map(|(name, loaded, key, file)| {
+            let artifact = user_artifact(format!("hive-{}-{}", name, user));
+            if *loaded {
+                Artifact {
+                    command: vec!["reg".to_string(), "save".to_string(), key.clone()],
+                    large: true,
+                    overwrite_switch: Some("/y".to_string()),
+                    ..artifact
+                }
+            } else {
+                Artifact {
+                    file: Some(format!("{}\\{}", profile.path, file)),
+                    ..artifact
+                }
+            }
+        })
+        .collect()
+}
+
+impl<'a> HiveAcquirer<'a> {
+    pub fn new(
+        store_directory: &'a Path,
+        connector: Box<dyn Connector>,
+        compress_timeout: Option<Duration>,
+        compression: Compression,
+        profile: &Profile,
+    ) -> HiveAcquirer<'a> {
+        HiveAcquirer {
+            store_directory,
+            artifacts: profile.artifacts(Category::Hives, connector.remote_platform()),
+            connector,
+            compress_timeout,
+            compression,
+        }
+    }
+
+    pub fn psexec(
+        store_directory: &'a Path,
+        computer: Computer,
+        no_7zip: bool,
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> HiveAcquirer<'a> {
+        HiveAcquirer::new(
+            store_directory,
+            Box::new(PsExec::psexec(computer, remote_temp_storage)),
+            None,
+            if no_7zip { Compression::No } else { Compression::Yes },
+            profile,
+        )
+    }
+
+    pub fn psremote(
+        store_directory: &'a Path,
+        computer: Computer,
+        _no_7zip: bool,
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> HiveAcquirer<'a> {
+        HiveAcquirer::new(
+            store_directory,
+            Box::new(PsRemote::new(computer, remote_temp_storage)),
+            None,
+            Compression::No,
+            profile,
+        )
+    }
+
+    pub fn wmi(
+        store_directory: &'a Path,
+        computer: Computer,
+        compress_timeout: Duration,
+        no_7zip: bool,
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> HiveAcquirer<'a> {
+        HiveAcquirer::new(
+            store_directory,
+            Box::new(Wmi { computer, remote_temp_storage }),
+            Some(compress_timeout),
+            if no_7zip { Compression::No } else { Compression::YesSplit },
+            profile,
+        )
+    }
+
+    pub fn rdp(
+        store_directory: &'a Path,
+        computer: Computer,
+        compress_timeout: Duration,
+        nla: bool,
+        no_7zip: bool,
+        remote_temp_storage: PathBuf,
+        profile: &Profile
+    ) -> HiveAcquirer<'a> {
+        HiveAcquirer::new(
+            store_directory,
+            Box::new(Rdp { computer, nla, remote_temp_storage }),
+            Some(compress_timeout),
+            if no_7zip { Compression::No } else { Compression::YesSplit },
+            profile,
+        )
+    }
+
+    pub fn acquire(&self) -> Vec<io::Result<CommandOutcome>> {
+        let acquirer = ArtifactAcquirer {
+            store_directory: self.store_directory,
+            connector: self.connector.as_ref(),
+            compress_timeout: self.compress_timeout,
+            compression: self.compression,
+        };
+        let mut outcomes = acquirer.acquire_all(&self.artifacts);
+        let profiles = match self.user_profiles() {
+            Ok(profiles) => profiles,
+            Err(err) => {
+                error!("Cannot list user profiles of {}. Cause: {}", self.connector.computer().address, err);
+                outcomes.push(Err(err));
+                return outcomes;
+            }
+        };
+        for profile in profiles {
+            // hive files of all users have the same names, so each user has a directory
+            let user_directory = self.store_directory.join(USER_HIVES_DIRECTORY).join(profile.user_name());
+            if let Err(err) = std::fs::create_dir_all(&user_directory) {
+                outcomes.push(Err(err));
+                continue;
+            }
+            let user_acquirer = ArtifactAcquirer {
+                store_directory: &user_directory,
+                ..acquirer
+            };
+            outcomes.extend(user_acquirer.acquire_all(&user_hive_artifacts(&profile)));
+        }
+        outcomes
+    }
+
+    fn user_profiles(&self) -> io::Result<Vec<UserProfile>> {
+        let query = |command: Vec<&str>| command_output(
+            self.connector.as_ref(),
+            command.into_iter().map(str::to_string).collect(),
+            "user-profiles",
+        );
+        let profile_list = query(vec!["reg", "query", PROFILE_LIST, "/s", "/v", "ProfileImagePath"])?;
+        let loaded_hives = query(vec!["reg", "query", "HKU"])?;
+        let profiles = parse_user_profiles(&profile_list, &loaded_hives);
+        info!("{}: Acquiring hives of {} user profiles", self.connector.connect_method_name(), profiles.len());
+        Ok(profiles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hive_acquirer::{parse_user_profiles, user_hive_artifacts, UserProfile};
+
+    const PROFILE_LIST: &str = "
+HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\ProfileList\\S-1-5-18
+    ProfileImagePath    REG_EXPAND_SZ    %systemroot%\\system32\\config\\systemprofile
+
+HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\ProfileList\\S-1-5-21-1-2-3-1001
+    ProfileImagePath    REG_EXPAND_SZ    C:\\Users\\Jano
+
+HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\ProfileList\\S-1-5-21-1-2-3-1002
+    ProfileImagePath    REG_EXPAND_SZ    C:\\Users\\John Smith
+
+End of search: 3 match(es) found.
+";
+
+    const LOADED_HIVES: &str = "
+HKEY_USERS\\.DEFAULT
+HKEY_USERS\\S-1-5-19
+HKEY_USERS\\S-1-5-21-1-2-3-1001
+HKEY_USERS\\S-1-5-21-1-2-3-1001_Classes
+";
+
+    fn profile(path: &str, loaded: bool) -> UserProfile {
+        UserProfile {
+            sid: "S-1-5-21-1-2-3-1001".to_string(),
+            path: path.to_string(),
+            loaded,
+            classes_loaded: loaded,
+        }
+    }
+
+    #[test]
+    fn test_user_profiles_are_parsed() {
+        let profiles = parse_user_profiles(PROFILE_LIST, LOADED_HIVES);
+
+        assert_eq!(vec![
+            UserProfile {
+                sid: "S-1-5-21-1-2-3-1001".to_string(),
+                path: "C:\\Users\\Jano".to_string(),
+                loaded: true,
+                classes_loaded: true,
+            },
+            UserProfile {
+                sid: "S-1-5-21-1-2-3-1002".to_string(),
+                path: "C:\\Users\\John Smith".to_string(),
+                loaded: false,
+                classes_loaded: false,
+            },
+        ], profiles);
+        assert!(parse_user_profiles("ERROR: Access is denied.", LOADED_HIVES).is_empty());
+    }
+
+    #[test]
+    fn test_user_name_is_safe_directory_name() {
+        assert_eq!("John_Smith", profile("C:\\Users\\John Smith", false).user_name());
+        assert_eq!("jano.domain", profile("C:\\Users\\jano.domain", false).user_name());
+        assert_eq!("S-1-5-21-1-2-3-1001", profile("", false).user_name());
+    }
+
+    #[test]
+    fn test_hives_of_logged_on_user_are_saved() {
+        let saved = user_hive_artifacts(&profile("C:\\Users\\Jano", true));
+
+        assert_eq!("hive-ntuser-Jano", saved[0].name);
+        assert_eq!(vec!["reg", "save", "HKU\\S-1-5-21-1-2-3-1001"], saved[0].command);
+        assert_eq!("hive-usrclass-Jano", saved[1].name);
+        assert_eq!(vec!["reg", "save", "HKU\\S-1-5-21-1-2-3-1001_Classes"], saved[1].command);
+        assert!(saved.iter().all(|it| it.large && it.file.is_none()));
+    }
+
+    #[test]
+    fn test_hives_of_logged_off_user_are_downloaded() {
+        let downloaded = user_hive_artifacts(&profile("C:\\Users\\John Smith", false));
+
+        assert_eq!(Some("C:\\Users\\John Smith\\NTUSER.DAT".to_string()), downloaded[0].file);
+        assert_eq!(Some("C:\\Users\\John Smith\\AppData\\Local\\Microsoft\\Windows\\UsrClass.dat".to_string()), downloaded[1].file);
+        assert!(downloaded.iter().all(|it| it.command.is_empty()));
+    }
+}
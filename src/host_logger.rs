@@ -0,0 +1,46 @@
+use log::{Log, Metadata, Record, LevelFilter, SetLoggerError};
+use std::cell::RefCell;
+
+thread_local! {
+    static HOST_PREFIX: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub fn set_host_prefix(host: &str) {
+    HOST_PREFIX.with(|prefix| *prefix.borrow_mut() = Some(host.to_string()));
+}
+
+pub struct HostPrefixLogger {
+    inner: Box<dyn Log>,
+}
+
+impl HostPrefixLogger {
+    pub fn init(inner: Box<dyn Log>, level: LevelFilter) -> Result<(), SetLoggerError> {
+        log::set_max_level(level);
+        log::set_boxed_logger(Box::new(HostPrefixLogger { inner }))
+    }
+}
+
+impl Log for HostPrefixLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        HOST_PREFIX.with(|prefix| match prefix.borrow().as_ref() {
+            None => self.inner.log(record),
+            Some(host) => self.inner.log(
+                &Record::builder()
+                    .args(format_args!("[{}] {}", host, record.args()))
+                    .metadata(record.metadata().clone())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build()
+            ),
+        })
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::io;
+use std::io::ErrorKind;
+use serde::Deserialize;
+use crate::arg_parser::Opts;
+use gargamel::plan::AcquisitionReport;
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct InventoryHost {
+    pub address: String,
+    #[serde(default)]
+    pub os: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TomlInventory {
+    host: Vec<InventoryHost>,
+}
+
+impl InventoryHost {
+    pub fn apply(&self, opts: &Opts) -> io::Result<Opts> {
+        let mut opts = Opts {
+            computer: self.address.clone(),
+            user: self.username.clone().unwrap_or(opts.user.clone()),
+            domain: self.domain.clone().or(opts.domain.clone()),
+            ssh_key: self.key_file.clone().or(opts.ssh_key.clone()),
+            ..opts.clone()
+        };
+        let is_linux = self.os.as_ref()
+            .map(|os| os.eq_ignore_ascii_case("linux"))
+            .unwrap_or(false);
+        if self.methods.is_empty() && !is_linux {
+            return Ok(opts);
+        }
+        opts.all = false;
+        opts.psexec = false;
+        opts.psrem = false;
+        opts.wmi = false;
+        opts.rdp = false;
+        opts.ssh = false;
+        if self.methods.is_empty() {
+            opts.ssh = true;
+        }
+        for method in self.methods.iter() {
+            match method.to_ascii_lowercase().as_str() {
+                "all" => opts.all = true,
+                "psexec" => opts.psexec = true,
+                "psrem" => opts.psrem = true,
+                "wmi" => opts.wmi = true,
+                "rdp" => opts.rdp = true,
+                "ssh" => opts.ssh = true,
+                _ => return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown method {} for host {}", method, self.address),
+                )),
+            }
+        }
+        Ok(opts)
+    }
+
+    pub fn directory_name(&self) -> String {
+        self.address.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect()
+    }
+}
+
+pub fn load_inventory(path: &Path) -> io::Result<Vec<InventoryHost>> {
+    let content = std::fs::read_to_string(path)?;
+    let is_toml = path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("toml"))
+        .unwrap_or(false);
+    let hosts = if is_toml {
+        parse_toml_inventory(&content)?
+    } else {
+        parse_csv_inventory(&content)?
+    };
+    if hosts.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Inventory {} contains no hosts", path.display()),
+        ));
+    }
+    Ok(hosts)
+}
+
+fn parse_toml_inventory(content: &str) -> io::Result<Vec<InventoryHost>> {
+    toml::from_str::<TomlInventory>(content)
+        .map(|inventory| inventory.host)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+fn parse_csv_inventory(content: &str) -> io::Result<Vec<InventoryHost>> {
+    let mut lines = content.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("#"));
+    let header: Vec<String> = match lines.next() {
+        None => return Ok(vec![]),
+        Some(header) => header.split(',').map(|it| it.trim().to_ascii_lowercase()).collect(),
+    };
+    if !header.iter().any(|column| column == "address") {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Inventory header must contain the address column",
+        ));
+    }
+    let mut hosts = vec![];
+    for line in lines {
+        let mut host = InventoryHost::default();
+        for (column, value) in header.iter().zip(line.split(',')) {
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match column.as_str() {
+                "address" => host.address = value.to_string(),
+                "os" => host.os = Some(value.to_string()),
+                "username" => host.username = Some(value.to_string()),
+                "domain" => host.domain = Some(value.to_string()),
+                "methods" => host.methods = value
+                    .split([';', ' '])
+                    .filter(|it| !it.is_empty())
+                    .map(|it| it.to_string())
+                    .collect(),
+                "key_file" => host.key_file = Some(value.to_string()),
+                _ => warn!("Ignoring unknown inventory column {}", column),
+            }
+        }
+        if host.address.is_empty() {
+            warn!("Ignoring inventory line without address: {}", line);
+            continue;
+        }
+        hosts.push(host);
+    }
+    Ok(hosts)
+}
+
+pub fn log_hosts_summary(reports: &[(String, io::Result<AcquisitionReport>)]) {
+    let succeeded = reports.iter()
+        .filter(|(_, report)| report.as_ref().map(|it| it.is_success()).unwrap_or(false))
+        .count();
+    info!("Summary: {} of {} hosts acquired successfully", succeeded, reports.len());
+    for (address, report) in reports {
+        match report {
+            Ok(report) if report.is_success() =>
+                info!("{}: OK ({} operations succeeded)", address, report.succeeded()),
+            Ok(report) =>
+                warn!("{}: FAILED ({} operations succeeded, {} failed)", address, report.succeeded(), report.failed()),
+            Err(err) =>
+                error!("{}: FAILED ({})", address, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inventory::{parse_csv_inventory, parse_toml_inventory, InventoryHost};
+
+    #[test]
+    fn test_csv_and_toml_inventories_are_equal() {
+        let csv = "address,os,username,domain,methods,key_file\n\
+        # workstation\n\
+        192.168.42.47,windows,Jano,WORKSPACE,psexec;wmi,\n\
+        10.0.0.5,linux,root,,,id_ed25519\n";
+        let toml = r#"
+        [[host]]
+        address = "192.168.42.47"
+        os = "windows"
+        username = "Jano"
+        domain = "WORKSPACE"
+        methods = ["psexec", "wmi"]
+
+        [[host]]
+        address = "10.0.0.5"
+        os = "linux"
+        username = "root"
+        key_file = "id_ed25519"
+        "#;
+        let expected = vec![
+            InventoryHost {
+                address: "192.168.42.47".to_string(),
+                os: Some("windows".to_string()),
+                username: Some("Jano".to_string()),
+                domain: Some("WORKSPACE".to_string()),
+                methods: vec!["psexec".to_string(), "wmi".to_string()],
+                key_file: None,
+            },
+            InventoryHost {
+                address: "10.0.0.5".to_string(),
+                os: Some("linux".to_string()),
+                username: Some("root".to_string()),
+                domain: None,
+                methods: vec![],
+                key_file: Some("id_ed25519".to_string()),
+            },
+        ];
+        assert_eq!(expected, parse_csv_inventory(csv).unwrap());
+        assert_eq!(expected, parse_toml_inventory(toml).unwrap());
+    }
+}
@@ -0,0 +1,439 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::fs::File;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use serde::{Serialize, Deserialize};
+use flate2::read::GzDecoder;
+use crate::manifest::{Manifest, MANIFEST_JSON, is_manifest_file};
+use crate::parsers::parsed_report_path;
+use crate::hash::hash_file;
+
+pub const IOC_FINDINGS_JSON: &str = "ioc-findings.json";
+
+// Larger files, like memory images, are only matched by their hashes.
+const MAX_SCANNED_SIZE: u64 = 256 * 1024 * 1024;
+const MAX_CONTEXT_LENGTH: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IocKind {
+    Ip,
+    Domain,
+    Hash,
+    Process,
+    RegistryKey,
+    // rule of the YARA rule set matching a downloaded file
+    YaraRule,
+}
+
+impl IocKind {
+    fn from_prefix(prefix: &str) -> Option<IocKind> {
+        match prefix.to_lowercase().as_str() {
+            "ip" => Some(IocKind::Ip),
+            "domain" => Some(IocKind::Domain),
+            "hash" | "md5" | "sha1" | "sha256" => Some(IocKind::Hash),
+            "process" => Some(IocKind::Process),
+            "registry" | "reg" => Some(IocKind::RegistryKey),
+            _ => None,
+        }
+    }
+
+    // Characters which may not surround a match, so that e.g. `10.0.0.1` does not match `10.0.0.12`.
+    fn is_word_char(&self, character: char) -> bool {
+        match self {
+            IocKind::Ip => character.is_ascii_hexdigit() || character == '.',
+            IocKind::Domain => character.is_alphanumeric() || character == '-',
+            IocKind::Hash => character.is_ascii_hexdigit(),
+            IocKind::Process | IocKind::YaraRule => character.is_alphanumeric() || character == '_' || character == '-' || character == '.',
+            IocKind::RegistryKey => character.is_alphanumeric() || character == '_',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ioc {
+    pub kind: IocKind,
+    pub value: String,
+    // lowercase forms searched for in the evidence
+    needles: Vec<String>,
+}
+
+const REGISTRY_ROOTS: [(&str, &str); 5] = [
+    ("hklm", "hkey_local_machine"),
+    ("hkcu", "hkey_current_user"),
+    ("hkcr", "hkey_classes_root"),
+    ("hku", "hkey_users"),
+    ("hkcc", "hkey_current_config"),
+];
+
+fn is_hash(value: &str) -> bool {
+    [32, 40, 64].contains(&value.len()) && value.chars().all(|it| it.is_ascii_hexdigit())
+}
+
+impl Ioc {
+    pub fn new(kind: IocKind, value: &str) -> Ioc {
+        let value = value.trim().to_string();
+        let lowercase = value.to_lowercase();
+        let mut needles = vec![lowercase.clone()];
+        if kind == IocKind::RegistryKey {
+            // reg export writes full root key names, while IOCs often use the abbreviations
+            for (short, long) in REGISTRY_ROOTS.iter() {
+                for (from, to) in [(short, long), (long, short)].iter() {
+                    if let Some(rest) = lowercase.strip_prefix(&format!("{}\\", from)) {
+                        needles.push(format!("{}\\{}", to, rest));
+                    }
+                }
+            }
+        }
+        Ioc { kind, value, needles }
+    }
+
+    // Parses `type:value`, or guesses the type of a bare value.
+    pub fn parse(line: &str) -> Option<Ioc> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        if let Some((prefix, value)) = line.split_once(':') {
+            if let Some(kind) = IocKind::from_prefix(prefix) {
+                return Some(Ioc::new(kind, value));
+            }
+        }
+        let lowercase = line.to_lowercase();
+        let kind = if line.parse::<IpAddr>().is_ok() {
+            IocKind::Ip
+        } else if is_hash(line) {
+            IocKind::Hash
+        } else if REGISTRY_ROOTS.iter().any(|(short, long)| lowercase.starts_with(&format!("{}\\", short)) || lowercase.starts_with(&format!("{}\\", long))) {
+            IocKind::RegistryKey
+        } else if [".exe", ".dll", ".sys", ".ps1", ".bat", ".scr"].iter().any(|it| lowercase.ends_with(it)) {
+            IocKind::Process
+        } else if line.contains('.') && !line.contains(char::is_whitespace) {
+            IocKind::Domain
+        } else {
+            IocKind::Process
+        };
+        Some(Ioc::new(kind, line))
+    }
+
+    fn is_bounded(&self, line: &str, start: usize, end: usize) -> bool {
+        let before = line[..start].chars().next_back();
+        let mut after = line[end..].chars();
+        let next = after.next();
+        if before.map(|it| self.kind.is_word_char(it)).unwrap_or(false) {
+            return false;
+        }
+        match next {
+            None => true,
+            // a domain followed by a dot and more labels is a different domain
+            Some('.') if self.kind == IocKind::Domain => !after.next().map(char::is_alphanumeric).unwrap_or(false),
+            Some(next) => !self.kind.is_word_char(next),
+        }
+    }
+
+    pub fn matches(&self, lowercase_line: &str) -> bool {
+        self.needles.iter().any(|needle| {
+            lowercase_line.match_indices(needle.as_str())
+                .any(|(start, _)| self.is_bounded(lowercase_line, start, start + needle.len()))
+        })
+    }
+}
+
+pub fn load_iocs(path: &Path) -> io::Result<Vec<Ioc>> {
+    let mut iocs = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        if let Some(ioc) = Ioc::parse(&line?) {
+            iocs.push(ioc);
+        }
+    }
+    Ok(iocs)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub ioc: String,
+    pub kind: IocKind,
+    // evidence file relative to the case directory
+    pub file: String,
+    // file inside a tar.gz archive
+    pub entry: Option<String>,
+    // none when the hash of the whole file matched
+    pub line: Option<usize>,
+    pub context: String,
+}
+
+// reg export writes UTF-16 with a byte order mark, other reports are UTF-8 or ANSI
+pub(crate) fn decode(content: &[u8]) -> Option<String> {
+    if content.starts_with(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = content[2..].chunks_exact(2).map(|it| u16::from_le_bytes([it[0], it[1]])).collect();
+        return Some(String::from_utf16_lossy(&units));
+    }
+    // binary files like evtx, hives or images are not scanned as text
+    if content.iter().take(8192).any(|it| *it == 0) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(content).to_string())
+}
+
+fn context(line: &str) -> String {
+    let line = line.trim();
+    match line.char_indices().nth(MAX_CONTEXT_LENGTH) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+fn scan_text(text: &str, iocs: &[Ioc], file: &str, entry: Option<&str>, findings: &mut Vec<Finding>) {
+    for (number, line) in text.lines().enumerate() {
+        let lowercase = line.to_lowercase();
+        for ioc in iocs.iter().filter(|ioc| ioc.matches(&lowercase)) {
+            findings.push(Finding {
+                ioc: ioc.value.clone(),
+                kind: ioc.kind,
+                file: file.to_string(),
+                entry: entry.map(str::to_string),
+                line: Some(number + 1),
+                context: context(line),
+            });
+        }
+    }
+}
+
+fn scan_archive(path: &Path, iocs: &[Ioc], file: &str, findings: &mut Vec<Finding>) -> io::Result<()> {
+    let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(File::open(path)?)));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() || entry.size() > MAX_SCANNED_SIZE {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+        if let Some(text) = decode(&content) {
+            scan_text(&text, iocs, file, Some(&name), findings);
+        }
+    }
+    Ok(())
+}
+
+fn list_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else if !is_manifest_file(&path) && !path.ends_with(IOC_FINDINGS_JSON) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Scans reports, registry exports, logs and downloaded files of the case directory for the IOCs.
+// Hashes are matched against the whole files too.
+pub fn scan_directory(directory: &Path, iocs: &[Ioc]) -> io::Result<Vec<Finding>> {
+    // the manifest records file names relative to the canonical case directory
+    let directory = &dunce::canonicalize(directory)?;
+    let manifest_path = directory.join(MANIFEST_JSON);
+    let manifest: Option<Manifest> = if manifest_path.exists() {
+        Some(serde_json::from_reader(BufReader::new(File::open(&manifest_path)?))?)
+    } else {
+        None
+    };
+    let mut files = vec![];
+    list_files(directory, &mut files)?;
+    files.sort();
+    // JSON records parsed from a report would repeat the findings of the report
+    let parsed: HashSet<PathBuf> = files.iter().map(|file| parsed_report_path(file)).collect();
+    let hashes: Vec<&Ioc> = iocs.iter().filter(|ioc| ioc.kind == IocKind::Hash).collect();
+    let mut findings = vec![];
+    for path in files.iter().filter(|file| !parsed.contains(*file)) {
+        let file = path.strip_prefix(directory).unwrap_or(path).to_string_lossy().to_string();
+        trace!("Scanning {} for IOCs", file);
+        if !hashes.is_empty() {
+            let recorded = manifest.as_ref()
+                .and_then(|manifest| manifest.entries.iter().find(|entry| entry.file == file))
+                .map(|entry| vec![entry.sha256.clone(), entry.md5.clone()]);
+            let file_hashes = match recorded {
+                Some(recorded) => recorded,
+                None => hash_file(path).map(|it| vec![it.sha256, it.md5])?,
+            };
+            for ioc in hashes.iter().filter(|ioc| file_hashes.iter().any(|hash| hash.eq_ignore_ascii_case(&ioc.value))) {
+                findings.push(Finding {
+                    ioc: ioc.value.clone(),
+                    kind: ioc.kind,
+                    file: file.clone(),
+                    entry: None,
+                    line: None,
+                    context: "hash of the file".to_string(),
+                });
+            }
+        }
+        let result = if file.ends_with(".tar.gz") {
+            scan_archive(path, iocs, &file, &mut findings)
+        } else if std::fs::metadata(path)?.len() <= MAX_SCANNED_SIZE {
+            std::fs::read(path).map(|content| if let Some(text) = decode(&content) {
+                scan_text(&text, iocs, &file, None, &mut findings);
+            })
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            warn!("Cannot scan {} for IOCs due to {}", file, err);
+        }
+    }
+    Ok(findings)
+}
+
+// Scans the case directory for the IOCs.
+pub fn scan_case(case_directory: &Path, iocs: &[Ioc]) -> io::Result<Vec<Finding>> {
+    info!("Scanning acquired evidence for {} IOCs", iocs.len());
+    let findings = scan_directory(case_directory, iocs)?;
+    for finding in findings.iter() {
+        warn!("IOC {} found in {}{}{}",
+              finding.ioc,
+              finding.file,
+              finding.entry.as_ref().map(|it| format!(":{}", it)).unwrap_or_default(),
+              finding.line.map(|it| format!(" on line {}", it)).unwrap_or_default()
+        );
+    }
+    Ok(findings)
+}
+
+// Writes the IOC and YARA findings into `ioc-findings.json` of the case directory.
+pub fn write_findings(case_directory: &Path, findings: &[Finding]) -> io::Result<PathBuf> {
+    let path = case_directory.join(IOC_FINDINGS_JSON);
+    serde_json::to_writer_pretty(File::create(&path)?, findings)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ioc::{Ioc, IocKind, scan_directory, decode, Finding};
+    use crate::hash::hash_bytes;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn case_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("gargamel-ioc-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn found(findings: &[Finding]) -> Vec<(String, String, Option<usize>)> {
+        findings.iter()
+            .map(|it| (it.ioc.clone(), it.file.replace('\\', "/"), it.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_ioc_kinds_are_parsed_or_guessed() {
+        let iocs: Vec<Ioc> = "# threat intel
+203.0.113.9
+evil.example.com
+
+HKLM\\Software\\Microsoft\\Windows\\CurrentVersion\\Run\\Updater
+process:mimikatz.exe
+dropper.dll
+4e4b5f47ff8dc92f3d387481bcf0c49b2f86a3353313b4f17b3ab5cdc34a58a1
+domain:2001:db8::1
+".lines().filter_map(Ioc::parse).collect();
+
+        assert_eq!(vec![
+            IocKind::Ip,
+            IocKind::Domain,
+            IocKind::RegistryKey,
+            IocKind::Process,
+            IocKind::Process,
+            IocKind::Hash,
+            IocKind::Domain,
+        ], iocs.iter().map(|it| it.kind).collect::<Vec<_>>());
+        assert_eq!("mimikatz.exe", iocs[3].value);
+        assert_eq!("2001:db8::1", iocs[6].value);
+    }
+
+    #[test]
+    fn test_match_is_bounded() {
+        let ip = Ioc::new(IocKind::Ip, "10.0.0.1");
+        assert!(ip.matches("10.0.0.1:445"));
+        assert!(!ip.matches("10.0.0.12:445"));
+        assert!(!ip.matches("110.0.0.1"));
+        let domain = Ioc::new(IocKind::Domain, "evil.example.com");
+        assert!(domain.matches("query www.evil.example.com."));
+        assert!(!domain.matches("notevil.example.com"));
+        assert!(!domain.matches("evil.example.com.attacker.net"));
+    }
+
+    #[test]
+    fn test_registry_root_abbreviations_match() {
+        let short = Ioc::new(IocKind::RegistryKey, "HKLM\\Software\\Run");
+        let long = Ioc::new(IocKind::RegistryKey, "HKEY_LOCAL_MACHINE\\Software\\Run");
+        for ioc in [short, long].iter() {
+            assert!(ioc.matches("[hkey_local_machine\\software\\run]"));
+            assert!(ioc.matches("hklm\\software\\run"));
+            assert!(!ioc.matches("[hkey_local_machine\\software\\runonce]"));
+        }
+    }
+
+    #[test]
+    fn test_binary_content_is_not_decoded() {
+        assert_eq!(None, decode(b"ElfFile\0\0\0"));
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend("evil".encode_utf16().flat_map(|it| it.to_le_bytes().to_vec()));
+        assert_eq!(Some("evil".to_string()), decode(&utf16));
+    }
+
+    #[test]
+    fn test_iocs_are_found_in_reports_registry_exports_and_files() {
+        let iocs: Vec<Ioc> = "203.0.113.9
+evil.example.com
+HKLM\\Software\\Microsoft\\Windows\\CurrentVersion\\Run\\Updater
+process:mimikatz.exe
+".lines().filter_map(Ioc::parse).chain(vec![Ioc::new(IocKind::Hash, &hash_bytes(b"dropper").sha256)]).collect();
+        let directory = case_directory();
+        fs::create_dir_all(directory.join("10.0.0.1")).unwrap();
+        fs::write(directory.join("10.0.0.1").join("netstat.txt"), "
+  TCP    10.0.0.1:50000         203.0.113.9:443        ESTABLISHED     848
+  TCP    10.0.0.1:50001         203.0.113.95:443       ESTABLISHED     848
+  DNS    notevil.example.com evil.example.com.attacker.net
+  DNS    www.evil.example.com
+").unwrap();
+        let registry = "Windows Registry Editor Version 5.00\r\n\r\n\
+            [HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Run\\Updater]\r\n\
+            [HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Run\\UpdaterService]\r\n\
+            \"Mimikatz.exe\"=\"C:\\\\Temp\\\\mimikatz.exe.bak\"\r\n";
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend(registry.encode_utf16().flat_map(|it| it.to_le_bytes().to_vec()));
+        fs::write(directory.join("registry.reg"), utf16).unwrap();
+        fs::write(directory.join("dropper.bin"), b"dropper").unwrap();
+
+        let findings = scan_directory(&directory, &iocs).unwrap();
+
+        assert_eq!(vec![
+            ("203.0.113.9".to_string(), "10.0.0.1/netstat.txt".to_string(), Some(2)),
+            ("evil.example.com".to_string(), "10.0.0.1/netstat.txt".to_string(), Some(5)),
+            (hash_bytes(b"dropper").sha256, "dropper.bin".to_string(), None),
+            ("HKLM\\Software\\Microsoft\\Windows\\CurrentVersion\\Run\\Updater".to_string(), "registry.reg".to_string(), Some(3)),
+            ("mimikatz.exe".to_string(), "registry.reg".to_string(), Some(5)),
+        ], found(&findings));
+    }
+
+    #[test]
+    fn test_iocs_are_found_in_archive_entries() {
+        let directory = case_directory();
+        let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(vec![], flate2::Compression::default()));
+        let content = b"root 812 /tmp/.x/mimikatz.exe\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, "proc/cmdline", &content[..]).unwrap();
+        fs::write(directory.join("files.tar.gz"), archive.into_inner().unwrap().finish().unwrap()).unwrap();
+
+        let findings = scan_directory(&directory, &[Ioc::new(IocKind::Process, "mimikatz.exe")]).unwrap();
+
+        assert_eq!(vec![("mimikatz.exe".to_string(), "files.tar.gz".to_string(), Some(1))], found(&findings));
+        assert_eq!(Some("proc/cmdline".to_string()), findings[0].entry);
+    }
+}
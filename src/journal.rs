@@ -0,0 +1,293 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::io;
+use std::fs::File;
+use std::io::BufReader;
+use serde::{Serialize, Deserialize};
+use chrono::Utc;
+use crate::remote::Computer;
+use crate::manifest::timestamp;
+use crate::process_runner::CommandOutcome;
+
+pub const JOURNAL_JSON: &str = "journal.json";
+
+static JOURNAL: Mutex<Option<Journal>> = Mutex::new(None);
+
+// Progress of one acquisition step. A step is repeated from the start unless it got at least
+// to `CommandRun`, after which only the transfer of its remote file is resumed. A `Failed` step
+// kept its remote file on the target because the transfer did not succeed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StepState {
+    Started,
+    CommandRun,
+    Downloaded,
+    Completed,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JournalStep {
+    pub computer: String,
+    pub method: String,
+    pub name: String,
+    pub state: StepState,
+    pub command: Option<String>,
+    pub remote_file: Option<String>,
+    pub local_file: Option<String>,
+    #[serde(default)]
+    pub downloaded_parts: Vec<usize>,
+    #[serde(default)]
+    pub deleted_parts: Vec<usize>,
+    pub updated: String,
+}
+
+impl JournalStep {
+    fn is(&self, key: &StepKey) -> bool {
+        self.computer == key.computer && self.method == key.method && self.name == key.name
+    }
+
+    // The first 7zip part which is still present on the remote computer.
+    pub fn next_part(&self) -> usize {
+        self.deleted_parts.iter().max().map(|part| part + 1).unwrap_or(1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepKey {
+    pub computer: String,
+    pub method: String,
+    pub name: String,
+}
+
+impl StepKey {
+    pub fn new(computer: &Computer, method: &str, name: &str) -> StepKey {
+        StepKey {
+            computer: computer.address.clone(),
+            method: method.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Journal {
+    #[serde(skip)]
+    path: PathBuf,
+    pub steps: Vec<JournalStep>,
+}
+
+impl Journal {
+    fn open(directory: &Path, resume: bool) -> io::Result<Journal> {
+        let path = directory.join(JOURNAL_JSON);
+        if resume && path.exists() {
+            let existing: Journal = serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+            return Ok(Journal { path, ..existing });
+        }
+        Ok(Journal { path, steps: vec![] })
+    }
+
+    fn step_mut(&mut self, key: &StepKey) -> &mut JournalStep {
+        let index = match self.steps.iter().position(|step| step.is(key)) {
+            Some(index) => index,
+            None => {
+                self.steps.push(JournalStep {
+                    computer: key.computer.clone(),
+                    method: key.method.clone(),
+                    name: key.name.clone(),
+                    state: StepState::Started,
+                    command: None,
+                    remote_file: None,
+                    local_file: None,
+                    downloaded_parts: vec![],
+                    deleted_parts: vec![],
+                    updated: timestamp(Utc::now()),
+                });
+                self.steps.len() - 1
+            }
+        };
+        &mut self.steps[index]
+    }
+
+    // Parts of a split archive are named after the remote file they were created from.
+    fn step_of_part(&mut self, part: &Path) -> Option<&mut JournalStep> {
+        let part = part.to_string_lossy();
+        self.steps.iter_mut().find(|step| match &step.remote_file {
+            None => false,
+            Some(remote_file) => part.starts_with(remote_file.as_str()),
+        })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let temp = self.path.with_file_name(format!("{}.tmp", JOURNAL_JSON));
+        {
+            let mut file = File::create(&temp)?;
+            serde_json::to_writer_pretty(&mut file, self)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&temp, &self.path)
+    }
+}
+
+// Starts a new journal in the case directory, or continues the existing one when resuming.
+pub fn start_journal(case_directory: &Path, resume: bool) -> io::Result<()> {
+    let journal = Journal::open(case_directory, resume)?;
+    if resume {
+        let unfinished = journal.steps.iter().filter(|step| step.state != StepState::Completed).count();
+        info!("Resuming acquisition, {} of {} journaled steps are unfinished", unfinished, journal.steps.len());
+    }
+    journal.save()?;
+    *JOURNAL.lock().unwrap() = Some(journal);
+    Ok(())
+}
+
+pub fn journaled_step(key: &StepKey) -> Option<JournalStep> {
+    JOURNAL.lock().unwrap().as_ref()
+        .and_then(|journal| journal.steps.iter().find(|step| step.is(key)).cloned())
+}
+
+// Outcome of a step completed by an earlier run, which is therefore not repeated.
+pub fn skipped_step(key: &StepKey) -> Option<CommandOutcome> {
+    let step = journaled_step(key).filter(|step| step.state == StepState::Completed)?;
+    info!("{}: {} already acquired, skipping", step.method, step.name);
+    Some(CommandOutcome {
+        exit_code: Some(0),
+        report_path: step.local_file.map(PathBuf::from),
+        ..Default::default()
+    })
+}
+
+fn update<F>(update: F) where F: FnOnce(&mut Journal) {
+    let mut journal = JOURNAL.lock().unwrap();
+    if let Some(journal) = journal.as_mut() {
+        update(journal);
+        if let Err(err) = journal.save() {
+            error!("Cannot save journal {} due to {}", journal.path.display(), err);
+        }
+    }
+}
+
+pub fn start_step(key: &StepKey, command: &[String], remote_file: Option<&Path>) {
+    update(|journal| {
+        let step = journal.step_mut(key);
+        step.state = StepState::Started;
+        step.command = Some(command.join(" "));
+        step.remote_file = remote_file.map(|it| it.to_string_lossy().to_string());
+        step.downloaded_parts.clear();
+        step.deleted_parts.clear();
+        step.updated = timestamp(Utc::now());
+    })
+}
+
+pub fn advance_step(key: &StepKey, state: StepState) {
+    update(|journal| {
+        let step = journal.step_mut(key);
+        step.state = state;
+        step.updated = timestamp(Utc::now());
+    })
+}
+
+pub fn fail_step(key: &StepKey) {
+    advance_step(key, StepState::Failed)
+}
+
+pub fn complete_step(key: &StepKey, local_file: Option<&Path>) {
+    update(|journal| {
+        let step = journal.step_mut(key);
+        step.state = StepState::Completed;
+        step.local_file = local_file.map(|it| it.to_string_lossy().to_string());
+        step.updated = timestamp(Utc::now());
+    })
+}
+
+fn part_number(part: &Path) -> Option<usize> {
+    part.extension().and_then(|extension| extension.to_string_lossy().parse().ok())
+}
+
+pub fn record_part_downloaded(part: &Path) {
+    if let Some(number) = part_number(part) {
+        update(|journal| if let Some(step) = journal.step_of_part(part) {
+            step.downloaded_parts.push(number);
+        })
+    }
+}
+
+pub fn record_part_deleted(part: &Path) {
+    if let Some(number) = part_number(part) {
+        update(|journal| if let Some(step) = journal.step_of_part(part) {
+            step.deleted_parts.push(number);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journal::{Journal, StepKey, StepState, JOURNAL_JSON};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn key(name: &str) -> StepKey {
+        StepKey {
+            computer: "10.0.0.1".to_string(),
+            method: "PsExec".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn case_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("gargamel-journal-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_step_is_created_once() {
+        let mut journal = Journal::default();
+
+        journal.step_mut(&key("mem-image")).state = StepState::CommandRun;
+        journal.step_mut(&key("registry"));
+
+        assert_eq!(2, journal.steps.len());
+        assert_eq!(StepState::CommandRun, journal.step_mut(&key("mem-image")).state);
+        assert_eq!(StepState::Started, journal.steps[1].state);
+    }
+
+    #[test]
+    fn test_parts_are_assigned_to_step_of_remote_file() {
+        let mut journal = Journal::default();
+        journal.step_mut(&key("mem-image")).remote_file = Some("C:\\Users\\Public\\mem-image.aff4".to_string());
+        journal.step_mut(&key("mem-image")).state = StepState::CommandRun;
+        assert_eq!(1, journal.steps[0].next_part());
+
+        journal.step_of_part(Path::new("C:\\Users\\Public\\mem-image.aff4.7z.002")).unwrap().deleted_parts.push(2);
+
+        assert!(journal.step_of_part(Path::new("C:\\Users\\Public\\registry.txt.7z.001")).is_none());
+        assert_eq!(1, journal.steps.len());
+        assert_eq!(3, journal.steps[0].next_part());
+    }
+
+    #[test]
+    fn test_journal_is_continued_only_when_resuming() {
+        let directory = case_directory();
+        let mut journal = Journal::open(&directory, false).unwrap();
+        journal.step_mut(&key("mem-image")).state = StepState::Failed;
+        journal.save().unwrap();
+
+        let resumed = Journal::open(&directory, true).unwrap();
+        let restarted = Journal::open(&directory, false).unwrap();
+
+        assert_eq!(StepState::Failed, resumed.steps[0].state);
+        assert!(restarted.steps.is_empty());
+        assert!(!directory.join(format!("{}.tmp", JOURNAL_JSON)).exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_journal_is_not_resumed() {
+        let directory = case_directory();
+        fs::write(directory.join(JOURNAL_JSON), "{\"steps\": [").unwrap();
+
+        assert!(Journal::open(&directory, true).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,8 +1,12 @@
-use crate::process_runner::create_report_path;
-use std::path::Path;
-use crate::remote::{Connector, Compression, CompressCopier, RemoteFileCopier, Command};
+use crate::process_runner::{create_report_path, CommandOutcome};
+use std::path::{Path, PathBuf};
+use crate::remote::{Connector, Compression, CompressCopier, RemoteFileCopier, Command, ReDownloader, download_verified, wait_until_ready, delete_when_released};
 use std::time::Duration;
-use std::thread;
+use std::io;
+use std::io::ErrorKind;
+use crate::manifest::{Provenance, record_file};
+use crate::cleanup::{register_remote_file, retain_remote_file};
+use crate::journal::{StepKey, StepState, JournalStep, journaled_step, skipped_step, start_step, advance_step, complete_step, fail_step};
 
 pub(crate) struct LargeEvidenceAcquirer<'a> {
     pub(crate) store_directory: &'a Path,
@@ -10,7 +14,10 @@ pub(crate) struct LargeEvidenceAcquirer<'a> {
     pub(crate) compress_timeout: Option<Duration>,
     pub(crate) compression: Compression,
     pub(crate) report_extension: &'a str,
-    pub(crate) overwrite_switch: Option<&'a str>
+    // appended after the remote file path, like an overwrite switch
+    pub(crate) switches: Vec<String>,
+    pub(crate) elevated: bool,
+    pub(crate) command_timeout: Option<Duration>,
 }
 
 impl<'a> LargeEvidenceAcquirer<'a> {
@@ -18,9 +25,25 @@ impl<'a> LargeEvidenceAcquirer<'a> {
         &self,
         command: &[String],
         report_filename_prefix: &str
-    ) {
+    ) -> io::Result<CommandOutcome> {
         if command.is_empty() {
-            return;
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Empty command to acquire {}", report_filename_prefix),
+            ));
+        }
+        let key = StepKey::new(self.connector.computer(), self.connector.connect_method_name(), report_filename_prefix);
+        if let Some(outcome) = skipped_step(&key) {
+            return Ok(outcome);
+        }
+        if let Some(step) = journaled_step(&key) {
+            if step.state != StepState::Started && step.remote_file.is_some() {
+                // the remote file may have been deleted by the cleanup of the interrupted run
+                match resume_transfer(self.connector, self.store_directory, &key, &step) {
+                    Ok(outcome) => return Ok(outcome),
+                    Err(err) => warn!("{}, acquiring {} again", err, report_filename_prefix),
+                }
+            }
         }
         let report_path = create_report_path(
             self.connector.computer(),
@@ -36,14 +59,16 @@ impl<'a> LargeEvidenceAcquirer<'a> {
             .to_string();
         let mut command = command.to_vec();
         command.push(remote_report_path.clone());
-        if let Some(overwrite) = self.overwrite_switch {
-            command.push(overwrite.to_string());
-        }
+        command.extend(self.switches.iter().cloned());
+        let provenance = Provenance::new(self.connector.computer(), self.connector.connect_method_name())
+            .command(&command);
+        start_step(&key, &command, Some(Path::new(&remote_report_path)));
+        register_remote_file(self.connector.computer(), Path::new(&remote_report_path));
         let remote_connection = Command::new(
             command,
             None,
             report_filename_prefix,
-            false,
+            self.elevated,
         );
 
         info!("{}: Checking {}",
@@ -51,8 +76,18 @@ impl<'a> LargeEvidenceAcquirer<'a> {
               report_filename_prefix.replace("-", " ")
         );
 
-        match self.connector.connect_and_run_command(remote_connection, None) {
-            Ok(_) => {}
+        let outcome = self.connector.connect_and_run_command(remote_connection, self.command_timeout);
+        match &outcome {
+            Ok(outcome) => {
+                if !outcome.is_success() {
+                    warn!(
+                        "Command to acquire {} finished with exit code {:?}{}",
+                        report_filename_prefix,
+                        outcome.exit_code,
+                        if outcome.timed_out { " (timed out)" } else { "" }
+                    )
+                }
+            }
             Err(err) => {
                 error!(
                     "Error running command to acquire {}. Cause: {}",
@@ -61,37 +96,101 @@ impl<'a> LargeEvidenceAcquirer<'a> {
                 )
             }
         }
-        thread::sleep(Duration::from_millis(10_000));
+        // a failed export leaves no report behind, there is nothing to wait for
+        let failed = match &outcome {
+            Ok(outcome) => outcome.exit_code.map(|code| code != 0).unwrap_or(false),
+            Err(_) => true,
+        };
+        if !failed {
+            advance_step(&key, StepState::CommandRun);
+            if let Err(err) = wait_until_ready(self.connector.copier(), Path::new(&remote_report_path), Duration::from_millis(10_000)) {
+                warn!("{}", err);
+            }
+        }
 
-        let _compression_split_copier = CompressCopier::new(self.connector, true, self.compress_timeout.clone());
-        let _compression_copier = CompressCopier::new(self.connector, false, self.compress_timeout.clone());
+        let _compression_split_copier = CompressCopier::new(self.connector, true, self.compress_timeout);
+        let _compression_copier = CompressCopier::new(self.connector, false, self.compress_timeout);
         let copier = match self.compression {
             Compression::No => self.connector.copier(),
             Compression::Yes => &_compression_copier as &dyn RemoteFileCopier,
             Compression::YesSplit => &_compression_split_copier as &dyn RemoteFileCopier,
         };
 
-        match copier.copy_from_remote(Path::new(&remote_report_path), report_path.parent().unwrap()) {
-            Ok(_) => {}
+        let download_result = match self.compression {
+            Compression::No => download_verified(
+                self.connector,
+                Path::new(&remote_report_path),
+                report_path.parent().unwrap(),
+            ),
+            _ => copier.copy_from_remote(Path::new(&remote_report_path), report_path.parent().unwrap()),
+        };
+        match download_result {
+            Ok(_) => {
+                record_file(&report_path, &provenance.source(Path::new(&remote_report_path)));
+                advance_step(&key, StepState::Downloaded);
+            }
             Err(err) => {
                 error!("Cannot download {} report from {} using method {} due to {}",
                        report_filename_prefix,
                        self.connector.computer().address,
                        self.connector.connect_method_name(),
                        err
-                )
+                );
+                if failed {
+                    return outcome.map(|outcome| outcome.with_report_path(Some(report_path)));
+                }
+                // the remote report is kept, --resume or --redownload can transfer it again
+                retain_remote_file(self.connector.computer(), Path::new(&remote_report_path));
+                fail_step(&key);
+                return Err(err);
             }
         }
-        thread::sleep(Duration::from_secs(2));
-        match copier.delete_remote_file(Path::new(&remote_report_path)) {
-            Ok(_) => {}
-            Err(err) => {
-                error!("Cannot delete remote file {} using method {} due to: {}",
-                       report_filename_prefix,
-                       self.connector.connect_method_name(),
-                       err
-                )
-            }
+        // the local copy completes the step, a remote file left behind stays in the cleanup registry
+        complete_step(&key, Some(&report_path));
+        if let Err(err) = delete_when_released(copier, Path::new(&remote_report_path), Duration::from_secs(2)) {
+            error!("Cannot delete remote file {} using method {} due to: {}",
+                   report_filename_prefix,
+                   self.connector.connect_method_name(),
+                   err
+            )
+        }
+        outcome.map(|outcome| outcome.with_report_path(Some(report_path)))
+    }
+}
+
+// Finishes a step interrupted after its command created the remote file.
+pub(crate) fn resume_transfer(
+    connector: &dyn Connector,
+    store_directory: &Path,
+    key: &StepKey,
+    step: &JournalStep,
+) -> io::Result<CommandOutcome> {
+    let remote_file = PathBuf::from(step.remote_file.as_ref().unwrap());
+    let copier = connector.copier();
+    if step.state == StepState::CommandRun || step.state == StepState::Failed {
+        info!("{}: Resuming download of {}", connector.connect_method_name(), remote_file.display());
+        let re_downloader = ReDownloader {
+            copier,
+            target_dir: store_directory,
+        };
+        if !re_downloader.resume(&remote_file, step.next_part()) {
+            return Err(io::Error::other(
+                format!("Cannot resume download of {}", remote_file.display()),
+            ));
         }
+        advance_step(key, StepState::Downloaded);
     }
-}
\ No newline at end of file
+    let archive = PathBuf::from(format!("{}.7z", remote_file.display()));
+    for leftover in [&remote_file, &archive].iter() {
+        if let Err(err) = delete_when_released(copier, leftover, Duration::from_secs(2)) {
+            debug!("{}", err);
+        }
+    }
+    let report_path = store_directory.join(remote_file.file_name().unwrap());
+    complete_step(key, Some(&report_path));
+    Ok(CommandOutcome {
+        exit_code: Some(0),
+        report_path: Some(report_path),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,35 @@
+#[macro_use]
+extern crate log;
+
+pub mod process_runner;
+pub mod evidence_acquirer;
+pub mod remote;
+pub mod memory_acquirer;
+mod command_utils;
+mod utils;
+mod large_evidence_acquirer;
+pub mod events_acquirer;
+pub mod event_timeline;
+pub mod timeline;
+pub mod file_acquirer;
+pub mod registry_acquirer;
+pub mod hive_acquirer;
+pub mod hive;
+pub mod command_runner;
+pub mod hash;
+pub mod manifest;
+pub mod journal;
+pub mod cleanup;
+pub mod container;
+pub mod profile;
+pub mod parsers;
+pub mod correlation;
+pub mod ioc;
+pub mod yara_scanner;
+pub mod case_analysis;
+mod artifact_acquirer;
+pub mod plan;
+
+pub use crate::remote::{Computer, Connector, RemoteFileCopier};
+pub use crate::plan::{AcquisitionPlan, AcquisitionReport, Method, Stage, StageResult, run_plan};
+pub use crate::case_analysis::CaseAnalysis;
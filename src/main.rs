@@ -4,51 +4,118 @@ use std::io;
 use simplelog::{CombinedLogger, TermLogger, WriteLogger, Config, TerminalMode, LevelFilter};
 use std::fs::{File, create_dir_all};
 use crate::logo::print_logo;
-use crate::arg_parser::Opts;
+use crate::arg_parser::{Opts, UnpackOpts, ReportOpts, HiveOpts, UNPACK_COMMAND, REPORT_COMMAND, HIVE_COMMAND};
 
 #[macro_use]
 extern crate log;
 extern crate simplelog;
 
-use clap::derive::Clap;
-use crate::evidence_acquirer::EvidenceAcquirer;
+use clap::Clap;
 use std::path::{Path, PathBuf};
-use crate::remote::{Computer, Cmd, Powershell, WindowsRemoteFileHandler, Rdp, Wmi, Ssh, RemoteFileCopier, ReDownloader};
-use crate::memory_acquirer::MemoryAcquirer;
-use crate::command_runner::CommandRunner;
-use crate::file_acquirer::download_files;
 use rpassword::read_password;
-use crate::registry_acquirer::RegistryAcquirer;
 use std::time::Duration;
-use crate::events_acquirer::EventsAcquirer;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+use gargamel::manifest;
+use gargamel::journal;
+use gargamel::cleanup;
+use gargamel::container;
+use gargamel::container::EncryptionKey;
+use gargamel::correlation;
+use gargamel::hive;
+use gargamel::ioc;
+use gargamel::CaseAnalysis;
+use gargamel::manifest::default_operator;
+use gargamel::plan::{run_plan, clean_up_plan, clean_up_active_plans, cancel_plan};
+use gargamel::profile::Profile;
+use gargamel::remote::integrity;
+use crate::inventory::{load_inventory, log_hosts_summary};
+use crate::host_logger::HostPrefixLogger;
+use crate::worker_pool::{run_jobs, Job};
 
-mod process_runner;
-mod evidence_acquirer;
-mod remote;
 mod arg_parser;
 mod logo;
-mod memory_acquirer;
-mod command_utils;
-mod utils;
-mod large_evidence_acquirer;
-mod events_acquirer;
-mod file_acquirer;
-mod registry_acquirer;
-mod command_runner;
+mod inventory;
+mod host_logger;
+mod worker_pool;
 
 fn setup_logger() {
-    CombinedLogger::init(
-        vec![
-            TermLogger::new(LevelFilter::Info, Config::default(), TerminalMode::Mixed).unwrap(),
-            WriteLogger::new(LevelFilter::Info, Config::default(), File::create("gargamel.log").unwrap()),
-        ]
+    HostPrefixLogger::init(
+        CombinedLogger::new(
+            vec![
+                TermLogger::new(LevelFilter::Info, Config::default(), TerminalMode::Mixed),
+                WriteLogger::new(LevelFilter::Info, Config::default(), File::create("gargamel.log").unwrap()),
+            ]
+        ),
+        LevelFilter::Info,
     ).unwrap();
 }
 
+fn read_passphrase() -> String {
+    println!("Evidence passphrase: ");
+    read_password().unwrap_or_default()
+}
+
+fn unpack(opts: &UnpackOpts) -> Result<(), io::Error> {
+    let key = opts.decryption_key(read_passphrase);
+    let verification = container::unpack(Path::new(&opts.container), &key, Path::new(&opts.output))?;
+    info!("Unpacked {} into {}, {} files match the manifest, {} do not",
+          opts.container, opts.output, verification.verified, verification.failed.len());
+    if verification.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("Evidence in {} does not match its manifest", opts.container)))
+    }
+}
+
+fn report(opts: &ReportOpts) -> Result<(), io::Error> {
+    let evidence = correlation::load_evidence(Path::new(&opts.directory))?;
+    let report = correlation::correlate(&evidence, opts.rare_threshold);
+    let files = report.write(Path::new(&opts.output))?;
+    info!("Correlated {} hosts: {} rare processes, {} shared external addresses, {} listening ports",
+          report.hosts.len(), report.rare_processes.len(), report.shared_addresses.len(), report.listening_ports.len());
+    info!("Report is written to {}", files[0].display());
+    Ok(())
+}
+
+fn dump_hives(opts: &HiveOpts) -> Result<(), io::Error> {
+    let output_directory = opts.output.as_ref().map(Path::new);
+    if let Some(directory) = output_directory {
+        create_dir_all(directory)?;
+    }
+    let mut failed = 0;
+    for hive_path in opts.hives.iter().map(Path::new) {
+        let dump_path = hive::dump_path(hive_path, output_directory);
+        match hive::dump_hive(hive_path, &dump_path) {
+            Ok(count) => info!("Dumped {} keys of {} into {}", count, hive_path.display(), dump_path.display()),
+            Err(err) => {
+                error!("Cannot dump {} due to {}", hive_path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} of {} hives cannot be dumped", failed, opts.hives.len())))
+    }
+}
+
 fn main() -> Result<(), io::Error> {
     setup_logger();
     print_logo();
 
+    if std::env::args().nth(1).as_deref() == Some(UNPACK_COMMAND) {
+        return unpack(&UnpackOpts::parse_from(std::env::args().skip(1)));
+    }
+    if std::env::args().nth(1).as_deref() == Some(REPORT_COMMAND) {
+        return report(&ReportOpts::parse_from(std::env::args().skip(1)));
+    }
+    if std::env::args().nth(1).as_deref() == Some(HIVE_COMMAND) {
+        return dump_hives(&HiveOpts::parse_from(std::env::args().skip(1)));
+    }
+
     let opts: Opts = Opts::parse();
     create_dir_all(&opts.local_store_directory)?;
 
@@ -65,629 +132,132 @@ fn main() -> Result<(), io::Error> {
         }
     };
 
-    let remote_computer = Computer::from(opts.clone());
+    let encryption_key = opts.encryption_key(read_passphrase)?;
 
-    // let local_store_directory_owned = match Path::new(&opts.store_directory).can {
-    //     None => std::env::current_dir().unwrap().join(&opts.s),
-    //     Some(_) => {},
-    // };
     let local_store_directory_owned = dunce::canonicalize(Path::new(&opts.local_store_directory)).unwrap();
     let local_store_directory = local_store_directory_owned.as_path();
-    let remote_temp_storage = Path::new(&opts.remote_store_directory);
-    let key_file = opts.ssh_key.clone().map(|it| PathBuf::from(it));
-
-    if let Some(remote_file) = &opts.re_download {
-        let copiers = create_file_copiers(&opts, &remote_computer, remote_temp_storage);
-        let remote_file = Path::new(&remote_file);
-        for copier in copiers {
-            info!("Trying to download {} from {} using method {}", remote_file.display(), remote_computer.address, copier.method_name());
-            let re_downloader = ReDownloader {
-                copier: copier.as_ref(),
-                target_dir: local_store_directory,
-            };
-            re_downloader.retry_download(remote_file);
-        }
+    let operator = opts.operator.clone().unwrap_or_else(default_operator);
+    manifest::start_case(local_store_directory, &operator)?;
+    journal::start_journal(local_store_directory, opts.resume)?;
+    cleanup::start_cleanup_registry(local_store_directory)?;
+    if encryption_key.is_some() {
+        warn!("Acquired files stay unencrypted in {} until the case is finished and sealed", local_store_directory.display());
     }
-
-    if !opts.disable_evidence_download {
-        let evidence_acquirers = create_evidence_acquirers(
-            &remote_computer,
-            local_store_directory,
-            &opts,
-            key_file.as_ref().map(|it| it.to_path_buf()),
-            remote_temp_storage
-        );
-        for acquirer in evidence_acquirers {
-            acquirer.run_all();
-        }
+    let interrupted_case = (local_store_directory.to_path_buf(), encryption_key.clone());
+    if let Err(err) = ctrlc::set_handler(move || {
+        warn!("Interrupted, deleting files left on the targets");
+        clean_up_active_plans();
+        let (case_directory, encryption_key) = &interrupted_case;
+        if let Err(err) = finish_case(case_directory, encryption_key.as_ref()) {
+            error!("Cannot finish the case due to {}", err);
+        }
+        std::process::exit(130);
+    }) {
+        warn!("Cannot handle Ctrl-C, remote files will not be deleted when interrupted. Cause: {}", err);
     }
 
-    if !opts.disable_event_download {
-        let event_acquirers = create_events_acquirers(
-            &remote_computer,
-            local_store_directory,
-            &opts,
-            remote_temp_storage
-        );
-        for acquirer in event_acquirers {
-            acquirer.acquire();
+    // the evidence is sealed also when the acquisition fails or panics
+    let acquired = panic::catch_unwind(AssertUnwindSafe(|| acquire(&opts, local_store_directory)));
+    let finished = finish_case(local_store_directory, encryption_key.as_ref());
+    match acquired {
+        Ok(acquired) => acquired.and(finished),
+        Err(cause) => {
+            if let Err(err) = finished {
+                error!("Cannot finish the case due to {}", err);
+            }
+            panic::resume_unwind(cause)
         }
     }
+}
 
-    if let Some(custom_commands_path) = &opts.custom_command_path {
-        let command_runners = create_command_runners(
-            &remote_computer,
-            local_store_directory,
-            &opts,
-            key_file.as_ref().map(|it| it.to_path_buf()),
-            remote_temp_storage
-        );
-        for command_runner in command_runners {
-            info!("Running commands using method {}", command_runner.connector.connect_method_name());
-            command_runner.run_commands(
-                Path::new(custom_commands_path),
-                Some(Duration::from_secs(opts.timeout)),
-            );
-        }
+// Finishes the manifest and encrypts the case directory, only once even if Ctrl-C comes during the sealing.
+fn finish_case(case_directory: &Path, encryption_key: Option<&EncryptionKey>) -> Result<(), io::Error> {
+    static FINISHED: Mutex<bool> = Mutex::new(false);
+    let mut finished = FINISHED.lock().unwrap_or_else(|err| err.into_inner());
+    if *finished {
+        return Ok(());
     }
-    if !opts.disable_registry_download {
-        let registry_acquirers = create_registry_acquirers(
-            &remote_computer,
-            local_store_directory,
-            &opts,
-            remote_temp_storage
-        );
-        for acquirer in registry_acquirers {
-            acquirer.acquire();
-        }
-    }
-    if let Some(search_files_path) = &opts.search_files_path {
-        let search_files_path = Path::new(search_files_path);
-        if opts.ssh {
-            let remote_copier = Ssh {
-                computer: remote_computer.clone(),
-                key_file: key_file.as_ref().map(|it| it.clone()),
-            };
-            download_files(
-                search_files_path,
-                local_store_directory,
-                &remote_copier,
-            )?;
-        } else {
-            let copiers = create_file_copiers(&opts, &remote_computer, remote_temp_storage);
-            for copier in copiers.into_iter() {
-                info!("Downloading specified files using {}", copier.copier_impl().method_name());
-                let result = download_files(
-                    search_files_path,
-                    local_store_directory,
-                    copier.as_ref(),
-                );
-                if result.is_ok() {
-                    info!("Files in {} successfully transferred.", search_files_path.display());
-                    break;
-                }
-            }
-        }
-    }
-    if opts.image_memory {
-        let memory_acquirers = create_memory_acquirers(
-            &remote_computer,
-            local_store_directory,
-            &opts,
-            remote_temp_storage
-        );
-        for acquirer in memory_acquirers {
-            info!("Running memory acquirer using method {}", acquirer.connector.connect_method_name());
-            let image_res = acquirer.image_memory();
-            if image_res.is_ok() {
-                break;
-            }
-        }
+    *finished = true;
+    integrity::log_summary();
+    manifest::finish_case()?;
+    if let Some(key) = encryption_key {
+        let sealed = container::seal_case(case_directory, key)?;
+        info!("Evidence is encrypted into {}", sealed.display());
     }
-
     Ok(())
 }
 
-fn create_evidence_acquirers<'a>(
-    computer: &'a Computer,
-    local_store_directory: &'a Path,
-    opts: &Opts,
-    key_file: Option<PathBuf>,
-    remote_temp_storage: &Path,
-) -> Vec<EvidenceAcquirer<'a>> {
-    let acquirers: Vec<EvidenceAcquirer<'a>> = if opts.all {
-        vec![
-            EvidenceAcquirer::psexec(
-                computer.clone(),
-                local_store_directory,
-                remote_temp_storage.to_path_buf()
-            ),
-            EvidenceAcquirer::wmi(
-                computer.clone(),
-                local_store_directory,
-                remote_temp_storage.to_path_buf()
-            ),
-            EvidenceAcquirer::psremote(
-                computer.clone(),
-                local_store_directory,
-                remote_temp_storage.to_path_buf()
-            ),
-            EvidenceAcquirer::rdp(
-                computer.clone(),
-                local_store_directory,
-                opts.nla,
-                remote_temp_storage.to_path_buf()
-            ),
-        ]
-    } else {
-        let mut acquirers = Vec::<EvidenceAcquirer<'a>>::new();
-        if opts.psexec {
-            acquirers.push(
-                EvidenceAcquirer::psexec(
-                    computer.clone(),
-                    local_store_directory,
-                    remote_temp_storage.to_path_buf()
-                ),
-            );
-        }
-        if opts.wmi {
-            acquirers.push(
-                EvidenceAcquirer::wmi(
-                    computer.clone(),
-                    local_store_directory,
-                    remote_temp_storage.to_path_buf()
-                ),
-            );
+fn acquire(opts: &Opts, local_store_directory: &Path) -> Result<(), io::Error> {
+    let profile = match &opts.profile {
+        None => Profile::built_in(),
+        Some(profile_path) => {
+            info!("Using acquisition profile {}", profile_path);
+            Profile::load(Path::new(profile_path))?
         }
-        if opts.psrem {
-            acquirers.push(
-                EvidenceAcquirer::psremote(
-                    computer.clone(),
-                    local_store_directory,
-                    remote_temp_storage.to_path_buf()
-                )
-            );
-        }
-        // if opts.local {
-        //     acquirers.push(
-        //         EvidenceAcquirer::local(
-        //             local_store_directory,
-        //         )
-        //     )
-        // }
-        if opts.ssh {
-            acquirers.push(
-                EvidenceAcquirer::ssh(
-                    computer.clone(),
-                    local_store_directory,
-                    key_file,
-                )
-            )
-        }
-        if opts.rdp {
-            acquirers.push(
-                EvidenceAcquirer::rdp(
-                    computer.clone(),
-                    local_store_directory,
-                    opts.nla,
-                    remote_temp_storage.to_path_buf()
-                ),
-            )
-        }
-        acquirers
     };
-    acquirers
-}
-
-fn create_memory_acquirers<'a>(
-    computer: &'a Computer,
-    local_store_directory: &'a Path,
-    opts: &Opts,
-    remote_temp_storage: &Path
-) -> Vec<MemoryAcquirer<'a>> {
-    let acquirers: Vec<MemoryAcquirer<'a>> = if opts.all {
-        vec![
-            MemoryAcquirer::psexec(
-                computer.clone(),
-                local_store_directory,
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            MemoryAcquirer::psremote(
-                computer.clone(),
-                local_store_directory,
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            MemoryAcquirer::rdp(
-                computer.clone(),
-                local_store_directory,
-                opts.nla,
-                Duration::from_secs(opts.timeout),
-                Duration::from_secs(opts.timeout),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            MemoryAcquirer::wmi(
-                computer.clone(),
-                local_store_directory,
-                Duration::from_secs(opts.timeout),
-                Duration::from_secs(opts.timeout),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-        ]
-    } else {
-        let mut acquirers = Vec::<MemoryAcquirer>::new();
-        if opts.psexec {
-            acquirers.push(
-                MemoryAcquirer::psexec(
-                    computer.clone(),
-                    local_store_directory,
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                )
-            );
-        }
-        if opts.psrem {
-            acquirers.push(
-                MemoryAcquirer::psremote(
-                    computer.clone(),
-                    local_store_directory,
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                )
-            );
-        }
-        if opts.rdp {
-            acquirers.push(
-                MemoryAcquirer::rdp(
-                    computer.clone(),
-                    local_store_directory,
-                    opts.nla,
-                    Duration::from_secs(opts.timeout),
-                    Duration::from_secs(opts.timeout),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                )
-            );
-        }
-        if opts.wmi {
-            acquirers.push(
-                MemoryAcquirer::wmi(
-                    computer.clone(),
-                    local_store_directory,
-                    Duration::from_secs(opts.timeout),
-                    Duration::from_secs(opts.timeout),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                )
-            );
-        }
-        acquirers
-    };
-    acquirers
-}
-
-fn create_command_runners<'a>(
-    computer: &'a Computer,
-    local_store_directory: &'a Path,
-    opts: &Opts,
-    key_file: Option<PathBuf>,
-    remote_temp_storage: &Path
-) -> Vec<CommandRunner<'a>> {
-    let acquirers: Vec<CommandRunner<'a>> = if opts.all {
-        vec![
-            CommandRunner::psexec(
-                computer.clone(),
-                local_store_directory,
-                remote_temp_storage.to_path_buf()
-            ),
-            CommandRunner::psremote(
-                computer.clone(),
-                local_store_directory,
-                remote_temp_storage.to_path_buf()
-            ),
-            CommandRunner::rdp(
-                computer.clone(),
-                local_store_directory,
-                opts.nla,
-                remote_temp_storage.to_path_buf()
-            ),
-            CommandRunner::wmi(
-                computer.clone(),
-                local_store_directory,
-                remote_temp_storage.to_path_buf()
-            ),
-        ]
-    } else {
-        let mut acquirers = Vec::<CommandRunner>::new();
-        if opts.psexec {
-            acquirers.push(
-                CommandRunner::psexec(
-                    computer.clone(),
-                    local_store_directory,
-                    remote_temp_storage.to_path_buf()
-                )
-            );
-        }
-        if opts.psrem {
-            acquirers.push(
-                CommandRunner::psremote(
-                    computer.clone(),
-                    local_store_directory,
-                    remote_temp_storage.to_path_buf()
-                )
-            );
-        }
-        // if opts.local {
-        //     acquirers.push(
-        //         CommandRunner::local(
-        //             local_store_directory,
-        //         )
-        //     )
-        // }
-        if opts.ssh {
-            acquirers.push(
-                CommandRunner::ssh(
-                    computer.clone(),
-                    local_store_directory,
-                    key_file,
-                )
-            )
-        }
-        if opts.wmi {
-            acquirers.push(
-                CommandRunner::wmi(
-                    computer.clone(),
-                    local_store_directory,
-                    remote_temp_storage.to_path_buf()
-                )
-            )
-        }
-        if opts.rdp {
-            acquirers.push(
-                CommandRunner::rdp(
-                    computer.clone(),
-                    local_store_directory,
-                    opts.nla,
-                    remote_temp_storage.to_path_buf()
-                )
-            )
-        }
-        acquirers
+    let analysis = CaseAnalysis {
+        iocs: match &opts.ioc {
+            None => None,
+            Some(ioc_path) => Some(ioc::load_iocs(Path::new(ioc_path))?),
+        },
+        yara_rules: opts.yara.as_ref().map(PathBuf::from),
+        timeline: opts.timeline,
     };
-    acquirers
-}
+    analysis.start(local_store_directory)?;
 
-fn create_registry_acquirers<'a>(
-    computer: &'a Computer,
-    local_store_directory: &'a Path,
-    opts: &Opts,
-    remote_temp_storage: &Path
-) -> Vec<RegistryAcquirer<'a>> {
-    let acquirers: Vec<RegistryAcquirer<'a>> = if opts.all {
-        vec![
-            RegistryAcquirer::psexec(
-                local_store_directory,
-                computer.clone(),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            RegistryAcquirer::psremote(
-                local_store_directory,
-                computer.clone(),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            RegistryAcquirer::wmi(
-                local_store_directory,
-                computer.clone(),
-                Duration::from_secs(opts.timeout),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            RegistryAcquirer::rdp(
-                local_store_directory,
-                computer.clone(),
-                Duration::from_secs(opts.timeout),
-                opts.nla,
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-        ]
-    } else {
-        let mut acquirers = Vec::<RegistryAcquirer<'a>>::new();
-        if opts.psexec {
-            acquirers.push(
-                RegistryAcquirer::psexec(
-                    local_store_directory,
-                    computer.clone(),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
-            );
-        }
-        if opts.psrem {
-            acquirers.push(
-                RegistryAcquirer::psremote(
-                    local_store_directory,
-                    computer.clone(),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
-            );
-        }
-        if opts.wmi {
-            acquirers.push(
-                RegistryAcquirer::wmi(
-                    local_store_directory,
-                    computer.clone(),
-                    Duration::from_secs(opts.timeout),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
-            );
-        }
-        if opts.rdp {
-            acquirers.push(
-                RegistryAcquirer::rdp(
-                    local_store_directory,
-                    computer.clone(),
-                    Duration::from_secs(opts.timeout),
-                    opts.nla,
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
-            )
-        }
-        acquirers
-    };
-    acquirers
-}
+    if opts.cleanup_only {
+        let left = match &opts.inventory {
+            None => clean_up_plan(&opts.acquisition_plan(local_store_directory, &profile)),
+            Some(inventory_path) => {
+                let mut left = 0;
+                for host in load_inventory(Path::new(inventory_path))? {
+                    left += clean_up_plan(&host.apply(opts)?.acquisition_plan(local_store_directory, &profile));
+                }
+                left
+            }
+        };
+        info!("Cleanup finished, {} remote files could not be deleted", left);
+        return Ok(());
+    }
 
-fn create_events_acquirers<'a>(
-    computer: &'a Computer,
-    local_store_directory: &'a Path,
-    opts: &Opts,
-    remote_temp_storage: &Path
-) -> Vec<EventsAcquirer<'a>> {
-    let acquirers: Vec<EventsAcquirer<'a>> = if opts.all {
-        vec![
-            EventsAcquirer::psexec(
-                local_store_directory,
-                computer.clone(),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            EventsAcquirer::psremote(
-                local_store_directory,
-                computer.clone(),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            EventsAcquirer::wmi(
-                local_store_directory,
-                computer.clone(),
-                Duration::from_secs(opts.timeout),
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-            EventsAcquirer::rdp(
-                local_store_directory,
-                computer.clone(),
+    match &opts.inventory {
+        None => {
+            let report = run_plan(&opts.acquisition_plan(local_store_directory, &profile));
+            info!("Acquisition finished, {} operations succeeded, {} failed", report.succeeded(), report.failed());
+        }
+        Some(inventory_path) => {
+            let hosts = load_inventory(Path::new(inventory_path))?;
+            info!("Acquiring evidence from {} hosts listed in {} using {} workers", hosts.len(), inventory_path, opts.jobs);
+            let jobs = hosts.into_iter()
+                .map(|host| Job { name: host.address.clone(), input: host })
+                .collect();
+            let shared_opts = opts.clone();
+            let store_directory = local_store_directory.to_path_buf();
+            let reports = run_jobs(
+                jobs,
+                opts.jobs,
+                opts.host_timeout.map(Duration::from_secs),
+                // a cancelled host stops after its current operation, which is bounded by the timeout
                 Duration::from_secs(opts.timeout),
-                opts.nla,
-                opts.no_compression,
-                remote_temp_storage.to_path_buf()
-            ),
-        ]
-    } else {
-        let mut acquirers = Vec::<EventsAcquirer<'a>>::new();
-        if opts.psexec {
-            acquirers.push(
-                EventsAcquirer::psexec(
-                    local_store_directory,
-                    computer.clone(),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
-            );
-        }
-        if opts.psrem {
-            acquirers.push(
-                EventsAcquirer::psremote(
-                    local_store_directory,
-                    computer.clone(),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
+                move |host| {
+                    let host_opts = host.apply(&shared_opts)?;
+                    let host_directory = store_directory.join(host.directory_name());
+                    create_dir_all(&host_directory)?;
+                    info!("Acquiring evidence into {}", host_directory.display());
+                    Ok(run_plan(&host_opts.acquisition_plan(&host_directory, &profile)))
+                },
+                cancel_plan,
             );
+            for (address, report) in reports.iter() {
+                if let Err(err) = report {
+                    if err.kind() == io::ErrorKind::TimedOut {
+                        manifest::record_incomplete(address, &err.to_string());
+                    }
+                }
+            }
+            log_hosts_summary(&reports);
         }
-        if opts.wmi {
-            acquirers.push(
-                EventsAcquirer::wmi(
-                    local_store_directory,
-                    computer.clone(),
-                    Duration::from_secs(opts.timeout),
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
-            );
-        }
-        if opts.rdp {
-            acquirers.push(
-                EventsAcquirer::rdp(
-                    local_store_directory,
-                    computer.clone(),
-                    Duration::from_secs(opts.timeout),
-                    opts.nla,
-                    opts.no_compression,
-                    remote_temp_storage.to_path_buf()
-                ),
-            )
-        }
-        acquirers
-    };
-    acquirers
-}
-
+    }
 
-fn create_file_copiers(opts: &Opts, computer: &Computer, remote_temp_storage: &Path) -> Vec<Box<dyn RemoteFileCopier>> {
-    let copiers: Vec<Box<dyn RemoteFileCopier>> = if opts.all {
-        vec![
-            Box::new(WindowsRemoteFileHandler::new(
-                computer.clone(),
-                Box::new(Cmd {}),
-            )),
-            Box::new(WindowsRemoteFileHandler::new(
-                computer.clone(),
-                Box::new(Powershell {}),
-            )),
-            Box::new(Rdp {
-                computer: computer.clone(),
-                nla: opts.nla,
-                remote_temp_storage: remote_temp_storage.to_path_buf()
-            }),
-            Box::new(Wmi {
-                computer: computer.clone(),
-                remote_temp_storage: remote_temp_storage.to_path_buf()
-            }),
-        ]
-    } else {
-        let mut copiers = Vec::<Box<dyn RemoteFileCopier>>::new();
-        if opts.psexec {
-            copiers.push(
-                Box::new(WindowsRemoteFileHandler::new(
-                    computer.clone(),
-                    Box::new(Cmd {}),
-                ))
-            );
-        }
-        if opts.psrem {
-            copiers.push(
-                Box::new(WindowsRemoteFileHandler::new(
-                    computer.clone(),
-                    Box::new(Powershell {}),
-                ))
-            );
-        }
-        if opts.rdp {
-            copiers.push(
-                Box::new(Rdp {
-                    computer: computer.clone(),
-                    nla: opts.nla,
-                    remote_temp_storage: remote_temp_storage.to_path_buf()
-                })
-            );
-        }
-        if opts.wmi {
-            copiers.push(Box::new(Wmi {
-                computer: computer.clone(),
-                remote_temp_storage: remote_temp_storage.to_path_buf()
-            }));
-        }
-        copiers
-    };
-    copiers
+    analysis.finish(local_store_directory)
 }
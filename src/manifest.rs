@@ -0,0 +1,355 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::io;
+use std::fs::{File, OpenOptions};
+use std::io::{Write, BufReader};
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc, SecondsFormat};
+use crate::remote::{Computer, FileTimes, integrity_of};
+use crate::hash::hash_file;
+use crate::journal::JOURNAL_JSON;
+use crate::cleanup::CLEANUP_JSON;
+use crate::container::is_container;
+
+pub const MANIFEST_JSON: &str = "manifest.json";
+pub const MANIFEST_TEXT: &str = "manifest.txt";
+
+static MANIFEST: Mutex<Option<Manifest>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+    pub md5: String,
+    #[serde(default)]
+    pub integrity: Option<String>,
+    pub computer: Option<String>,
+    pub method: Option<String>,
+    pub source: Option<String>,
+    pub command: Option<String>,
+    pub operator: String,
+    pub started: String,
+    pub finished: String,
+    // YARA rules matching the file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub yara: Vec<String>,
+    // times of the downloaded file on the target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<FileTimes>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Manifest {
+    pub case_directory: PathBuf,
+    pub operator: String,
+    pub started: String,
+    pub entries: Vec<ManifestEntry>,
+    // hosts whose acquisition was abandoned, their evidence may be partial
+    #[serde(default)]
+    pub incomplete: Vec<String>,
+}
+
+pub struct Provenance<'a> {
+    pub computer: &'a Computer,
+    pub method: &'a str,
+    pub source: Option<&'a Path>,
+    pub command: Option<String>,
+    pub started: DateTime<Utc>,
+    pub times: Option<FileTimes>,
+}
+
+impl<'a> Provenance<'a> {
+    pub fn new(computer: &'a Computer, method: &'a str) -> Provenance<'a> {
+        Provenance {
+            computer,
+            method,
+            source: None,
+            command: None,
+            started: Utc::now(),
+            times: None,
+        }
+    }
+
+    pub fn source(self, source: &'a Path) -> Provenance<'a> {
+        Provenance {
+            source: Some(source),
+            ..self
+        }
+    }
+
+    // Time the acquisition of the file started, when the provenance is created after it.
+    pub fn started(self, started: DateTime<Utc>) -> Provenance<'a> {
+        Provenance {
+            started,
+            ..self
+        }
+    }
+
+    pub fn times(self, times: Option<FileTimes>) -> Provenance<'a> {
+        Provenance {
+            times,
+            ..self
+        }
+    }
+
+    pub fn command(self, command: &[String]) -> Provenance<'a> {
+        Provenance {
+            command: Some(command.join(" ")),
+            ..self
+        }
+    }
+}
+
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn default_operator() -> String {
+    std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_default()
+}
+
+impl Manifest {
+    fn open(case_directory: &Path, operator: &str) -> io::Result<Manifest> {
+        let json_path = case_directory.join(MANIFEST_JSON);
+        if json_path.exists() {
+            let existing: Manifest = serde_json::from_reader(BufReader::new(File::open(&json_path)?))?;
+            return Ok(Manifest {
+                case_directory: case_directory.to_path_buf(),
+                operator: operator.to_string(),
+                ..existing
+            });
+        }
+        Ok(Manifest {
+            case_directory: case_directory.to_path_buf(),
+            operator: operator.to_string(),
+            started: timestamp(Utc::now()),
+            entries: vec![],
+            incomplete: vec![],
+        })
+    }
+
+    fn relative_name(&self, path: &Path) -> String {
+        relative_name(&self.case_directory, path)
+    }
+
+    fn record(&mut self, entry: ManifestEntry) -> io::Result<()> {
+        trace!("Recording {} (SHA256 {}) in manifest", entry.file, entry.sha256);
+        self.entries.retain(|it| it.file != entry.file);
+        self.entries.push(entry);
+        self.save()
+    }
+
+    fn record_yara(&mut self, path: &Path, rules: &[String]) -> io::Result<()> {
+        let name = self.relative_name(path);
+        if let Some(entry) = self.entries.iter_mut().find(|it| it.file == name) {
+            entry.yara = rules.to_vec();
+        }
+        self.save()
+    }
+
+    fn record_incomplete(&mut self, computer: &str, reason: &str) -> io::Result<()> {
+        self.incomplete.retain(|it| !it.starts_with(&format!("{} ", computer)));
+        self.incomplete.push(format!("{} ({})", computer, reason));
+        self.save()
+    }
+
+    fn is_recorded(&self, path: &Path) -> bool {
+        let name = self.relative_name(path);
+        self.entries.iter().any(|it| it.file == name)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        // written to a temporary file and renamed, so the manifest stays valid if Gargamel is killed
+        let json_path = self.case_directory.join(MANIFEST_JSON);
+        let json_temp = self.case_directory.join(format!("{}.tmp", MANIFEST_JSON));
+        {
+            let mut file = File::create(&json_temp)?;
+            serde_json::to_writer_pretty(&mut file, self)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&json_temp, &json_path)?;
+
+        let text_path = self.case_directory.join(MANIFEST_TEXT);
+        let text_temp = self.case_directory.join(format!("{}.tmp", MANIFEST_TEXT));
+        {
+            let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&text_temp)?;
+            file.write_all(self.to_text().as_bytes())?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&text_temp, &text_path)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "Gargamel evidence manifest\nCase directory: {}\nOperator: {}\nStarted: {}\n\n",
+            self.case_directory.display(),
+            self.operator,
+            self.started
+        );
+        for computer in self.incomplete.iter() {
+            text.push_str(&format!("Incomplete: {}\n", computer));
+        }
+        if !self.incomplete.is_empty() {
+            text.push('\n');
+        }
+        for entry in self.entries.iter() {
+            text.push_str(&format!("File:     {}\n", entry.file));
+            text.push_str(&format!("Size:     {}\n", entry.size));
+            text.push_str(&format!("SHA-256:  {}\n", entry.sha256));
+            text.push_str(&format!("MD5:      {}\n", entry.md5));
+            text.push_str(&format!("Transfer: {}\n", entry.integrity.as_deref().unwrap_or("-")));
+            text.push_str(&format!("Computer: {}\n", entry.computer.as_deref().unwrap_or("-")));
+            text.push_str(&format!("Method:   {}\n", entry.method.as_deref().unwrap_or("-")));
+            text.push_str(&format!("Source:   {}\n", entry.source.as_deref().unwrap_or("-")));
+            text.push_str(&format!("Command:  {}\n", entry.command.as_deref().unwrap_or("-")));
+            if !entry.yara.is_empty() {
+                text.push_str(&format!("YARA:     {}\n", entry.yara.join(", ")));
+            }
+            text.push_str(&format!("Operator: {}\n", entry.operator));
+            text.push_str(&format!("Started:  {}\n", entry.started));
+            text.push_str(&format!("Finished: {}\n\n", entry.finished));
+        }
+        text
+    }
+}
+
+pub(crate) fn is_manifest_file(path: &Path) -> bool {
+    match path.file_name() {
+        None => false,
+        Some(name) => {
+            let name = name.to_string_lossy();
+            name.starts_with(MANIFEST_JSON) || name.starts_with(MANIFEST_TEXT) || name.starts_with(JOURNAL_JSON) || name.starts_with(CLEANUP_JSON) || is_container(path)
+        }
+    }
+}
+
+fn list_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else if !is_manifest_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub fn start_case(case_directory: &Path, operator: &str) -> io::Result<()> {
+    let case_directory = dunce::canonicalize(case_directory)?;
+    let manifest = Manifest::open(&case_directory, operator)?;
+    manifest.save()?;
+    info!("Evidence manifest is written to {}", case_directory.join(MANIFEST_JSON).display());
+    *MANIFEST.lock().unwrap() = Some(manifest);
+    Ok(())
+}
+
+pub fn record_file(path: &Path, provenance: &Provenance) {
+    record(path, Some(provenance))
+}
+
+pub fn record_report(report_path: Option<&Path>, provenance: &Provenance) {
+    if let Some(report_path) = report_path {
+        record_file(report_path, provenance);
+    }
+}
+
+fn relative_name(case_directory: &Path, path: &Path) -> String {
+    let path = dunce::canonicalize(path).unwrap_or(path.to_path_buf());
+    path.strip_prefix(case_directory)
+        .unwrap_or(&path)
+        .to_string_lossy()
+        .to_string()
+}
+
+// Hashes the file, returns None for files outside the case directory.
+fn new_entry(case_directory: &Path, operator: &str, path: &Path, provenance: Option<&Provenance>) -> io::Result<Option<ManifestEntry>> {
+    let canonical_path = dunce::canonicalize(path)?;
+    if !canonical_path.starts_with(case_directory) {
+        return Ok(None);
+    }
+    let hashes = hash_file(path)?;
+    let now = Utc::now();
+    Ok(Some(ManifestEntry {
+        file: relative_name(case_directory, path),
+        size: hashes.size,
+        sha256: hashes.sha256,
+        md5: hashes.md5,
+        integrity: integrity_of(path).map(|it| it.to_string()),
+        computer: provenance.map(|it| it.computer.address.clone()),
+        method: provenance.map(|it| it.method.to_string()),
+        source: provenance.and_then(|it| it.source).map(|it| it.to_string_lossy().to_string()),
+        command: provenance.and_then(|it| it.command.clone()),
+        operator: operator.to_string(),
+        started: timestamp(provenance.map(|it| it.started).unwrap_or(now)),
+        finished: timestamp(now),
+        yara: vec![],
+        times: provenance.and_then(|it| it.times.clone()),
+    }))
+}
+
+// The file is hashed without holding the manifest, so hosts acquired in parallel do not wait for each other's large files.
+fn record(path: &Path, provenance: Option<&Provenance>) {
+    if !path.is_file() {
+        return;
+    }
+    let (case_directory, operator) = match MANIFEST.lock().unwrap().as_ref() {
+        None => return,
+        Some(manifest) => (manifest.case_directory.clone(), manifest.operator.clone()),
+    };
+    let entry = match new_entry(&case_directory, &operator, path, provenance) {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return,
+        Err(err) => {
+            error!("Cannot record {} in manifest due to {}", path.display(), err);
+            return;
+        }
+    };
+    let mut manifest = MANIFEST.lock().unwrap();
+    if let Some(manifest) = manifest.as_mut() {
+        if let Err(err) = manifest.record(entry) {
+            error!("Cannot record {} in manifest due to {}", path.display(), err);
+        }
+    }
+}
+
+// Records the YARA rules matching an already recorded file.
+pub fn record_yara_matches(path: &Path, rules: &[String]) {
+    let mut manifest = MANIFEST.lock().unwrap();
+    if let Some(manifest) = manifest.as_mut() {
+        if let Err(err) = manifest.record_yara(path, rules) {
+            error!("Cannot record YARA matches of {} in manifest due to {}", path.display(), err);
+        }
+    }
+}
+
+// Marks the evidence of the computer as partial, e.g. when its acquisition exceeded the time budget.
+pub fn record_incomplete(computer: &str, reason: &str) {
+    let mut manifest = MANIFEST.lock().unwrap();
+    if let Some(manifest) = manifest.as_mut() {
+        if let Err(err) = manifest.record_incomplete(computer, reason) {
+            error!("Cannot record incomplete acquisition of {} in manifest due to {}", computer, err);
+        }
+    }
+}
+
+pub fn finish_case() -> io::Result<()> {
+    let case_directory = match MANIFEST.lock().unwrap().as_ref() {
+        None => return Ok(()),
+        Some(manifest) => manifest.case_directory.clone(),
+    };
+    let mut files = vec![];
+    list_files(&case_directory, &mut files)?;
+    for file in files {
+        let is_recorded = MANIFEST.lock().unwrap().as_ref()
+            .map(|manifest| manifest.is_recorded(&file))
+            .unwrap_or(true);
+        if !is_recorded {
+            record(&file, None);
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,61 @@
-use crate::remote::{Connector, Computer, Command, PsExec, PsRemote, Rdp, Wmi, CompressCopier, RemoteFileCopier, Compression};
+use crate::remote::{Connector, Computer, Command, PsExec, PsRemote, Rdp, Wmi, Ssh, CompressCopier, RemoteFileCopier, Compression, HostPlatform, download_verified, delete_when_released, sh_quoted};
 use std::path::{Path, PathBuf};
-use std::{io, thread};
+use std::io;
+use std::io::ErrorKind;
+use std::fs::File;
+use flate2::read::GzDecoder;
 use std::time::Duration;
-use crate::process_runner::create_report_path;
+use crate::process_runner::{create_report_path, report_file_name, CommandOutcome};
+use crate::manifest::{Provenance, record_file};
+use crate::cleanup::{register_remote_file, retain_remote_file};
+use crate::journal::{StepKey, StepState, journaled_step, skipped_step, start_step, advance_step, complete_step, fail_step};
+use crate::large_evidence_acquirer::resume_transfer;
+
+// Static tool imaging the memory of Linux targets, like AVML or a LiME kernel module built for the target kernel.
+#[derive(Clone, Debug)]
+pub struct LinuxMemoryTool {
+    pub path: PathBuf,
+    // {tool} is replaced by the uploaded tool and {output} by the image path, or /dev/stdout when streaming
+    pub command: String,
+    // the image is sent over the SSH channel instead of being written to the target
+    pub stream: bool,
+}
+
+impl Default for LinuxMemoryTool {
+    fn default() -> Self {
+        LinuxMemoryTool {
+            path: PathBuf::from("avml"),
+            command: "{tool} {output}".to_string(),
+            stream: false,
+        }
+    }
+}
+
+impl LinuxMemoryTool {
+    fn local_path(&self) -> io::Result<PathBuf> {
+        let path = if self.path.exists() {
+            self.path.clone()
+        } else {
+            PathBuf::from(HostPlatform::current().resolve_local_program(&self.path.to_string_lossy()))
+        };
+        if !path.is_file() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Memory acquisition tool {} not found", self.path.display()),
+            ));
+        }
+        Ok(path)
+    }
+
+    fn script(&self, remote_tool: &Path, output: &str) -> String {
+        let tool = sh_quoted(&remote_tool.to_string_lossy());
+        format!(
+            "chmod 700 {} && {}",
+            tool,
+            self.command.replace("{tool}", &tool).replace("{output}", output)
+        )
+    }
+}
 
 pub struct MemoryAcquirer<'a> {
     pub local_store_directory: &'a Path,
@@ -10,6 +63,7 @@ pub struct MemoryAcquirer<'a> {
     pub image_timeout: Option<Duration>,
     pub compress_timeout: Option<Duration>,
     pub compression: Compression,
+    pub linux_tool: LinuxMemoryTool,
 }
 
 impl<'a> MemoryAcquirer<'a> {
@@ -25,6 +79,7 @@ impl<'a> MemoryAcquirer<'a> {
             image_timeout: None,
             compress_timeout: None,
             compression: if no_7zip { Compression::No } else { Compression::Yes },
+            linux_tool: LinuxMemoryTool::default(),
         }
     }
 
@@ -40,6 +95,7 @@ impl<'a> MemoryAcquirer<'a> {
             image_timeout: None,
             compress_timeout: None,
             compression: Compression::No,
+            linux_tool: LinuxMemoryTool::default(),
         }
     }
 
@@ -57,6 +113,7 @@ impl<'a> MemoryAcquirer<'a> {
             image_timeout: Some(timeout),
             compress_timeout: Some(compress_timeout),
             compression: if no_7zip { Compression::No } else { Compression::YesSplit },
+            linux_tool: LinuxMemoryTool::default(),
         }
     }
 
@@ -79,14 +136,50 @@ impl<'a> MemoryAcquirer<'a> {
             image_timeout: Some(image_timeout),
             compress_timeout: Some(compress_timeout),
             compression: if no_7zip { Compression::No } else { Compression::YesSplit },
+            linux_tool: LinuxMemoryTool::default(),
+        }
+    }
+
+    pub fn ssh(
+        remote_computer: Computer,
+        local_store_directory: &'a Path,
+        key_file: Option<PathBuf>,
+        known_hosts: Option<PathBuf>,
+        image_timeout: Duration,
+        no_compression: bool,
+        linux_tool: LinuxMemoryTool,
+    ) -> MemoryAcquirer<'a> {
+        MemoryAcquirer {
+            local_store_directory,
+            connector: Box::new(Ssh::new(remote_computer, key_file, known_hosts)),
+            image_timeout: Some(image_timeout),
+            compress_timeout: None,
+            // images of Linux targets are compressed by gzip on the target
+            compression: if no_compression { Compression::No } else { Compression::Yes },
+            linux_tool,
         }
     }
 
     pub fn image_memory(
         &self
     ) -> io::Result<()> {
+        if self.connector.remote_platform() == HostPlatform::Linux {
+            return self.image_linux_memory();
+        }
         let local_store_directory = self.local_store_directory;
         let winpmem = "winpmem.exe";
+        let key = StepKey::new(self.connector.computer(), self.connector.connect_method_name(), "mem-image");
+        if skipped_step(&key).is_some() {
+            return Ok(());
+        }
+        if let Some(step) = journaled_step(&key) {
+            if step.state != StepState::Started && step.remote_file.is_some() {
+                match resume_transfer(self.connector.as_ref(), local_store_directory, &key, &step) {
+                    Ok(_) => return Ok(()),
+                    Err(err) => warn!("{}, imaging memory again", err),
+                }
+            }
+        }
 
         // let target_name = remote_storage_file(target_name.file_name().unwrap());
         let target_name = create_report_path(
@@ -96,15 +189,21 @@ impl<'a> MemoryAcquirer<'a> {
             self.connector.connect_method_name(),
             "aff4"
         );
+        let command = vec![
+            winpmem.to_string(),
+            "--format".to_string(),
+            "map".to_string(),
+            "-t".to_string(),
+            "-o".to_string(),
+            target_name.to_string_lossy().to_string(),
+        ];
+        let provenance = Provenance::new(self.connector.computer(), self.connector.connect_method_name())
+            .command(&command)
+            .source(&target_name);
+        start_step(&key, &command, Some(&target_name));
+        register_remote_file(self.connector.computer(), &target_name);
         let connection = Command {
-            command: vec![
-                winpmem.to_string(),
-                "--format".to_string(),
-                "map".to_string(),
-                "-t".to_string(),
-                "-o".to_string(),
-                target_name.to_string_lossy().to_string(),
-            ],
+            command,
             report_store_directory: None,
             report_filename_prefix: "mem-ack-log",
             elevated: true,
@@ -113,6 +212,7 @@ impl<'a> MemoryAcquirer<'a> {
             connection,
             self.image_timeout,
         )?;
+        advance_step(&key, StepState::CommandRun);
         let _copier = self.connector.copier();
         let _compression_split_copier = CompressCopier::new(self.connector.as_ref(), true, self.compress_timeout);
         let _compression_copier = CompressCopier::new(self.connector.as_ref(), false, self.compress_timeout);
@@ -121,24 +221,35 @@ impl<'a> MemoryAcquirer<'a> {
             Compression::Yes => &_compression_copier as &dyn RemoteFileCopier,
             Compression::YesSplit => &_compression_split_copier as &dyn RemoteFileCopier,
         };
-        match copier.copy_from_remote(
-            &target_name,
-            &local_store_directory,
-            // &self.local_store_directory.join(target_name.file_name().unwrap()),
-        ) {
-            Ok(_) => {}
+        let download_result = match self.compression {
+            Compression::No => download_verified(self.connector.as_ref(), &target_name, local_store_directory),
+            _ => copier.copy_from_remote(
+                &target_name,
+                local_store_directory,
+                // &self.local_store_directory.join(target_name.file_name().unwrap()),
+            ),
+        };
+        let local_image = local_store_directory.join(target_name.file_name().unwrap());
+        let downloaded = download_result.is_ok();
+        match &download_result {
+            Ok(_) => {
+                record_file(&local_image, &provenance);
+                advance_step(&key, StepState::Downloaded);
+            }
             Err(err) => {
                 error!("Cannot download {} report from {} using method {} due to {}",
                        target_name.display(),
                        self.connector.computer().address,
                        self.connector.connect_method_name(),
                        err
-                )
+                );
+                // the image is kept on the target, --resume or --redownload can transfer it again
+                retain_remote_file(self.connector.computer(), &target_name);
+                fail_step(&key);
             }
         }
-        thread::sleep(Duration::from_millis(1000));
         let winpem_path = self.connector.remote_temp_storage().join(winpmem);
-        match copier.delete_remote_file(&winpem_path) {
+        match delete_when_released(copier, &winpem_path, Duration::from_millis(1000)) {
             Ok(_) => {}
             Err(err) => {
                 error!("Cannot delete remote file {} using method {} due to {}",
@@ -148,17 +259,183 @@ impl<'a> MemoryAcquirer<'a> {
                 )
             }
         };
-        thread::sleep(Duration::from_millis(1000));
-        match copier.delete_remote_file(&target_name) {
-            Ok(_) => {}
-            Err(err) => {
-                error!("Cannot delete remote file {} using method {} due to {}",
-                       target_name.display(),
-                       self.connector.connect_method_name(),
-                       err
-                )
+        if !downloaded {
+            return download_result;
+        }
+        // the local copy completes the step, a remote image left behind stays in the cleanup registry
+        complete_step(&key, Some(&local_image));
+        if let Err(err) = delete_when_released(copier, &target_name, Duration::from_millis(1000)) {
+            error!("Cannot delete remote file {} using method {} due to {}",
+                   target_name.display(),
+                   self.connector.connect_method_name(),
+                   err
+            )
+        }
+        Ok(())
+    }
+    fn image_linux_memory(&self) -> io::Result<()> {
+        let key = StepKey::new(self.connector.computer(), self.connector.connect_method_name(), "mem-image");
+        if skipped_step(&key).is_some() {
+            return Ok(());
+        }
+        if let Some(step) = journaled_step(&key) {
+            if step.state != StepState::Started && step.remote_file.is_some() {
+                match resume_transfer(self.connector.as_ref(), self.local_store_directory, &key, &step) {
+                    Ok(_) => return Ok(()),
+                    Err(err) => warn!("{}, imaging memory again", err),
+                }
             }
+        }
+        let tool = self.linux_tool.local_path()?;
+        let remote_storage = self.connector.remote_temp_storage();
+        let remote_tool = remote_storage.join(tool.file_name().unwrap());
+        let copier = self.connector.copier();
+        info!("{}: Uploading {} to {}", self.connector.connect_method_name(), tool.display(), self.connector.computer().address);
+        copier.copy_to_remote(&tool, remote_storage)?;
+        register_remote_file(self.connector.computer(), &remote_tool);
+        let compressed = !matches!(self.compression, Compression::No);
+        let extension = if compressed { "lime.gz" } else { "lime" };
+        let result = if self.linux_tool.stream {
+            self.stream_linux_image(&key, &remote_tool, extension)
+        } else {
+            self.download_linux_image(&key, &remote_tool, extension)
+        };
+        if let Err(err) = delete_when_released(copier, &remote_tool, Duration::from_millis(1000)) {
+            error!("Cannot delete remote file {} using method {} due to {}",
+                   remote_tool.display(),
+                   self.connector.connect_method_name(),
+                   err
+            )
+        }
+        result
+    }
+
+    fn image_command(&self, shell: &str, script: String) -> Command<'_> {
+        Command {
+            command: vec![shell.to_string(), "-c".to_string(), sh_quoted(&script)],
+            report_store_directory: None,
+            report_filename_prefix: "mem-image",
+            elevated: true,
+        }
+    }
+
+    fn check_image_outcome(&self, outcome: &CommandOutcome) -> io::Result<()> {
+        if outcome.is_success() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            if outcome.timed_out { ErrorKind::TimedOut } else { ErrorKind::Other },
+            format!(
+                "Memory acquisition on {} finished with exit code {:?}{}: {}",
+                self.connector.computer().address,
+                outcome.exit_code,
+                if outcome.timed_out { " (timed out)" } else { "" },
+                outcome.stderr.trim()
+            ),
+        ))
+    }
+
+    // An empty image, or a truncated gzip stream, means that the memory tool failed.
+    fn check_image(&self, image: &Path) -> io::Result<()> {
+        let file = File::open(image)?;
+        let size = match self.compression {
+            Compression::No => file.metadata()?.len(),
+            _ => io::copy(&mut GzDecoder::new(file), &mut io::sink()).map_err(|err| io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Memory image {} is truncated: {}", image.display(), err),
+            ))?,
         };
+        if size == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Memory image {} is empty", image.display()),
+            ));
+        }
         Ok(())
     }
-}
\ No newline at end of file
+
+    // The image never touches the disk of the target, so only its local hash is recorded.
+    fn stream_linux_image(&self, key: &StepKey, remote_tool: &Path, extension: &str) -> io::Result<()> {
+        let local_image = create_report_path(
+            self.connector.computer(),
+            self.local_store_directory,
+            "mem-image",
+            self.connector.connect_method_name(),
+            extension
+        );
+        // without pipefail the exit code would be the one of gzip even when the tool fails
+        let mut script = format!("set -o pipefail; {}", self.linux_tool.script(remote_tool, "/dev/stdout"));
+        if !matches!(self.compression, Compression::No) {
+            script.push_str(" | gzip -1 -c");
+        }
+        let connection = self.image_command("bash", script);
+        let provenance = Provenance::new(self.connector.computer(), self.connector.connect_method_name())
+            .command(&connection.command);
+        start_step(key, &connection.command, None);
+        info!("{}: Streaming memory image to {}", self.connector.connect_method_name(), local_image.display());
+        let outcome = self.connector.connect_and_stream_command(connection, &local_image, self.image_timeout)?;
+        self.check_image_outcome(&outcome)?;
+        self.check_image(&local_image)?;
+        record_file(&local_image, &provenance);
+        complete_step(key, Some(&local_image));
+        Ok(())
+    }
+
+    fn download_linux_image(&self, key: &StepKey, remote_tool: &Path, extension: &str) -> io::Result<()> {
+        // the remote temp storage is on the target, so the path is only joined
+        let image = self.connector.remote_temp_storage().join(report_file_name(
+            self.connector.computer(),
+            "mem-image",
+            self.connector.connect_method_name(),
+            "lime"
+        ));
+        let remote_image = image.with_file_name(format!("{}.{}", image.file_stem().unwrap().to_string_lossy(), extension));
+        let mut script = self.linux_tool.script(remote_tool, &sh_quoted(&image.to_string_lossy()));
+        if remote_image != image {
+            script.push_str(&format!(" && gzip -1 -f {}", sh_quoted(&image.to_string_lossy())));
+        }
+        let connection = self.image_command("sh", script);
+        let provenance = Provenance::new(self.connector.computer(), self.connector.connect_method_name())
+            .command(&connection.command)
+            .source(&remote_image);
+        start_step(key, &connection.command, Some(&remote_image));
+        register_remote_file(self.connector.computer(), &image);
+        register_remote_file(self.connector.computer(), &remote_image);
+        info!("{}: Imaging memory to {}", self.connector.connect_method_name(), remote_image.display());
+        let copier = self.connector.copier();
+        let delete_leftover = |leftover: &Path| if let Err(err) = delete_when_released(copier, leftover, Duration::from_millis(1000)) {
+            debug!("{}", err);
+        };
+        let imaged = self.connector.connect_and_run_command(connection, self.image_timeout)
+            .and_then(|outcome| self.check_image_outcome(&outcome));
+        if let Err(err) = imaged {
+            delete_leftover(&image);
+            delete_leftover(&remote_image);
+            return Err(err);
+        }
+        advance_step(key, StepState::CommandRun);
+        if let Err(err) = download_verified(self.connector.as_ref(), &remote_image, self.local_store_directory) {
+            // the image is kept on the target, --resume or --redownload can transfer it again
+            retain_remote_file(self.connector.computer(), &remote_image);
+            fail_step(key);
+            return Err(err);
+        }
+        let local_image = self.local_store_directory.join(remote_image.file_name().unwrap());
+        self.check_image(&local_image)?;
+        record_file(&local_image, &provenance);
+        advance_step(key, StepState::Downloaded);
+        // gzip already removed the uncompressed image, this only unregisters it from the cleanup
+        if remote_image != image {
+            delete_leftover(&image);
+        }
+        complete_step(key, Some(&local_image));
+        if let Err(err) = delete_when_released(copier, &remote_image, Duration::from_millis(1000)) {
+            error!("Cannot delete remote file {} using method {} due to {}",
+                   remote_image.display(),
+                   self.connector.connect_method_name(),
+                   err
+            )
+        }
+        Ok(())
+    }
+}
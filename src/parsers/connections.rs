@@ -0,0 +1,161 @@
+use serde::{Serialize, Deserialize};
+use crate::parsers::fields;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Connection {
+    pub protocol: String,
+    pub local_address: String,
+    pub local_port: Option<u16>,
+    pub remote_address: String,
+    pub remote_port: Option<u16>,
+    pub state: Option<String>,
+    pub pid: Option<u32>,
+    pub process_name: Option<String>,
+}
+
+// Splits `address:port`, where IPv6 addresses may be enclosed in brackets and the port may be `*`.
+pub(crate) fn split_endpoint(endpoint: &str) -> (String, Option<u16>) {
+    match endpoint.rfind(':') {
+        None => (endpoint.to_string(), None),
+        Some(colon) => (
+            endpoint[..colon].trim_start_matches('[').trim_end_matches(']').to_string(),
+            endpoint[colon + 1..].parse().ok(),
+        ),
+    }
+}
+
+fn connection(protocol: &str, local: &str, remote: &str, state: Option<&str>) -> Connection {
+    let (local_address, local_port) = split_endpoint(local);
+    let (remote_address, remote_port) = split_endpoint(remote);
+    Connection {
+        protocol: protocol.to_lowercase(),
+        local_address,
+        local_port,
+        remote_address,
+        remote_port,
+        state: state.map(|it| it.to_uppercase()),
+        pid: None,
+        process_name: None,
+    }
+}
+
+pub fn parse_netstat_ano(text: &str) -> Vec<Connection> {
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let protocol = *fields.first()?;
+            if protocol != "TCP" && protocol != "UDP" {
+                return None;
+            }
+            // UDP has no state
+            let (state, pid) = match fields.len() {
+                5 => (Some(fields[3]), fields[4]),
+                4 => (None, fields[3]),
+                _ => return None,
+            };
+            Some(Connection {
+                pid: pid.parse().ok(),
+                ..connection(protocol, fields[1], fields[2], state)
+            })
+        })
+        .collect()
+}
+
+pub fn parse_netstat_natp(text: &str) -> Vec<Connection> {
+    text.lines()
+        .filter_map(|line| {
+            let protocol = line.split_whitespace().next()?;
+            if !protocol.starts_with("tcp") && !protocol.starts_with("udp") {
+                return None;
+            }
+            let has_state = protocol.starts_with("tcp");
+            let fields = fields(line, if has_state { 7 } else { 6 });
+            if fields.len() < 6 {
+                return None;
+            }
+            let state = if has_state { Some(fields[5]) } else { None };
+            let program = fields.get(if has_state { 6 } else { 5 }).copied().unwrap_or("-");
+            let (pid, process_name) = match program.split_once('/') {
+                Some((pid, name)) => (pid.parse().ok(), Some(name.trim().to_string())),
+                None => (None, None),
+            };
+            Some(Connection {
+                pid,
+                process_name,
+                ..connection(protocol, fields[3], fields[4], state)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{parse_netstat_ano, parse_netstat_natp, Connection};
+    use crate::parsers::connections::split_endpoint;
+
+    #[test]
+    fn test_netstat_ano_connections_are_normalized() {
+        let windows = "
+Active Connections
+
+  Proto  Local Address          Foreign Address        State           PID
+  TCP    0.0.0.0:135            0.0.0.0:0              LISTENING       1000
+  TCP    [::1]:49670            [::1]:443              ESTABLISHED     2200
+  UDP    0.0.0.0:123            *:*                                    1234
+";
+
+        let connections = parse_netstat_ano(windows);
+
+        assert_eq!(3, connections.len());
+        assert_eq!(Connection {
+            protocol: "tcp".to_string(),
+            local_address: "::1".to_string(),
+            local_port: Some(49670),
+            remote_address: "::1".to_string(),
+            remote_port: Some(443),
+            state: Some("ESTABLISHED".to_string()),
+            pid: Some(2200),
+            process_name: None,
+        }, connections[1]);
+        assert_eq!((None, Some(1234)), (connections[2].state.clone(), connections[2].pid));
+    }
+
+    #[test]
+    fn test_netstat_natp_connections_are_normalized() {
+        let linux = "\
+Active Internet connections (servers and established)
+Proto Recv-Q Send-Q Local Address           Foreign Address         State       PID/Program name
+tcp        0      0 0.0.0.0:22              0.0.0.0:*               LISTEN      812/sshd: /usr/sbin
+tcp6       0      0 :::80                   :::*                    LISTEN      -
+udp        0      0 0.0.0.0:68              0.0.0.0:*                           640/dhclient
+";
+
+        let connections = parse_netstat_natp(linux);
+
+        assert_eq!(Connection {
+            protocol: "tcp".to_string(),
+            local_address: "0.0.0.0".to_string(),
+            local_port: Some(22),
+            remote_address: "0.0.0.0".to_string(),
+            remote_port: None,
+            state: Some("LISTEN".to_string()),
+            pid: Some(812),
+            process_name: Some("sshd: /usr/sbin".to_string()),
+        }, connections[0]);
+        assert_eq!(("::".to_string(), None), (connections[1].local_address.clone(), connections[1].pid));
+        assert_eq!((None, Some(640)), (connections[2].state.clone(), connections[2].pid));
+    }
+
+    #[test]
+    fn test_endpoints_are_split() {
+        assert_eq!(("fe80::1%12".to_string(), Some(445)), split_endpoint("[fe80::1%12]:445"));
+        assert_eq!(("*".to_string(), None), split_endpoint("*:*"));
+        assert_eq!(("localhost".to_string(), None), split_endpoint("localhost"));
+    }
+
+    #[test]
+    fn test_error_output_has_no_connections() {
+        assert!(parse_netstat_ano("The requested operation requires elevation.").is_empty());
+        assert!(parse_netstat_natp("bash: netstat: command not found").is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FirewallProfile {
+    pub profile: String,
+    pub state: String,
+    pub enabled: bool,
+}
+
+pub fn parse_netsh_firewall(text: &str) -> Vec<FirewallProfile> {
+    let mut profiles = vec![];
+    let mut profile = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line.strip_suffix(" Profile Settings:") {
+            profile = Some(name.to_string());
+        } else if let (Some(name), true) = (&profile, line.starts_with("State ")) {
+            let state = line["State".len()..].trim().to_string();
+            profiles.push(FirewallProfile {
+                profile: name.clone(),
+                enabled: state.eq_ignore_ascii_case("on"),
+                state,
+            });
+            profile = None;
+        }
+    }
+    profiles
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{parse_netsh_firewall, FirewallProfile};
+
+    #[test]
+    fn test_firewall_profiles_are_parsed() {
+        let netsh = "
+Domain Profile Settings:
+----------------------------------------------------------------------
+State                                 ON
+
+Public Profile Settings:
+----------------------------------------------------------------------
+State                                 OFF
+Ok.
+";
+
+        assert_eq!(vec![
+            FirewallProfile { profile: "Domain".to_string(), state: "ON".to_string(), enabled: true },
+            FirewallProfile { profile: "Public".to_string(), state: "OFF".to_string(), enabled: false },
+        ], parse_netsh_firewall(netsh));
+    }
+
+    #[test]
+    fn test_state_outside_profile_is_ignored() {
+        let netsh = "
+State                                 ON
+Private Profile Settings:
+----------------------------------------------------------------------
+Firewall Policy                       BlockInbound,AllowOutbound
+";
+
+        assert!(parse_netsh_firewall(netsh).is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NetworkInterface {
+    pub interface: String,
+    pub description: Option<String>,
+    pub mac: Option<String>,
+    pub state: Option<String>,
+    pub ipv4: Vec<String>,
+    pub ipv6: Vec<String>,
+    pub gateways: Vec<String>,
+    pub dns_servers: Vec<String>,
+    pub dhcp: Option<bool>,
+}
+
+impl NetworkInterface {
+    fn new(interface: &str) -> NetworkInterface {
+        NetworkInterface {
+            interface: interface.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn add_address(&mut self, address: &str) {
+        // ipconfig appends `(Preferred)`, ip appends the prefix length and IPv6 scopes may follow `%`
+        let address = address.split(['(', '/', '%']).next().unwrap_or("").trim();
+        if address.is_empty() {
+            return;
+        }
+        if address.contains(':') {
+            self.ipv6.push(address.to_string());
+        } else {
+            self.ipv4.push(address.to_string());
+        }
+    }
+}
+
+fn normalize_mac(mac: &str) -> String {
+    mac.replace('-', ":").to_lowercase()
+}
+
+pub fn parse_ipconfig(text: &str) -> Vec<NetworkInterface> {
+    let mut interfaces: Vec<NetworkInterface> = vec![];
+    let mut key = String::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            key.clear();
+            if let (Some(start), true) = (line.find(" adapter "), line.trim_end().ends_with(':')) {
+                interfaces.push(NetworkInterface::new(line[start + " adapter ".len()..].trim_end().trim_end_matches(':')));
+            }
+            continue;
+        }
+        let interface = match interfaces.last_mut() {
+            None => continue,
+            Some(interface) => interface,
+        };
+        let value = match line.find(" : ") {
+            Some(separator) => {
+                key = line[..separator].trim().trim_end_matches(['.', ' ']).to_string();
+                line[separator + 3..].trim()
+            }
+            // continuation of a multi-valued key like DNS Servers
+            None => line.trim(),
+        };
+        match key.as_str() {
+            "Description" => interface.description = Some(value.to_string()),
+            "Physical Address" => interface.mac = Some(normalize_mac(value)),
+            "Media State" => interface.state = Some(value.to_string()),
+            "DHCP Enabled" => interface.dhcp = Some(value.eq_ignore_ascii_case("yes")),
+            "IPv4 Address" | "IP Address" | "IPv6 Address" | "Link-local IPv6 Address" | "Temporary IPv6 Address" =>
+                interface.add_address(value),
+            "Default Gateway" if !value.is_empty() =>
+                interface.gateways.push(value.split('%').next().unwrap_or(value).to_string()),
+            "DNS Servers" => interface.dns_servers.push(value.to_string()),
+            _ => {}
+        }
+    }
+    interfaces
+}
+
+pub fn parse_ip_addr(text: &str) -> Vec<NetworkInterface> {
+    let mut interfaces: Vec<NetworkInterface> = vec![];
+    for line in text.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if !line.starts_with(char::is_whitespace) {
+            // `2: eth0@if5: <BROADCAST,...> mtu 1500 ... state UP ...`
+            if let Some(name) = tokens.get(1) {
+                let mut interface = NetworkInterface::new(name.trim_end_matches(':').split('@').next().unwrap_or(name));
+                interface.state = tokens.iter().position(|it| *it == "state")
+                    .and_then(|position| tokens.get(position + 1))
+                    .map(|state| state.to_uppercase());
+                interfaces.push(interface);
+            }
+            continue;
+        }
+        if let (Some(interface), Some(key), Some(value)) = (interfaces.last_mut(), tokens.first(), tokens.get(1)) {
+            match *key {
+                "inet" | "inet6" => interface.add_address(value),
+                key if key.starts_with("link/") && key != "link/none" => interface.mac = Some(normalize_mac(value)),
+                _ => {}
+            }
+        }
+    }
+    interfaces
+}
+
+// Supports both the current net-tools format and the older one with `HWaddr` and `inet addr:`.
+pub fn parse_ifconfig(text: &str) -> Vec<NetworkInterface> {
+    let mut interfaces: Vec<NetworkInterface> = vec![];
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if !line.starts_with(char::is_whitespace) {
+            let mut interface = NetworkInterface::new(tokens[0].trim_end_matches(':'));
+            if let Some(flags) = tokens.iter().find(|it| it.starts_with("flags=")) {
+                interface.state = Some(if flags.contains("<UP") { "UP" } else { "DOWN" }.to_string());
+            }
+            interfaces.push(interface);
+        }
+        let interface = match interfaces.last_mut() {
+            None => continue,
+            Some(interface) => interface,
+        };
+        for (index, token) in tokens.iter().enumerate() {
+            let next = tokens.get(index + 1).copied().unwrap_or("");
+            match *token {
+                "ether" | "HWaddr" => interface.mac = Some(normalize_mac(next)),
+                "inet" | "inet6" => interface.add_address(next.trim_start_matches("addr:")),
+                "addr:" if index > 0 && tokens[index - 1] == "inet6" => interface.add_address(next),
+                "UP" if interface.state.is_none() => interface.state = Some("UP".to_string()),
+                _ => {}
+            }
+        }
+    }
+    interfaces
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{parse_ipconfig, parse_ip_addr, parse_ifconfig, NetworkInterface};
+
+    fn eth0() -> NetworkInterface {
+        NetworkInterface {
+            interface: "eth0".to_string(),
+            mac: Some("08:00:27:4e:66:a1".to_string()),
+            state: Some("UP".to_string()),
+            ipv4: vec!["192.168.1.20".to_string()],
+            ipv6: vec!["fe80::a00:27ff:fe4e:66a1".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ipconfig_interfaces_are_normalized() {
+        let ipconfig = "
+Windows IP Configuration
+
+   Host Name . . . . . . . . . . . . : DESKTOP-1
+
+Ethernet adapter Ethernet0:
+
+   Connection-specific DNS Suffix  . : localdomain
+   Description . . . . . . . . . . . : Intel(R) 82574L Gigabit Network Connection
+   Physical Address. . . . . . . . . : 00-0C-29-AA-BB-CC
+   DHCP Enabled. . . . . . . . . . . : Yes
+   Link-local IPv6 Address . . . . . : fe80::1234:5678:9abc:def0%12(Preferred)
+   IPv4 Address. . . . . . . . . . . : 192.168.1.10(Preferred)
+   Subnet Mask . . . . . . . . . . . : 255.255.255.0
+   Default Gateway . . . . . . . . . : 192.168.1.1
+   DNS Servers . . . . . . . . . . . : 192.168.1.1
+                                       8.8.8.8
+
+Tunnel adapter isatap.localdomain:
+
+   Media State . . . . . . . . . . . : Media disconnected
+";
+
+        assert_eq!(vec![
+            NetworkInterface {
+                interface: "Ethernet0".to_string(),
+                description: Some("Intel(R) 82574L Gigabit Network Connection".to_string()),
+                mac: Some("00:0c:29:aa:bb:cc".to_string()),
+                state: None,
+                ipv4: vec!["192.168.1.10".to_string()],
+                ipv6: vec!["fe80::1234:5678:9abc:def0".to_string()],
+                gateways: vec!["192.168.1.1".to_string()],
+                dns_servers: vec!["192.168.1.1".to_string(), "8.8.8.8".to_string()],
+                dhcp: Some(true),
+            },
+            NetworkInterface {
+                interface: "isatap.localdomain".to_string(),
+                state: Some("Media disconnected".to_string()),
+                ..Default::default()
+            },
+        ], parse_ipconfig(ipconfig));
+    }
+
+    #[test]
+    fn test_ip_addr_interfaces_are_normalized() {
+        let ip_addr = "\
+1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 qdisc noqueue state UNKNOWN group default qlen 1000
+    link/loopback 00:00:00:00:00:00 brd 00:00:00:00:00:00
+    inet 127.0.0.1/8 scope host lo
+2: eth0@if5: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc fq_codel state UP group default qlen 1000
+    link/ether 08:00:27:4e:66:a1 brd ff:ff:ff:ff:ff:ff
+    inet 192.168.1.20/24 brd 192.168.1.255 scope global dynamic eth0
+    inet6 fe80::a00:27ff:fe4e:66a1/64 scope link
+";
+
+        let interfaces = parse_ip_addr(ip_addr);
+
+        assert_eq!(2, interfaces.len());
+        assert_eq!(Some("UNKNOWN".to_string()), interfaces[0].state);
+        assert_eq!(eth0(), interfaces[1]);
+    }
+
+    #[test]
+    fn test_ifconfig_interfaces_are_normalized() {
+        let ifconfig = "\
+eth0: flags=4163<UP,BROADCAST,RUNNING,MULTICAST>  mtu 1500
+        inet 192.168.1.20  netmask 255.255.255.0  broadcast 192.168.1.255
+        inet6 fe80::a00:27ff:fe4e:66a1  prefixlen 64  scopeid 0x20<link>
+        ether 08:00:27:4e:66:a1  txqueuelen 1000  (Ethernet)
+
+eth1      Link encap:Ethernet  HWaddr 08:00:27:4E:66:A2
+          inet addr:10.0.0.5  Bcast:10.0.0.255  Mask:255.255.255.0
+          inet6 addr: fe80::a00:27ff:fe4e:66a2/64 Scope:Link
+          UP BROADCAST RUNNING MULTICAST  MTU:1500  Metric:1
+";
+
+        assert_eq!(vec![
+            eth0(),
+            NetworkInterface {
+                interface: "eth1".to_string(),
+                mac: Some("08:00:27:4e:66:a2".to_string()),
+                state: Some("UP".to_string()),
+                ipv4: vec!["10.0.0.5".to_string()],
+                ipv6: vec!["fe80::a00:27ff:fe4e:66a2".to_string()],
+                ..Default::default()
+            },
+        ], parse_ifconfig(ifconfig));
+    }
+
+    #[test]
+    fn test_error_output_has_no_interfaces() {
+        assert!(parse_ipconfig("Windows IP Configuration\n\n   Host Name . . . : DESKTOP-1\n").is_empty());
+        assert!(parse_ip_addr("").is_empty());
+    }
+}
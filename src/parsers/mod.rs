@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::fs::File;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+pub mod processes;
+
+pub use self::processes::*;
+
+pub mod connections;
+
+pub use self::connections::*;
+
+pub mod sessions;
+
+pub use self::sessions::*;
+
+pub mod interfaces;
+
+pub use self::interfaces::*;
+
+pub mod firewall;
+
+pub use self::firewall::*;
+
+// Parser turning the text output of a quick-evidence command into JSON records,
+// selected by the `parser` of an artifact in the acquisition profile.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Parser {
+    // Windows
+    Ipconfig,
+    Tasklist,
+    NetstatAno,
+    QueryUser,
+    NetshFirewall,
+    // Linux
+    IpAddr,
+    Ifconfig,
+    PsAux,
+    NetstatNatp,
+    Who,
+}
+
+// JSON document written next to a parsed report.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ParsedReport {
+    pub parser: Parser,
+    pub report: Option<String>,
+    pub records: serde_json::Value,
+}
+
+pub fn parse(parser: Parser, text: &str) -> serde_json::Value {
+    match parser {
+        Parser::Ipconfig => json!(parse_ipconfig(text)),
+        Parser::Tasklist => json!(parse_tasklist(text)),
+        Parser::NetstatAno => json!(parse_netstat_ano(text)),
+        Parser::QueryUser => json!(parse_query_user(text)),
+        Parser::NetshFirewall => json!(parse_netsh_firewall(text)),
+        Parser::IpAddr => json!(parse_ip_addr(text)),
+        Parser::Ifconfig => json!(parse_ifconfig(text)),
+        Parser::PsAux => json!(parse_ps_aux(text)),
+        Parser::NetstatNatp => json!(parse_netstat_natp(text)),
+        Parser::Who => json!(parse_who(text)),
+    }
+}
+
+pub fn parsed_report_path(report: &Path) -> PathBuf {
+    if report.extension().map(|it| it == "json").unwrap_or(false) {
+        report.with_extension("parsed.json")
+    } else {
+        report.with_extension("json")
+    }
+}
+
+// Parses the report and writes the records into a JSON file next to it. Returns the path of the JSON file.
+pub fn write_parsed(parser: Parser, report: &Path) -> io::Result<PathBuf> {
+    let text = String::from_utf8_lossy(&std::fs::read(report)?).to_string();
+    let document = ParsedReport {
+        parser,
+        report: report.file_name().map(|it| it.to_string_lossy().to_string()),
+        records: parse(parser, &text),
+    };
+    let path = parsed_report_path(report);
+    serde_json::to_writer_pretty(File::create(&path)?, &document)?;
+    Ok(path)
+}
+
+// Splits the line into `count - 1` whitespace separated fields and the rest of the line.
+pub(crate) fn fields(line: &str, count: usize) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut rest = line.trim();
+    while fields.len() + 1 < count && !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() {
+        fields.push(rest);
+    }
+    fields
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Option<T> {
+    text.chars().filter(|it| it.is_ascii_digit()).collect::<String>().parse().ok()
+}
@@ -0,0 +1,137 @@
+use serde::{Serialize, Deserialize};
+use crate::parsers::{fields, parse_number};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    pub user: Option<String>,
+    pub session: Option<String>,
+    pub memory_kb: Option<u64>,
+    pub command: Option<String>,
+}
+
+// Column ranges given by the `=== ====` line under the header of tasklist.
+pub(crate) fn columns(separator: &str) -> Vec<(usize, usize)> {
+    let mut columns = vec![];
+    let mut start = None;
+    for (index, character) in separator.char_indices().chain(std::iter::once((separator.len(), ' '))) {
+        match (character == '=', start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                columns.push((begin, index));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    columns
+}
+
+fn column<'a>(line: &'a str, columns: &[(usize, usize)], index: usize) -> &'a str {
+    let (start, end) = columns[index];
+    // the last column takes the rest of the line
+    let end = if index + 1 == columns.len() { line.len() } else { end.min(line.len()) };
+    line.get(start.min(line.len())..end).unwrap_or("").trim()
+}
+
+pub fn parse_tasklist(text: &str) -> Vec<Process> {
+    let mut lines = text.lines().skip_while(|line| !line.trim_start().starts_with('='));
+    let columns = match lines.next() {
+        None => return vec![],
+        Some(separator) => columns(separator),
+    };
+    if columns.len() < 5 {
+        return vec![];
+    }
+    lines.filter_map(|line| {
+        let pid = column(line, &columns, 1).parse().ok()?;
+        let session = column(line, &columns, 2);
+        Some(Process {
+            pid,
+            name: column(line, &columns, 0).to_string(),
+            user: None,
+            session: if session.is_empty() { None } else { Some(session.to_string()) },
+            memory_kb: parse_number(column(line, &columns, 4)),
+            command: None,
+        })
+    }).collect()
+}
+
+pub fn parse_ps_aux(text: &str) -> Vec<Process> {
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = fields(line, 11);
+            if fields.len() < 11 {
+                return None;
+            }
+            let command = fields[10];
+            let program = command.split_whitespace().next().unwrap_or(command);
+            Some(Process {
+                pid: fields[1].parse().ok()?,
+                name: program.rsplit('/').next().unwrap_or(program).trim_end_matches(':').to_string(),
+                user: Some(fields[0].to_string()),
+                session: if fields[6] == "?" { None } else { Some(fields[6].to_string()) },
+                memory_kb: fields[5].parse().ok(),
+                command: Some(command.to_string()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{parse_tasklist, parse_ps_aux, Process};
+
+    #[test]
+    fn test_tasklist_is_normalized() {
+        let tasklist = "
+Image Name                     PID Session Name        Session#    Mem Usage
+========================= ======== ================ =========== ============
+System Idle Process              0 Services                   0          8 K
+svchost.exe                    848 Services                   0     21,400 K
+";
+
+        let processes = parse_tasklist(tasklist);
+
+        assert_eq!(2, processes.len());
+        assert_eq!(Process {
+            pid: 848,
+            name: "svchost.exe".to_string(),
+            user: None,
+            session: Some("Services".to_string()),
+            memory_kb: Some(21400),
+            command: None,
+        }, processes[1]);
+        assert_eq!("System Idle Process", processes[0].name);
+    }
+
+    #[test]
+    fn test_ps_aux_is_normalized() {
+        let ps = "\
+USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND
+root           1  0.0  0.1 167640 11660 ?        Ss   Oct16   0:04 /sbin/init splash
+jano        2048  1.2  0.5  20000  5120 pts/0    S+   10:10   0:00 sshd: jano@pts/0
+";
+
+        let processes = parse_ps_aux(ps);
+
+        assert_eq!(Process {
+            pid: 1,
+            name: "init".to_string(),
+            user: Some("root".to_string()),
+            session: None,
+            memory_kb: Some(11660),
+            command: Some("/sbin/init splash".to_string()),
+        }, processes[0]);
+        assert_eq!("sshd", processes[1].name);
+        assert_eq!(Some("pts/0".to_string()), processes[1].session);
+    }
+
+    #[test]
+    fn test_error_output_has_no_processes() {
+        assert!(parse_tasklist("ERROR: Access denied").is_empty());
+        assert!(parse_ps_aux("USER PID %CPU %MEM VSZ RSS TTY STAT START TIME COMMAND\nps: error\n").is_empty());
+    }
+}
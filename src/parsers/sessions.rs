@@ -0,0 +1,132 @@
+use serde::{Serialize, Deserialize};
+use crate::parsers::fields;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UserSession {
+    pub user: String,
+    pub session: Option<String>,
+    pub id: Option<u32>,
+    pub state: Option<String>,
+    pub idle: Option<String>,
+    pub logon_time: Option<String>,
+    pub host: Option<String>,
+}
+
+pub fn parse_query_user(text: &str) -> Vec<UserSession> {
+    text.lines()
+        .skip_while(|line| !line.contains("USERNAME"))
+        .skip(1)
+        .filter_map(|line| {
+            // the current session is marked by `>`
+            let line = line.trim_start_matches(|it: char| it == '>' || it.is_whitespace());
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.len() < 5 {
+                return None;
+            }
+            // a disconnected session has no session name
+            let (session, rest) = match tokens[1].parse::<u32>() {
+                Ok(_) => (None, 1),
+                Err(_) => (Some(tokens[1].to_string()), 2),
+            };
+            Some(UserSession {
+                user: tokens[0].to_string(),
+                session,
+                id: tokens.get(rest)?.parse().ok(),
+                state: tokens.get(rest + 1).map(|it| it.to_string()),
+                idle: tokens.get(rest + 2).map(|it| it.to_string()),
+                logon_time: Some(tokens[rest + 3..].join(" ")).filter(|it| !it.is_empty()),
+                host: None,
+            })
+        })
+        .collect()
+}
+
+pub fn parse_who(text: &str) -> Vec<UserSession> {
+    text.lines()
+        .filter_map(|line| {
+            let fields = fields(line, 3);
+            if fields.len() < 3 {
+                return None;
+            }
+            let (logon_time, host) = match fields[2].find('(') {
+                Some(start) => (
+                    fields[2][..start].trim(),
+                    Some(fields[2][start + 1..].trim_end().trim_end_matches(')').to_string()),
+                ),
+                None => (fields[2], None),
+            };
+            Some(UserSession {
+                user: fields[0].to_string(),
+                session: Some(fields[1].to_string()),
+                id: None,
+                state: None,
+                idle: None,
+                logon_time: Some(logon_time.to_string()),
+                host,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{parse_query_user, parse_who, UserSession};
+
+    #[test]
+    fn test_query_user_sessions_are_normalized() {
+        let query_user = " USERNAME              SESSIONNAME        ID  STATE   IDLE TIME  LOGON TIME
+>jano                  console             1  Active      none   10/10/2020 10:10 AM
+ admin                                     2  Disc           5   10/10/2020 9:00 AM
+";
+
+        assert_eq!(vec![
+            UserSession {
+                user: "jano".to_string(),
+                session: Some("console".to_string()),
+                id: Some(1),
+                state: Some("Active".to_string()),
+                idle: Some("none".to_string()),
+                logon_time: Some("10/10/2020 10:10 AM".to_string()),
+                host: None,
+            },
+            UserSession {
+                user: "admin".to_string(),
+                session: None,
+                id: Some(2),
+                state: Some("Disc".to_string()),
+                idle: Some("5".to_string()),
+                logon_time: Some("10/10/2020 9:00 AM".to_string()),
+                host: None,
+            },
+        ], parse_query_user(query_user));
+    }
+
+    #[test]
+    fn test_who_sessions_are_normalized() {
+        let who = "\
+jano     tty7         2020-10-10 10:10 (:0)
+jano     pts/0        2020-10-10 10:12 (192.168.1.5)
+root     tty1         2020-10-10 09:00
+";
+
+        let sessions = parse_who(who);
+
+        assert_eq!(3, sessions.len());
+        assert_eq!(UserSession {
+            user: "jano".to_string(),
+            session: Some("pts/0".to_string()),
+            id: None,
+            state: None,
+            idle: None,
+            logon_time: Some("2020-10-10 10:12".to_string()),
+            host: Some("192.168.1.5".to_string()),
+        }, sessions[1]);
+        assert_eq!(None, sessions[2].host);
+    }
+
+    #[test]
+    fn test_no_sessions() {
+        assert!(parse_query_user("No User exists for *").is_empty());
+        assert!(parse_who("").is_empty());
+    }
+}
@@ -0,0 +1,546 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::remote::{Computer, Cmd, Powershell, WindowsRemoteFileHandler, Rdp, Wmi, Ssh, RemoteFileCopier, ReDownloader, Readiness};
+use crate::evidence_acquirer::EvidenceAcquirer;
+use crate::events_acquirer::EventsAcquirer;
+use crate::event_timeline::EventFilter;
+use crate::registry_acquirer::RegistryAcquirer;
+use crate::hive_acquirer::HiveAcquirer;
+use crate::memory_acquirer::{MemoryAcquirer, LinuxMemoryTool};
+use crate::command_runner::CommandRunner;
+use crate::file_acquirer::download_files;
+use crate::process_runner::CommandOutcome;
+use crate::profile::Profile;
+use crate::cleanup::{clean_up, registered_remote_files, retained_remote_files};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+#[cfg(any(test, feature = "mock"))]
+use crate::remote::{MockTarget, MockConnector, MockCopier, Compression};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    PsExec,
+    PsRemote,
+    Wmi,
+    Rdp,
+    Ssh,
+    // in-memory fake of the target, see `AcquisitionPlan::mock_target`
+    #[cfg(any(test, feature = "mock"))]
+    Mock,
+}
+
+impl Method {
+    pub fn windows() -> Vec<Method> {
+        vec![Method::PsExec, Method::Wmi, Method::PsRemote, Method::Rdp]
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Method::PsExec => "psexec",
+            Method::PsRemote => "psremote",
+            Method::Wmi => "wmi",
+            Method::Rdp => "rdp",
+            Method::Ssh => "ssh",
+            #[cfg(any(test, feature = "mock"))]
+            Method::Mock => "mock",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    ReDownload,
+    Evidence,
+    Events,
+    CustomCommands,
+    Registry,
+    Files,
+    Memory,
+}
+
+// Everything needed to acquire evidence from one target. Start from `AcquisitionPlan::new`
+// and override the fields using the struct update syntax.
+#[derive(Clone)]
+pub struct AcquisitionPlan {
+    pub computer: Computer,
+    pub methods: Vec<Method>,
+    pub local_store_directory: PathBuf,
+    pub remote_temp_storage: PathBuf,
+    pub key_file: Option<PathBuf>,
+    pub known_hosts: Option<PathBuf>,
+    pub nla: bool,
+    pub no_compression: bool,
+    pub timeout: Duration,
+    pub readiness: Readiness,
+    pub profile: Profile,
+    pub evidence: bool,
+    pub events: bool,
+    // exported event logs are parsed into JSON lines with the events accepted by the filter
+    pub events_timeline: Option<EventFilter>,
+    pub registry: bool,
+    // the registry is saved as binary hives instead of exported to text
+    pub registry_hives: bool,
+    pub custom_commands: Option<PathBuf>,
+    pub search_files: Option<PathBuf>,
+    pub re_download: Option<PathBuf>,
+    pub memory_image: bool,
+    // uploaded to Linux targets to image their memory
+    pub linux_memory_tool: LinuxMemoryTool,
+    // target of `Method::Mock`
+    #[cfg(any(test, feature = "mock"))]
+    pub mock_target: MockTarget,
+}
+
+impl AcquisitionPlan {
+    pub fn new(computer: Computer, local_store_directory: &Path) -> AcquisitionPlan {
+        AcquisitionPlan {
+            computer,
+            methods: vec![],
+            local_store_directory: local_store_directory.to_path_buf(),
+            remote_temp_storage: PathBuf::from("C:\\Users\\Public"),
+            key_file: None,
+            known_hosts: None,
+            nla: false,
+            no_compression: false,
+            timeout: Duration::from_secs(300),
+            readiness: Readiness::default(),
+            profile: Profile::built_in(),
+            evidence: true,
+            events: true,
+            events_timeline: Some(EventFilter::high_value()),
+            registry: true,
+            registry_hives: false,
+            custom_commands: None,
+            search_files: None,
+            re_download: None,
+            memory_image: false,
+            linux_memory_tool: LinuxMemoryTool::default(),
+            #[cfg(any(test, feature = "mock"))]
+            mock_target: MockTarget::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StageResult {
+    pub stage: Stage,
+    pub method: Method,
+    pub outcome: io::Result<CommandOutcome>,
+}
+
+impl StageResult {
+    pub fn is_success(&self) -> bool {
+        self.outcome.as_ref().map(|it| it.is_success()).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AcquisitionReport {
+    pub results: Vec<StageResult>,
+}
+
+impl AcquisitionReport {
+    fn add(&mut self, stage: Stage, method: Method, outcome: io::Result<CommandOutcome>) {
+        self.results.push(StageResult { stage, method, outcome });
+    }
+
+    fn add_all(&mut self, stage: Stage, method: Method, outcomes: Vec<io::Result<CommandOutcome>>) {
+        for outcome in outcomes {
+            self.add(stage, method, outcome);
+        }
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.results.iter().filter(|it| it.is_success()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.succeeded()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.succeeded() > 0 && self.failed() == 0
+    }
+}
+
+// Operations that do not run a single command are reported as a successful empty outcome.
+fn completed(result: io::Result<()>) -> io::Result<CommandOutcome> {
+    result.map(|_| CommandOutcome {
+        exit_code: Some(0),
+        ..Default::default()
+    })
+}
+
+// Plans being run, whose remote files are cleaned up when Gargamel is interrupted.
+static ACTIVE_PLANS: Mutex<Vec<AcquisitionPlan>> = Mutex::new(Vec::new());
+
+// Runs the plan and deletes the remote files it left on the target afterwards, even if it panics.
+// Acquired files are recorded in the manifest, journal and cleanup registry of the case when they were started.
+pub fn run_plan(plan: &AcquisitionPlan) -> AcquisitionReport {
+    ACTIVE_PLANS.lock().unwrap().push(plan.clone());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run_stages(plan)));
+    clean_up_plan(plan);
+    CANCELLED_PLANS.lock().unwrap().retain(|address| *address != plan.computer.address);
+    let mut active_plans = ACTIVE_PLANS.lock().unwrap();
+    if let Some(position) = active_plans.iter().position(|active| active.computer.address == plan.computer.address) {
+        active_plans.remove(position);
+    }
+    drop(active_plans);
+    match result {
+        Ok(report) => report,
+        Err(cause) => panic::resume_unwind(cause),
+    }
+}
+
+// Deletes the remote files registered for cleanup on the computer of the plan using any of its methods.
+// Returns the number of files which could not be deleted.
+pub fn clean_up_plan(plan: &AcquisitionPlan) -> usize {
+    if registered_remote_files(&plan.computer).is_empty() && retained_remote_files(&plan.computer).is_empty() {
+        return 0;
+    }
+    let copiers: Vec<Box<dyn RemoteFileCopier>> = plan.methods.iter()
+        .filter_map(|method| file_copier(plan, *method))
+        .collect();
+    let copiers: Vec<&dyn RemoteFileCopier> = copiers.iter().map(|it| it.as_ref()).collect();
+    clean_up(&plan.computer, &copiers)
+}
+
+// Addresses of computers whose plans stop before their next operation, e.g. when they exceeded the time budget.
+static CANCELLED_PLANS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn cancel_plan(address: &str) {
+    CANCELLED_PLANS.lock().unwrap().push(address.to_string());
+}
+
+// Acquirers check it before each artifact or command, so a cancelled plan stops within one operation.
+pub fn is_cancelled(computer: &Computer) -> bool {
+    CANCELLED_PLANS.lock().unwrap().contains(&computer.address)
+}
+
+// Methods of the plan, the iteration stops once the plan is cancelled.
+fn methods(plan: &AcquisitionPlan) -> impl Iterator<Item = Method> + '_ {
+    plan.methods.iter().copied().take_while(move |_| {
+        let cancelled = is_cancelled(&plan.computer);
+        if cancelled {
+            debug!("Acquisition from {} is cancelled", plan.computer.address);
+        }
+        !cancelled
+    })
+}
+
+// Remote files of the computer are polled with the readiness of the plan being run on it.
+pub fn active_plan_readiness(computer: &Computer) -> Option<Readiness> {
+    ACTIVE_PLANS.lock().unwrap().iter()
+        .find(|plan| plan.computer.address == computer.address)
+        .map(|plan| plan.readiness)
+}
+
+pub fn clean_up_active_plans() {
+    let plans = ACTIVE_PLANS.lock().unwrap().clone();
+    for plan in plans.iter() {
+        clean_up_plan(plan);
+    }
+}
+
+fn run_stages(plan: &AcquisitionPlan) -> AcquisitionReport {
+    let mut report = AcquisitionReport::default();
+    let store_directory = plan.local_store_directory.as_path();
+
+    if let Some(remote_file) = &plan.re_download {
+        for method in methods(plan) {
+            let copier = match file_copier(plan, method) {
+                None => continue,
+                Some(copier) => copier,
+            };
+            info!("Trying to download {} from {} using method {}", remote_file.display(), plan.computer.address, copier.method_name());
+            let re_downloader = ReDownloader {
+                copier: copier.as_ref(),
+                target_dir: store_directory,
+            };
+            let result = if re_downloader.retry_download(remote_file) {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!("Cannot download {}", remote_file.display())))
+            };
+            report.add(Stage::ReDownload, method, completed(result));
+        }
+    }
+
+    if plan.evidence {
+        for method in methods(plan) {
+            if let Some(acquirer) = evidence_acquirer(plan, method) {
+                report.add_all(Stage::Evidence, method, acquirer.run_all());
+            }
+        }
+    }
+
+    if plan.events {
+        for method in methods(plan) {
+            if let Some(acquirer) = events_acquirer(plan, method) {
+                report.add_all(Stage::Events, method, acquirer.acquire());
+            }
+        }
+    }
+
+    if let Some(custom_commands_path) = &plan.custom_commands {
+        for method in methods(plan) {
+            if let Some(command_runner) = command_runner(plan, method) {
+                info!("Running commands using method {}", command_runner.connector.connect_method_name());
+                report.add_all(Stage::CustomCommands, method, command_runner.run_commands(
+                    custom_commands_path,
+                    Some(plan.timeout),
+                ));
+            }
+        }
+    }
+
+    if plan.registry {
+        for method in methods(plan) {
+            if plan.registry_hives {
+                if let Some(acquirer) = hive_acquirer(plan, method) {
+                    report.add_all(Stage::Registry, method, acquirer.acquire());
+                }
+            } else if let Some(acquirer) = registry_acquirer(plan, method) {
+                report.add_all(Stage::Registry, method, acquirer.acquire());
+            }
+        }
+    }
+
+    if let Some(search_files_path) = &plan.search_files {
+        for method in methods(plan) {
+            let copier = match file_copier(plan, method) {
+                None => continue,
+                Some(copier) => copier,
+            };
+            info!("Downloading specified files using {}", copier.copier_impl().method_name());
+            let result = download_files(
+                search_files_path,
+                store_directory,
+                copier.as_ref(),
+            );
+            let downloaded = result.is_ok();
+            report.add(Stage::Files, method, completed(result));
+            if downloaded {
+                info!("Files in {} successfully transferred.", search_files_path.display());
+                break;
+            }
+        }
+    }
+
+    if plan.memory_image {
+        for method in methods(plan) {
+            let acquirer = match memory_acquirer(plan, method) {
+                None => continue,
+                Some(acquirer) => acquirer,
+            };
+            info!("Running memory acquirer using method {}", acquirer.connector.connect_method_name());
+            let image_res = acquirer.image_memory();
+            let imaged = image_res.is_ok();
+            report.add(Stage::Memory, method, completed(image_res));
+            if imaged {
+                break;
+            }
+        }
+    }
+
+    report
+}
+
+fn evidence_acquirer<'a>(plan: &'a AcquisitionPlan, method: Method) -> Option<EvidenceAcquirer<'a>> {
+    let computer = plan.computer.clone();
+    let store_directory = plan.local_store_directory.as_path();
+    let remote_temp_storage = plan.remote_temp_storage.clone();
+    let acquirer = match method {
+        Method::PsExec => EvidenceAcquirer::psexec(computer, store_directory, remote_temp_storage, &plan.profile),
+        Method::PsRemote => EvidenceAcquirer::psremote(computer, store_directory, remote_temp_storage, &plan.profile),
+        Method::Wmi => EvidenceAcquirer::wmi(computer, store_directory, remote_temp_storage, &plan.profile),
+        Method::Rdp => EvidenceAcquirer::rdp(computer, store_directory, plan.nla, remote_temp_storage, &plan.profile),
+        Method::Ssh => EvidenceAcquirer::ssh(
+            computer,
+            store_directory,
+            plan.key_file.clone(),
+            plan.known_hosts.clone(),
+            &plan.profile,
+        ),
+        #[cfg(any(test, feature = "mock"))]
+        Method::Mock => EvidenceAcquirer::new(store_directory, Box::new(mock_connector(plan)), &plan.profile),
+    };
+    Some(acquirer)
+}
+
+fn events_acquirer<'a>(plan: &'a AcquisitionPlan, method: Method) -> Option<EventsAcquirer<'a>> {
+    let computer = plan.computer.clone();
+    let store_directory = plan.local_store_directory.as_path();
+    let remote_temp_storage = plan.remote_temp_storage.clone();
+    let acquirer = match method {
+        Method::PsExec =>
+            EventsAcquirer::psexec(store_directory, computer, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::PsRemote =>
+            EventsAcquirer::psremote(store_directory, computer, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Wmi =>
+            EventsAcquirer::wmi(store_directory, computer, plan.timeout, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Rdp =>
+            EventsAcquirer::rdp(store_directory, computer, plan.timeout, plan.nla, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Ssh => return None,
+        #[cfg(any(test, feature = "mock"))]
+        Method::Mock => 
+            EventsAcquirer::new(store_directory, Box::new(mock_connector(plan)), Some(plan.timeout), Compression::No, &plan.profile),
+    };
+    Some(acquirer.with_timeline(plan.events_timeline.clone()))
+}
+
+fn registry_acquirer<'a>(plan: &'a AcquisitionPlan, method: Method) -> Option<RegistryAcquirer<'a>> {
+    let computer = plan.computer.clone();
+    let store_directory = plan.local_store_directory.as_path();
+    let remote_temp_storage = plan.remote_temp_storage.clone();
+    let acquirer = match method {
+        Method::PsExec =>
+            RegistryAcquirer::psexec(store_directory, computer, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::PsRemote =>
+            RegistryAcquirer::psremote(store_directory, computer, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Wmi =>
+            RegistryAcquirer::wmi(store_directory, computer, plan.timeout, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Rdp =>
+            RegistryAcquirer::rdp(store_directory, computer, plan.timeout, plan.nla, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Ssh => return None,
+        #[cfg(any(test, feature = "mock"))]
+        Method::Mock => 
+            RegistryAcquirer::new(store_directory, Box::new(mock_connector(plan)), Some(plan.timeout), Compression::No, &plan.profile),
+    };
+    Some(acquirer)
+}
+
+fn hive_acquirer<'a>(plan: &'a AcquisitionPlan, method: Method) -> Option<HiveAcquirer<'a>> {
+    let computer = plan.computer.clone();
+    let store_directory = plan.local_store_directory.as_path();
+    let remote_temp_storage = plan.remote_temp_storage.clone();
+    let acquirer = match method {
+        Method::PsExec =>
+            HiveAcquirer::psexec(store_directory, computer, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::PsRemote =>
+            HiveAcquirer::psremote(store_directory, computer, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Wmi =>
+            HiveAcquirer::wmi(store_directory, computer, plan.timeout, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Rdp =>
+            HiveAcquirer::rdp(store_directory, computer, plan.timeout, plan.nla, plan.no_compression, remote_temp_storage, &plan.profile),
+        Method::Ssh => return None,
+        #[cfg(any(test, feature = "mock"))]
+        Method::Mock => 
+            HiveAcquirer::new(store_directory, Box::new(mock_connector(plan)), Some(plan.timeout), Compression::No, &plan.profile),
+    };
+    Some(acquirer)
+}
+
+fn command_runner<'a>(plan: &'a AcquisitionPlan, method: Method) -> Option<CommandRunner<'a>> {
+    let computer = plan.computer.clone();
+    let store_directory = plan.local_store_directory.as_path();
+    let remote_temp_storage = plan.remote_temp_storage.clone();
+    let runner = match method {
+        Method::PsExec => CommandRunner::psexec(computer, store_directory, remote_temp_storage),
+        Method::PsRemote => CommandRunner::psremote(computer, store_directory, remote_temp_storage),
+        Method::Wmi => CommandRunner::wmi(computer, store_directory, remote_temp_storage),
+        Method::Rdp => CommandRunner::rdp(computer, store_directory, plan.nla, remote_temp_storage),
+        Method::Ssh => CommandRunner::ssh(computer, store_directory, plan.key_file.clone(), plan.known_hosts.clone()),
+        #[cfg(any(test, feature = "mock"))]
+        Method::Mock => CommandRunner::new(Box::new(mock_connector(plan)), store_directory, true),
+    };
+    Some(runner)
+}
+
+fn memory_acquirer<'a>(plan: &'a AcquisitionPlan, method: Method) -> Option<MemoryAcquirer<'a>> {
+    let computer = plan.computer.clone();
+    let store_directory = plan.local_store_directory.as_path();
+    let remote_temp_storage = plan.remote_temp_storage.clone();
+    let acquirer = match method {
+        Method::PsExec =>
+            MemoryAcquirer::psexec(computer, store_directory, plan.no_compression, remote_temp_storage),
+        Method::PsRemote =>
+            MemoryAcquirer::psremote(computer, store_directory, plan.no_compression, remote_temp_storage),
+        Method::Wmi =>
+            MemoryAcquirer::wmi(computer, store_directory, plan.timeout, plan.timeout, plan.no_compression, remote_temp_storage),
+        Method::Rdp =>
+            MemoryAcquirer::rdp(computer, store_directory, plan.nla, plan.timeout, plan.timeout, plan.no_compression, remote_temp_storage),
+        Method::Ssh =>
+            MemoryAcquirer::ssh(computer, store_directory, plan.key_file.clone(), plan.known_hosts.clone(), plan.timeout, plan.no_compression, plan.linux_memory_tool.clone()),
+        #[cfg(any(test, feature = "mock"))]
+        Method::Mock => MemoryAcquirer {
+            local_store_directory: store_directory,
+            connector: Box::new(mock_connector(plan)),
+            image_timeout: Some(plan.timeout),
+            compress_timeout: None,
+            compression: Compression::No,
+            linux_tool: plan.linux_memory_tool.clone(),
+        },
+    };
+    Some(acquirer)
+}
+
+fn file_copier(plan: &AcquisitionPlan, method: Method) -> Option<Box<dyn RemoteFileCopier>> {
+    let computer = plan.computer.clone();
+    let copier: Box<dyn RemoteFileCopier> = match method {
+        Method::PsExec => Box::new(WindowsRemoteFileHandler::new(computer, Box::new(Cmd {}))),
+        Method::PsRemote => Box::new(WindowsRemoteFileHandler::new(computer, Box::new(Powershell {}))),
+        Method::Wmi => Box::new(Wmi {
+            computer,
+            remote_temp_storage: plan.remote_temp_storage.clone(),
+        }),
+        Method::Rdp => Box::new(Rdp {
+            computer,
+            nla: plan.nla,
+            remote_temp_storage: plan.remote_temp_storage.clone(),
+        }),
+        Method::Ssh => Box::new(Ssh::new(computer, plan.key_file.clone(), plan.known_hosts.clone())),
+        #[cfg(any(test, feature = "mock"))]
+        Method::Mock => Box::new(MockCopier {
+            computer,
+            target: plan.mock_target.clone(),
+        }),
+    };
+    Some(copier)
+}
+
+#[cfg(any(test, feature = "mock"))]
+fn mock_connector(plan: &AcquisitionPlan) -> MockConnector {
+    MockConnector::new(plan.computer.clone(), &plan.remote_temp_storage, &plan.mock_target)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan::{AcquisitionPlan, ACTIVE_PLANS};
+    use crate::remote::{Computer, Readiness, readiness};
+    use std::path::Path;
+    use std::time::Duration;
+
+    fn computer(address: &str) -> Computer {
+        Computer {
+            address: address.to_string(),
+            username: "Jano".to_string(),
+            domain: None,
+            password: None,
+        }
+    }
+
+    #[test]
+    fn test_remote_files_are_polled_with_readiness_of_active_plan() {
+        let plan = AcquisitionPlan {
+            readiness: Readiness {
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(20),
+                timeout: Duration::from_secs(1),
+            },
+            ..AcquisitionPlan::new(computer("10.0.0.31"), Path::new("evidence"))
+        };
+        ACTIVE_PLANS.lock().unwrap().push(plan.clone());
+
+        assert_eq!(plan.readiness, readiness(&plan.computer));
+        assert_eq!(Readiness::default(), readiness(&computer("10.0.0.32")));
+
+        ACTIVE_PLANS.lock().unwrap().retain(|active| active.computer.address != plan.computer.address);
+    }
+}
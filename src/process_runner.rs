@@ -1,98 +1,191 @@
-use std::process::{Command, Stdio};
+use std::process::{Command, Stdio, Output};
 use std::ops::Not;
 use std::path::{Path, PathBuf};
-use std::io::Result;
+use std::io::{Result, Read};
 use crate::remote::Computer;
 use std::fs::File;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::thread;
+use std::sync::{Arc, Mutex};
 use wait_timeout::ChildExt;
 
 extern crate dunce;
 
+#[derive(Clone, Debug, Default)]
+pub struct CommandOutcome {
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+    pub report_path: Option<PathBuf>,
+}
+
+impl CommandOutcome {
+    fn from_output(output: Output, duration: Duration) -> CommandOutcome {
+        CommandOutcome {
+            exit_code: output.status.code(),
+            timed_out: false,
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            duration,
+            report_path: None,
+        }
+    }
+
+    pub fn with_report_path(self, report_path: Option<PathBuf>) -> CommandOutcome {
+        CommandOutcome {
+            report_path,
+            ..self
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        !self.timed_out && self.exit_code == Some(0)
+    }
+}
+
 pub fn run_process_blocking(
     command_name: &str,
     command_args: &[String],
-) -> Result<()> {
+) -> Result<CommandOutcome> {
     trace!("Starting process {}, with args: {:?}", command_name, command_args);
     let mut command = Command::new(command_name);
     // command.stdout(Stdio::null());
     if command_args.is_empty().not() {
         command.args(command_args);
     }
+    let started = Instant::now();
     let output = command.output()?;
-    trace!("Command {} output: {}", command_name, String::from_utf8_lossy(&output.stdout));
-    trace!("Command {} error: {}", command_name, String::from_utf8_lossy(&output.stderr));
-    Ok(())
+    log_output(command_name, &output.stdout, &output.stderr);
+    Ok(CommandOutcome::from_output(output, started.elapsed()))
 }
 
-pub fn run_piped_processes_blocking(
-    command_name_first: &str,
-    command_args_first: &[String],
-    command_name_second: &str,
-    command_args_second: &[String],
-) -> Result<()> {
-    let mut first = Command::new(command_name_first);
-    if !command_args_first.is_empty() {
-        first.args(command_args_first);
-    }
-    trace!("Starting process \"{}\" with params {:?}", command_name_first, command_args_first);
-    let mut first = first.stdout(Stdio::piped())
-        .spawn()?;
-
-    if let Some(first_output) = first.stdout.take() {
-        let mut second = Command::new(command_name_second);
-        second.stdin(first_output);
-        if !command_args_second.is_empty() {
-            second.args(command_args_second);
-        }
-        trace!("Starting process {} with params {:?}", command_name_second, command_args_second);
-        let output = second.output()?;
-        trace!("Command {} output: {}", command_name_second, String::from_utf8_lossy(&output.stdout));
-        trace!("Command {} error: {}", command_name_second, String::from_utf8_lossy(&output.stderr));
-    } else {
-        trace!("Child not invoked")
-    }
-    Ok(())
+fn log_output(command_name: &str, stdout: &[u8], stderr: &[u8]) {
+    trace!("Command {} output: {}", command_name, String::from_utf8_lossy(stdout));
+    trace!("Command {} error: {}", command_name, String::from_utf8_lossy(stderr));
 }
 
 pub fn run_process_blocking_maybe_timed(
     command_name: &str,
     command_args: &[String],
     wait_for: Option<Duration>,
-) -> Result<()> {
+) -> Result<CommandOutcome> {
     match wait_for {
         None => run_process_blocking(command_name, command_args),
         Some(wait) => run_process_blocking_timed(command_name, command_args, wait),
     }
 }
 
+// Processes spawned by the child may still hold the pipes open after it exited or was killed,
+// the output is awaited only this long.
+const KILLED_OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+// Output read by a background thread, available also while the thread is still blocked on the pipe.
+struct OutputReader {
+    handle: thread::JoinHandle<()>,
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl OutputReader {
+    fn start<R: Read + Send + 'static>(source: Option<R>) -> OutputReader {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let read = buffer.clone();
+        let handle = thread::spawn(move || {
+            let mut source = match source {
+                None => return,
+                Some(source) => source,
+            };
+            let mut chunk = [0u8; 8192];
+            loop {
+                match source.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(size) => read.lock().unwrap().extend_from_slice(&chunk[..size]),
+                }
+            }
+        });
+        OutputReader { handle, buffer }
+    }
+
+    // Returns the output read until the pipe closed, or until the grace period ended.
+    fn finish(self, grace: Duration) -> Vec<u8> {
+        let deadline = Instant::now() + grace;
+        while !self.handle.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if self.handle.is_finished() {
+            let _ = self.handle.join();
+        }
+        std::mem::take(&mut *self.buffer.lock().unwrap())
+    }
+}
+
 pub fn run_process_blocking_timed(
     command_name: &str,
     command_args: &[String],
     wait_for: Duration,
-) -> Result<()> {
+) -> Result<CommandOutcome> {
     trace!("Starting process {}, with args: {:?} and timeout of {} seconds", command_name, command_args, wait_for.as_secs());
     let mut command = Command::new(command_name);
     if command_args.is_empty().not() {
         command.args(command_args);
     }
-    let mut child = command.spawn()?;
-    match child.wait_timeout(wait_for)? {
-        Some(_) => {}
+    let started = Instant::now();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout_reader = OutputReader::start(child.stdout.take());
+    let stderr_reader = OutputReader::start(child.stderr.take());
+    let (exit_code, timed_out) = match child.wait_timeout(wait_for)? {
+        Some(status) => (status.code(), false),
         None => {
             // child hasn't exited yet
-            match child.kill() {
-                Ok(_) => {}
-                Err(_) => {}
-            }
+            let _ = child.kill();
+            let _ = child.wait();
 
             trace!("Process \"{} {}\" reached time out", command_name, command_args.join(" "));
+            (None, true)
         }
     };
-    Ok(())
+    let stdout = stdout_reader.finish(KILLED_OUTPUT_GRACE);
+    let stderr = stderr_reader.finish(KILLED_OUTPUT_GRACE);
+    log_output(command_name, &stdout, &stderr);
+    if timed_out {
+        // the output of a killed child is partial, but may tell why it did not finish
+        debug!("Process {} timed out, its output: {} {}",
+               command_name,
+               String::from_utf8_lossy(&stdout).trim(),
+               String::from_utf8_lossy(&stderr).trim()
+        );
+    }
+    Ok(CommandOutcome {
+        exit_code,
+        timed_out,
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        duration: started.elapsed(),
+        report_path: None,
+    })
 }
 
 
+pub fn report_file_name(
+    remote_computer: &Computer,
+    filename_prefix: &str,
+    method_name: &str,
+    file_extension: &str,
+) -> String {
+    let address_formatted = remote_computer.address.replace(".", "-");
+    format!("{}-{}-{}-{}.{}",
+            method_name,
+            filename_prefix,
+            address_formatted,
+            remote_computer.username.replace(" ", ""),
+            file_extension
+    )
+}
+
 pub fn create_report_path(
     remote_computer: &Computer,
     store_directory: &Path,
@@ -100,19 +193,41 @@ pub fn create_report_path(
     method_name: &str,
     file_extension: &str,
 ) -> PathBuf {
-    let address_formatted = remote_computer.address.replace(".", "-");
-    let filename = format!("{}-{}-{}-{}.{}",
-                           method_name,
-                           filename_prefix,
-                           address_formatted,
-                           remote_computer.username.replace(" ", ""),
-                           file_extension
-    );
+    let filename = report_file_name(remote_computer, filename_prefix, method_name, file_extension);
     let file_path = store_directory.join(filename);
     {
-        File::create(&file_path).expect(&format!("Cannot create file {}", file_path.display()));
+        File::create(&file_path).unwrap_or_else(|_| panic!("Cannot create file {}", file_path.display()));
     }
     let result = dunce::canonicalize(file_path).expect("Cannot canonicalize");
     trace!("Report will be saved at {}", result.display());
     result
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::process_runner::run_process_blocking_timed;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[cfg(unix)]
+    fn test_timed_out_process_returns_partial_output() {
+        let args = vec!["-c".to_string(), "echo partial; echo failing >&2; sleep 10".to_string()];
+        let outcome = run_process_blocking_timed("sh", &args, Duration::from_millis(500)).unwrap();
+        assert!(outcome.timed_out);
+        assert_eq!("partial\n", outcome.stdout);
+        assert_eq!("failing\n", outcome.stderr);
+        assert!(outcome.duration < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_output_held_by_background_process_is_not_awaited() {
+        let args = vec!["-c".to_string(), "echo done; sleep 10 &".to_string()];
+        let started = Instant::now();
+        let outcome = run_process_blocking_timed("sh", &args, Duration::from_secs(5)).unwrap();
+        assert!(!outcome.timed_out);
+        assert_eq!(Some(0), outcome.exit_code);
+        assert_eq!("done\n", outcome.stdout);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
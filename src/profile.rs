@@ -0,0 +1,320 @@
+use std::path::Path;
+use std::io;
+use std::io::ErrorKind;
+use std::time::Duration;
+use serde::Deserialize;
+use crate::remote::{HostPlatform, sh_quoted};
+use crate::parsers::Parser;
+
+const DEFAULT_PROFILE: &str = include_str!("../profiles/default.toml");
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[derive(Default)]
+pub enum Category {
+    #[default]
+    Evidence,
+    Registry,
+    // binary registry hives, acquired instead of the registry exports with --hives
+    Hives,
+    Events,
+}
+
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Artifact {
+    // named after the channel when omitted for a channel artifact
+    #[serde(default)]
+    pub name: String,
+    pub os: HostPlatform,
+    #[serde(default)]
+    pub category: Category,
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub script: Option<String>,
+    #[serde(default)]
+    pub file: Option<String>,
+    // Windows event log channel exported by wevtutil
+    #[serde(default)]
+    pub channel: Option<String>,
+    // XPath query selecting the exported events of a channel
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default = "default_extension")]
+    pub extension: String,
+    #[serde(default)]
+    pub large: bool,
+    #[serde(default = "default_compress")]
+    pub compress: bool,
+    #[serde(default)]
+    pub overwrite_switch: Option<String>,
+    #[serde(default)]
+    pub elevated: bool,
+    #[serde(default)]
+    pub requires: Option<String>,
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub parser: Option<Parser>,
+}
+
+fn default_extension() -> String {
+    "txt".to_string()
+}
+
+fn default_compress() -> bool {
+    true
+}
+
+impl Artifact {
+    pub fn command_line(&self) -> Vec<String> {
+        match &self.script {
+            // the remote report path is appended as the first script argument
+            Some(script) => vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                sh_quoted(script),
+                "sh".to_string(),
+            ],
+            None => self.command.clone(),
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+
+    // Switches appended after the remote file path of a large artifact.
+    pub fn switches(&self) -> Vec<String> {
+        self.overwrite_switch.iter()
+            .cloned()
+            .chain(self.query.iter().map(|query| format!("/q:{}", query)))
+            .collect()
+    }
+
+    // e.g. `events-microsoft-windows-powershell-operational` for `Microsoft-Windows-PowerShell/Operational`
+    pub fn channel_name(channel: &str) -> String {
+        let name: String = channel.to_lowercase().chars()
+            .map(|it| if it.is_alphanumeric() { it } else { '-' })
+            .collect();
+        format!("events-{}", name.split('-').filter(|it| !it.is_empty()).collect::<Vec<&str>>().join("-"))
+    }
+
+    pub fn event_channel(os: HostPlatform, channel: &str) -> Artifact {
+        Artifact {
+            name: Artifact::channel_name(channel),
+            os,
+            category: Category::Events,
+            command: vec![],
+            script: None,
+            file: None,
+            channel: Some(channel.to_string()),
+            query: None,
+            extension: default_extension(),
+            large: false,
+            compress: default_compress(),
+            overwrite_switch: None,
+            elevated: false,
+            requires: None,
+            timeout: None,
+            parser: None,
+        }.exporting_channel()
+    }
+
+    // An event log channel is exported by `wevtutil epl <channel> <remote file> /ow:true` into an evtx file.
+    fn exporting_channel(self) -> Artifact {
+        match &self.channel {
+            None => self,
+            Some(channel) => Artifact {
+                command: vec!["wevtutil".to_string(), "epl".to_string(), channel.clone()],
+                large: true,
+                extension: if self.extension == default_extension() { "evtx".to_string() } else { self.extension.clone() },
+                overwrite_switch: self.overwrite_switch.clone().or_else(|| Some("/ow:true".to_string())),
+                ..self
+            },
+        }
+    }
+
+    fn validate(self) -> io::Result<Artifact> {
+        let artifact = match &self.channel {
+            Some(channel) if self.name.is_empty() => Artifact { name: Artifact::channel_name(channel), ..self },
+            _ => self,
+        };
+        if artifact.name.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Artifact without a channel must have a name"));
+        }
+        artifact.validate_sources()
+    }
+
+    fn validate_sources(self) -> io::Result<Artifact> {
+        let sources = [!self.command.is_empty(), self.script.is_some(), self.file.is_some(), self.channel.is_some()]
+            .iter()
+            .filter(|it| **it)
+            .count();
+        if sources != 1 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Artifact {} must specify exactly one of command, script, file or channel", self.name),
+            ));
+        }
+        if self.query.is_some() && self.channel.is_none() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Artifact {} has a query but no event log channel", self.name),
+            ));
+        }
+        Ok(self.exporting_channel())
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Profile {
+    #[serde(default)]
+    pub artifact: Vec<Artifact>,
+}
+
+impl Profile {
+    pub fn built_in() -> Profile {
+        Profile::parse_toml(DEFAULT_PROFILE).expect("Built-in profile is invalid")
+    }
+
+    pub fn load(path: &Path) -> io::Result<Profile> {
+        let content = std::fs::read_to_string(path)?;
+        let extension = path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "yaml" | "yml" => Profile::parse_yaml(&content),
+            _ => Profile::parse_toml(&content),
+        }
+    }
+
+    pub fn parse_toml(content: &str) -> io::Result<Profile> {
+        let profile: Profile = toml::from_str(content)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        profile.validate()
+    }
+
+    pub fn parse_yaml(content: &str) -> io::Result<Profile> {
+        let profile: Profile = serde_yaml::from_str(content)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        profile.validate()
+    }
+
+    fn validate(self) -> io::Result<Profile> {
+        let artifact = self.artifact.into_iter()
+            .map(Artifact::validate)
+            .collect::<io::Result<Vec<Artifact>>>()?;
+        Ok(Profile { artifact })
+    }
+
+    // Replaces the event log artifacts by exports of the channels.
+    pub fn with_event_channels(self, channels: &[String]) -> Profile {
+        let mut artifact: Vec<Artifact> = self.artifact.into_iter()
+            .filter(|artifact| artifact.category != Category::Events)
+            .collect();
+        artifact.extend(channels.iter().map(|channel| Artifact::event_channel(HostPlatform::Windows, channel)));
+        Profile { artifact }
+    }
+
+    // Event log channels are exported only with events created in the last days.
+    // Artifacts with their own query keep it.
+    pub fn with_events_since(self, days: u64) -> Profile {
+        let query = format!("*[System[TimeCreated[timediff(@SystemTime) <= {}]]]", days * 24 * 60 * 60 * 1000);
+        let artifact = self.artifact.into_iter()
+            .map(|artifact| match (&artifact.channel, &artifact.query) {
+                (Some(_), None) => Artifact { query: Some(query.clone()), ..artifact },
+                _ => artifact,
+            })
+            .collect();
+        Profile { artifact }
+    }
+
+    pub fn artifacts(&self, category: Category, os: HostPlatform) -> Vec<Artifact> {
+        self.artifact.iter()
+            .filter(|artifact| artifact.category == category && artifact.os == os)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::{Profile, Category};
+    use crate::remote::HostPlatform;
+
+    #[test]
+    fn test_built_in_profile_matches_standard_acquisition() {
+        let profile = Profile::built_in();
+        let names = |category, os| profile.artifacts(category, os).into_iter()
+            .map(|artifact| artifact.name)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            vec!["firewall-status", "network-status", "active-network-connections", "running-processes", "logged-users"],
+            names(Category::Evidence, HostPlatform::Windows)
+        );
+        assert_eq!(5, names(Category::Registry, HostPlatform::Windows).len());
+        assert_eq!(
+            vec!["hive-system", "hive-software", "hive-sam", "hive-security", "hive-default"],
+            names(Category::Hives, HostPlatform::Windows)
+        );
+        assert_eq!(10, names(Category::Events, HostPlatform::Windows).len());
+        assert_eq!(vec!["events-system", "events-application", "events-security"], names(Category::Events, HostPlatform::Windows)[..3].to_vec());
+        assert_eq!(11, names(Category::Evidence, HostPlatform::Linux).len());
+        assert!(names(Category::Registry, HostPlatform::Linux).is_empty());
+    }
+
+    #[test]
+    fn test_yaml_profile() {
+        let profile = Profile::parse_yaml(r#"
+artifact:
+  - name: hosts
+    os: windows
+    file: C:\Windows\System32\drivers\etc\hosts
+  - name: services
+    os: windows
+    command: [sc, query]
+    timeout: 60
+"#).unwrap();
+        let artifacts = profile.artifacts(Category::Evidence, HostPlatform::Windows);
+        assert_eq!(2, artifacts.len());
+        assert_eq!(Some("C:\\Windows\\System32\\drivers\\etc\\hosts".to_string()), artifacts[0].file);
+        assert_eq!(vec!["sc", "query"], artifacts[1].command);
+        assert_eq!(Some(60), artifacts[1].timeout);
+    }
+
+    #[test]
+    fn test_script_is_quoted() {
+        let profile = Profile::parse_toml(r#"
+[[artifact]]
+name = "users"
+os = "linux"
+script = '''awk -F: '{ print $1 }' /etc/passwd > "$1"'''
+"#).unwrap();
+        let artifacts = profile.artifacts(Category::Evidence, HostPlatform::Linux);
+        assert_eq!(
+            vec!["sh", "-c", "'awk -F: '\\''{ print $1 }'\\'' /etc/passwd > \"$1\"'", "sh"],
+            artifacts[0].command_line()
+        );
+    }
+
+    #[test]
+    fn test_event_channels() {
+        let profile = Profile::built_in()
+            .with_event_channels(&["Security".to_string(), "Microsoft-Windows-PowerShell/Operational".to_string()])
+            .with_events_since(7);
+        let events = profile.artifacts(Category::Events, HostPlatform::Windows);
+        assert_eq!(2, events.len());
+        assert_eq!("events-microsoft-windows-powershell-operational", events[1].name);
+        assert!(Profile::built_in().artifacts(Category::Events, HostPlatform::Windows).iter()
+            .any(|artifact| artifact.name == events[1].name));
+        assert_eq!(vec!["wevtutil", "epl", "Microsoft-Windows-PowerShell/Operational"], events[1].command);
+        assert_eq!("evtx", events[1].extension);
+        assert_eq!(
+            vec!["/ow:true", "/q:*[System[TimeCreated[timediff(@SystemTime) <= 604800000]]]"],
+            events[1].switches()
+        );
+        assert!(Profile::parse_yaml("artifact:\n  - name: x\n    os: windows\n    command: [ver]\n    query: '*'\n").is_err());
+    }
+}
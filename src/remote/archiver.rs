@@ -1,8 +1,10 @@
-use crate::remote::{Connector, Command, Local, FileCopier, RemoteFileCopier, Computer, file_is_empty, path_to_part};
+use crate::remote::{Connector, Command, FileCopier, RemoteFileCopier, Computer, file_is_empty, path_to_part, HostPlatform, Integrity, check, report, copy_from_remote_verified, corrupted_transfer, remote_hash, MAX_TRANSFER_ATTEMPTS, RemoteFileState, readiness, is_missing, wait_until_ready, delete_when_released, compress_file, extract_archive, ArchiveFormat, VOLUME_SIZE};
 use std::path::{Path, PathBuf};
 use std::{io, thread};
 use std::time::Duration;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
+use crate::journal::{record_part_downloaded, record_part_deleted};
+use crate::cleanup::{register_remote_file, unregister_remote_file};
 
 #[derive(Clone, Copy)]
 pub enum Compression {
@@ -14,6 +16,7 @@ pub enum Compression {
 pub struct Archiver<'a> {
     connector: &'a dyn Connector,
     timeout: Option<Duration>,
+    program: &'static str,
 }
 
 impl<'a> Archiver<'a> {
@@ -24,17 +27,14 @@ impl<'a> Archiver<'a> {
         Archiver {
             connector,
             timeout,
+            program: HostPlatform::Windows.archiver_program(),
         }
     }
 
-    pub fn local(local: &'a Local) -> Archiver {
-        Archiver::remote(local, None)
-    }
-
     pub fn compress(&self, path: &Path, split: bool) -> io::Result<()> {
         let path_string = path.to_string_lossy().to_string();
         let mut run_params = vec![
-            "7za.exe".to_string(),
+            self.program.to_string(),
         ];
 
         if split {
@@ -48,8 +48,10 @@ impl<'a> Archiver<'a> {
 
         run_params.push("a".to_string());
         run_params.push(format!("{}.7z", path_string));
-        run_params.push(path_string);
+        run_params.push(path_string.clone());
 
+        let archive = PathBuf::from(format!("{}.7z", path_string));
+        register_remote_file(self.connector.computer(), &if split { path_to_part(&archive, 1) } else { archive });
         let command = Command {
             command: run_params,
             report_store_directory: None,
@@ -58,15 +60,16 @@ impl<'a> Archiver<'a> {
         };
         if let Err(err) = self.connector.connect_and_run_local_program_in_current_directory(
             command,
-            self.timeout.clone(),
+            self.timeout,
         ) {
             debug!("{}", err)
         }
         if split {
             // already deleted by 7zip itself
         } else {
-            if let Err(err) = self.connector.copier().delete_remote_file(path) {
-                debug!("{}", err)
+            match self.connector.copier().delete_remote_file(path) {
+                Ok(_) => unregister_remote_file(self.connector.computer(), path),
+                Err(err) => debug!("{}", err),
             }
         }
         Ok(())
@@ -76,7 +79,7 @@ impl<'a> Archiver<'a> {
         let path_string = path.to_string_lossy().to_string();
         let command = Command {
             command: vec![
-                "7za.exe".to_string(),
+                self.program.to_string(),
                 "-aoa".to_string(),
                 "-bd".to_string(),
                 "e".to_string(),
@@ -89,8 +92,8 @@ impl<'a> Archiver<'a> {
         };
         self.connector.connect_and_run_local_program_in_current_directory(
             command,
-            self.timeout.clone(),
-        )
+            self.timeout,
+        ).map(|_| ())
     }
 }
 
@@ -104,7 +107,7 @@ impl<'a> CompressCopier<'a> {
         connector: &'a dyn Connector,
         split: bool,
         timeout: Option<Duration>,
-    ) -> CompressCopier {
+    ) -> CompressCopier<'a> {
         CompressCopier {
             archiver: Archiver::remote(connector, timeout),
             split,
@@ -128,9 +131,7 @@ impl<'a> RemoteFileCopier for CompressCopier<'a> {
     fn copy_to_remote(&self, source: &Path, target: &Path) -> Result<(), Error> {
         trace!("Copying {} to {} using compression", source.display(), &self.archiver.connector.computer().address);
         let remote_copier_impl = self.archiver.connector.copier();
-        let local = Local::new();
-        let local_archiver = Archiver::local(&local);
-        if let Err(err) = local_archiver.compress(source, self.split) {
+        if let Err(err) = compress_file(source, ArchiveFormat::SevenZip, if self.split { Some(VOLUME_SIZE) } else { None }) {
             debug!("{}", err);
         }
 
@@ -142,18 +143,20 @@ impl<'a> RemoteFileCopier for CompressCopier<'a> {
             let mut i = 1;
             let mut part = path_to_part(archived_source, i);
             while part.exists() {
+                register_remote_file(self.remote_computer(), &target.join(part.file_name().unwrap()));
                 if let Err(err) = remote_copier_impl.copy_to_remote(&part, target) {
                     debug!("{}", err)
                 } else {
-                    std::thread::sleep(wait_time_s.clone());
-                    if let Err(err) = local.delete_file(&part) {
+                    if let Err(err) = wait_until_ready(remote_copier_impl, &target.join(part.file_name().unwrap()), wait_time_s) {
+                        warn!("{}", err);
+                    }
+                    if let Err(err) = std::fs::remove_file(&part) {
                         debug!("{}", err);
                     }
                 }
                 i += 1;
                 part = path_to_part(archived_source, i);
             }
-            std::thread::sleep(wait_time_l.clone());
             if let Err(err) = self.archiver.uncompress(
                 &target.join(
                     path_to_part(archived_source, 1).file_name().unwrap()
@@ -166,31 +169,32 @@ impl<'a> RemoteFileCopier for CompressCopier<'a> {
                     path_to_part(archived_source, 1).file_name().unwrap());
 
                 while i > 0 {
-                    if let Err(err) = remote_copier_impl.delete_remote_file(&remote_part) {
+                    if let Err(err) = delete_when_released(remote_copier_impl, &remote_part, wait_time_l) {
                         debug!("{}", err);
                     }
-                    std::thread::sleep(wait_time_l.clone());
                     i -= 1;
                     remote_part = target.join(path_to_part(archived_source, 1).file_name().unwrap());
                 }
             }
         } else {
-            if let Err(err) = remote_copier_impl.copy_to_remote(&archived_source, target) {
+            register_remote_file(self.remote_computer(), &target.join(archived_source.file_name().unwrap()));
+            if let Err(err) = remote_copier_impl.copy_to_remote(archived_source, target) {
                 debug!("{}", err);
             } else {
-                if let Err(err) = local.delete_file(&archived_source) {
+                if let Err(err) = std::fs::remove_file(archived_source) {
                     debug!("{}", err)
                 }
             }
-            std::thread::sleep(wait_time_l.clone());
             let target_archived = &target.join(
                 archived_source.file_name().unwrap()
             );
-            if let Err(err) = self.archiver.uncompress(&target_archived) {
+            if let Err(err) = wait_until_ready(remote_copier_impl, target_archived, wait_time_l) {
+                warn!("{}", err);
+            }
+            if let Err(err) = self.archiver.uncompress(target_archived) {
                 debug!("{}", err)
             } else {
-                std::thread::sleep(wait_time_l.clone());
-                if let Err(err) = remote_copier_impl.delete_remote_file(&target_archived) {
+                if let Err(err) = delete_when_released(remote_copier_impl, target_archived, wait_time_l) {
                     debug!("{}", err);
                 }
             }
@@ -203,62 +207,104 @@ impl<'a> RemoteFileCopier for CompressCopier<'a> {
         self.archiver.connector.copier().delete_remote_file(target)
     }
 
+    fn remote_file_state(&self, path: &Path) -> io::Result<RemoteFileState> {
+        self.archiver.connector.copier().remote_file_state(path)
+    }
+
     fn copy_from_remote(&self, source: &Path, target: &Path) -> Result<(), Error> {
         trace!("Copying {} from {} using compression", source.display(), &self.archiver.connector.computer().address);
+        let connector = self.archiver.connector;
+        let source_hash = remote_hash(connector, source);
         if let Err(err) = self.archiver.compress(source, self.split) {
             debug!("{}", err);
         }
 
         let wait_time_s = Duration::from_secs(10);
         let wait_time_l = Duration::from_secs(30);
-        thread::sleep(wait_time_s.clone());
 
         let archive_name = format!("{}.7z", source.display());
         let archived_source = Path::new(&archive_name);
         let remote_copier_impl = self.archiver.connector.copier();
-
-        let local = Local::new();
-        let local_archiver = Archiver::local(&local);
+        let archive = if self.split { path_to_part(archived_source, 1) } else { archived_source.to_path_buf() };
+        if let Err(err) = wait_until_ready(remote_copier_impl, &archive, wait_time_s) {
+            warn!("{}", err);
+        }
 
         if self.split {
             let mut unsuccessful_trials = 0;
+            let mut corrupted_trials = 0;
+            let mut corrupted = false;
             let mut i = 0;
             loop {
                 i += 1;
                 let part = path_to_part(archived_source, i);
+                if is_missing(remote_copier_impl, &part) {
+                    // 7zip created no more parts
+                    i -= 1;
+                    break;
+                }
+                register_remote_file(self.remote_computer(), &part);
                 trace!("Copying {} from {} using compression", part.display(), &self.archiver.connector.computer().address);
+                let part_hash = remote_hash(connector, &part);
                 if let Err(err) = remote_copier_impl.copy_from_remote(&part, target) {
                     debug!("{}", err);
                 }
                 let target_downloaded = target.join(part.file_name().unwrap());
 
+                let mut downloaded = false;
                 if file_is_empty(&target_downloaded) {
                     unsuccessful_trials += 1;
                     i -= 1;
                     if unsuccessful_trials == 2 {
                         break;
                     }
-                    debug!("File download may ended with errors. Waiting {} seconds before retry.", wait_time_l.as_secs());
-                    thread::sleep(wait_time_l);
+                    debug!("File download may ended with errors. Waiting for {} before retry.", part.display());
+                    if let Err(err) = wait_until_ready(remote_copier_impl, &part, wait_time_l) {
+                        debug!("{}", err);
+                    }
+                } else if check(&target_downloaded, part_hash.as_deref()) == Integrity::Corrupted {
+                    corrupted_trials += 1;
+                    if corrupted_trials < MAX_TRANSFER_ATTEMPTS {
+                        warn!("Transfer of {} is corrupted, retrying ({}/{})",
+                              part.display(),
+                              corrupted_trials,
+                              MAX_TRANSFER_ATTEMPTS - 1
+                        );
+                        i -= 1;
+                        thread::sleep(readiness(remote_copier_impl.remote_computer()).delay(corrupted_trials as u32));
+                    } else {
+                        // the part stays on the target and the next one is downloaded
+                        error!("{}", corrupted_transfer(remote_copier_impl.remote_computer(), &part));
+                        report(&target_downloaded, Integrity::Corrupted);
+                        unsuccessful_trials = 0;
+                        corrupted_trials = 0;
+                        corrupted = true;
+                    }
                 } else {
                     unsuccessful_trials = 0;
+                    corrupted_trials = 0;
+                    downloaded = true;
                 }
 
-                thread::sleep(wait_time_s.clone());
-
-                if unsuccessful_trials == 0 {
-                    if let Err(err) = remote_copier_impl.delete_remote_file(&part) {
+                if downloaded {
+                    record_part_downloaded(&part);
+                    if let Err(err) = delete_when_released(remote_copier_impl, &part, wait_time_s) {
                         debug!("{}", err);
+                    } else {
+                        record_part_deleted(&part);
                     }
                 }
             }
+            if corrupted {
+                return Err(corrupted_transfer(remote_copier_impl.remote_computer(), archived_source));
+            }
             let target_downloaded_without_part_suffix = target.join(archived_source.file_name().unwrap());
-            if let Err(err) = local_archiver.uncompress(&path_to_part(&target_downloaded_without_part_suffix, 1)) {
-                debug!("{}", err);
+            if let Err(err) = extract_archive(&path_to_part(&target_downloaded_without_part_suffix, 1), target) {
+                error!("{}", err);
             } else {
                 let mut part = path_to_part(&target_downloaded_without_part_suffix, i);
                 while i > 0 {
-                    if let Err(err) = local.delete_file(&part) {
+                    if let Err(err) = std::fs::remove_file(&part) {
                         debug!("{}", err);
                     }
                     i -= 1;
@@ -266,25 +312,44 @@ impl<'a> RemoteFileCopier for CompressCopier<'a> {
                 }
             }
         } else {
-            if let Err(err) = remote_copier_impl.copy_from_remote(archived_source, target) {
-                debug!("{}", err);
-            } else {
-                thread::sleep(wait_time_s.clone());
-                if let Err(err) = remote_copier_impl.delete_remote_file(archived_source) {
-                    debug!("{}", err);
+            let archive_hash = remote_hash(connector, archived_source);
+            let (result, archive_integrity) = copy_from_remote_verified(
+                remote_copier_impl,
+                archive_hash.as_deref(),
+                archived_source,
+                target,
+            );
+            if let Err(err) = result {
+                if archive_integrity == Integrity::Corrupted {
+                    report(&target.join(archived_source.file_name().unwrap()), archive_integrity);
                 }
+                return Err(err);
+            }
+            if let Err(err) = delete_when_released(remote_copier_impl, archived_source, wait_time_s) {
+                debug!("{}", err);
             }
 
             let target_downloaded = target.join(archived_source.file_name().unwrap());
-            if let Err(err) = local_archiver.uncompress(&target_downloaded) {
-                debug!("{}", err);
+            if let Err(err) = extract_archive(&target_downloaded, target) {
+                error!("{}", err);
             } else {
-                thread::sleep(wait_time_s.clone());
-                if let Err(err) = local.delete_file(&target_downloaded) {
+                if let Err(err) = std::fs::remove_file(&target_downloaded) {
                     debug!("{}", err);
                 }
             }
         }
+        let extracted = target.join(source.file_name().unwrap());
+        if !extracted.exists() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} was not extracted from the downloaded archive", extracted.display()),
+            ));
+        }
+        let integrity = check(&extracted, source_hash.as_deref());
+        report(&extracted, integrity.clone());
+        if integrity == Integrity::Corrupted {
+            return Err(corrupted_transfer(remote_copier_impl.remote_computer(), source));
+        }
         Ok(())
     }
 }
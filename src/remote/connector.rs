@@ -1,10 +1,10 @@
-use std::io::Result;
-use crate::process_runner::{run_process_blocking, create_report_path, run_process_blocking_timed};
-use std::{iter, thread};
-use std::path::Path;
-use crate::arg_parser::Opts;
+use std::io::{Error, ErrorKind, Result};
+use crate::process_runner::{run_process_blocking, create_report_path, run_process_blocking_timed, CommandOutcome};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use crate::remote::RemoteFileCopier;
+use crate::remote::{RemoteFileCopier, HostPlatform, certutil_hash, wait_until_ready, delete_when_released};
+use crate::manifest::{Provenance, record_report};
+use crate::cleanup::register_remote_file;
 
 #[derive(Clone)]
 pub struct Computer {
@@ -32,17 +32,6 @@ pub struct Command<'a> {
     pub elevated: bool,
 }
 
-impl From<Opts> for Computer {
-    fn from(opts: Opts) -> Self {
-        Computer {
-            address: opts.computer,
-            username: opts.user,
-            domain: opts.domain,
-            password: opts.password,
-        }
-    }
-}
-
 impl<'a> Command<'a> {
     pub fn new(
         command: Vec<String>,
@@ -72,11 +61,9 @@ pub trait Connector {
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>
-    ) -> Result<()> {
+    ) -> Result<CommandOutcome> {
         let mut command = command_to_run.command;
-        command[0] = std::env::current_dir().unwrap()
-            .join(Path::new(&command[0]).file_name().unwrap())
-            .to_string_lossy().to_string();
+        command[0] = HostPlatform::current().resolve_local_program(&command[0]);
         let command_to_run = Command {
             command,
             ..command_to_run
@@ -91,33 +78,35 @@ pub trait Connector {
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>
-    ) -> Result<()> {
+    ) -> Result<CommandOutcome> {
         let local_program_path = Path::new(command_to_run.command.first().unwrap());
         let remote_storage = self.remote_temp_storage();
         let copier = self.copier();
-        copier.copy_to_remote(&local_program_path, &remote_storage)?;
-        thread::sleep(Duration::from_millis(20_000));
+        copier.copy_to_remote(local_program_path, remote_storage)?;
         let remote_program_path = remote_storage.join(local_program_path
             .file_name()
-            .expect(&format!("Must specify file instead of {}", local_program_path.display())
-            )
+            .unwrap_or_else(|| panic!("Must specify file instead of {}", local_program_path.display()))
         );
+        register_remote_file(self.computer(), &remote_program_path);
+        if let Err(err) = wait_until_ready(copier, &remote_program_path, Duration::from_millis(20_000)) {
+            warn!("{}", err);
+        }
         let mut command = command_to_run.command;
         command[0] = remote_program_path.to_string_lossy().to_string();
         let command_to_run = Command {
             command,
             ..command_to_run
         };
-        self.connect_and_run_command(command_to_run, timeout)?;
-        thread::sleep(Duration::from_millis(10_000));
-        copier.delete_remote_file(&remote_program_path)
+        let outcome = self.connect_and_run_command(command_to_run, timeout)?;
+        delete_when_released(copier, &remote_program_path, Duration::from_millis(10_000))?;
+        Ok(outcome)
     }
 
     fn connect_and_run_command(
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>
-    ) -> Result<()> {
+    ) -> Result<CommandOutcome> {
         debug!("Trying to run command {:?} on {}",
                command_to_run.command,
                &self.computer().address
@@ -128,7 +117,7 @@ pub trait Connector {
                 let file_path = create_report_path(
                     self.computer(),
                     store_directory,
-                    &command_to_run.report_filename_prefix,
+                    command_to_run.report_filename_prefix,
                     self.connect_method_name(),
                     "txt"
                 );
@@ -136,6 +125,9 @@ pub trait Connector {
             }
         };
 
+        let report_path = output_file_path.as_ref().map(PathBuf::from);
+        let provenance = Provenance::new(self.computer(), self.connect_method_name())
+            .command(&command_to_run.command);
         let processed_command = self.prepare_command(
             command_to_run.command,
             output_file_path,
@@ -143,32 +135,56 @@ pub trait Connector {
         );
 
         let prepared_command = self.prepare_remote_process(processed_command);
-        match timeout {
+        let shell = HostPlatform::current().shell();
+        let outcome = match timeout {
             None =>
                 run_process_blocking(
-                    "cmd.exe",
+                    shell,
                     &prepared_command,
                 ),
             Some(timeout) =>
                 run_process_blocking_timed(
-                    "cmd.exe",
+                    shell,
                     &prepared_command,
-                    timeout.clone(),
+                    timeout,
                 ),
-        }
+        }?;
+        record_report(report_path.as_deref(), &provenance);
+        Ok(outcome.with_report_path(report_path))
+    }
+
+    // Runs the command and writes its standard output into the local file, without a copy on the target.
+    fn connect_and_stream_command(
+        &self,
+        command_to_run: Command<'_>,
+        _local_file: &Path,
+        _timeout: Option<Duration>
+    ) -> Result<CommandOutcome> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("{} cannot stream the output of {:?}", self.connect_method_name(), command_to_run.command),
+        ))
+    }
+
+    fn remote_file_hash(&self, path: &Path) -> Result<String> {
+        certutil_hash(self, path)
+    }
+
+    fn remote_platform(&self) -> HostPlatform {
+        HostPlatform::Windows
     }
 
     fn prepare_remote_process(&self,
-                              // pre_command: Vec<String>,
                               processed_command: Vec<String>,
-                              // post_command: Vec<String>,
     ) -> Vec<String> {
-        let all_args = iter::once("/c".to_string())
-            // .chain(pre_command.into_iter())
-            .chain(processed_command.into_iter())
-            // .chain(post_command.into_iter())
-            .collect();
-        all_args
+        HostPlatform::current().shell_args(processed_command)
+    }
+
+    // Quotes an argument which is data, like an event log channel with spaces, so that the program on
+    // the target receives it as one argument. Arguments passed to a local process separately are
+    // quoted by the process runner.
+    fn quote_argument(&self, arg: &str) -> String {
+        arg.to_string()
     }
 
     fn prepare_command(&self,
@@ -1,5 +1,5 @@
 use std::path::{Path, PathBuf};
-use crate::remote::Computer;
+use crate::remote::{Computer, HostPlatform, RemoteFileState, FileTimes, unsupported_hash, unsupported_probe, unsupported_times, local_file_state, metadata_times};
 use std::io;
 use crate::process_runner::run_process_blocking;
 
@@ -25,29 +25,54 @@ impl FileCopier for Cmd {
         source: &Path,
         target: &Path,
     ) -> io::Result<()> {
-        let args = vec![
-            "/y".to_string(),
-            "/i".to_string(),
-            "/c".to_string(),
-            source.to_string_lossy().to_string(),
-            target.to_string_lossy().to_string(),
-        ];
-        run_process_blocking(
-            "xcopy",
-            &args,
-        )
+        match HostPlatform::current() {
+            HostPlatform::Windows => {
+                let args = vec![
+                    "/y".to_string(),
+                    "/i".to_string(),
+                    "/c".to_string(),
+                    source.to_string_lossy().to_string(),
+                    target.to_string_lossy().to_string(),
+                ];
+                run_process_blocking(
+                    "xcopy",
+                    &args,
+                ).map(|_| ())
+            }
+            HostPlatform::Linux => {
+                let args = vec![
+                    "-r".to_string(),
+                    source.to_string_lossy().to_string(),
+                    target.to_string_lossy().to_string(),
+                ];
+                run_process_blocking(
+                    "cp",
+                    &args,
+                ).map(|_| ())
+            }
+        }
     }
 
     fn delete_file(&self, target: &Path) -> io::Result<()> {
-        let args = vec![
-            "/F".to_string(),
-            "/Q".to_string(),
-            target.to_string_lossy().to_string(),
-        ];
+        let platform = HostPlatform::current();
+        let command = match platform {
+            // del is a cmd.exe builtin
+            HostPlatform::Windows => vec![
+                "del".to_string(),
+                "/F".to_string(),
+                "/Q".to_string(),
+                target.to_string_lossy().to_string(),
+            ],
+            HostPlatform::Linux => vec![
+                "rm".to_string(),
+                "-f".to_string(),
+                target.to_string_lossy().to_string(),
+            ],
+        };
         run_process_blocking(
-            "del",
-            &args,
-        )
+            platform.shell(),
+            &platform.shell_args(command),
+        ).map(|_| ())
     }
 
     fn method_name(&self) -> &'static str {
@@ -87,6 +112,18 @@ pub trait RemoteFileCopier {
     fn method_name(&self) -> &'static str {
         self.copier_impl().method_name()
     }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        Err(unsupported_hash(path, self.method_name()))
+    }
+
+    fn remote_file_state(&self, path: &Path) -> io::Result<RemoteFileState> {
+        Err(unsupported_probe(path, self.method_name()))
+    }
+
+    fn remote_file_times(&self, path: &Path) -> io::Result<FileTimes> {
+        Err(unsupported_times(path, self.method_name()))
+    }
 }
 
 pub struct WindowsRemoteFileHandler {
@@ -96,6 +133,9 @@ pub struct WindowsRemoteFileHandler {
 
 impl Drop for WindowsRemoteFileHandler {
     fn drop(&mut self) {
+        if HostPlatform::current() != HostPlatform::Windows {
+            return;
+        }
         run_process_blocking(
             "NET",
             &[
@@ -104,9 +144,7 @@ impl Drop for WindowsRemoteFileHandler {
                 // format!("\\\\{}", self.computer.address),
                 "/D".to_string()
             ],
-        ).expect(&format!(
-            "Cannot drop connection using \"net use\" to {}", self.computer.address
-        ));
+        ).unwrap_or_else(|_| panic!("Cannot drop connection using \"net use\" to {}", self.computer.address));
     }
 }
 
@@ -115,27 +153,18 @@ impl WindowsRemoteFileHandler {
         computer: Computer,
         copier_impl: Box<dyn FileCopier>,
     ) -> WindowsRemoteFileHandler {
-        let mut args = vec![
-            "USE".to_string(),
-            format!("\\\\{}", computer.address),
-        ];
-        let username = computer.domain_username();
-        args.push(format!("/u:{}", username));
-        if let Some(password) = &computer.password {
-            args.push(password.clone());
-        }
-        run_process_blocking(
-            "NET",
-            &args,
-        ).expect(&format!(
-            "Cannot establish connection using \"net use\" to {}", &computer.address
-        ));
-        WindowsRemoteFileHandler { computer, copier_impl }
+        let handler = WindowsRemoteFileHandler { computer, copier_impl };
+        handler.open_connection();
+        handler
     }
 
     fn open_connection(
         &self
     ) {
+        if HostPlatform::current() != HostPlatform::Windows {
+            debug!("Skipping \"net use\" to {} on non-Windows host", self.computer.address);
+            return;
+        }
         let mut args = vec![
             "USE".to_string(),
             format!("\\\\{}", self.computer.address),
@@ -148,9 +177,7 @@ impl WindowsRemoteFileHandler {
         run_process_blocking(
             "NET",
             &args,
-        ).expect(&format!(
-            "Cannot establish connection using \"net use\" to {}", &self.computer.address
-        ));
+        ).unwrap_or_else(|_| panic!("Cannot establish connection using \"net use\" to {}", &self.computer.address));
     }
 }
 
@@ -174,6 +201,21 @@ impl RemoteFileCopier for WindowsRemoteFileHandler {
             path.to_str().unwrap().replacen(":", "$", 1)
         ))
     }
+
+    fn remote_file_state(&self, path: &Path) -> io::Result<RemoteFileState> {
+        // administrative shares are reachable only from Windows
+        if HostPlatform::current() != HostPlatform::Windows {
+            return Err(unsupported_probe(path, self.method_name()));
+        }
+        local_file_state(&self.path_to_remote_form(path))
+    }
+
+    fn remote_file_times(&self, path: &Path) -> io::Result<FileTimes> {
+        if HostPlatform::current() != HostPlatform::Windows {
+            return Err(unsupported_times(path, self.method_name()));
+        }
+        metadata_times(&self.path_to_remote_form(path))
+    }
 }
 
 // pub struct Downloader<'a> {
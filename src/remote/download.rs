@@ -1,7 +1,9 @@
-use std::path::Path;
-use crate::remote::{RemoteFileCopier, file_is_empty, path_to_part};
-use std::thread;
+use std::path::{Path, PathBuf};
+use crate::remote::{RemoteFileCopier, file_is_empty, path_to_part, Integrity, check, report, copy_from_remote_verified, corrupted_transfer, MAX_TRANSFER_ATTEMPTS, is_missing, wait_until_ready, delete_when_released, extract_archive};
 use std::time::Duration;
+use crate::manifest::{Provenance, record_file};
+use chrono::{DateTime, Utc};
+use crate::journal::{record_part_downloaded, record_part_deleted};
 
 pub struct ReDownloader<'a> {
     pub copier: &'a dyn RemoteFileCopier,
@@ -34,6 +36,47 @@ impl<'a> ReDownloader<'a> {
         }
     }
 
+    // Continues an interrupted transfer of `remote_path`. When it was being downloaded as a split
+    // 7zip archive, the download continues with part `next_part`. The downloaded archive is extracted.
+    pub fn resume(&self, remote_path: &Path, next_part: usize) -> bool {
+        let archive = PathBuf::from(format!("{}.7z", remote_path.display()));
+        let downloaded = if next_part > 1 {
+            self.download_as_splitted(&path_to_part(&archive, next_part))
+        } else {
+            self.retry_download(remote_path)
+        };
+        if downloaded {
+            self.extract(&self.target_dir.join(archive.file_name().unwrap()));
+        }
+        downloaded
+    }
+
+    fn extract(&self, local_archive: &Path) {
+        let first_part = path_to_part(local_archive, 1);
+        let archive = if first_part.exists() {
+            first_part
+        } else if local_archive.exists() {
+            local_archive.to_path_buf()
+        } else {
+            return;
+        };
+        if let Err(err) = extract_archive(&archive, self.target_dir) {
+            error!("{}", err);
+            return;
+        }
+        let mut parts = vec![local_archive.to_path_buf()];
+        let mut i = 1;
+        while path_to_part(local_archive, i).exists() {
+            parts.push(path_to_part(local_archive, i));
+            i += 1;
+        }
+        for part in parts.iter().filter(|part| part.exists()) {
+            if let Err(err) = std::fs::remove_file(part) {
+                debug!("{}", err);
+            }
+        }
+    }
+
     fn download_as_splitted(&self, remote_path: &Path) -> bool {
         let extension = remote_path.extension().unwrap().to_string_lossy();
         let wait_time_s = Duration::from_secs(10);
@@ -41,35 +84,69 @@ impl<'a> ReDownloader<'a> {
         let mut i = extension.parse::<usize>().unwrap() - 1;
         let archived_remote_file = remote_path.parent().unwrap().join(remote_path.file_stem().unwrap());
         let mut unsuccessful_trials = 0;
+        let mut corrupted_trials = 0;
+        let mut downloaded_parts = 0;
+        let mut corrupted = false;
         loop {
             i += 1;
             let part = path_to_part(&archived_remote_file, i);
+            if is_missing(self.copier, &part) {
+                break;
+            }
             trace!("Copying {} from {}", part.display(), &self.copier.remote_computer().address);
+            let part_hash = self.remote_hash(&part);
+            let started = Utc::now();
             if let Err(err) = self.copier.copy_from_remote(&part, self.target_dir) {
                 debug!("{}", err);
             }
             let target_downloaded = self.target_dir.join(part.file_name().unwrap());
 
+            let integrity = check(&target_downloaded, part_hash.as_deref());
+            if integrity == Integrity::Corrupted && !file_is_empty(&target_downloaded) {
+                corrupted_trials += 1;
+                if corrupted_trials < MAX_TRANSFER_ATTEMPTS {
+                    warn!("Transfer of {} is corrupted, retrying ({}/{})",
+                          part.display(),
+                          corrupted_trials,
+                          MAX_TRANSFER_ATTEMPTS - 1
+                    );
+                    i -= 1;
+                    continue;
+                }
+                error!("{}", corrupted_transfer(self.copier.remote_computer(), &part));
+                report(&target_downloaded, integrity);
+                corrupted_trials = 0;
+                corrupted = true;
+                continue;
+            }
             if file_is_empty(&target_downloaded) {
                 unsuccessful_trials += 1;
                 i -= 1;
                 if unsuccessful_trials == 2 {
                     break;
                 }
-                debug!("File download may ended with errors. Waiting 30 seconds before retry.");
-                thread::sleep(Duration::from_secs(30));
+                debug!("File download may ended with errors. Waiting for {} before retry.", part.display());
+                if let Err(err) = wait_until_ready(self.copier, &part, Duration::from_secs(30)) {
+                    debug!("{}", err);
+                }
             } else {
                 unsuccessful_trials = 0;
+                corrupted_trials = 0;
+                report(&target_downloaded, integrity);
+                self.record(&part, &target_downloaded, started);
+                record_part_downloaded(&part);
+                downloaded_parts += 1;
             }
 
-            thread::sleep(wait_time_s.clone());
             if unsuccessful_trials == 0 {
-                if let Err(err) = self.copier.delete_remote_file(&part) {
+                if let Err(err) = delete_when_released(self.copier, &part, wait_time_s) {
                     debug!("{}", err);
+                } else {
+                    record_part_deleted(&part);
                 }
             }
         }
-        true
+        downloaded_parts > 0 && !corrupted
     }
 
     fn download_as_non_splitted(&self, remote_path: &Path) -> bool {
@@ -90,14 +167,47 @@ impl<'a> ReDownloader<'a> {
                error!("{}", err)
            }
         }
-        if let Err(err) = self.copier.copy_from_remote(remote_path, self.target_dir) {
+        let expected = self.remote_hash(remote_path);
+        let started = Utc::now();
+        let (download_result, integrity) = copy_from_remote_verified(
+            self.copier,
+            expected.as_deref(),
+            remote_path,
+            self.target_dir,
+        );
+        let target = self.target_dir.join(remote_path.file_name().unwrap());
+        if let Err(err) = download_result {
+            if integrity == Integrity::Corrupted && !file_is_empty(&target) {
+                error!("{}", err);
+                report(&target, integrity);
+                return false;
+            }
             debug!("{}", err)
         }
-        let target = self.target_dir.join(remote_path.file_name().unwrap());
         let result = file_is_empty(&target);
         if result {
             info!("Downloaded remote file {} to {}", remote_path.display(), self.target_dir.display());
+        } else {
+            report(&target, integrity);
+            self.record(remote_path, &target, started);
         }
         !result
     }
+
+    fn remote_hash(&self, remote_path: &Path) -> Option<String> {
+        match self.copier.remote_file_hash(remote_path) {
+            Ok(hash) => Some(hash),
+            Err(err) => {
+                debug!("{}", err);
+                None
+            }
+        }
+    }
+
+    fn record(&self, remote_path: &Path, local_path: &Path, started: DateTime<Utc>) {
+        let provenance = Provenance::new(self.copier.remote_computer(), self.copier.method_name())
+            .source(remote_path)
+            .started(started);
+        record_file(local_path, &provenance);
+    }
 }
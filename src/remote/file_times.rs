@@ -0,0 +1,149 @@
+use crate::remote::{Connector, command_output, ps_quoted};
+use crate::manifest::timestamp;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::SystemTime;
+
+// `stat` format of the modification, access, change and birth time in seconds since the epoch.
+pub const STAT_TIMES_FORMAT: &str = "%Y %X %Z %W";
+
+// Times of a file on the target, which the download does not preserve. Times in RFC 3339,
+// those the file system or the method does not provide are missing.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FileTimes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accessed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub born: Option<String>,
+}
+
+pub fn unsupported_times(path: &Path, method_name: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::Unsupported,
+        format!("Method {} cannot read times of remote file {}", method_name, path.display()),
+    )
+}
+
+fn invalid_times(path: &Path, method_name: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("Cannot read times of remote file {} using method {}", path.display(), method_name),
+    )
+}
+
+fn system_time(time: io::Result<SystemTime>) -> Option<String> {
+    time.ok().map(|time| timestamp(time.into()))
+}
+
+// Times of a file reachable from this computer, e.g. over the administrative share of the target.
+pub fn metadata_times(path: &Path) -> io::Result<FileTimes> {
+    let metadata = std::fs::metadata(path)?;
+    Ok(FileTimes {
+        modified: system_time(metadata.modified()),
+        accessed: system_time(metadata.accessed()),
+        changed: None,
+        born: system_time(metadata.created()),
+    })
+}
+
+fn epoch_time(seconds: &str) -> Option<String> {
+    // stat prints 0 or - for a time the file system does not keep
+    match seconds.parse::<i64>() {
+        Ok(seconds) if seconds > 0 => Utc.timestamp_opt(seconds, 0).single().map(timestamp),
+        _ => None,
+    }
+}
+
+pub fn parse_stat_times(output: &str) -> Option<FileTimes> {
+    let fields: Vec<&str> = output.split_whitespace().collect();
+    if fields.len() != 4 {
+        return None;
+    }
+    let times = FileTimes {
+        modified: epoch_time(fields[0]),
+        accessed: epoch_time(fields[1]),
+        changed: epoch_time(fields[2]),
+        born: epoch_time(fields[3]),
+    };
+    times.modified.as_ref().map(|_| times.clone())
+}
+
+// Windows PowerShell writes dates to JSON as \/Date(milliseconds)\/, PowerShell 7 in ISO 8601.
+fn json_time(value: &Value) -> Option<String> {
+    let text = value.as_str()?;
+    if let Some(millis) = text.strip_prefix("/Date(").and_then(|it| it.strip_suffix(")/")) {
+        return Utc.timestamp_millis_opt(millis.parse().ok()?).single().map(timestamp);
+    }
+    DateTime::parse_from_rfc3339(text).ok().map(|time| timestamp(time.with_timezone(&Utc)))
+}
+
+pub fn parse_powershell_times(output: &str) -> Option<FileTimes> {
+    let json = &output[output.find('{')?..=output.rfind('}')?];
+    let value: Value = serde_json::from_str(json).ok()?;
+    let times = FileTimes {
+        modified: json_time(&value["LastWriteTimeUtc"]),
+        accessed: json_time(&value["LastAccessTimeUtc"]),
+        changed: None,
+        born: json_time(&value["CreationTimeUtc"]),
+    };
+    times.modified.as_ref().map(|_| times.clone())
+}
+
+pub fn powershell_times<C: Connector + ?Sized>(connector: &C, path: &Path) -> io::Result<FileTimes> {
+    let command = vec![
+        "powershell".to_string(),
+        "-NoProfile".to_string(),
+        "-Command".to_string(),
+        format!(
+            "Get-Item -LiteralPath {} | Select-Object LastWriteTimeUtc,LastAccessTimeUtc,CreationTimeUtc | ConvertTo-Json -Compress",
+            ps_quoted(&path.to_string_lossy())
+        ),
+    ];
+    let output = command_output(connector, command, "remote-times")?;
+    parse_powershell_times(&output).ok_or_else(|| invalid_times(path, connector.connect_method_name()))
+}
+
+pub fn stat_times(output: &str, path: &Path, method_name: &str) -> io::Result<FileTimes> {
+    parse_stat_times(output).ok_or_else(|| invalid_times(path, method_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::{parse_stat_times, parse_powershell_times, FileTimes};
+
+    #[test]
+    fn test_stat_times_without_birth() {
+        assert_eq!(Some(FileTimes {
+            modified: Some("2020-10-10T10:10:10Z".to_string()),
+            accessed: Some("2020-10-10T10:10:11Z".to_string()),
+            changed: Some("2020-10-10T10:10:12Z".to_string()),
+            born: None,
+        }), parse_stat_times("1602324610 1602324611 1602324612 0\n"));
+        assert_eq!(None, parse_stat_times("stat: cannot stat '/tmp/x': No such file or directory"));
+    }
+
+    #[test]
+    fn test_powershell_times_of_both_versions() {
+        let expected = Some(FileTimes {
+            modified: Some("2020-10-10T10:10:10Z".to_string()),
+            accessed: Some("2020-10-10T10:10:11Z".to_string()),
+            changed: None,
+            born: Some("2020-10-10T10:10:12Z".to_string()),
+        });
+        assert_eq!(expected, parse_powershell_times(
+            r#"{"LastWriteTimeUtc":"\/Date(1602324610000)\/","LastAccessTimeUtc":"\/Date(1602324611000)\/","CreationTimeUtc":"\/Date(1602324612000)\/"}"#
+        ));
+        assert_eq!(expected, parse_powershell_times(
+            "\r\n{\"LastWriteTimeUtc\":\"2020-10-10T10:10:10.0000000Z\",\"LastAccessTimeUtc\":\"2020-10-10T10:10:11Z\",\"CreationTimeUtc\":\"2020-10-10T10:10:12Z\"}\r\n"
+        ));
+        assert_eq!(None, parse_powershell_times("Get-Item : Cannot find path"));
+    }
+}
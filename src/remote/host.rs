@@ -0,0 +1,125 @@
+use std::iter;
+use std::path::Path;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostPlatform {
+    Windows,
+    Linux,
+}
+
+impl HostPlatform {
+    pub fn current() -> HostPlatform {
+        if cfg!(windows) {
+            HostPlatform::Windows
+        } else {
+            HostPlatform::Linux
+        }
+    }
+
+    pub fn shell(&self) -> &'static str {
+        match self {
+            HostPlatform::Windows => "cmd.exe",
+            HostPlatform::Linux => "sh",
+        }
+    }
+
+    pub fn shell_args(&self, command: Vec<String>) -> Vec<String> {
+        match self {
+            HostPlatform::Windows => iter::once("/c".to_string())
+                .chain(command)
+                .collect(),
+            HostPlatform::Linux => vec![
+                "-c".to_string(),
+                command.iter()
+                    .map(|arg| sh_quoted(arg))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ],
+        }
+    }
+
+    pub fn archiver_program(&self) -> &'static str {
+        match self {
+            HostPlatform::Windows => "7za.exe",
+            HostPlatform::Linux => "7za",
+        }
+    }
+
+    pub fn resolve_local_program(&self, program: &str) -> String {
+        let program_name = Path::new(program).file_name().unwrap();
+        let in_current_directory = std::env::current_dir().unwrap().join(program_name);
+        if in_current_directory.exists() {
+            in_current_directory.to_string_lossy().to_string()
+        } else {
+            // rely on PATH lookup
+            program_name.to_string_lossy().to_string()
+        }
+    }
+}
+
+pub(crate) fn sh_quoted(arg: &str) -> String {
+    let is_plain = !arg.is_empty() && arg.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./=:@,+%".contains(c));
+    if arg == ">" || arg == "|" || is_plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace("'", "'\\''"))
+    }
+}
+
+// Quotes an argument of a command line joined into one string and run by cmd.exe on the target.
+pub(crate) fn cmd_quoted(arg: &str) -> String {
+    let is_plain = !arg.is_empty() && !arg.chars()
+        .any(|c| c.is_whitespace() || "\"<>|&^()%!".contains(c));
+    if is_plain {
+        arg.to_string()
+    } else {
+        format!("\"{}\"", arg.replace("\"", "\"\""))
+    }
+}
+
+pub(crate) fn ps_quoted(arg: &str) -> String {
+    let is_plain = !arg.is_empty() && arg.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./\\:=,".contains(c));
+    if is_plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace("'", "''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::HostPlatform;
+    use crate::remote::host::{cmd_quoted, ps_quoted};
+
+    #[test]
+    fn test_windows_shell_args_are_prefixed() {
+        let args = HostPlatform::Windows.shell_args(vec!["ipconfig".to_string(), "/all".to_string()]);
+        assert_eq!(vec!["/c", "ipconfig", "/all"], args);
+    }
+
+    #[test]
+    fn test_linux_shell_args_are_quoted() {
+        let args = HostPlatform::Linux.shell_args(vec![
+            "ssh".to_string(),
+            "ps aux".to_string(),
+            ">".to_string(),
+            "/tmp/it's here.txt".to_string(),
+        ]);
+        assert_eq!(vec!["-c", "ssh 'ps aux' > '/tmp/it'\\''s here.txt'"], args);
+    }
+
+    #[test]
+    fn test_windows_args_are_quoted() {
+        let query = "/q:*[System[TimeCreated[timediff(@SystemTime) <= 86400000]]]";
+        assert_eq!(format!("\"{}\"", query), cmd_quoted(query));
+        assert_eq!("\"Windows PowerShell\"", cmd_quoted("Windows PowerShell"));
+        assert_eq!("Security", cmd_quoted("Security"));
+        assert_eq!(format!("'{}'", query), ps_quoted(query));
+        assert_eq!("'it''s'", ps_quoted("it's"));
+        assert_eq!("C:\\Users\\Public\\x.evtx", ps_quoted("C:\\Users\\Public\\x.evtx"));
+    }
+}
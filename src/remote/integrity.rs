@@ -0,0 +1,292 @@
+use crate::remote::{Connector, Command, RemoteFileCopier, Computer};
+use crate::cleanup::retain_remote_file;
+use crate::hash::hash_file;
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::ErrorKind;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::fmt;
+
+pub const MAX_TRANSFER_ATTEMPTS: usize = 3;
+
+static RESULTS: Mutex<Option<HashMap<PathBuf, Integrity>>> = Mutex::new(None);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Integrity {
+    Verified,
+    Corrupted,
+    Unverified,
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Integrity::Verified => write!(f, "verified"),
+            Integrity::Corrupted => write!(f, "corrupted"),
+            Integrity::Unverified => write!(f, "unverified"),
+        }
+    }
+}
+
+pub fn parse_sha256(output: &str) -> Option<String> {
+    // certutil on older Windows versions separates the bytes of the hash by spaces
+    output.lines()
+        .map(|line| line.trim().replace(" ", "").to_lowercase())
+        .find(|line| line.len() == 64 && line.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn read_report(path: &Path) -> io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    // PowerShell Out-File writes UTF-16LE with BOM
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        let wide: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        return Ok(String::from_utf16_lossy(&wide));
+    }
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+pub fn certutil_hash<C: Connector + ?Sized>(connector: &C, path: &Path) -> io::Result<String> {
+    let command = vec![
+        "certutil".to_string(),
+        "-hashfile".to_string(),
+        path.to_string_lossy().to_string(),
+        "SHA256".to_string(),
+    ];
+    hash_by_command(connector, command, path)
+}
+
+// Runs a command on the target and returns its output, which is not kept as a report.
+pub fn command_output<C: Connector + ?Sized>(
+    connector: &C,
+    command: Vec<String>,
+    report_filename_prefix: &str,
+) -> io::Result<String> {
+    let store_directory = std::env::temp_dir();
+    let command = Command::new(
+        command,
+        Some(&store_directory),
+        report_filename_prefix,
+        false,
+    );
+    let outcome = connector.connect_and_run_command(command, None)?;
+    let output = match &outcome.report_path {
+        None => outcome.stdout.clone(),
+        Some(report_path) => {
+            let output = read_report(report_path).unwrap_or_default();
+            if let Err(err) = std::fs::remove_file(report_path) {
+                debug!("Cannot delete {} due to {}", report_path.display(), err);
+            }
+            output
+        }
+    };
+    Ok(output)
+}
+
+pub fn hash_by_command<C: Connector + ?Sized>(
+    connector: &C,
+    command: Vec<String>,
+    path: &Path,
+) -> io::Result<String> {
+    let output = command_output(connector, command, "remote-hash")?;
+    parse_sha256(&output).ok_or_else(|| io::Error::new(
+        ErrorKind::InvalidData,
+        format!("Cannot compute SHA256 of remote file {} using method {}",
+                path.display(),
+                connector.connect_method_name()
+        ),
+    ))
+}
+
+pub fn remote_hash(connector: &dyn Connector, path: &Path) -> Option<String> {
+    match connector.remote_file_hash(path) {
+        Ok(hash) => {
+            debug!("SHA256 of remote file {} is {}", path.display(), hash);
+            Some(hash)
+        }
+        Err(err) => {
+            debug!("Cannot compute hash of remote file {} due to {}", path.display(), err);
+            None
+        }
+    }
+}
+
+pub fn unsupported_hash(path: &Path, method_name: &str) -> io::Error {
+    io::Error::other(
+        format!("Method {} cannot compute hash of remote file {}", method_name, path.display()),
+    )
+}
+
+pub fn check(local_path: &Path, expected: Option<&str>) -> Integrity {
+    let expected = match expected {
+        None => return Integrity::Unverified,
+        Some(expected) => expected,
+    };
+    match hash_file(local_path) {
+        Ok(hashes) => if hashes.sha256.eq_ignore_ascii_case(expected) {
+            Integrity::Verified
+        } else {
+            debug!("SHA256 of {} is {}, expected {}", local_path.display(), hashes.sha256, expected);
+            Integrity::Corrupted
+        }
+        Err(err) => {
+            debug!("Cannot hash {} due to {}", local_path.display(), err);
+            Integrity::Corrupted
+        }
+    }
+}
+
+pub fn report(local_path: &Path, integrity: Integrity) {
+    match integrity {
+        Integrity::Verified =>
+            info!("Integrity of {} verified", local_path.display()),
+        Integrity::Corrupted =>
+            error!("{} is corrupted, its hash does not match the remote file", local_path.display()),
+        Integrity::Unverified =>
+            warn!("Integrity of {} cannot be verified", local_path.display()),
+    }
+    RESULTS.lock().unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(local_path.to_path_buf(), integrity);
+}
+
+pub fn integrity_of(local_path: &Path) -> Option<Integrity> {
+    RESULTS.lock().unwrap()
+        .as_ref()
+        .and_then(|results| results.get(local_path).cloned())
+}
+
+pub fn log_summary() {
+    let results = RESULTS.lock().unwrap();
+    let results = match results.as_ref() {
+        None => return,
+        Some(results) => results,
+    };
+    let count = |integrity: Integrity| results.values().filter(|it| **it == integrity).count();
+    info!("Integrity summary: {} verified, {} corrupted, {} unverified",
+          count(Integrity::Verified),
+          count(Integrity::Corrupted),
+          count(Integrity::Unverified)
+    );
+    for (path, integrity) in results.iter() {
+        if *integrity == Integrity::Corrupted {
+            error!("Corrupted: {}", path.display());
+        }
+    }
+}
+
+// The remote file of a corrupted transfer is the only good copy, so it is kept also by the cleanup.
+pub fn corrupted_transfer(computer: &Computer, source: &Path) -> io::Error {
+    retain_remote_file(computer, source);
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("Transfer of {} is corrupted after {} attempts, keeping the remote file", source.display(), MAX_TRANSFER_ATTEMPTS),
+    )
+}
+
+// Fails when the copy is still corrupted after the last attempt.
+pub fn copy_from_remote_verified(
+    copier: &dyn RemoteFileCopier,
+    expected: Option<&str>,
+    source: &Path,
+    target: &Path,
+) -> (io::Result<()>, Integrity) {
+    let local_path = match source.file_name() {
+        None => target.to_path_buf(),
+        Some(file_name) => target.join(file_name),
+    };
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = copier.copy_from_remote(source, target);
+        let integrity = check(&local_path, expected);
+        if integrity != Integrity::Corrupted {
+            return (result, integrity);
+        }
+        if attempt >= MAX_TRANSFER_ATTEMPTS {
+            return (result.and(Err(corrupted_transfer(copier.remote_computer(), source))), integrity);
+        }
+        warn!("Transfer of {} is corrupted, retrying ({}/{})",
+              source.display(),
+              attempt,
+              MAX_TRANSFER_ATTEMPTS - 1
+        );
+    }
+}
+
+pub fn download_verified(connector: &dyn Connector, source: &Path, target: &Path) -> io::Result<()> {
+    let expected = remote_hash(connector, source);
+    let (result, integrity) = copy_from_remote_verified(
+        connector.copier(),
+        expected.as_deref(),
+        source,
+        target,
+    );
+    if let Some(file_name) = source.file_name() {
+        report(&target.join(file_name), integrity);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::parse_sha256;
+    use crate::remote::integrity::{check, read_report, Integrity};
+    use std::fs;
+
+    const GARGAMEL_SHA256: &str = "4e4b5f47ff8dc92f3d387481bcf0c49b2f86a3353313b4f17b3ab5cdc34a58a1";
+
+    fn write_temp_file(name: &str, content: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("gargamel-integrity-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_certutil_output() {
+        let output = "SHA256 hash of C:\\Users\\Public\\test.txt:\r\n\
+        4e 4b 5f 47 ff 8d c9 2f 3d 38 74 81 bc f0 c4 9b 2f 86 a3 35 33 13 b4 f1 7b 3a b5 cd c3 4a 58 a1\r\n\
+        CertUtil: -hashfile command completed successfully.\r\n";
+        assert_eq!(Some(GARGAMEL_SHA256.to_string()), parse_sha256(output));
+    }
+
+    #[test]
+    fn test_parse_uppercase_hash() {
+        let output = format!("Algorithm : SHA256\r\nHash : \r\n{}\r\n", GARGAMEL_SHA256.to_uppercase());
+        assert_eq!(Some(GARGAMEL_SHA256.to_string()), parse_sha256(&output));
+    }
+
+    #[test]
+    fn test_parse_error_output() {
+        assert_eq!(None, parse_sha256("CertUtil: The system cannot find the file specified."));
+        assert_eq!(None, parse_sha256(""));
+    }
+
+    #[test]
+    fn test_check_matching_hash() {
+        let path = write_temp_file("verified", b"gargamel");
+        assert_eq!(Integrity::Verified, check(&path, Some(&GARGAMEL_SHA256.to_uppercase())));
+        assert_eq!(Integrity::Unverified, check(&path, None));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_check_corrupted_copy() {
+        let path = write_temp_file("corrupted", b"gargame");
+        assert_eq!(Integrity::Corrupted, check(&path, Some(GARGAMEL_SHA256)));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Integrity::Corrupted, check(&path, Some(GARGAMEL_SHA256)));
+    }
+
+    #[test]
+    fn test_read_utf16_report() {
+        let mut content = vec![0xFF, 0xFE];
+        content.extend("Hash\r\n".encode_utf16().flat_map(|unit| unit.to_le_bytes().to_vec()));
+        let path = write_temp_file("utf16", &content);
+        assert_eq!("Hash\r\n", read_report(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
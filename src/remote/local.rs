@@ -1,14 +1,21 @@
-use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, Command};
+use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, Command, HostPlatform, RemoteFileState, FileTimes, local_file_state, metadata_times};
 use std::path::{Path, PathBuf};
 use std::{io, fs};
 use std::time::Duration;
 use fs_extra::dir::CopyOptions;
-use std::io::ErrorKind;
+use crate::process_runner::CommandOutcome;
+use crate::hash::hash_file;
 
 pub struct Local {
     localhost: Computer
 }
 
+impl Default for Local {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Local {
     pub fn new() -> Local {
         Local {
@@ -24,7 +31,7 @@ impl Local {
 
 impl Connector for Local {
     fn connect_method_name(&self) -> &'static str {
-        return "LOCAL";
+        "LOCAL"
     }
 
     fn computer(&self) -> &Computer {
@@ -47,12 +54,23 @@ impl Connector for Local {
         &self,
         command_to_run: Command<'_>,
         timeout: Option<Duration>
-    ) -> io::Result<()> {
+    ) -> io::Result<CommandOutcome> {
         self.connect_and_run_command(command_to_run, timeout)
     }
 
     fn remote_temp_storage(&self) -> &Path {
-        Path::new("C:\\Users\\Public")
+        match HostPlatform::current() {
+            HostPlatform::Windows => Path::new("C:\\Users\\Public"),
+            HostPlatform::Linux => Path::new("/tmp"),
+        }
+    }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        hash_file(path).map(|hashes| hashes.sha256)
+    }
+
+    fn remote_platform(&self) -> HostPlatform {
+        HostPlatform::current()
     }
 }
 
@@ -72,7 +90,7 @@ impl FileCopier for Local {
             let mut options = CopyOptions::new();
             options.copy_inside = true;
             options.overwrite = true;
-            fs_extra::dir::copy(source, target, &options).map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+            fs_extra::dir::copy(source, target, &options).map_err(io::Error::other)?;
         }
         Ok(())
     }
@@ -102,4 +120,16 @@ impl RemoteFileCopier for Local {
     fn path_to_remote_form(&self, path: &Path) -> PathBuf {
         path.to_path_buf()
     }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        hash_file(path).map(|hashes| hashes.sha256)
+    }
+
+    fn remote_file_state(&self, path: &Path) -> io::Result<RemoteFileState> {
+        local_file_state(path)
+    }
+
+    fn remote_file_times(&self, path: &Path) -> io::Result<FileTimes> {
+        metadata_times(path)
+    }
 }
\ No newline at end of file
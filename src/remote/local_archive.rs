@@ -0,0 +1,420 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write, ErrorKind};
+use std::path::{Path, PathBuf};
+use sevenz_rust::{SevenZReader, SevenZWriter, SevenZArchiveEntry, Password};
+use zip::{ZipArchive, ZipWriter, CompressionMethod};
+use zip::write::FileOptions;
+use crate::remote::path_to_part;
+
+// Size of one volume of a split archive, the same as `7za -v2m` uses on the target.
+pub const VOLUME_SIZE: u64 = 2 * 1024 * 1024;
+
+const SEVEN_ZIP_SIGNATURE: [u8; 6] = [b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C];
+const ZIP_SIGNATURE: [u8; 4] = [b'P', b'K', 3, 4];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    SevenZip,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::SevenZip => "7z",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    // Detects the format of a file by its signature, none when it is not an archive.
+    pub fn of_file(path: &Path) -> io::Result<Option<ArchiveFormat>> {
+        let mut signature = vec![];
+        File::open(path)?.take(SEVEN_ZIP_SIGNATURE.len() as u64).read_to_end(&mut signature)?;
+        Ok(ArchiveFormat::detect(&signature))
+    }
+
+    fn detect(signature: &[u8]) -> Option<ArchiveFormat> {
+        if signature.starts_with(&SEVEN_ZIP_SIGNATURE) {
+            Some(ArchiveFormat::SevenZip)
+        } else if signature.starts_with(&ZIP_SIGNATURE) {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_volume(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().parse::<usize>().is_ok())
+        .unwrap_or(false)
+}
+
+// Reads the volumes .001, .002, ... of a split archive as a single file.
+// Any other path is read as an archive consisting of one volume.
+pub struct SplitVolumes {
+    volumes: Vec<(PathBuf, u64)>,
+    len: u64,
+    position: u64,
+    current: Option<(usize, File)>,
+}
+
+impl SplitVolumes {
+    pub fn open(archive: &Path) -> io::Result<SplitVolumes> {
+        let mut paths = vec![];
+        if is_volume(archive) {
+            let whole = archive.with_extension("");
+            let mut part = 1;
+            while path_to_part(&whole, part).exists() {
+                paths.push(path_to_part(&whole, part));
+                part += 1;
+            }
+            if paths.first().map(|first| first != archive).unwrap_or(true) {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("Split archive {} does not start with volume {}", whole.display(), archive.display()),
+                ));
+            }
+        } else {
+            paths.push(archive.to_path_buf());
+        }
+        let mut volumes = vec![];
+        let mut len = 0;
+        for path in paths {
+            let size = std::fs::metadata(&path)?.len();
+            len += size;
+            volumes.push((path, size));
+        }
+        Ok(SplitVolumes { volumes, len, position: 0, current: None })
+    }
+
+    pub fn volumes(&self) -> Vec<PathBuf> {
+        self.volumes.iter().map(|(path, _)| path.clone()).collect()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Index of the volume containing the current position and the offset within it.
+    fn locate(&self) -> Option<(usize, u64)> {
+        let mut start = 0;
+        for (index, (_, size)) in self.volumes.iter().enumerate() {
+            if self.position < start + size {
+                return Some((index, self.position - start));
+            }
+            start += size;
+        }
+        None
+    }
+}
+
+impl Read for SplitVolumes {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (index, offset) = match self.locate() {
+            None => return Ok(0),
+            Some(location) => location,
+        };
+        let is_open = matches!(&self.current, Some((current, _)) if *current == index);
+        if !is_open {
+            self.current = Some((index, File::open(&self.volumes[index].0)?));
+        }
+        let (_, file) = self.current.as_mut().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        let available = (self.volumes[index].1 - offset).min(buf.len() as u64) as usize;
+        let read = file.read(&mut buf[..available])?;
+        if read == 0 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Volume {} is shorter than expected", self.volumes[index].0.display()),
+            ));
+        }
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for SplitVolumes {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.len as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+        if position < 0 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "Seek before the start of the archive"));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+fn invalid(archive: &Path, cause: impl std::fmt::Display) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("Cannot extract {}: {}", archive.display(), cause))
+}
+
+// Archives are extracted without their directory structure, like `7za e` does.
+fn extracted_path(target_dir: &Path, name: &str) -> Option<PathBuf> {
+    let name = name.replace("\\", "/");
+    Path::new(&name).file_name().map(|file_name| target_dir.join(file_name))
+}
+
+// Extracts a 7zip or zip archive, possibly split into volumes, into the target directory.
+// The checksums of the extracted files are verified. Returns the paths of the extracted files.
+pub fn extract_archive(archive: &Path, target_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut volumes = SplitVolumes::open(archive)?;
+    let mut signature = [0u8; 6];
+    volumes.read_exact(&mut signature).map_err(|err| invalid(archive, err))?;
+    volumes.seek(SeekFrom::Start(0))?;
+    trace!("Extracting {} volumes of {} into {}", volumes.volumes().len(), archive.display(), target_dir.display());
+    match ArchiveFormat::detect(&signature) {
+        Some(ArchiveFormat::SevenZip) => extract_seven_zip(archive, volumes, target_dir),
+        Some(ArchiveFormat::Zip) => extract_zip(archive, volumes, target_dir),
+        None => Err(invalid(archive, "unknown archive format")),
+    }
+}
+
+fn extract_seven_zip(archive: &Path, volumes: SplitVolumes, target_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let len = volumes.len();
+    let mut reader = SevenZReader::new(volumes, len, Password::empty())
+        .map_err(|err| invalid(archive, err))?;
+    let mut extracted = vec![];
+    let mut failure = None;
+    reader.for_each_entries(|entry, content| {
+        if entry.is_directory() {
+            return Ok(true);
+        }
+        let path = match extracted_path(target_dir, entry.name()) {
+            None => return Ok(true),
+            Some(path) => path,
+        };
+        let copied = File::create(&path).and_then(|mut file| io::copy(content, &mut file));
+        match copied {
+            Ok(_) => {
+                extracted.push(path);
+                Ok(true)
+            }
+            Err(err) => {
+                failure = Some(invalid(archive, format!("{} ({})", err, entry.name())));
+                Ok(false)
+            }
+        }
+    }).map_err(|err| invalid(archive, err))?;
+    match failure {
+        Some(err) => Err(err),
+        None => Ok(extracted),
+    }
+}
+
+fn extract_zip(archive: &Path, volumes: SplitVolumes, target_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut zip = ZipArchive::new(volumes).map_err(|err| invalid(archive, err))?;
+    let mut extracted = vec![];
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index).map_err(|err| invalid(archive, err))?;
+        if entry.is_dir() {
+            continue;
+        }
+        let path = match extracted_path(target_dir, entry.name()) {
+            None => continue,
+            Some(path) => path,
+        };
+        let mut file = File::create(&path)?;
+        io::copy(&mut entry, &mut file)
+            .map_err(|err| invalid(archive, format!("{} ({})", err, entry.name())))?;
+        extracted.push(path);
+    }
+    Ok(extracted)
+}
+
+fn write_seven_zip<W: Write + Seek>(writer: W, name: &str, content: impl Read) -> io::Result<W> {
+    let to_io = |err: sevenz_rust::Error| io::Error::other(err.to_string());
+    let mut archive = SevenZWriter::new(writer).map_err(to_io)?;
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = name.to_string();
+    entry.has_stream = true;
+    archive.push_archive_entry(entry, Some(content)).map_err(to_io)?;
+    archive.finish()
+}
+
+fn write_zip<W: Write + Seek>(writer: W, name: &str, size: u64, mut content: impl Read) -> io::Result<W> {
+    let mut archive = ZipWriter::new(writer);
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(size >= u32::MAX as u64);
+    archive.start_file(name, options)?;
+    io::copy(&mut content, &mut archive)?;
+    Ok(archive.finish()?)
+}
+
+// In-memory 7zip archive with a single file, used to simulate archives created on targets.
+#[cfg(any(test, feature = "mock"))]
+pub(crate) fn seven_zip_bytes(name: &str, content: &[u8]) -> io::Result<Vec<u8>> {
+    write_seven_zip(io::Cursor::new(vec![]), name, content).map(|cursor| cursor.into_inner())
+}
+
+fn split_into_volumes(archive: &Path, volume_size: u64) -> io::Result<Vec<PathBuf>> {
+    let mut source = File::open(archive)?;
+    let mut volumes = vec![];
+    loop {
+        let volume = path_to_part(archive, volumes.len() + 1);
+        let mut target = File::create(&volume)?;
+        let copied = io::copy(&mut (&mut source).take(volume_size), &mut target)?;
+        if copied == 0 && !volumes.is_empty() {
+            drop(target);
+            std::fs::remove_file(&volume)?;
+            break;
+        }
+        volumes.push(volume);
+        if copied < volume_size {
+            break;
+        }
+    }
+    drop(source);
+    std::fs::remove_file(archive)?;
+    Ok(volumes)
+}
+
+// Compresses the file into `<source>.7z` or `<source>.zip`, split into volumes when `volume_size` is given.
+// Returns the created archive or its volumes.
+pub fn compress_file(source: &Path, format: ArchiveFormat, volume_size: Option<u64>) -> io::Result<Vec<PathBuf>> {
+    let name = source.file_name()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, format!("{} is not a file", source.display())))?
+        .to_string_lossy()
+        .to_string();
+    let archive = PathBuf::from(format!("{}.{}", source.display(), format.extension()));
+    let content = File::open(source)?;
+    let size = content.metadata()?.len();
+    let target = File::create(&archive)?;
+    match format {
+        ArchiveFormat::SevenZip => write_seven_zip(target, &name, content)?,
+        ArchiveFormat::Zip => write_zip(target, &name, size, content)?,
+    };
+    match volume_size {
+        None => Ok(vec![archive]),
+        Some(volume_size) => split_into_volumes(&archive, volume_size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::{compress_file, extract_archive, path_to_part, ArchiveFormat, SplitVolumes};
+    use crate::remote::local_archive::extracted_path;
+    use std::fs;
+    use std::io::{ErrorKind, Read, Seek, SeekFrom};
+    use std::path::{Path, PathBuf};
+
+    // Returns the directory and the source file with incompressible content.
+    fn source_file() -> (PathBuf, PathBuf, Vec<u8>) {
+        let directory = std::env::temp_dir().join(format!("gargamel-archive-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(directory.join("extracted")).unwrap();
+        let source = directory.join("registry.txt");
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        let content: Vec<u8> = (0..300_000).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u8
+        }).collect();
+        fs::write(&source, &content).unwrap();
+        (directory, source, content)
+    }
+
+    fn assert_split_archive_is_extracted(format: ArchiveFormat) {
+        let (directory, source, content) = source_file();
+        let extracted = directory.join("extracted");
+
+        let volumes = compress_file(&source, format, Some(100_000)).unwrap();
+
+        assert!(volumes.len() > 1);
+        assert_eq!(vec![extracted.join("registry.txt")], extract_archive(&volumes[0], &extracted).unwrap());
+        assert_eq!(content, fs::read(extracted.join("registry.txt")).unwrap());
+    }
+
+    fn assert_corrupted_archive_is_rejected(format: ArchiveFormat) {
+        let (directory, source, _) = source_file();
+        let volumes = compress_file(&source, format, Some(100_000)).unwrap();
+        let mut corrupted = fs::read(&volumes[0]).unwrap();
+        let last = corrupted.len() - 1;
+        corrupted[last] = !corrupted[last];
+        fs::write(&volumes[0], corrupted).unwrap();
+
+        assert!(extract_archive(&volumes[0], &directory.join("extracted")).is_err());
+    }
+
+    #[test]
+    fn test_split_seven_zip_is_extracted() {
+        assert_split_archive_is_extracted(ArchiveFormat::SevenZip);
+    }
+
+    #[test]
+    fn test_split_zip_is_extracted() {
+        assert_split_archive_is_extracted(ArchiveFormat::Zip);
+    }
+
+    #[test]
+    fn test_corrupted_seven_zip_is_rejected() {
+        assert_corrupted_archive_is_rejected(ArchiveFormat::SevenZip);
+    }
+
+    #[test]
+    fn test_corrupted_zip_is_rejected() {
+        assert_corrupted_archive_is_rejected(ArchiveFormat::Zip);
+    }
+
+    #[test]
+    fn test_volumes_are_read_across_boundaries() {
+        let (_, source, _) = source_file();
+        let volumes = compress_file(&source, ArchiveFormat::Zip, Some(100_000)).unwrap();
+        let whole: Vec<u8> = volumes.iter().flat_map(|it| fs::read(it).unwrap()).collect();
+
+        let mut reader = SplitVolumes::open(&volumes[0]).unwrap();
+        reader.seek(SeekFrom::Start(99_999)).unwrap();
+        let mut buffer = [0u8; 2];
+        reader.read_exact(&mut buffer).unwrap();
+
+        assert_eq!(whole.len() as u64, reader.len());
+        assert_eq!(&whole[99_999..100_001], &buffer);
+    }
+
+    #[test]
+    fn test_split_archive_without_first_volume_is_rejected() {
+        let (_, source, _) = source_file();
+        let volumes = compress_file(&source, ArchiveFormat::SevenZip, Some(100_000)).unwrap();
+        fs::remove_file(&volumes[0]).unwrap();
+
+        assert_eq!(ErrorKind::NotFound, SplitVolumes::open(&volumes[1]).err().unwrap().kind());
+    }
+
+    #[test]
+    fn test_split_archive_without_last_volume_is_rejected() {
+        let (directory, source, _) = source_file();
+        let volumes = compress_file(&source, ArchiveFormat::SevenZip, Some(100_000)).unwrap();
+        let last = volumes.len();
+        fs::remove_file(path_to_part(&volumes[0].with_extension(""), last)).unwrap();
+
+        assert!(extract_archive(&volumes[0], &directory.join("extracted")).is_err());
+    }
+
+    #[test]
+    fn test_unknown_format_is_rejected() {
+        let (directory, source, _) = source_file();
+
+        let err = extract_archive(&source, &directory.join("extracted")).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert_eq!(None, ArchiveFormat::of_file(&source).unwrap());
+    }
+
+    #[test]
+    fn test_entries_are_extracted_without_directories() {
+        let target = Path::new("extracted");
+        assert_eq!(Some(target.join("evil.exe")), extracted_path(target, "..\\..\\Windows\\evil.exe"));
+        assert_eq!(Some(target.join("passwd")), extracted_path(target, "/etc/passwd"));
+        assert_eq!(None, extracted_path(target, ".."));
+    }
+}
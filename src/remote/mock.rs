@@ -0,0 +1,439 @@
+use crate::remote::{Connector, Computer, Command, FileCopier, RemoteFileCopier, HostPlatform, RemoteFileState, path_to_part, seven_zip_bytes, VOLUME_SIZE};
+use crate::process_runner::{create_report_path, CommandOutcome};
+use crate::manifest::{Provenance, record_report};
+use crate::hash::hash_bytes;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// In-memory fake of a remote computer, used to test acquirers without spawning any tools.
+// Commands are answered by the first rule whose pattern is contained in the space-joined command.
+// A command without a matching rule succeeds and, like reg export or wevtutil, writes its output
+// into every file of the remote temp storage it references which does not exist yet.
+
+#[derive(Clone, Debug, Default)]
+pub struct MockResponse {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub timed_out: bool,
+    pub error: Option<ErrorKind>,
+}
+
+impl MockResponse {
+    pub fn output(stdout: &str) -> MockResponse {
+        MockResponse { stdout: stdout.to_string(), ..Default::default() }
+    }
+
+    pub fn exit_code(exit_code: i32) -> MockResponse {
+        MockResponse { exit_code, ..Default::default() }
+    }
+
+    pub fn timeout() -> MockResponse {
+        MockResponse { timed_out: true, ..Default::default() }
+    }
+
+    pub fn error(kind: ErrorKind) -> MockResponse {
+        MockResponse { error: Some(kind), ..Default::default() }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IssuedCommand {
+    pub command: Vec<String>,
+    pub elevated: bool,
+    pub timeout: Option<Duration>,
+}
+
+impl IssuedCommand {
+    pub fn joined(&self) -> String {
+        self.command.join(" ")
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    files: HashMap<PathBuf, Vec<u8>>,
+    rules: Vec<(String, MockResponse)>,
+    commands: Vec<IssuedCommand>,
+    failed_transfers: usize,
+    corrupted_transfers: usize,
+    downloads: Vec<PathBuf>,
+    locked: HashSet<PathBuf>,
+}
+
+#[derive(Clone, Default)]
+pub struct MockTarget {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTarget {
+    pub fn new() -> MockTarget {
+        MockTarget::default()
+    }
+
+    pub fn add_file(&self, path: &Path, content: &[u8]) {
+        self.state.lock().unwrap().files.insert(path.to_path_buf(), content.to_vec());
+    }
+
+    pub fn file(&self, path: &Path) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().files.get(path).cloned()
+    }
+
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.state.lock().unwrap().files.keys().cloned().collect();
+        files.sort();
+        files
+    }
+
+    pub fn respond(&self, pattern: &str, response: MockResponse) {
+        self.state.lock().unwrap().rules.push((pattern.to_string(), response));
+    }
+
+    // The next `count` downloads fail without creating the local file.
+    pub fn fail_transfers(&self, count: usize) {
+        self.state.lock().unwrap().failed_transfers = count;
+    }
+
+    // The next `count` downloads deliver a file with a flipped byte.
+    pub fn corrupt_transfers(&self, count: usize) {
+        self.state.lock().unwrap().corrupted_transfers = count;
+    }
+
+    // The file stays open by a remote process, so it cannot be deleted.
+    pub fn lock_file(&self, path: &Path) {
+        self.state.lock().unwrap().locked.insert(path.to_path_buf());
+    }
+
+    pub fn commands(&self) -> Vec<IssuedCommand> {
+        self.state.lock().unwrap().commands.clone()
+    }
+
+    pub fn downloads(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().downloads.clone()
+    }
+
+    fn run(&self, command: &[String], elevated: bool, timeout: Option<Duration>, remote_temp_storage: &Path) -> io::Result<CommandOutcome> {
+        let mut state = self.state.lock().unwrap();
+        state.commands.push(IssuedCommand { command: command.to_vec(), elevated, timeout });
+        let joined = command.join(" ");
+        let rule = state.rules.iter()
+            .find(|(pattern, _)| joined.contains(pattern.as_str()))
+            .map(|(_, response)| response.clone());
+        let response = match rule {
+            Some(response) => response,
+            None => {
+                let exit_code = if is_archiver(command) {
+                    archive(&mut state.files, command)
+                } else {
+                    write_outputs(&mut state.files, command, remote_temp_storage);
+                    0
+                };
+                MockResponse::exit_code(exit_code)
+            }
+        };
+        if let Some(kind) = response.error {
+            return Err(io::Error::new(kind, format!("Mock failure of {}", joined)));
+        }
+        Ok(CommandOutcome {
+            exit_code: if response.timed_out { None } else { Some(response.exit_code) },
+            timed_out: response.timed_out,
+            stdout: response.stdout,
+            ..Default::default()
+        })
+    }
+
+    fn download(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let mut content = match state.files.get(source) {
+            None => return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Remote file {} does not exist", source.display()),
+            )),
+            Some(content) => content.clone(),
+        };
+        state.downloads.push(source.to_path_buf());
+        if state.failed_transfers > 0 {
+            state.failed_transfers -= 1;
+            return Err(io::Error::new(ErrorKind::ConnectionReset, format!("Mock transfer of {} failed", source.display())));
+        }
+        if state.corrupted_transfers > 0 {
+            state.corrupted_transfers -= 1;
+            if let Some(byte) = content.first_mut() {
+                *byte = !*byte;
+            }
+        }
+        let file_name = source.file_name().unwrap_or(source.as_os_str());
+        std::fs::write(target.join(file_name), content)
+    }
+
+    fn upload(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let content = std::fs::read(source)?;
+        let file_name = source.file_name().unwrap_or(source.as_os_str());
+        self.state.lock().unwrap().files.insert(target.join(file_name), content);
+        Ok(())
+    }
+
+    fn delete(&self, target: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.locked.contains(target) {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Remote file {} is used by another process", target.display()),
+            ));
+        }
+        match state.files.remove(target) {
+            Some(_) => Ok(()),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Remote file {} does not exist", target.display()),
+            )),
+        }
+    }
+
+    fn state(&self, path: &Path) -> RemoteFileState {
+        let state = self.state.lock().unwrap();
+        match state.files.get(path) {
+            None => RemoteFileState::Missing,
+            Some(content) => RemoteFileState::Present { size: content.len() as u64, locked: state.locked.contains(path) },
+        }
+    }
+
+    fn hash(&self, path: &Path) -> io::Result<String> {
+        match self.state.lock().unwrap().files.get(path) {
+            Some(content) => Ok(hash_bytes(content).sha256),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Remote file {} does not exist", path.display()),
+            )),
+        }
+    }
+}
+
+fn is_archiver(command: &[String]) -> bool {
+    command.first()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|program| program == HostPlatform::Windows.archiver_program() || program == HostPlatform::Linux.archiver_program())
+        .unwrap_or(false)
+}
+
+// Mimics `7za [switches] a archive source`, creating a real 7zip archive split into volumes with `-v`.
+fn archive(files: &mut HashMap<PathBuf, Vec<u8>>, command: &[String]) -> i32 {
+    let add = match command.iter().position(|arg| arg == "a") {
+        None => return 0,
+        Some(add) => add,
+    };
+    let (archive, source) = match (command.get(add + 1), command.get(add + 2)) {
+        (Some(archive), Some(source)) => (PathBuf::from(archive), PathBuf::from(source)),
+        _ => return 7,
+    };
+    let content = match files.get(&source) {
+        None => return 2,
+        Some(content) => content.clone(),
+    };
+    let name = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let archived = match seven_zip_bytes(&name, &content) {
+        Err(_) => return 2,
+        Ok(archived) => archived,
+    };
+    if command.iter().any(|arg| arg.starts_with("-v")) {
+        for (i, volume) in archived.chunks(VOLUME_SIZE as usize).enumerate() {
+            files.insert(path_to_part(&archive, i + 1), volume.to_vec());
+        }
+    } else {
+        files.insert(archive, archived);
+    }
+    if command.iter().any(|arg| arg == "-sdel") {
+        files.remove(&source);
+    }
+    0
+}
+
+fn write_outputs(files: &mut HashMap<PathBuf, Vec<u8>>, command: &[String], remote_temp_storage: &Path) {
+    for arg in command.iter().skip(1) {
+        let path = PathBuf::from(arg);
+        if path.starts_with(remote_temp_storage) && !files.contains_key(&path) {
+            files.insert(path, mock_output(command));
+        }
+    }
+}
+
+// long enough not to be considered an empty download
+fn mock_output(command: &[String]) -> Vec<u8> {
+    format!("{}\n", command.join(" ")).repeat(10).into_bytes()
+}
+
+#[derive(Clone)]
+pub struct MockCopier {
+    pub computer: Computer,
+    pub target: MockTarget,
+}
+
+impl FileCopier for MockCopier {
+    fn copy_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        if self.target.file(source).is_some() {
+            self.target.download(source, target)
+        } else {
+            self.target.upload(source, target)
+        }
+    }
+
+    fn delete_file(&self, target: &Path) -> io::Result<()> {
+        self.target.delete(target)
+    }
+
+    fn method_name(&self) -> &'static str {
+        "Mock"
+    }
+}
+
+impl RemoteFileCopier for MockCopier {
+    fn remote_computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    fn copier_impl(&self) -> &dyn FileCopier {
+        self
+    }
+
+    fn path_to_remote_form(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+
+    fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.target.upload(source, target)
+    }
+
+    fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
+        self.target.delete(target)
+    }
+
+    fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.target.download(source, target)
+    }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        self.target.hash(path)
+    }
+
+    fn remote_file_state(&self, path: &Path) -> io::Result<RemoteFileState> {
+        Ok(self.target.state(path))
+    }
+}
+
+pub struct MockConnector {
+    pub computer: Computer,
+    pub remote_temp_storage: PathBuf,
+    pub platform: HostPlatform,
+    pub copier: MockCopier,
+}
+
+impl MockConnector {
+    pub fn new(computer: Computer, remote_temp_storage: &Path, target: &MockTarget) -> MockConnector {
+        MockConnector {
+            copier: MockCopier {
+                computer: computer.clone(),
+                target: target.clone(),
+            },
+            computer,
+            remote_temp_storage: remote_temp_storage.to_path_buf(),
+            platform: HostPlatform::Windows,
+        }
+    }
+}
+
+impl Connector for MockConnector {
+    fn connect_method_name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    fn copier(&self) -> &dyn RemoteFileCopier {
+        &self.copier
+    }
+
+    fn remote_temp_storage(&self) -> &Path {
+        &self.remote_temp_storage
+    }
+
+    fn connect_and_run_local_program_in_current_directory(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        self.connect_and_run_local_program(command_to_run, timeout)
+    }
+
+    // the program is considered to be already uploaded
+    fn connect_and_run_local_program(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        self.connect_and_run_command(command_to_run, timeout)
+    }
+
+    fn connect_and_run_command(
+        &self,
+        command_to_run: Command<'_>,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let report_path = command_to_run.report_store_directory.map(|store_directory| create_report_path(
+            &self.computer,
+            store_directory,
+            command_to_run.report_filename_prefix,
+            self.connect_method_name(),
+            "txt",
+        ));
+        let outcome = self.copier.target.run(
+            &command_to_run.command,
+            command_to_run.elevated,
+            timeout,
+            &self.remote_temp_storage,
+        )?;
+        if let Some(report_path) = &report_path {
+            std::fs::write(report_path, &outcome.stdout)?;
+        }
+        let provenance = Provenance::new(&self.computer, self.connect_method_name())
+            .command(&command_to_run.command);
+        record_report(report_path.as_deref(), &provenance);
+        Ok(outcome.with_report_path(report_path))
+    }
+
+    fn connect_and_stream_command(
+        &self,
+        command_to_run: Command<'_>,
+        local_file: &Path,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let outcome = self.copier.target.run(
+            &command_to_run.command,
+            command_to_run.elevated,
+            timeout,
+            &self.remote_temp_storage,
+        )?;
+        std::fs::write(local_file, &outcome.stdout)?;
+        Ok(outcome)
+    }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        self.copier.target.hash(path)
+    }
+
+    fn remote_platform(&self) -> HostPlatform {
+        self.platform
+    }
+
+    fn prepare_command(&self,
+                       command: Vec<String>,
+                       _output_file_path: Option<String>,
+                       _elevated: bool,
+    ) -> Vec<String> {
+        command
+    }
+}
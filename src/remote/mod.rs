@@ -38,6 +38,32 @@ pub mod download;
 
 pub use self::download::*;
 
+pub mod host;
+
+pub use self::host::*;
+
+pub mod file_times;
+
+pub use self::file_times::*;
+
+pub mod integrity;
+
+pub use self::integrity::*;
+
 pub mod utils;
 
-pub use self::utils::*;
\ No newline at end of file
+pub use self::utils::*;
+
+pub mod readiness;
+
+pub use self::readiness::*;
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+#[cfg(any(test, feature = "mock"))]
+pub use self::mock::*;
+
+pub mod local_archive;
+
+pub use self::local_archive::*;
\ No newline at end of file
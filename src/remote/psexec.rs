@@ -1,5 +1,6 @@
-use crate::remote::{Connector, Computer, Command, RemoteFileCopier, Cmd, WindowsRemoteFileHandler, FileCopier};
+use crate::remote::{Connector, Computer, Command, RemoteFileCopier, Cmd, WindowsRemoteFileHandler, FileCopier, RemoteFileState, FileTimes, certutil_hash};
 use std::time::Duration;
+use crate::process_runner::CommandOutcome;
 use std::io::Error;
 use std::path::{PathBuf, Path};
 use std::io;
@@ -21,6 +22,7 @@ impl PsExec {
         }
     }
 
+    #[allow(clippy::self_named_constructors)]
     pub fn psexec(computer: Computer, remote_temp_storage: PathBuf) -> PsExec {
         PsExec {
             computer: computer.clone(),
@@ -33,7 +35,7 @@ impl PsExec {
 
 impl Connector for PsExec {
     fn connect_method_name(&self) -> &'static str {
-        return "PSEXEC";
+        "PSEXEC"
     }
 
     fn computer(&self) -> &Computer {
@@ -51,7 +53,7 @@ impl Connector for PsExec {
     fn connect_and_run_local_program(&self,
                                      command_to_run: Command<'_>,
                                      timeout: Option<Duration>
-    ) -> Result<(), Error> {
+    ) -> Result<CommandOutcome, Error> {
         let mut command = command_to_run.command;
         command.insert(0, "-c".to_string());
         command.insert(0, "-f".to_string());
@@ -83,7 +85,7 @@ impl Connector for PsExec {
         if elevated {
             prepared_command.push("-h".to_string());
         }
-        prepared_command.extend(command.into_iter());
+        prepared_command.extend(command);
         match output_file_path {
             None => prepared_command,
             Some(output_file_path) => {
@@ -128,10 +130,22 @@ impl RemoteFileCopier for PsExec {
                elevated: false
            },
            None
-       )
+       ).map(|_| ())
     }
 
     fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
         self.copier_impl.copy_from_remote(source, target)
     }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        certutil_hash(self, path)
+    }
+
+    fn remote_file_state(&self, path: &Path) -> io::Result<RemoteFileState> {
+        self.copier_impl.remote_file_state(path)
+    }
+
+    fn remote_file_times(&self, path: &Path) -> io::Result<FileTimes> {
+        self.copier_impl.remote_file_times(path)
+    }
 }
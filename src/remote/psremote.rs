@@ -1,4 +1,4 @@
-use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, WindowsRemoteFileHandler};
+use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, WindowsRemoteFileHandler, hash_by_command, ps_quoted};
 use std::path::{Path, PathBuf};
 use std::io;
 use crate::process_runner::run_process_blocking;
@@ -20,7 +20,7 @@ impl PsRemote {
 }
 impl Connector for PsRemote {
     fn connect_method_name(&self) -> &'static str {
-        return "PSREM";
+        "PSREM"
     }
 
     fn computer(&self) -> &Computer {
@@ -35,6 +35,14 @@ impl Connector for PsRemote {
         self.remote_temp_storage.as_path()
     }
 
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        hash_by_command(self, get_file_hash(path), path)
+    }
+
+    fn quote_argument(&self, arg: &str) -> String {
+        ps_quoted(arg)
+    }
+
     fn prepare_command(&self,
                        command: Vec<String>,
                        output_file_path: Option<String>,
@@ -83,6 +91,16 @@ impl Connector for PsRemote {
     }
 }
 
+fn get_file_hash(path: &Path) -> Vec<String> {
+    vec![
+        "(Get-FileHash".to_string(),
+        "-Algorithm".to_string(),
+        "SHA256".to_string(),
+        "-LiteralPath".to_string(),
+        format!("{}).Hash", ps_quoted(&path.to_string_lossy())),
+    ]
+}
+
 pub struct Powershell {}
 
 impl FileCopier for Powershell {
@@ -99,7 +117,7 @@ impl FileCopier for Powershell {
         run_process_blocking(
             "powershell.exe",
             &args,
-        )
+        ).map(|_| ())
     }
 
     fn delete_file(&self, target: &Path) -> io::Result<()> {
@@ -111,10 +129,22 @@ impl FileCopier for Powershell {
         run_process_blocking(
             "powershell.exe",
             &args,
-        )
+        ).map(|_| ())
     }
 
     fn method_name(&self) -> &'static str {
         "PSCOPY"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::psremote::get_file_hash;
+    use std::path::Path;
+
+    #[test]
+    fn test_hashed_path_is_quoted() {
+        assert_eq!("C:\\Users\\Public\\x.evtx).Hash", get_file_hash(Path::new("C:\\Users\\Public\\x.evtx"))[4]);
+        assert_eq!("'C:\\Users\\O''Brien\\my notes.txt').Hash", get_file_hash(Path::new("C:\\Users\\O'Brien\\my notes.txt"))[4]);
+    }
+}
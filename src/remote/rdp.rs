@@ -1,8 +1,9 @@
-use crate::remote::{Connector, Computer, FileCopier, Command, RemoteFileCopier};
+use crate::remote::{Connector, Computer, FileCopier, Command, RemoteFileCopier, HostPlatform, certutil_hash, cmd_quoted, FileTimes, powershell_times};
 use std::path::{Path, PathBuf};
 use std::io;
-use crate::process_runner::{run_process_blocking, create_report_path};
+use crate::process_runner::{run_process_blocking, create_report_path, CommandOutcome};
 use std::time::Duration;
+use crate::manifest::{Provenance, record_report};
 
 #[derive(Clone)]
 pub struct Rdp {
@@ -13,7 +14,7 @@ pub struct Rdp {
 
 impl Connector for Rdp {
     fn connect_method_name(&self) -> &'static str {
-        return "RDP";
+        "RDP"
     }
 
     fn computer(&self) -> &Computer {
@@ -32,7 +33,7 @@ impl Connector for Rdp {
         &self,
         remote_connection: Command<'_>,
         timeout: Option<Duration>
-    ) -> io::Result<()> {
+    ) -> io::Result<CommandOutcome> {
         debug!("Trying to run command {:?} on {}",
                remote_connection.command,
                &self.computer().address
@@ -43,7 +44,7 @@ impl Connector for Rdp {
                 let file_path = create_report_path(
                     self.computer(),
                     store_directory,
-                    &remote_connection.report_filename_prefix,
+                    remote_connection.report_filename_prefix,
                     self.connect_method_name(),
                     "txt"
                 );
@@ -51,6 +52,9 @@ impl Connector for Rdp {
             }
         };
 
+        let report_path = output_file_path.as_ref().map(PathBuf::from);
+        let provenance = Provenance::new(self.computer(), self.connect_method_name())
+            .command(&remote_connection.command);
         let processed_command = self.prepare_command(
             remote_connection.command,
             output_file_path,
@@ -59,13 +63,18 @@ impl Connector for Rdp {
 
         let prepared_command = self.prepare_remote_process(processed_command);
         let result = run_process_blocking(
-            "cmd.exe",
+            HostPlatform::current().shell(),
             &prepared_command
         );
         if let Some(timeout) = timeout {
             std::thread::sleep(timeout);
         }
-        result
+        record_report(report_path.as_deref(), &provenance);
+        result.map(|outcome| outcome.with_report_path(report_path))
+    }
+
+    fn quote_argument(&self, arg: &str) -> String {
+        cmd_quoted(arg)
     }
 
     fn prepare_command(&self,
@@ -150,7 +159,7 @@ impl Rdp {
         run_process_blocking(
             "SharpRDP.exe",
             &args,
-        )
+        ).map(|_| ())
     }
 }
 
@@ -213,5 +222,13 @@ impl RemoteFileCopier for Rdp {
     ) -> io::Result<()> {
         self.copier_impl().copy_file(source, &self.path_to_remote_form(target))
     }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        certutil_hash(self, path)
+    }
+
+    fn remote_file_times(&self, path: &Path) -> io::Result<FileTimes> {
+        powershell_times(self, path)
+    }
 }
 
@@ -0,0 +1,261 @@
+use crate::remote::{RemoteFileCopier, Computer};
+use crate::cleanup::unregister_remote_file;
+use crate::plan::active_plan_readiness;
+use std::fs::{File, metadata};
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+const WINDOWS_LOCK_ERRORS: [i32; 2] = [32, 33];
+const DELETE_ATTEMPTS: u32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RemoteFileState {
+    Missing,
+    Present { size: u64, locked: bool },
+}
+
+// Exponential backoff used while waiting for remote files. The delay between two probes starts
+// at `initial_delay`, doubles up to `max_delay` and the whole wait is given up after `timeout`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Readiness {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness {
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl Readiness {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.initial_delay.checked_mul(1 << attempt.min(16)).unwrap_or(self.max_delay);
+        delay.min(self.max_delay)
+    }
+
+    pub fn poll<F>(&self, description: &str, mut ready: F) -> io::Result<()>
+        where F: FnMut() -> io::Result<bool> {
+        let started = Instant::now();
+        let mut attempt = 0;
+        loop {
+            if ready()? {
+                return Ok(());
+            }
+            let elapsed = started.elapsed();
+            if elapsed >= self.timeout {
+                return Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!("{} not reached within {} s", description, self.timeout.as_secs()),
+                ));
+            }
+            let delay = self.delay(attempt).min(self.timeout - elapsed);
+            trace!("Waiting {} ms until {}", delay.as_millis(), description);
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+// Readiness of the plan being run on the computer, the default one when no plan is running there.
+pub fn readiness(computer: &Computer) -> Readiness {
+    active_plan_readiness(computer).unwrap_or_default()
+}
+
+pub fn unsupported_probe(path: &Path, method_name: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::Unsupported,
+        format!("Method {} cannot probe remote file {}", method_name, path.display()),
+    )
+}
+
+// State of a file accessible through the local file system, e.g. using an UNC path.
+pub fn local_file_state(path: &Path) -> io::Result<RemoteFileState> {
+    let size = match metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(RemoteFileState::Missing),
+        Err(err) => return Err(err),
+    };
+    let locked = match File::open(path) {
+        Ok(_) => false,
+        Err(err) => match err.raw_os_error() {
+            Some(code) if WINDOWS_LOCK_ERRORS.contains(&code) => true,
+            _ => return Err(err),
+        }
+    };
+    Ok(RemoteFileState::Present { size, locked })
+}
+
+// Methods unable to probe remote files wait for the `fallback` duration instead.
+fn poll_or_wait<F>(
+    copier: &dyn RemoteFileCopier,
+    path: &Path,
+    fallback: Duration,
+    description: &str,
+    mut ready: F,
+) -> io::Result<()>
+    where F: FnMut(RemoteFileState) -> bool {
+    match copier.remote_file_state(path) {
+        Err(err) if err.kind() == ErrorKind::Unsupported => {
+            debug!("{}, waiting {} s instead", err, fallback.as_secs());
+            thread::sleep(fallback);
+            return Ok(());
+        }
+        _ => {}
+    }
+    readiness(copier.remote_computer()).poll(description, || copier.remote_file_state(path).map(&mut ready))
+}
+
+// True only when the copier is able to tell the remote file does not exist.
+pub fn is_missing(copier: &dyn RemoteFileCopier, path: &Path) -> bool {
+    matches!(copier.remote_file_state(path), Ok(RemoteFileState::Missing))
+}
+
+// Waits until the remote file exists, its size does not change between two probes and it is not locked.
+pub fn wait_until_ready(copier: &dyn RemoteFileCopier, path: &Path, fallback: Duration) -> io::Result<()> {
+    let mut last_size = None;
+    poll_or_wait(copier, path, fallback, &format!("{} is ready", path.display()), |state| {
+        match state {
+            RemoteFileState::Missing => false,
+            RemoteFileState::Present { size, locked } => {
+                let stable = last_size == Some(size);
+                last_size = Some(size);
+                stable && !locked
+            }
+        }
+    })
+}
+
+pub fn wait_until_removed(copier: &dyn RemoteFileCopier, path: &Path, fallback: Duration) -> io::Result<()> {
+    poll_or_wait(copier, path, fallback, &format!("{} is removed", path.display()), |state| {
+        state == RemoteFileState::Missing
+    })
+}
+
+// Deletes the remote file once it is released by the process which used it. The deletion itself is
+// retried as well, as some methods cannot tell whether the file is still locked. A file which no
+// longer exists counts as deleted.
+pub fn delete_when_released(copier: &dyn RemoteFileCopier, path: &Path, fallback: Duration) -> io::Result<()> {
+    let released = poll_or_wait(copier, path, fallback, &format!("{} is released", path.display()), |state| {
+        match state {
+            RemoteFileState::Missing => true,
+            RemoteFileState::Present { locked, .. } => !locked,
+        }
+    });
+    if let Err(err) = released {
+        warn!("{}", err);
+    }
+    let readiness = readiness(copier.remote_computer());
+    let mut attempt = 0;
+    loop {
+        match copier.delete_remote_file(path) {
+            Ok(_) => {
+                unregister_remote_file(copier.remote_computer(), path);
+                return Ok(());
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                // already gone, e.g. deleted by an earlier interrupted run
+                debug!("{} is already deleted", path.display());
+                unregister_remote_file(copier.remote_computer(), path);
+                return Ok(());
+            }
+            Err(err) if attempt + 1 >= DELETE_ATTEMPTS => return Err(err),
+            Err(err) => debug!("Cannot delete {} yet due to {}", path.display(), err),
+        }
+        thread::sleep(readiness.delay(attempt));
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::{Readiness, Computer, MockTarget, MockCopier, wait_until_ready, delete_when_released};
+    use crate::cleanup::{register_remote_file, registered_remote_files};
+    use std::io;
+    use std::io::ErrorKind;
+    use std::path::Path;
+    use std::time::Duration;
+
+    fn readiness(timeout: Duration) -> Readiness {
+        Readiness {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(100),
+            timeout,
+        }
+    }
+
+    fn copier(address: &str) -> MockCopier {
+        MockCopier {
+            computer: Computer {
+                address: address.to_string(),
+                username: "Jano".to_string(),
+                domain: None,
+                password: None,
+            },
+            target: MockTarget::new(),
+        }
+    }
+
+    #[test]
+    fn test_delay_grows_exponentially_up_to_ceiling() {
+        let readiness = Readiness {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            timeout: Duration::from_secs(5),
+        };
+        assert_eq!(Duration::from_millis(100), readiness.delay(0));
+        assert_eq!(Duration::from_millis(400), readiness.delay(2));
+        assert_eq!(Duration::from_millis(1000), readiness.delay(4));
+        assert_eq!(Duration::from_millis(1000), readiness.delay(100));
+    }
+
+    #[test]
+    fn test_poll_until_ready() {
+        let mut probes = 0;
+        readiness(Duration::from_secs(5)).poll("third probe", || {
+            probes += 1;
+            Ok(probes == 3)
+        }).unwrap();
+        assert_eq!(3, probes);
+    }
+
+    #[test]
+    fn test_poll_times_out() {
+        let err = readiness(Duration::from_millis(50)).poll("never", || Ok(false)).unwrap_err();
+        assert_eq!(ErrorKind::TimedOut, err.kind());
+    }
+
+    #[test]
+    fn test_poll_fails_with_probe_error() {
+        let err = readiness(Duration::from_secs(5))
+            .poll("probe failing", || Err(io::Error::new(ErrorKind::PermissionDenied, "Access is denied")))
+            .unwrap_err();
+        assert_eq!(ErrorKind::PermissionDenied, err.kind());
+    }
+
+    #[test]
+    fn test_wait_until_file_is_ready() {
+        let copier = copier("10.0.0.21");
+        copier.target.add_file(Path::new("/tmp/memory.aff4"), b"content");
+        wait_until_ready(&copier, Path::new("/tmp/memory.aff4"), Duration::from_secs(0)).unwrap();
+    }
+
+    #[test]
+    fn test_delete_already_deleted_file() {
+        let copier = copier("10.0.0.22");
+        register_remote_file(&copier.computer, Path::new("/tmp/deleted.txt"));
+
+        delete_when_released(&copier, Path::new("/tmp/deleted.txt"), Duration::from_secs(0)).unwrap();
+
+        assert!(registered_remote_files(&copier.computer).is_empty());
+    }
+}
@@ -1,13 +1,304 @@
-use crate::remote::{Connector, Computer, Command, FileCopier, RemoteFileCopier};
+use crate::remote::{Connector, Computer, Command, FileCopier, RemoteFileCopier, HostPlatform, RemoteFileState, FileTimes, parse_sha256, sh_quoted, stat_times, STAT_TIMES_FORMAT};
 use std::io;
-use crate::process_runner::{create_report_path, run_piped_processes_blocking};
+use crate::process_runner::{create_report_path, CommandOutcome};
 use std::fs::File;
 use std::path::{PathBuf, Path};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::io::{Read, Write, ErrorKind};
+use std::thread;
+use ssh2::{Session, CheckResult, KnownHostFileKind, HashType};
+use crate::manifest::{Provenance, record_report};
+
+const SSH_PORT: u16 = 22;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct Ssh {
     pub computer: Computer,
-    pub key_file: Option<PathBuf>
+    pub key_file: Option<PathBuf>,
+    pub known_hosts: PathBuf,
+    session: Mutex<Option<Session>>,
+}
+
+// Quotes everything except the wildcards, so that the shell still expands them.
+fn sh_glob_quoted(pattern: &str) -> String {
+    let mut quoted = String::new();
+    let mut literal = String::new();
+    for c in pattern.chars() {
+        if c == '*' || c == '?' {
+            if !literal.is_empty() {
+                quoted.push_str(&sh_quoted(&literal));
+                literal.clear();
+            }
+            quoted.push(c);
+        } else {
+            literal.push(c);
+        }
+    }
+    if !literal.is_empty() {
+        quoted.push_str(&sh_quoted(&literal));
+    }
+    quoted
+}
+
+pub fn default_known_hosts() -> PathBuf {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .unwrap_or_default();
+    Path::new(&home).join(".ssh").join("known_hosts")
+}
+
+impl Ssh {
+    pub fn new(
+        computer: Computer,
+        key_file: Option<PathBuf>,
+        known_hosts: Option<PathBuf>,
+    ) -> Ssh {
+        Ssh {
+            computer,
+            key_file,
+            known_hosts: known_hosts.unwrap_or_else(default_known_hosts),
+            session: Mutex::new(None),
+        }
+    }
+
+    fn session(&self) -> io::Result<Session> {
+        let mut session = self.session.lock().unwrap();
+        if let Some(session) = session.as_ref() {
+            return Ok(session.clone());
+        }
+        let connected = self.connect()?;
+        *session = Some(connected.clone());
+        Ok(connected)
+    }
+
+    fn connect(&self) -> io::Result<Session> {
+        debug!("Opening SSH session to {}", self.computer.address);
+        let address = (self.computer.address.as_str(), SSH_PORT).to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("Cannot resolve {}", self.computer.address)))?;
+        let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        self.verify_host_key(&session)?;
+        self.authenticate(&session)?;
+        Ok(session)
+    }
+
+    fn verify_host_key(&self, session: &Session) -> io::Result<()> {
+        let (key, _) = session.host_key()
+            .ok_or_else(|| io::Error::other("Remote host did not provide a host key"))?;
+        let fingerprint = session.host_key_hash(HashType::Sha256)
+            .map(|hash| hash.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(":"))
+            .unwrap_or_default();
+        let mut known_hosts = session.known_hosts()?;
+        if self.known_hosts.exists() {
+            known_hosts.read_file(&self.known_hosts, KnownHostFileKind::OpenSSH)?;
+        }
+        match known_hosts.check_port(&self.computer.address, SSH_PORT, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "Host key of {} (SHA256 {}) is not present in {}",
+                    self.computer.address,
+                    fingerprint,
+                    self.known_hosts.display()
+                ),
+            )),
+            CheckResult::Mismatch => Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "Host key of {} (SHA256 {}) does NOT match the one in {}",
+                    self.computer.address,
+                    fingerprint,
+                    self.known_hosts.display()
+                ),
+            )),
+            CheckResult::Failure => Err(io::Error::other(
+                format!("Cannot verify host key of {}", self.computer.address),
+            )),
+        }
+    }
+
+    fn authenticate(&self, session: &Session) -> io::Result<()> {
+        let username = &self.computer.username;
+        if let Some(key_file) = &self.key_file {
+            if let Err(err) = session.userauth_pubkey_file(username, None, key_file, None) {
+                debug!("Public key authentication to {} failed: {}", self.computer.address, err);
+            }
+        }
+        if !session.authenticated() {
+            if let Err(err) = session.userauth_agent(username) {
+                debug!("SSH agent authentication to {} failed: {}", self.computer.address, err);
+            }
+        }
+        if !session.authenticated() {
+            if let Some(password) = &self.computer.password {
+                if let Err(err) = session.userauth_password(username, password) {
+                    debug!("Password authentication to {} failed: {}", self.computer.address, err);
+                }
+            }
+        }
+        if session.authenticated() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Cannot authenticate {} on {}", username, self.computer.address),
+            ))
+        }
+    }
+
+    fn exec(
+        &self,
+        command: &str,
+        stdin: Option<String>,
+        output: &mut dyn Write,
+        timeout: Option<Duration>,
+    ) -> io::Result<CommandOutcome> {
+        let session = self.session()?;
+        let started = Instant::now();
+        let mut channel = session.channel_session()?;
+        channel.exec(command)?;
+        if let Some(stdin) = stdin {
+            channel.write_all(stdin.as_bytes())?;
+        }
+        channel.send_eof()?;
+
+        // stdout and stderr are read alternately so that neither of them can exhaust the channel window
+        session.set_blocking(false);
+        let mut stderr = Vec::new();
+        let mut buffer = [0u8; 32 * 1024];
+        let mut stdout_open = true;
+        let mut stderr_open = true;
+        let mut timed_out = false;
+        let read_result: io::Result<()> = loop {
+            let mut progressed = false;
+            if stdout_open {
+                match channel.read(&mut buffer) {
+                    Ok(0) => stdout_open = false,
+                    Ok(read) => {
+                        if let Err(err) = output.write_all(&buffer[..read]) {
+                            break Err(err);
+                        }
+                        progressed = true;
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                    Err(err) => break Err(err),
+                }
+            }
+            if stderr_open {
+                match channel.stderr().read(&mut buffer) {
+                    Ok(0) => stderr_open = false,
+                    Ok(read) => {
+                        stderr.extend_from_slice(&buffer[..read]);
+                        progressed = true;
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                    Err(err) => break Err(err),
+                }
+            }
+            if !stdout_open && !stderr_open {
+                break Ok(());
+            }
+            if let Some(timeout) = timeout {
+                if started.elapsed() > timeout {
+                    timed_out = true;
+                    break Ok(());
+                }
+            }
+            if !progressed {
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        session.set_blocking(true);
+        read_result?;
+        output.flush()?;
+        if timed_out {
+            trace!("Command \"{}\" on {} reached time out", command, self.computer.address);
+            channel.close()?;
+        }
+        channel.wait_close()?;
+        let exit_code = if timed_out { None } else { Some(channel.exit_status()?) };
+        Ok(CommandOutcome {
+            exit_code,
+            timed_out,
+            stdout: String::new(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            duration: started.elapsed(),
+            report_path: None,
+        })
+    }
+
+    fn exec_captured(&self, command: &str) -> io::Result<CommandOutcome> {
+        let mut stdout = Vec::new();
+        let outcome = self.exec(command, None, &mut stdout, None)?;
+        Ok(CommandOutcome {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            ..outcome
+        })
+    }
+
+    fn sha256sum(&self, path: &Path) -> io::Result<String> {
+        let outcome = self.exec_captured(&format!("sha256sum -- {}", sh_quoted(&path.to_string_lossy())))?;
+        parse_sha256(outcome.stdout.split_whitespace().next().unwrap_or_default())
+            .ok_or_else(|| io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Cannot compute SHA256 of remote file {}: {}", path.display(), outcome.stderr.trim()),
+            ))
+    }
+
+    fn expand_remote_pattern(&self, pattern: &Path) -> io::Result<Vec<PathBuf>> {
+        let pattern = pattern.to_string_lossy();
+        if !pattern.contains('*') && !pattern.contains('?') {
+            return Ok(vec![PathBuf::from(pattern.to_string())]);
+        }
+        let outcome = self.exec_captured(&format!("ls -1d -- {}", sh_glob_quoted(&pattern)))?;
+        Ok(outcome.stdout
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect())
+    }
+
+    fn download(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let sftp = self.session()?.sftp()?;
+        let stat = sftp.stat(source)?;
+        let target = if target.is_dir() {
+            target.join(source.file_name().unwrap_or_default())
+        } else {
+            target.to_path_buf()
+        };
+        if stat.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            for (child, _) in sftp.readdir(source)? {
+                self.download(&child, &target)?;
+            }
+            Ok(())
+        } else {
+            trace!("Downloading {} from {} to {}", source.display(), self.computer.address, target.display());
+            let mut remote_file = sftp.open(source)?;
+            let mut local_file = File::create(&target)?;
+            io::copy(&mut remote_file, &mut local_file)?;
+            Ok(())
+        }
+    }
+
+    fn upload(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let sftp = self.session()?.sftp()?;
+        let target = match sftp.stat(target) {
+            Ok(stat) if stat.is_dir() => target.join(source.file_name().unwrap_or_default()),
+            _ => target.to_path_buf(),
+        };
+        trace!("Uploading {} to {} on {}", source.display(), target.display(), self.computer.address);
+        let mut local_file = File::open(source)?;
+        let mut remote_file = sftp.create(&target)?;
+        io::copy(&mut local_file, &mut remote_file)?;
+        Ok(())
+    }
 }
 
 impl Connector for Ssh {
@@ -27,156 +318,146 @@ impl Connector for Ssh {
         Path::new("/tmp")
     }
 
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        self.sha256sum(path)
+    }
+
+    fn remote_platform(&self) -> HostPlatform {
+        HostPlatform::Linux
+    }
+
     fn connect_and_run_command(&self,
                                remote_connection: Command<'_>,
-                               _timeout: Option<Duration>
-    ) -> io::Result<()> {
+                               timeout: Option<Duration>
+    ) -> io::Result<CommandOutcome> {
         debug!("Trying to run command {:?} on {}",
                remote_connection.command,
                &self.computer().address
         );
-        let output_file_path = match remote_connection.report_store_directory {
+        let report_path = match remote_connection.report_store_directory {
             None => None,
-            Some(store_directory) => {
-                let file_path = create_report_path(
-                    &self.computer(),
-                    store_directory,
-                    &remote_connection.report_filename_prefix,
-                    self.connect_method_name(),
-                    "txt"
-                );
-                {
-                    File::create(&file_path)?;
+            Some(store_directory) => Some(create_report_path(
+                self.computer(),
+                store_directory,
+                remote_connection.report_filename_prefix,
+                self.connect_method_name(),
+                "txt"
+            )),
+        };
+        let provenance = Provenance::new(self.computer(), self.connect_method_name())
+            .command(&remote_connection.command);
+        let command = self.prepare_command(
+            remote_connection.command,
+            None,
+            remote_connection.elevated,
+        ).join(" ");
+        let stdin = if remote_connection.elevated {
+            self.computer.password.as_ref().map(|password| format!("{}\n", password))
+        } else {
+            None
+        };
+        let outcome = match &report_path {
+            None => {
+                let mut stdout = Vec::new();
+                let outcome = self.exec(&command, stdin, &mut stdout, timeout)?;
+                CommandOutcome {
+                    stdout: String::from_utf8_lossy(&stdout).to_string(),
+                    ..outcome
                 }
-                Some(dunce::canonicalize(file_path)?.to_str().unwrap().to_string())
+            }
+            Some(report_path) => {
+                let mut report = File::create(report_path)?;
+                self.exec(&command, stdin, &mut report, timeout)?
             }
         };
+        record_report(report_path.as_deref(), &provenance);
+        Ok(outcome.with_report_path(report_path))
+    }
 
-        let echo = vec!["echo".to_string(), "n".to_string()];
-        let prepared_echo = self.prepare_remote_process(echo);
-
-        let processed_command = self.prepare_command(
-            remote_connection.command,
-            output_file_path,
-            false
+    fn connect_and_stream_command(&self,
+                                  remote_connection: Command<'_>,
+                                  local_file: &Path,
+                                  timeout: Option<Duration>
+    ) -> io::Result<CommandOutcome> {
+        debug!("Trying to stream output of command {:?} from {} to {}",
+               remote_connection.command,
+               &self.computer().address,
+               local_file.display()
         );
-        let prepared_command = self.prepare_remote_process(processed_command);
-        run_piped_processes_blocking(
-            "cmd.exe",
-            &prepared_echo,
-            "cmd.exe",
-            &prepared_command)
+        let command = self.prepare_command(
+            remote_connection.command,
+            None,
+            remote_connection.elevated,
+        ).join(" ");
+        let stdin = if remote_connection.elevated {
+            self.computer.password.as_ref().map(|password| format!("{}\n", password))
+        } else {
+            None
+        };
+        let mut output = File::create(local_file)?;
+        self.exec(&command, stdin, &mut output, timeout)
+    }
+
+    fn quote_argument(&self, arg: &str) -> String {
+        sh_quoted(arg)
     }
 
     fn prepare_command(&self,
                        command: Vec<String>,
-                       output_file_path: Option<String>,
+                       _output_file_path: Option<String>,
                        elevated: bool,
     ) -> Vec<String> {
-        let remote_computer = self.remote_computer();
-        let program_name = "plink.exe".to_string();
-        let mut prepared_command = vec![
-            program_name,
-            "-ssh".to_string(),
-            remote_computer.address.clone(),
-            "-l".to_string(),
-            remote_computer.username.clone(),
-            "-no-antispoof".to_string()
-        ];
-        if let Some(password) = &remote_computer.password {
-            prepared_command.push("-pw".to_string());
-            prepared_command.push(password.clone());
-        }
-
-        if let Some(key_file) = &self.key_file {
-            prepared_command.push("-i".to_string());
-            prepared_command.push(key_file.to_string_lossy().to_string())
-        }
         if elevated {
-            if let Some(password) = &remote_computer.password {
-                prepared_command.push(format!("echo {} | sudo -S {}", password, command.join(" ")));
-            } else {
-                prepared_command.push(format!("sudo -S {}", command.join(" ")));
-            }
-        }else {
-            prepared_command.push(command.join(" "));
-        }
-        match output_file_path {
-            None => prepared_command,
-            Some(output_file_path) => {
-                prepared_command.push(">".to_string());
-                prepared_command.push(output_file_path);
-                prepared_command
-            }
+            let mut prepared_command = vec![
+                "sudo".to_string(),
+                "-S".to_string(),
+                "-p".to_string(),
+                "''".to_string(),
+            ];
+            prepared_command.extend(command);
+            prepared_command
+        } else {
+            command
         }
     }
 }
 
+// Commands changing files on the target fail when they exit with a non-zero code, with their error output.
+fn succeeded(outcome: CommandOutcome, command: &str) -> io::Result<CommandOutcome> {
+    if outcome.is_success() {
+        return Ok(outcome);
+    }
+    Err(io::Error::other(format!(
+        "{} failed with exit code {:?}: {}",
+        command,
+        outcome.exit_code,
+        outcome.stderr.trim()
+    )))
+}
+
 impl FileCopier for Ssh {
     fn copy_file(
         &self,
         source: &Path,
         target: &Path,
     ) -> io::Result<()> {
-        let mut scp = vec![
-            "-l".to_string(),
-            self.computer.username.clone(),
-        ];
-        if let Some(password) = &self.computer.password {
-            scp.push("-pw".to_string());
-            scp.push(password.clone());
-        }
-        if let Some(key_file) = &self.key_file {
-            scp.push("-i".to_string());
-            scp.push(key_file.to_string_lossy().to_string())
-        }
-        scp.push(format!("{}", source.to_string_lossy()));
-        scp.push(format!("{}", target.to_string_lossy()));
-        run_piped_processes_blocking(
-            "cmd",
-            &[
-                "/c".to_string(),
-                "echo".to_string(),
-                "n".to_string()
-            ],
-            "pscp.exe",
-            &scp,
-        )
+        let command = format!("cp -r -- {} {}", sh_quoted(&source.to_string_lossy()), sh_quoted(&target.to_string_lossy()));
+        succeeded(self.exec_captured(&command)?, &command).map(|_| ())
     }
 
     fn delete_file(&self, target: &Path) -> io::Result<()> {
-        let mut params = vec![
-            "-ssh".to_string(),
-            self.computer.address.clone(),
-            "-l".to_string(),
-            self.computer.username.clone(),
-            "-no-antispoof".to_string()
-        ];
-        if let Some(password) = &self.computer.password {
-            params.push("-pw".to_string());
-            params.push(password.clone());
+        let sftp = self.session()?.sftp()?;
+        match sftp.stat(target) {
+            Ok(stat) if stat.is_dir() => {
+                let command = format!("rm -rf -- {}", sh_quoted(&target.to_string_lossy()));
+                succeeded(self.exec_captured(&command)?, &command).map(|_| ())
+            }
+            _ => sftp.unlink(target).map_err(io::Error::from),
         }
-        if let Some(key_file) = &self.key_file {
-            params.push("-i".to_string());
-            params.push(key_file.to_string_lossy().to_string())
-        }
-        params.push("rm".to_string());
-        params.push("-f".to_string());
-        params.push(target.to_string_lossy().to_string());
-        run_piped_processes_blocking(
-            "cmd",
-            &[
-                "/c".to_string(),
-                "echo".to_string(),
-                "n".to_string()
-            ],
-            "plink.exe",
-            &params,
-        )
     }
 
     fn method_name(&self) -> &'static str {
-        "SCP"
+        "SFTP"
     }
 }
 
@@ -190,10 +471,125 @@ impl RemoteFileCopier for Ssh {
     }
 
     fn path_to_remote_form(&self, path: &Path) -> PathBuf {
-        PathBuf::from(format!(
-            "{}:{}",
-            self.remote_computer().address,
-            path.to_str().unwrap()
-        ))
+        path.to_path_buf()
+    }
+
+    fn copy_to_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.upload(source, target)
+    }
+
+    fn delete_remote_file(&self, target: &Path) -> io::Result<()> {
+        self.delete_file(target)
+    }
+
+    fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
+        let sources = self.expand_remote_pattern(source)?;
+        if sources.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("No remote file matches {}", source.display()),
+            ));
+        }
+        for source in sources {
+            self.download(&source, target)?;
+        }
+        Ok(())
+    }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        self.sha256sum(path)
+    }
+
+    fn remote_file_times(&self, path: &Path) -> io::Result<FileTimes> {
+        let outcome = self.exec_captured(&format!("stat -c '{}' -- {}", STAT_TIMES_FORMAT, sh_quoted(&path.to_string_lossy())))?;
+        stat_times(&outcome.stdout, path, self.connect_method_name())
+    }
+
+    fn remote_file_state(&self, path: &Path) -> io::Result<RemoteFileState> {
+        let sftp = self.session()?.sftp()?;
+        match sftp.stat(path) {
+            Ok(stat) => Ok(RemoteFileState::Present { size: stat.size.unwrap_or(0), locked: false }),
+            Err(err) => {
+                let err = io::Error::from(err);
+                if err.kind() == ErrorKind::NotFound {
+                    Ok(RemoteFileState::Missing)
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::{Ssh, Computer, Connector, Command, RemoteFileCopier};
+    use crate::remote::ssh::{sh_glob_quoted, succeeded};
+    use crate::process_runner::CommandOutcome;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_glob_quoting_keeps_wildcards() {
+        assert_eq!("'/var/log/my logs/'*.log", sh_glob_quoted("/var/log/my logs/*.log"));
+        assert_eq!("'/tmp/$(reboot)'?", sh_glob_quoted("/tmp/$(reboot)?"));
+    }
+
+    #[test]
+    fn test_failed_file_command_is_error() {
+        let failed = CommandOutcome {
+            exit_code: Some(1),
+            stderr: "cp: cannot create regular file '/root/x': Permission denied\n".to_string(),
+            ..Default::default()
+        };
+
+        let err = succeeded(failed, "cp -r -- /tmp/x /root").unwrap_err();
+
+        assert_eq!("cp -r -- /tmp/x /root failed with exit code Some(1): cp: cannot create regular file '/root/x': Permission denied", err.to_string());
+        assert!(succeeded(CommandOutcome { exit_code: Some(0), ..Default::default() }, "rm -rf -- /tmp/x").is_ok());
+    }
+
+    // Requires a running sshd, e.g.
+    // GARGAMEL_SSH_HOST=127.0.0.1 GARGAMEL_SSH_USER=user GARGAMEL_SSH_KEY=~/.ssh/id_ed25519 cargo test -- --ignored
+    fn local_ssh() -> Ssh {
+        Ssh::new(
+            Computer {
+                address: std::env::var("GARGAMEL_SSH_HOST").unwrap_or("127.0.0.1".to_string()),
+                username: std::env::var("GARGAMEL_SSH_USER").unwrap_or_default(),
+                domain: None,
+                password: std::env::var("GARGAMEL_SSH_PASSWORD").ok(),
+            },
+            std::env::var("GARGAMEL_SSH_KEY").ok().map(PathBuf::from),
+            None,
+        )
+    }
+
+    #[test]
+    #[ignore]
+    fn test_run_command_and_transfer_file_over_local_sshd() {
+        let ssh = local_ssh();
+        let store_directory = std::env::temp_dir().join("gargamel-ssh-test");
+        std::fs::create_dir_all(&store_directory).unwrap();
+
+        let outcome = ssh.connect_and_run_command(
+            Command::new(
+                vec!["echo".to_string(), "gargamel".to_string(), ";".to_string(), "exit".to_string(), "3".to_string()],
+                Some(&store_directory),
+                "ssh-test",
+                false,
+            ),
+            None,
+        ).unwrap();
+        assert_eq!(Some(3), outcome.exit_code);
+        let report = std::fs::read_to_string(outcome.report_path.unwrap()).unwrap();
+        assert_eq!("gargamel\n", report);
+
+        let local_file = store_directory.join("ssh-test-upload.txt");
+        std::fs::write(&local_file, "uploaded").unwrap();
+        ssh.copy_to_remote(&local_file, ssh.remote_temp_storage()).unwrap();
+        std::fs::remove_file(&local_file).unwrap();
+        let remote_file = ssh.remote_temp_storage().join("ssh-test-upload.txt");
+        ssh.copy_from_remote(&remote_file, &store_directory).unwrap();
+        ssh.delete_remote_file(&remote_file).unwrap();
+        assert_eq!("uploaded", std::fs::read_to_string(&local_file).unwrap());
     }
 }
@@ -1,4 +1,4 @@
-use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier};
+use crate::remote::{Connector, Computer, FileCopier, RemoteFileCopier, certutil_hash, cmd_quoted, FileTimes, powershell_times};
 use std::path::{Path, PathBuf};
 use std::io;
 use crate::process_runner::{run_process_blocking_maybe_timed, run_process_blocking_timed};
@@ -26,6 +26,10 @@ impl Connector for Wmi {
         self.remote_temp_storage.as_path()
     }
 
+    fn quote_argument(&self, arg: &str) -> String {
+        cmd_quoted(arg)
+    }
+
     fn prepare_command(&self,
                        command: Vec<String>,
                        output_file_path: Option<String>,
@@ -111,7 +115,7 @@ impl Wmi {
             "powershell.exe",
             &prepared_command,
             None
-        )
+        ).map(|_| ())
     }
 }
 
@@ -144,7 +148,7 @@ impl FileCopier for Wmi {
             "powershell.exe",
             &prepared_command,
             Duration::from_secs(10)
-        )
+        ).map(|_| ())
     }
 
     fn method_name(&self) -> &'static str {
@@ -176,4 +180,12 @@ impl RemoteFileCopier for Wmi {
     fn copy_from_remote(&self, source: &Path, target: &Path) -> io::Result<()> {
         self.copy_impl(source, target, "-Download", false)
     }
+
+    fn remote_file_hash(&self, path: &Path) -> io::Result<String> {
+        certutil_hash(self, path)
+    }
+
+    fn remote_file_times(&self, path: &Path) -> io::Result<FileTimes> {
+        powershell_times(self, path)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,468 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::fs::File;
+use std::collections::BTreeMap;
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde_json::Value;
+use crate::event_timeline::EventRecord;
+use crate::hive::Hive;
+use crate::manifest::{Manifest, ManifestEntry, MANIFEST_JSON, is_manifest_file};
+use crate::correlation::{host_of, csv_field};
+use crate::ioc::decode;
+
+// Super-timeline merging event logs, registry key last write times, downloaded files and Linux logs.
+
+pub const TIMELINE_CSV: &str = "timeline.csv";
+pub const TIMELINE_BODYFILE: &str = "timeline.body";
+
+const TIMELINE_PREFIX: &str = "timeline";
+const EVENT_TIMELINE_SUFFIX: &str = ".evtx.jsonl";
+const MAX_LOG_SIZE: u64 = 256 * 1024 * 1024;
+const MAX_DESCRIPTION_LENGTH: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TimelineSource {
+    Event,
+    Registry,
+    File,
+    Log,
+}
+
+impl TimelineSource {
+    fn name(&self) -> &'static str {
+        match self {
+            TimelineSource::Event => "event",
+            TimelineSource::Registry => "registry",
+            TimelineSource::File => "file",
+            TimelineSource::Log => "log",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelineEntry {
+    pub time: DateTime<Utc>,
+    // modified, accessed, changed and born flags of the time like in mactime, e.g. "m..."
+    pub macb: &'static str,
+    pub host: String,
+    pub source: TimelineSource,
+    pub description: String,
+    // evidence file relative to the case directory
+    pub file: String,
+}
+
+fn shortened(text: &str) -> String {
+    let text = text.replace(['\r', '\n', '\t'], " ");
+    match text.char_indices().nth(MAX_DESCRIPTION_LENGTH) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+fn event_description(event: &EventRecord) -> String {
+    let data: Vec<String> = event.data.iter()
+        .filter_map(|(name, value)| match value {
+            Value::String(text) if !text.is_empty() && text != "-" => Some(format!("{}={}", name, text)),
+            Value::Number(number) => Some(format!("{}={}", name, number)),
+            _ => None,
+        })
+        .collect();
+    shortened(&format!("{} {} {}: {}", event.channel, event.event_id, event.provider, data.join(" ")))
+}
+
+fn event_entries(path: &Path, file: &str, host: &str, entries: &mut Vec<TimelineEntry>) -> io::Result<()> {
+    for line in BufReader::new(File::open(path)?).lines() {
+        let event: EventRecord = match serde_json::from_str(&line?) {
+            Ok(event) => event,
+            Err(_) => continue,
+        };
+        if let Ok(time) = DateTime::parse_from_rfc3339(&event.timestamp) {
+            entries.push(TimelineEntry {
+                time: time.with_timezone(&Utc),
+                macb: "macb",
+                host: host.to_string(),
+                source: TimelineSource::Event,
+                description: event_description(&event),
+                file: file.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn is_hive(path: &Path) -> io::Result<bool> {
+    let mut signature = [0u8; 4];
+    let mut hive = File::open(path)?;
+    Ok(hive.read(&mut signature)? == 4 && &signature == b"regf")
+}
+
+fn registry_entries(path: &Path, file: &str, host: &str, entries: &mut Vec<TimelineEntry>) -> io::Result<()> {
+    let hive_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    Hive::open(path)?.walk(&mut |key| {
+        if let Ok(time) = DateTime::parse_from_rfc3339(&key.last_written) {
+            entries.push(TimelineEntry {
+                time: time.with_timezone(&Utc),
+                macb: "m...",
+                host: host.to_string(),
+                source: TimelineSource::Registry,
+                description: shortened(&format!("{}\\{}", hive_name, key.path)),
+                file: file.to_string(),
+            });
+        }
+        Ok(())
+    })
+}
+
+// Syslog lines start with an RFC 3339 time or with a time without year like `Oct 10 10:10:10`.
+// A time without year is in the year of the file and is taken as UTC.
+pub fn parse_log_time(line: &str, modified: DateTime<Utc>) -> Option<(DateTime<Utc>, &str)> {
+    let (first, rest) = line.split_at(line.find(' ').unwrap_or(line.len()));
+    if let Ok(time) = DateTime::parse_from_rfc3339(first) {
+        return Some((time.with_timezone(&Utc), rest.trim()));
+    }
+    let prefix = line.get(..15)?;
+    let parse = |year: i32| NaiveDateTime::parse_from_str(&format!("{} {}", year, prefix), "%Y %b %e %H:%M:%S")
+        .ok()
+        .map(|time| Utc.from_utc_datetime(&time));
+    let time = parse(modified.year())?;
+    // the log was written last year when the time is later than the file
+    let time = if time > modified + Duration::days(1) { parse(modified.year() - 1)? } else { time };
+    Some((time, line[15..].trim()))
+}
+
+fn log_entries(path: &Path, file: &str, host: &str, entries: &mut Vec<TimelineEntry>) -> io::Result<()> {
+    let metadata = std::fs::metadata(path)?;
+    if metadata.len() > MAX_LOG_SIZE {
+        return Ok(());
+    }
+    let modified: DateTime<Utc> = metadata.modified()?.into();
+    let text = match decode(&std::fs::read(path)?) {
+        None => return Ok(()),
+        Some(text) => text,
+    };
+    let is_log = text.lines()
+        .find(|line| !line.trim().is_empty())
+        .map(|line| parse_log_time(line, modified).is_some())
+        .unwrap_or(false);
+    if !is_log {
+        return Ok(());
+    }
+    for line in text.lines() {
+        if let Some((time, message)) = parse_log_time(line, modified) {
+            entries.push(TimelineEntry {
+                time,
+                macb: "macb",
+                host: host.to_string(),
+                source: TimelineSource::Log,
+                description: shortened(message),
+                file: file.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Flags of the times of a file, indexed by modified, accessed, changed and born as bits from the highest.
+const MACB: [&str; 16] = [
+    "....", "...b", "..c.", "..cb", ".a..", ".a.b", ".ac.", ".acb",
+    "m...", "m..b", "m.c.", "m.cb", "ma..", "ma.b", "mac.", "macb",
+];
+
+// Times of a downloaded file on the target recorded in the manifest, equal times are merged into one entry like in mactime.
+fn file_entries(entry: &ManifestEntry, host: &str, entries: &mut Vec<TimelineEntry>) {
+    let (times, source) = match (&entry.times, &entry.source) {
+        (Some(times), Some(source)) => (times, source),
+        _ => return,
+    };
+    let parsed: Vec<Option<DateTime<Utc>>> = [&times.modified, &times.accessed, &times.changed, &times.born].iter()
+        .map(|time| time.as_ref()
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc)))
+        .collect();
+    let mut distinct: Vec<DateTime<Utc>> = parsed.iter().flatten().copied().collect();
+    distinct.sort();
+    distinct.dedup();
+    for time in distinct {
+        let flags = parsed.iter().fold(0, |flags, it| flags << 1 | (*it == Some(time)) as usize);
+        entries.push(TimelineEntry {
+            time,
+            macb: MACB[flags],
+            host: host.to_string(),
+            source: TimelineSource::File,
+            description: shortened(source),
+            file: entry.file.clone(),
+        });
+    }
+}
+
+fn is_timeline_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with(TIMELINE_PREFIX))
+        .unwrap_or(false)
+}
+
+fn list_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else if !is_manifest_file(&path) && !is_timeline_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Collects the timeline of all hosts acquired into the case directory.
+// Hosts of the files and the times of downloaded files on the target are taken from the manifest.
+pub fn build_timeline(case_directory: &Path) -> io::Result<Vec<TimelineEntry>> {
+    let directory = &dunce::canonicalize(case_directory)?;
+    let manifest_path = directory.join(MANIFEST_JSON);
+    let manifest: Option<Manifest> = if manifest_path.exists() {
+        Some(serde_json::from_reader(BufReader::new(File::open(&manifest_path)?))?)
+    } else {
+        None
+    };
+    let mut files = vec![];
+    list_files(directory, &mut files)?;
+    files.sort();
+    let mut entries = vec![];
+    for path in files {
+        let file = path.strip_prefix(directory).unwrap_or(&path).to_string_lossy().to_string();
+        let host = host_of(&path, directory, manifest.as_ref());
+        let result = if file.ends_with(EVENT_TIMELINE_SUFFIX) {
+            event_entries(&path, &file, &host, &mut entries)
+        } else {
+            is_hive(&path).and_then(|hive| if hive {
+                registry_entries(&path, &file, &host, &mut entries)
+            } else {
+                log_entries(&path, &file, &host, &mut entries)
+            })
+        };
+        if let Err(err) = result {
+            warn!("Cannot add {} to the timeline due to {}", path.display(), err);
+        }
+        let recorded = manifest.as_ref().and_then(|manifest| manifest.entries.iter().find(|entry| entry.file == file));
+        if let Some(entry) = recorded {
+            file_entries(entry, &host, &mut entries);
+        }
+    }
+    entries.sort_by(|a, b| (a.time, &a.host, a.source, &a.description).cmp(&(b.time, &b.host, b.source, &b.description)));
+    Ok(entries)
+}
+
+fn bodyfile_time(entry: &TimelineEntry, flag: usize) -> i64 {
+    if entry.macb.as_bytes()[flag] == b'.' { 0 } else { entry.time.timestamp() }
+}
+
+// Writes the timeline as CSV and as a bodyfile readable by `mactime -b`. Returns the paths of both files.
+pub fn write_timeline(directory: &Path, name: &str, entries: &[TimelineEntry]) -> io::Result<Vec<PathBuf>> {
+    let csv_path = directory.join(format!("{}.csv", name));
+    let mut csv = BufWriter::new(File::create(&csv_path)?);
+    writeln!(csv, "timestamp,macb,host,source,description,file")?;
+    for entry in entries {
+        let row = [
+            entry.time.to_rfc3339_opts(SecondsFormat::Micros, true),
+            entry.macb.to_string(),
+            entry.host.clone(),
+            entry.source.name().to_string(),
+            entry.description.clone(),
+            entry.file.clone(),
+        ];
+        writeln!(csv, "{}", row.iter().map(|it| csv_field(it)).collect::<Vec<_>>().join(","))?;
+    }
+    csv.flush()?;
+
+    let body_path = directory.join(format!("{}.body", name));
+    let mut body = BufWriter::new(File::create(&body_path)?);
+    for entry in entries {
+        // MD5|name|inode|mode|UID|GID|size|atime|mtime|ctime|crtime
+        let name = format!("{} [{}] {}", entry.host, entry.source.name(), entry.description).replace('|', "/");
+        writeln!(body, "0|{}|0|0|0|0|0|{}|{}|{}|{}",
+                 name, bodyfile_time(entry, 1), bodyfile_time(entry, 0), bodyfile_time(entry, 2), bodyfile_time(entry, 3))?;
+    }
+    body.flush()?;
+    Ok(vec![csv_path, body_path])
+}
+
+fn file_name_of(host: &str) -> String {
+    host.chars().map(|it| if it.is_alphanumeric() || it == '-' || it == '.' { it } else { '-' }).collect()
+}
+
+// Writes the timeline of the whole case and, when more hosts were acquired, of every host.
+// Returns the number of timeline entries.
+pub fn write_case_timeline(case_directory: &Path) -> io::Result<usize> {
+    let entries = build_timeline(case_directory)?;
+    let mut hosts: BTreeMap<&str, Vec<TimelineEntry>> = BTreeMap::new();
+    for entry in entries.iter() {
+        hosts.entry(&entry.host).or_default().push(entry.clone());
+    }
+    if hosts.len() > 1 {
+        for (host, host_entries) in hosts.iter() {
+            write_timeline(case_directory, &format!("{}-{}", TIMELINE_PREFIX, file_name_of(host)), host_entries)?;
+        }
+    }
+    write_timeline(case_directory, TIMELINE_PREFIX, &entries)?;
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timeline::{parse_log_time, build_timeline, write_timeline, write_case_timeline, TimelineEntry, TimelineSource, TIMELINE_CSV, TIMELINE_BODYFILE};
+    use crate::manifest::{Manifest, ManifestEntry, MANIFEST_JSON};
+    use crate::remote::FileTimes;
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Case with a security event of one host and an SSH log of another.
+    fn case_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("gargamel-timeline-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(directory.join("10.0.0.1")).unwrap();
+        fs::create_dir_all(directory.join("10.0.0.2")).unwrap();
+        fs::write(directory.join("10.0.0.1").join("psexec-events-security.evtx.jsonl"), concat!(
+            r#"{"timestamp":"2020-10-10T10:10:11.000000Z","event_id":4624,"record_id":2,"provider":"Microsoft-Windows-Security-Auditing","#,
+            r#""channel":"Security","computer":"WIN","user_sid":null,"data":{"TargetUserName":"Jano","LogonType":"10"}}"#, "\n",
+            "not an event\n"
+        )).unwrap();
+        fs::write(directory.join("10.0.0.2").join("ssh-auth-log.txt"), "2020-10-10T10:10:10Z web sshd[42]: Accepted password for root\n").unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_log_time_without_year() {
+        let modified = Utc.with_ymd_and_hms(2021, 1, 5, 0, 0, 0).unwrap();
+
+        let (time, message) = parse_log_time("Dec 31 23:59:01 web sshd[42]: Accepted password for root", modified).unwrap();
+
+        assert_eq!(Utc.with_ymd_and_hms(2020, 12, 31, 23, 59, 1).unwrap(), time);
+        assert_eq!("web sshd[42]: Accepted password for root", message);
+        let (time, _) = parse_log_time("Jan  4 10:00:00 web kernel: boot", modified).unwrap();
+        assert_eq!(Utc.with_ymd_and_hms(2021, 1, 4, 10, 0, 0).unwrap(), time);
+    }
+
+    #[test]
+    fn test_log_time_rfc3339() {
+        let modified = Utc.with_ymd_and_hms(2021, 1, 5, 0, 0, 0).unwrap();
+
+        let (time, message) = parse_log_time("2021-01-04T10:00:00.5+02:00 web kernel: boot", modified).unwrap();
+
+        assert_eq!(Utc.with_ymd_and_hms(2021, 1, 4, 8, 0, 0).unwrap() + chrono::Duration::milliseconds(500), time);
+        assert_eq!("web kernel: boot", message);
+    }
+
+    #[test]
+    fn test_lines_without_time_are_not_logs() {
+        let modified = Utc.with_ymd_and_hms(2021, 1, 5, 0, 0, 0).unwrap();
+        assert!(parse_log_time("USER TTY FROM", modified).is_none());
+        assert!(parse_log_time("", modified).is_none());
+    }
+
+    #[test]
+    fn test_events_and_logs_are_merged() {
+        let directory = case_directory();
+        fs::write(directory.join("10.0.0.2").join("ps.txt"), "USER PID COMMAND\n").unwrap();
+        fs::write(directory.join("10.0.0.2").join("damaged.hiv"), b"regf").unwrap();
+
+        let entries = build_timeline(&directory).unwrap();
+
+        assert_eq!(
+            vec![(TimelineSource::Log, "10.0.0.2"), (TimelineSource::Event, "10.0.0.1")],
+            entries.iter().map(|it| (it.source, it.host.as_str())).collect::<Vec<_>>()
+        );
+        assert_eq!("Security 4624 Microsoft-Windows-Security-Auditing: TargetUserName=Jano LogonType=10", entries[1].description);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_case_timeline_is_written_for_case_and_hosts() {
+        let directory = case_directory();
+
+        let count = write_case_timeline(&directory).unwrap();
+
+        assert_eq!(2, count);
+        let csv = fs::read_to_string(directory.join(TIMELINE_CSV)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!("timestamp,macb,host,source,description,file", lines[0]);
+        assert!(lines[1].starts_with("2020-10-10T10:10:10.000000Z,macb,10.0.0.2,log,web sshd[42]: Accepted password for root,"));
+        let body = fs::read_to_string(directory.join(TIMELINE_BODYFILE)).unwrap();
+        assert!(body.starts_with("0|10.0.0.2 [log] web sshd[42]: Accepted password for root|0|0|0|0|0|1602324610|1602324610|1602324610|1602324610\n"));
+        assert!(directory.join("timeline-10.0.0.1.csv").exists());
+        assert!(directory.join("timeline-10.0.0.2.body").exists());
+        // the written timelines are not part of the next timeline
+        assert_eq!(2, write_case_timeline(&directory).unwrap());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_downloaded_file_has_times_of_target() {
+        let directory = case_directory();
+        let file = PathBuf::from("10.0.0.2").join("passwd");
+        fs::write(directory.join(&file), "root:x:0:0:root:/root:/bin/bash\n").unwrap();
+        let times = FileTimes {
+            modified: Some("2020-10-09T08:00:00Z".to_string()),
+            accessed: Some("2020-10-10T10:10:12Z".to_string()),
+            changed: Some("2020-10-09T08:00:00Z".to_string()),
+            born: None,
+        };
+        let entry = ManifestEntry {
+            file: file.to_string_lossy().to_string(),
+            size: 0,
+            sha256: String::new(),
+            md5: String::new(),
+            integrity: None,
+            computer: Some("10.0.0.2".to_string()),
+            method: Some("SSH".to_string()),
+            source: Some("/etc/passwd".to_string()),
+            command: None,
+            operator: "Jano".to_string(),
+            started: "2020-10-10T10:10:12Z".to_string(),
+            finished: "2020-10-10T10:10:12Z".to_string(),
+            yara: vec![],
+            times: Some(times),
+        };
+        let manifest = Manifest {
+            case_directory: directory.clone(),
+            operator: "Jano".to_string(),
+            started: "2020-10-10T10:10:12Z".to_string(),
+            entries: vec![entry],
+            incomplete: vec![],
+        };
+        fs::write(directory.join(MANIFEST_JSON), serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+        let entries: Vec<TimelineEntry> = build_timeline(&directory).unwrap().into_iter()
+            .filter(|it| it.source == TimelineSource::File)
+            .collect();
+
+        assert_eq!(
+            vec![("2020-10-09T08:00:00+00:00".to_string(), "m.c."), ("2020-10-10T10:10:12+00:00".to_string(), ".a..")],
+            entries.iter().map(|it| (it.time.to_rfc3339(), it.macb)).collect::<Vec<_>>()
+        );
+        assert_eq!("/etc/passwd", entries[0].description);
+        assert_eq!("10.0.0.2", entries[0].host);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_registry_key_has_modified_time_only() {
+        let directory = std::env::temp_dir().join(format!("gargamel-timeline-body-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        let entry = TimelineEntry {
+            time: Utc.with_ymd_and_hms(2020, 10, 10, 10, 10, 10).unwrap(),
+            macb: "m...",
+            host: "10.0.0.1".to_string(),
+            source: TimelineSource::Registry,
+            description: "SOFTWARE\\Microsoft|Run".to_string(),
+            file: "SOFTWARE.hiv".to_string(),
+        };
+
+        write_timeline(&directory, "registry", &[entry]).unwrap();
+
+        assert_eq!(
+            "0|10.0.0.1 [registry] SOFTWARE\\Microsoft/Run|0|0|0|0|0|0|1602324610|0|0\n",
+            fs::read_to_string(directory.join("registry.body")).unwrap()
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
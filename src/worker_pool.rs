@@ -0,0 +1,224 @@
+use std::io;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::host_logger::set_host_prefix;
+
+pub struct Job<T> {
+    pub name: String,
+    pub input: T,
+}
+
+// Runs at most `concurrency` jobs at once. A job exceeding the `budget` is reported as timed out
+// and `abandon` is called with the name of the job, so that the job stops itself before its next operation.
+// Abandoned jobs are waited for at most `grace` before returning, so they do not write into the case
+// while it is being finished. A job still running after that is left behind, as there is no way to safely stop it.
+pub fn run_jobs<T, R, F, A>(
+    jobs: Vec<Job<T>>,
+    concurrency: usize,
+    budget: Option<Duration>,
+    grace: Duration,
+    work: F,
+    abandon: A,
+) -> Vec<(String, io::Result<R>)>
+    where T: Send + 'static,
+          R: Send + 'static,
+          F: Fn(T) -> io::Result<R> + Send + Sync + 'static,
+          A: Fn(&str) {
+    let concurrency = concurrency.max(1);
+    let work = Arc::new(work);
+    let (sender, receiver) = channel::<(usize, io::Result<R>)>();
+    let names: Vec<String> = jobs.iter().map(|job| job.name.clone()).collect();
+    let mut results: Vec<Option<io::Result<R>>> = names.iter().map(|_| None).collect();
+    let mut pending = jobs.into_iter().enumerate();
+    let mut running: Vec<(usize, Instant)> = vec![];
+    let mut abandoned: Vec<usize> = vec![];
+
+    loop {
+        while running.len() < concurrency {
+            let (index, job) = match pending.next() {
+                None => break,
+                Some(next) => next,
+            };
+            let work = work.clone();
+            let sender = sender.clone();
+            let name = job.name.clone();
+            let spawned = thread::Builder::new()
+                .name(job.name.clone())
+                .spawn(move || {
+                    set_host_prefix(&name);
+                    let result = work(job.input);
+                    // the receiver is gone if the job was left behind
+                    let _ = sender.send((index, result));
+                });
+            match spawned {
+                Ok(_) => running.push((index, Instant::now())),
+                Err(err) => results[index] = Some(Err(err)),
+            }
+        }
+        if running.is_empty() {
+            break;
+        }
+
+        let wait = match budget {
+            None => Duration::from_secs(1),
+            Some(budget) => running.iter()
+                .map(|(_, started)| budget.checked_sub(started.elapsed()).unwrap_or_default())
+                .min()
+                .unwrap_or_default(),
+        };
+        match receiver.recv_timeout(wait) {
+            Ok((index, result)) => {
+                if abandoned.contains(&index) {
+                    // the job already timed out, its late result is ignored
+                    abandoned.retain(|it| *it != index);
+                } else {
+                    running.retain(|(running_index, _)| *running_index != index);
+                    results[index] = Some(result);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if let Some(budget) = budget {
+            let (timed_out, still_running): (Vec<_>, Vec<_>) = running.into_iter()
+                .partition(|(_, started)| started.elapsed() >= budget);
+            running = still_running;
+            for (index, _) in timed_out {
+                warn!("Acquisition from {} exceeded its time budget of {} s and was abandoned",
+                      names[index],
+                      budget.as_secs()
+                );
+                abandon(&names[index]);
+                abandoned.push(index);
+                results[index] = Some(Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!("time budget of {} s exceeded", budget.as_secs()),
+                )));
+            }
+        }
+    }
+
+    let deadline = Instant::now() + grace;
+    while !abandoned.is_empty() {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((index, _)) => abandoned.retain(|it| *it != index),
+            Err(_) => break,
+        }
+    }
+    for index in abandoned {
+        warn!("Acquisition from {} did not stop within {} s after it was abandoned, its evidence may change while the case is finished",
+              names[index],
+              grace.as_secs()
+        );
+    }
+
+    names.into_iter()
+        .zip(results)
+        .map(|(name, result)| (name, result.unwrap_or(Err(io::Error::other("job did not finish")))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker_pool::{run_jobs, Job};
+    use std::io;
+    use std::time::{Duration, Instant};
+    use std::thread;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::io::ErrorKind;
+
+    fn jobs(inputs: &[u64]) -> Vec<Job<u64>> {
+        inputs.iter().enumerate()
+            .map(|(index, input)| Job { name: format!("job-{}", index), input: *input })
+            .collect()
+    }
+
+    fn sleep(millis: u64) -> io::Result<u64> {
+        thread::sleep(Duration::from_millis(millis));
+        Ok(millis)
+    }
+
+    #[test]
+    fn test_results_are_in_job_order() {
+        let results = run_jobs(jobs(&[300, 10, 100]), 3, None, Duration::ZERO, sleep, |_| {});
+
+        assert_eq!(
+            vec![("job-0".to_string(), 300), ("job-1".to_string(), 10), ("job-2".to_string(), 100)],
+            results.into_iter().map(|(name, result)| (name, result.unwrap())).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_slow_job_times_out() {
+        let results = run_jobs(jobs(&[100, 5_000, 100]), 2, Some(Duration::from_secs(1)), Duration::ZERO, sleep, |_| {});
+
+        assert_eq!(100, *results[0].1.as_ref().unwrap());
+        assert_eq!(ErrorKind::TimedOut, results[1].1.as_ref().unwrap_err().kind());
+        assert_eq!(100, *results[2].1.as_ref().unwrap());
+    }
+
+    #[test]
+    fn test_failed_job_does_not_stop_others() {
+        let results = run_jobs(jobs(&[0, 10]), 1, None, Duration::ZERO, |millis| if millis == 0 {
+            Err(io::Error::new(ErrorKind::PermissionDenied, "Access is denied"))
+        } else {
+            sleep(millis)
+        }, |_| {});
+
+        assert_eq!(ErrorKind::PermissionDenied, results[0].1.as_ref().unwrap_err().kind());
+        assert_eq!(10, *results[1].1.as_ref().unwrap());
+    }
+
+    #[test]
+    fn test_concurrency_is_limited() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (job_running, job_most) = (running.clone(), most.clone());
+
+        let results = run_jobs(jobs(&[50; 6]), 2, None, Duration::ZERO, move |millis| {
+            let now = job_running.fetch_add(1, Ordering::SeqCst) + 1;
+            job_most.fetch_max(now, Ordering::SeqCst);
+            let result = sleep(millis);
+            job_running.fetch_sub(1, Ordering::SeqCst);
+            result
+        }, |_| {});
+
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(2, most.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_abandoned_job_is_cancelled() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (job_cancelled, job_stopped) = (cancelled.clone(), stopped.clone());
+        let jobs = vec![Job { name: "stuck".to_string(), input: () }];
+        let results = run_jobs(jobs, 1, Some(Duration::from_millis(200)), Duration::from_secs(5), move |_| {
+            while !job_cancelled.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            // the operation in progress finishes before the job stops
+            thread::sleep(Duration::from_millis(200));
+            job_stopped.store(true, Ordering::SeqCst);
+            Ok(())
+        }, |name| {
+            assert_eq!("stuck", name);
+            cancelled.store(true, Ordering::SeqCst);
+        });
+        assert!(stopped.load(Ordering::SeqCst));
+        assert_eq!(ErrorKind::TimedOut, results[0].1.as_ref().unwrap_err().kind());
+    }
+
+    #[test]
+    fn test_job_ignoring_cancellation_is_left_behind() {
+        let started = Instant::now();
+        let results = run_jobs(jobs(&[3_000]), 1, Some(Duration::from_millis(200)), Duration::from_millis(300), sleep, |_| {});
+
+        assert_eq!(ErrorKind::TimedOut, results[0].1.as_ref().unwrap_err().kind());
+        assert!(started.elapsed() < Duration::from_millis(2_000));
+    }
+}
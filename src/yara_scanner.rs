@@ -0,0 +1,247 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::sync::{Arc, Mutex};
+use yara::{Compiler, Rules};
+use crate::ioc::{Finding, IocKind};
+use crate::manifest::record_yara_matches;
+use crate::remote::{ArchiveFormat, extract_archive};
+
+// Timeout in seconds of scanning one file.
+const SCAN_TIMEOUT: i32 = 60;
+const MAX_MATCHED_STRINGS: usize = 5;
+
+static YARA: Mutex<Option<YaraScanner>> = Mutex::new(None);
+
+struct YaraScanner {
+    // shared with the scans, which run without holding the lock
+    rules: Arc<Rules>,
+    case_directory: PathBuf,
+    findings: Vec<Finding>,
+}
+
+fn yara_error(path: &Path, err: impl std::fmt::Display) -> io::Error {
+    io::Error::other(format!("Cannot use YARA rules {} due to {}", path.display(), err))
+}
+
+// Compiles a YARA rule file, or all .yar and .yara files of a directory, each in a namespace named by the file.
+pub fn compile_rules(path: &Path) -> io::Result<Rules> {
+    let mut compiler = Compiler::new().map_err(|err| yara_error(path, err))?;
+    if path.is_dir() {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+            .filter_map(|it| it.ok())
+            .map(|it| it.path())
+            .filter(|it| it.extension().map(|it| it == "yar" || it == "yara").unwrap_or(false))
+            .collect();
+        files.sort();
+        for file in files {
+            let namespace = file.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            compiler = compiler.add_rules_file_with_namespace(&file, &namespace).map_err(|err| yara_error(&file, err))?;
+        }
+    } else {
+        compiler = compiler.add_rules_file(path).map_err(|err| yara_error(path, err))?;
+    }
+    compiler.compile_rules().map_err(|err| yara_error(path, err))
+}
+
+// Downloaded files are scanned by the rules from now on.
+pub fn start_yara_scanning(rules_path: &Path, case_directory: &Path) -> io::Result<()> {
+    let rules = compile_rules(rules_path)?;
+    info!("Downloaded files will be scanned by {} YARA rules from {}", rules.get_rules().len(), rules_path.display());
+    *YARA.lock().unwrap() = Some(YaraScanner {
+        rules: Arc::new(rules),
+        case_directory: dunce::canonicalize(case_directory)?,
+        findings: vec![],
+    });
+    Ok(())
+}
+
+// Returns the findings of all scans since the scanning started.
+pub fn take_yara_findings() -> Vec<Finding> {
+    YARA.lock().unwrap().as_mut()
+        .map(|scanner| std::mem::take(&mut scanner.findings))
+        .unwrap_or_default()
+}
+
+// Scans a downloaded file, or all files of a downloaded directory, when a YARA rule set is used.
+// Matching rules are recorded in the manifest, so the file must be recorded already.
+pub fn scan_downloaded(path: &Path) {
+    let (rules, case_directory) = match YARA.lock().unwrap().as_ref() {
+        None => return,
+        Some(scanner) => (scanner.rules.clone(), scanner.case_directory.clone()),
+    };
+    let mut files = vec![];
+    if let Err(err) = list_files(path, &mut files) {
+        warn!("Cannot list files in {} due to {}", path.display(), err);
+    }
+    for file in files {
+        match scan_file(&rules, &case_directory, &file) {
+            Ok(findings) if !findings.is_empty() => {
+                let mut rules: Vec<String> = findings.iter().map(|it| it.ioc.clone()).collect();
+                rules.sort();
+                rules.dedup();
+                warn!("YARA rules {} match {}", rules.join(", "), file.display());
+                record_yara_matches(&file, &rules);
+                if let Some(scanner) = YARA.lock().unwrap().as_mut() {
+                    scanner.findings.extend(findings);
+                }
+            }
+            Ok(_) => trace!("No YARA rule matches {}", file.display()),
+            Err(err) => warn!("Cannot scan {} by YARA rules due to {}", file.display(), err),
+        }
+    }
+}
+
+fn list_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            list_files(&entry?.path(), files)?;
+        }
+    } else {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+// Scans the file and, if it is a 7zip or zip archive, the files extracted from it.
+pub fn scan_file(rules: &Rules, case_directory: &Path, path: &Path) -> io::Result<Vec<Finding>> {
+    let file = path.strip_prefix(case_directory).unwrap_or(path).to_string_lossy().to_string();
+    let mut findings = matching_rules(rules, path, &file, None)?;
+    if ArchiveFormat::of_file(path)?.is_some() {
+        let extracted_directory = std::env::temp_dir().join(format!("gargamel-yara-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&extracted_directory)?;
+        let result = extract_archive(path, &extracted_directory).and_then(|extracted| {
+            for entry in extracted {
+                let entry_name = entry.file_name().unwrap_or_default().to_string_lossy().to_string();
+                findings.extend(matching_rules(rules, &entry, &file, Some(entry_name))?);
+            }
+            Ok(())
+        });
+        if let Err(err) = std::fs::remove_dir_all(&extracted_directory) {
+            warn!("Cannot delete {} due to {}", extracted_directory.display(), err);
+        }
+        result?;
+    }
+    Ok(findings)
+}
+
+fn matching_rules(rules: &Rules, path: &Path, file: &str, entry: Option<String>) -> io::Result<Vec<Finding>> {
+    let matches = rules.scan_file(path, SCAN_TIMEOUT).map_err(|err| yara_error(path, err))?;
+    Ok(matches.iter().map(|rule| {
+        let strings: Vec<String> = rule.strings.iter()
+            .filter_map(|string| string.matches.first().map(|it| format!("{} at {:#x}", string.identifier, it.offset)))
+            .take(MAX_MATCHED_STRINGS)
+            .collect();
+        Finding {
+            ioc: if rule.namespace == "default" {
+                rule.identifier.to_string()
+            } else {
+                format!("{}:{}", rule.namespace, rule.identifier)
+            },
+            kind: IocKind::YaraRule,
+            file: file.to_string(),
+            entry: entry.clone(),
+            line: None,
+            context: if strings.is_empty() {
+                format!("tags: {}", rule.tags.join(", "))
+            } else {
+                format!("matched {}", strings.join(", "))
+            },
+        }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yara_scanner::{compile_rules, scan_file};
+    use crate::remote::{compress_file, ArchiveFormat};
+    use std::fs;
+    use std::path::PathBuf;
+    use yara::Rules;
+
+    const MIMIKATZ_RULE: &str = "
+rule Mimikatz : credential_theft {
+    strings:
+        $a = \"sekurlsa::logonpasswords\" nocase
+    condition:
+        $a
+}";
+
+    // Case directory with the rule directory, and the rules compiled from it.
+    fn case_with_rules() -> (PathBuf, Rules) {
+        let directory = std::env::temp_dir().join(format!("gargamel-yara-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(directory.join("rules")).unwrap();
+        fs::write(directory.join("rules").join("mimikatz.yar"), MIMIKATZ_RULE).unwrap();
+        fs::write(directory.join("rules").join("readme.txt"), "not a rule").unwrap();
+        let rules = compile_rules(&directory.join("rules")).unwrap();
+        (directory, rules)
+    }
+
+    #[test]
+    fn test_matching_file_is_found() {
+        let (directory, rules) = case_with_rules();
+        fs::write(directory.join("tool.exe"), b"MZ.... SEKURLSA::LogonPasswords ....").unwrap();
+
+        let findings = scan_file(&rules, &directory, &directory.join("tool.exe")).unwrap();
+
+        assert_eq!(1, findings.len());
+        assert_eq!("mimikatz:Mimikatz", findings[0].ioc);
+        assert_eq!("tool.exe", findings[0].file);
+        assert_eq!(None, findings[0].entry);
+        assert_eq!("matched $a at 0x7", findings[0].context);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_other_file_is_not_found() {
+        let (directory, rules) = case_with_rules();
+        fs::write(directory.join("notes.txt"), b"nothing to see").unwrap();
+
+        assert!(scan_file(&rules, &directory, &directory.join("notes.txt")).unwrap().is_empty());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_archive_entries_are_scanned() {
+        let (directory, rules) = case_with_rules();
+        fs::write(directory.join("bundle.bin"), b"sekurlsa::logonpasswords").unwrap();
+        let archive = compress_file(&directory.join("bundle.bin"), ArchiveFormat::Zip, None).unwrap().remove(0);
+        fs::remove_file(directory.join("bundle.bin")).unwrap();
+
+        let findings = scan_file(&rules, &directory, &archive).unwrap();
+
+        assert_eq!(1, findings.len());
+        assert_eq!("bundle.bin.zip", findings[0].file);
+        assert_eq!(Some("bundle.bin".to_string()), findings[0].entry);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_archive_fails_the_scan() {
+        let (directory, rules) = case_with_rules();
+        fs::write(directory.join("bundle.zip"), b"PK\x03\x04 truncated").unwrap();
+
+        assert!(scan_file(&rules, &directory, &directory.join("bundle.zip")).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_rule_file_uses_default_namespace() {
+        let (directory, _) = case_with_rules();
+        fs::write(directory.join("tool.exe"), b"sekurlsa::logonpasswords").unwrap();
+        let rules = compile_rules(&directory.join("rules").join("mimikatz.yar")).unwrap();
+
+        let findings = scan_file(&rules, &directory, &directory.join("tool.exe")).unwrap();
+
+        assert_eq!("Mimikatz", findings[0].ioc);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_rule_is_rejected() {
+        let (directory, _) = case_with_rules();
+        fs::write(directory.join("rules").join("broken.yar"), "rule Broken { condition: $missing }").unwrap();
+
+        assert!(compile_rules(&directory.join("rules")).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,629 @@
+use gargamel::command_runner::CommandRunner;
+use gargamel::events_acquirer::EventsAcquirer;
+use gargamel::evidence_acquirer::EvidenceAcquirer;
+use gargamel::memory_acquirer::{MemoryAcquirer, LinuxMemoryTool};
+use gargamel::profile::Profile;
+use gargamel::registry_acquirer::RegistryAcquirer;
+use gargamel::hive_acquirer::HiveAcquirer;
+use gargamel::remote::{Computer, Compression, CompressCopier, HostPlatform, MockConnector, MockResponse, MockTarget, ReDownloader, RemoteFileCopier, download_verified, delete_when_released, MAX_TRANSFER_ATTEMPTS};
+use gargamel::cleanup::{register_remote_file, registered_remote_files, retained_remote_files};
+use gargamel::{AcquisitionPlan, Method, run_plan};
+use gargamel::plan::{cancel_plan, is_cancelled};
+use std::fs;
+use std::io::Write;
+use flate2::write::GzEncoder;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn computer() -> Computer {
+    Computer {
+        address: "10.0.0.1".to_string(),
+        username: "Jano".to_string(),
+        domain: None,
+        password: None,
+    }
+}
+
+fn temp_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("gargamel-{}-{}", name, uuid::Uuid::new_v4()));
+    fs::create_dir_all(&directory).unwrap();
+    dunce::canonicalize(directory).unwrap()
+}
+
+fn large_content(text: &str) -> Vec<u8> {
+    text.repeat(50).into_bytes()
+}
+
+#[test]
+fn evidence_acquirer_stores_command_outputs() {
+    let store = temp_directory("evidence");
+    let target = MockTarget::new();
+    target.respond("ipconfig", MockResponse::output("Windows IP Configuration"));
+    target.respond("netstat", MockResponse::timeout());
+    target.respond("tasklist", MockResponse::error(ErrorKind::ConnectionRefused));
+    target.respond("query user", MockResponse::exit_code(1));
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let acquirer = EvidenceAcquirer::new(&store, Box::new(connector), &Profile::built_in());
+
+    let outcomes = acquirer.run_all();
+
+    let commands: Vec<String> = target.commands().iter().map(|it| it.joined()).collect();
+    assert_eq!(vec![
+        "netsh advfirewall show allprofiles state",
+        "ipconfig /all",
+        "netstat -ano",
+        "tasklist",
+        "query user",
+    ], commands);
+    assert_eq!(5, outcomes.len());
+    assert!(outcomes[0].as_ref().unwrap().is_success());
+    let report_path = outcomes[1].as_ref().unwrap().report_path.clone().unwrap();
+    assert_eq!("Windows IP Configuration", fs::read_to_string(&report_path).unwrap());
+    let parsed: serde_json::Value = serde_json::from_slice(&fs::read(report_path.with_extension("json")).unwrap()).unwrap();
+    assert_eq!("ipconfig", parsed["parser"]);
+    assert_eq!(serde_json::json!([]), parsed["records"]);
+    assert!(outcomes[2].as_ref().unwrap().timed_out);
+    assert_eq!(ErrorKind::ConnectionRefused, outcomes[3].as_ref().unwrap_err().kind());
+    assert_eq!(Some(1), outcomes[4].as_ref().unwrap().exit_code);
+}
+
+#[test]
+fn evidence_acquirer_skips_artifacts_of_missing_programs() {
+    let store = temp_directory("linux-evidence");
+    let profile = Profile::parse_toml(r#"
+        [[artifact]]
+        name = "firewall-status-iptables"
+        os = "linux"
+        command = ["iptables", "-L"]
+        elevated = true
+        requires = "iptables"
+
+        [[artifact]]
+        name = "firewall-status-ufw"
+        os = "linux"
+        command = ["ufw", "status"]
+        elevated = true
+        requires = "ufw"
+
+        [[artifact]]
+        name = "logged-users"
+        os = "linux"
+        command = ["who"]
+        timeout = 60
+
+        [[artifact]]
+        name = "running-processes"
+        os = "windows"
+        command = ["tasklist"]
+    "#).unwrap();
+    let target = MockTarget::new();
+    target.respond("command -v", MockResponse::output("iptables\n"));
+    let connector = MockConnector {
+        platform: HostPlatform::Linux,
+        ..MockConnector::new(computer(), &temp_directory("remote"), &target)
+    };
+    let acquirer = EvidenceAcquirer::new(&store, Box::new(connector), &profile);
+
+    let outcomes = acquirer.run_all();
+
+    let commands = target.commands();
+    assert_eq!(3, commands.len());
+    assert!(commands[0].joined().contains("command -v"));
+    assert_eq!("iptables -L", commands[1].joined());
+    assert!(commands[1].elevated);
+    assert_eq!("who", commands[2].joined());
+    assert_eq!(Some(Duration::from_secs(60)), commands[2].timeout);
+    assert_eq!(2, outcomes.len());
+}
+
+#[test]
+fn registry_acquirer_downloads_exported_hive() {
+    let store = temp_directory("registry");
+    let profile = Profile::parse_toml(r#"
+        [[artifact]]
+        name = "registry-hklm"
+        os = "windows"
+        category = "registry"
+        command = ["reg", "export", "HKLM"]
+        large = true
+        overwrite_switch = "/y"
+    "#).unwrap();
+    let target = MockTarget::new();
+    let remote_storage = temp_directory("remote");
+    let connector = MockConnector::new(computer(), &remote_storage, &target);
+    let acquirer = RegistryAcquirer::new(&store, Box::new(connector), None, Compression::No, &profile);
+
+    let outcomes = acquirer.acquire();
+
+    assert_eq!(1, outcomes.len());
+    let report_path = outcomes[0].as_ref().unwrap().report_path.clone().unwrap();
+    let remote_report_path = remote_storage.join(report_path.file_name().unwrap());
+    let command = &target.commands()[0].command;
+    assert_eq!(&["reg", "export", "HKLM"], &command[..3]);
+    assert_eq!(remote_report_path.to_string_lossy(), command[3]);
+    assert_eq!("/y", command[4]);
+    assert!(fs::read_to_string(&report_path).unwrap().starts_with("reg export HKLM"));
+    assert!(target.files().is_empty());
+}
+
+#[test]
+fn registry_acquirer_keeps_remote_export_of_corrupted_transfer() {
+    let store = temp_directory("registry-corrupted");
+    let profile = Profile::parse_toml(r#"
+        [[artifact]]
+        name = "registry-hklm"
+        os = "windows"
+        category = "registry"
+        command = ["reg", "export", "HKLM"]
+        large = true
+    "#).unwrap();
+    let target = MockTarget::new();
+    target.corrupt_transfers(MAX_TRANSFER_ATTEMPTS);
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let acquirer = RegistryAcquirer::new(&store, Box::new(connector), None, Compression::No, &profile);
+
+    let outcomes = acquirer.acquire();
+
+    assert_eq!(ErrorKind::InvalidData, outcomes[0].as_ref().unwrap_err().kind());
+    assert_eq!(MAX_TRANSFER_ATTEMPTS, target.downloads().len());
+    assert_eq!(1, target.files().len());
+}
+
+#[test]
+fn hive_acquirer_saves_system_and_user_hives() {
+    let store = temp_directory("hives");
+    let profile = Profile::parse_toml(r#"
+        [[artifact]]
+        name = "hive-system"
+        os = "windows"
+        category = "hives"
+        command = ["reg", "save", "HKLM\\SYSTEM"]
+        extension = "hiv"
+        large = true
+        overwrite_switch = "/y"
+        elevated = true
+    "#).unwrap();
+    let target = MockTarget::new();
+    target.respond("ProfileList", MockResponse::output("
+HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\ProfileList\\S-1-5-21-1-2-3-1001
+    ProfileImagePath    REG_EXPAND_SZ    C:\\Users\\Jano
+
+HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\ProfileList\\S-1-5-21-1-2-3-1002
+    ProfileImagePath    REG_EXPAND_SZ    C:\\Users\\Fero
+"));
+    target.respond("reg query HKU", MockResponse::output("HKEY_USERS\\S-1-5-21-1-2-3-1001\nHKEY_USERS\\S-1-5-21-1-2-3-1001_Classes\n"));
+    target.add_file(&PathBuf::from("C:\\Users\\Fero\\NTUSER.DAT"), b"regf");
+    let remote_storage = temp_directory("remote");
+    let connector = MockConnector::new(computer(), &remote_storage, &target);
+    let acquirer = HiveAcquirer::new(&store, Box::new(connector), None, Compression::No, &profile);
+
+    let outcomes = acquirer.acquire();
+
+    let saved: Vec<String> = target.commands().iter()
+        .filter(|it| it.command[1] == "save")
+        .map(|it| it.command[2].clone())
+        .collect();
+    assert_eq!(vec!["HKLM\\SYSTEM", "HKU\\S-1-5-21-1-2-3-1001", "HKU\\S-1-5-21-1-2-3-1001_Classes"], saved);
+    assert_eq!(5, outcomes.len());
+    assert!(outcomes[..4].iter().all(|it| it.is_ok()));
+    let user_hive = outcomes[1].as_ref().unwrap().report_path.clone().unwrap();
+    assert_eq!(store.join("user-hives").join("Jano"), user_hive.parent().unwrap());
+    let downloaded = outcomes[3].as_ref().unwrap().report_path.clone().unwrap();
+    assert_eq!(store.join("user-hives").join("Fero"), downloaded.parent().unwrap());
+    assert_eq!(b"regf".to_vec(), fs::read(&downloaded).unwrap());
+    // Fero has no UsrClass.dat
+    assert!(outcomes[4].is_err());
+}
+
+#[test]
+fn hive_acquirer_reports_unlisted_user_profiles() {
+    let store = temp_directory("hives-denied");
+    let profile = Profile::parse_toml("").unwrap();
+    let target = MockTarget::new();
+    target.respond("ProfileList", MockResponse::error(ErrorKind::PermissionDenied));
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let acquirer = HiveAcquirer::new(&store, Box::new(connector), None, Compression::No, &profile);
+
+    let outcomes = acquirer.acquire();
+
+    assert_eq!(1, outcomes.len());
+    assert_eq!(ErrorKind::PermissionDenied, outcomes[0].as_ref().unwrap_err().kind());
+    assert!(!store.join("user-hives").exists());
+}
+
+#[test]
+fn events_acquirer_reports_failed_export() {
+    let store = temp_directory("events");
+    let profile = Profile::parse_toml(r#"
+        [[artifact]]
+        name = "events-system"
+        os = "windows"
+        category = "events"
+        command = ["wevtutil", "epl", "system"]
+        extension = "evtx"
+        large = true
+    "#).unwrap();
+    let target = MockTarget::new();
+    target.respond("wevtutil", MockResponse::exit_code(5));
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let acquirer = EventsAcquirer::new(&store, Box::new(connector), None, Compression::No, &profile);
+
+    let outcomes = acquirer.acquire();
+
+    assert_eq!(1, outcomes.len());
+    let outcome = outcomes[0].as_ref().unwrap();
+    assert!(!outcome.is_success());
+    assert!(outcome.report_path.as_ref().unwrap().to_string_lossy().ends_with(".evtx"));
+    assert!(target.downloads().is_empty());
+}
+
+#[test]
+fn events_acquirer_skips_missing_channels() {
+    let store = temp_directory("channels");
+    let profile = Profile::parse_toml("")
+        .unwrap()
+        .with_event_channels(&["System".to_string(), "Microsoft-Windows-Sysmon/Operational".to_string()])
+        .with_events_since(1);
+    let target = MockTarget::new();
+    target.respond("wevtutil el", MockResponse::output("Application\nSecurity\nSystem\n"));
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let acquirer = EventsAcquirer::new(&store, Box::new(connector), None, Compression::No, &profile);
+
+    let outcomes = acquirer.acquire();
+
+    assert_eq!(2, outcomes.len());
+    assert!(outcomes[0].as_ref().unwrap().is_success());
+    assert_eq!(ErrorKind::NotFound, outcomes[1].as_ref().unwrap_err().kind());
+    let export = target.commands().into_iter()
+        .find(|it| it.command[1] == "epl")
+        .unwrap();
+    assert_eq!("System", export.command[2]);
+    assert_eq!("/q:*[System[TimeCreated[timediff(@SystemTime) <= 86400000]]]", export.command[5]);
+}
+
+#[test]
+fn memory_acquirer_downloads_image() {
+    let store = temp_directory("memory");
+    let target = MockTarget::new();
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let acquirer = MemoryAcquirer {
+        local_store_directory: &store,
+        connector: Box::new(connector),
+        image_timeout: Some(Duration::from_secs(600)),
+        compress_timeout: None,
+        compression: Compression::No,
+        linux_tool: LinuxMemoryTool::default(),
+    };
+
+    acquirer.image_memory().unwrap();
+
+    let commands = target.commands();
+    assert_eq!(1, commands.len());
+    assert_eq!("winpmem.exe", commands[0].command[0]);
+    assert!(commands[0].elevated);
+    assert_eq!(Some(Duration::from_secs(600)), commands[0].timeout);
+    let image = PathBuf::from(commands[0].command.last().unwrap());
+    let local_image = store.join(image.file_name().unwrap());
+    assert!(fs::read_to_string(local_image).unwrap().starts_with("winpmem.exe"));
+    assert!(target.files().is_empty());
+}
+
+fn gzipped(content: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(content).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn linux_memory_acquirer<'a>(store: &'a Path, target: &MockTarget, remote_storage: &Path, stream: bool) -> MemoryAcquirer<'a> {
+    let tool = store.join("avml");
+    fs::write(&tool, "avml binary").unwrap();
+    MemoryAcquirer {
+        local_store_directory: store,
+        connector: Box::new(MockConnector {
+            platform: HostPlatform::Linux,
+            ..MockConnector::new(computer(), remote_storage, target)
+        }),
+        image_timeout: Some(Duration::from_secs(600)),
+        compress_timeout: None,
+        compression: Compression::Yes,
+        linux_tool: LinuxMemoryTool {
+            path: tool,
+            stream,
+            ..LinuxMemoryTool::default()
+        },
+    }
+}
+
+#[test]
+fn memory_acquirer_images_linux_target_with_uploaded_tool() {
+    let store = temp_directory("linux-memory");
+    // the temp storage exists only on the target
+    let remote_storage = Path::new("/gargamel-remote-tmp");
+    let target = MockTarget::new();
+    let remote_image = remote_storage.join("Mock-mem-image-10-0-0-1-Jano.lime.gz");
+    target.add_file(&remote_image, &gzipped(&large_content("memory ")));
+    let acquirer = linux_memory_acquirer(&store, &target, remote_storage, false);
+
+    acquirer.image_memory().unwrap();
+
+    let commands = target.commands();
+    assert_eq!(1, commands.len());
+    assert_eq!("sh", commands[0].command[0]);
+    assert!(commands[0].command[2].contains("/avml /"));
+    assert!(commands[0].command[2].contains("Jano.lime && gzip -1 -f "));
+    assert!(commands[0].elevated);
+    assert_eq!(Some(Duration::from_secs(600)), commands[0].timeout);
+    assert_eq!(gzipped(&large_content("memory ")), fs::read(store.join(remote_image.file_name().unwrap())).unwrap());
+    assert!(target.files().is_empty());
+    assert!(!remote_storage.exists());
+}
+
+#[test]
+fn memory_acquirer_streams_linux_image() {
+    let store = temp_directory("linux-memory-stream");
+    let target = MockTarget::new();
+    target.respond("avml", MockResponse::output("memory image"));
+    let acquirer = MemoryAcquirer {
+        compression: Compression::No,
+        ..linux_memory_acquirer(&store, &target, &temp_directory("remote"), true)
+    };
+
+    acquirer.image_memory().unwrap();
+
+    let commands = target.commands();
+    assert_eq!("bash", commands[0].command[0]);
+    assert!(commands[0].command[2].starts_with("'set -o pipefail; chmod 700 "));
+    let image = store.join("Mock-mem-image-10-0-0-1-Jano.lime");
+    assert_eq!("memory image", fs::read_to_string(image).unwrap());
+    assert!(target.files().is_empty());
+}
+
+#[test]
+fn memory_acquirer_rejects_truncated_linux_image_stream() {
+    let store = temp_directory("linux-memory-truncated");
+    let target = MockTarget::new();
+    // a stream cut off inside the gzip header
+    target.respond("avml", MockResponse::output("\x1f"));
+    let acquirer = linux_memory_acquirer(&store, &target, &temp_directory("remote"), true);
+
+    let result = acquirer.image_memory();
+
+    assert_eq!(ErrorKind::InvalidData, result.unwrap_err().kind());
+    assert!(target.commands()[0].command[2].ends_with("/dev/stdout | gzip -1 -c'"));
+}
+
+#[test]
+fn command_runner_selects_commands_by_method() {
+    let store = temp_directory("commands");
+    let command_file = store.join("commands.txt");
+    fs::write(&command_file, "# comment\n\
+    ipconfig /all\n\
+    :mock whoami\n\
+    :wmi hostname\n\
+    :mock:admin net session\n").unwrap();
+    let target = MockTarget::new();
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let runner = CommandRunner::new(Box::new(connector), &store, true);
+
+    let outcomes = runner.run_commands(&command_file, None);
+
+    let commands = target.commands();
+    let joined: Vec<String> = commands.iter().map(|it| it.joined()).collect();
+    assert_eq!(vec!["ipconfig /all", "whoami", "net session"], joined);
+    assert!(!commands[0].elevated);
+    assert!(commands[2].elevated);
+    assert_eq!(3, outcomes.len());
+    assert!(outcomes.iter().all(|it| it.as_ref().unwrap().report_path.as_ref().unwrap().exists()));
+}
+
+#[test]
+fn command_runner_without_implicit_commands() {
+    let store = temp_directory("explicit-commands");
+    let command_file = store.join("commands.txt");
+    fs::write(&command_file, "ipconfig /all\n:mock whoami\n").unwrap();
+    let target = MockTarget::new();
+    let connector = MockConnector::new(computer(), &temp_directory("remote"), &target);
+    let runner = CommandRunner::new(Box::new(connector), &store, false);
+
+    runner.run_commands(&command_file, None);
+
+    let joined: Vec<String> = target.commands().iter().map(|it| it.joined()).collect();
+    assert_eq!(vec!["whoami"], joined);
+}
+
+#[test]
+fn re_downloader_retries_corrupted_transfer() {
+    let store = temp_directory("re-download");
+    let target = MockTarget::new();
+    let remote_storage = temp_directory("remote");
+    let remote_file = remote_storage.join("memory.aff4");
+    target.add_file(&remote_file, &large_content("memory "));
+    target.corrupt_transfers(1);
+    let connector = MockConnector::new(computer(), &remote_storage, &target);
+    let re_downloader = ReDownloader {
+        copier: &connector.copier,
+        target_dir: &store,
+    };
+
+    assert!(re_downloader.retry_download(&remote_file));
+
+    assert_eq!(2, target.downloads().len());
+    assert_eq!(large_content("memory "), fs::read(store.join("memory.aff4")).unwrap());
+}
+
+#[test]
+fn compress_copier_downloads_archive_and_cleans_up() {
+    let store = temp_directory("compress");
+    let target = MockTarget::new();
+    let remote_storage = temp_directory("remote");
+    let remote_file = remote_storage.join("registry.txt");
+    target.add_file(&remote_file, &large_content("registry "));
+    let connector = MockConnector::new(computer(), &remote_storage, &target);
+    let copier = CompressCopier::new(&connector, false, None);
+
+    copier.copy_from_remote(&remote_file, &store).unwrap();
+
+    let command = target.commands()[0].joined();
+    assert!(command.starts_with("7za.exe"));
+    assert!(command.ends_with(&format!("a {}.7z {}", remote_file.display(), remote_file.display())));
+    let archive = PathBuf::from(format!("{}.7z", remote_file.display()));
+    assert_eq!(vec![archive], target.downloads());
+    assert!(target.files().is_empty());
+    assert_eq!(large_content("registry "), fs::read(store.join("registry.txt")).unwrap());
+    assert!(!store.join("registry.txt.7z").exists());
+}
+
+#[test]
+fn download_verified_keeps_remote_file_of_corrupted_transfer() {
+    let target = MockTarget::new();
+    let remote_storage = temp_directory("remote");
+    let remote_file = remote_storage.join("memory.aff4");
+    target.add_file(&remote_file, &large_content("memory "));
+    target.corrupt_transfers(MAX_TRANSFER_ATTEMPTS);
+    let connector = MockConnector::new(computer(), &remote_storage, &target);
+
+    let result = download_verified(&connector, &remote_file, &temp_directory("corrupted"));
+
+    assert_eq!(ErrorKind::InvalidData, result.unwrap_err().kind());
+    assert_eq!(vec![remote_file], target.files());
+}
+
+#[test]
+fn compress_copier_keeps_corrupted_archive() {
+    let store = temp_directory("compress-corrupted");
+    let target = MockTarget::new();
+    let remote_storage = temp_directory("remote");
+    let remote_file = remote_storage.join("registry.txt");
+    target.add_file(&remote_file, &large_content("registry "));
+    target.corrupt_transfers(MAX_TRANSFER_ATTEMPTS);
+    let connector = MockConnector::new(computer(), &remote_storage, &target);
+    let copier = CompressCopier::new(&connector, false, None);
+
+    let result = copier.copy_from_remote(&remote_file, &store);
+
+    assert_eq!(ErrorKind::InvalidData, result.unwrap_err().kind());
+    assert!(target.files().contains(&PathBuf::from(format!("{}.7z", remote_file.display()))));
+}
+
+#[test]
+fn re_downloader_keeps_corrupted_part() {
+    let store = temp_directory("re-download-corrupted");
+    let target = MockTarget::new();
+    let remote_storage = temp_directory("remote");
+    let part = remote_storage.join("memory.aff4.7z.001");
+    target.add_file(&part, &large_content("memory "));
+    target.corrupt_transfers(MAX_TRANSFER_ATTEMPTS);
+    let connector = MockConnector::new(computer(), &remote_storage, &target);
+    let re_downloader = ReDownloader {
+        copier: &connector.copier,
+        target_dir: &store,
+    };
+
+    assert!(!re_downloader.retry_download(&part));
+
+    assert_eq!(vec![part], target.files());
+}
+
+#[test]
+fn delete_when_released_accepts_already_deleted_file() {
+    let target = MockTarget::new();
+    let remote_storage = temp_directory("remote");
+    let remote_file = remote_storage.join("already-deleted.txt");
+    let computer = Computer { address: "10.0.0.12".to_string(), ..computer() };
+    let connector = MockConnector::new(computer.clone(), &remote_storage, &target);
+    register_remote_file(&computer, &remote_file);
+
+    delete_when_released(&connector.copier, &remote_file, Duration::from_millis(10)).unwrap();
+
+    assert!(registered_remote_files(&computer).is_empty());
+}
+
+fn registry_plan(address: &str, target: &MockTarget) -> AcquisitionPlan {
+    let profile = Profile::parse_toml(r#"
+        [[artifact]]
+        name = "registry-hklm"
+        os = "windows"
+        category = "registry"
+        command = ["reg", "export", "HKLM"]
+        large = true
+    "#).unwrap();
+    AcquisitionPlan {
+        methods: vec![Method::Mock],
+        remote_temp_storage: temp_directory("remote"),
+        profile,
+        evidence: false,
+        events: false,
+        mock_target: target.clone(),
+        ..AcquisitionPlan::new(Computer { address: address.to_string(), ..computer() }, &temp_directory("plan"))
+    }
+}
+
+#[test]
+fn run_plan_keeps_remote_file_of_corrupted_transfer() {
+    let target = MockTarget::new();
+    target.corrupt_transfers(MAX_TRANSFER_ATTEMPTS);
+    let plan = registry_plan("10.0.0.41", &target);
+
+    let report = run_plan(&plan);
+
+    assert_eq!(ErrorKind::InvalidData, report.results[0].outcome.as_ref().unwrap_err().kind());
+    assert_eq!(1, target.files().len());
+    assert_eq!(target.files(), retained_remote_files(&plan.computer));
+    assert!(registered_remote_files(&plan.computer).is_empty());
+}
+
+#[test]
+fn run_plan_keeps_remote_file_of_failed_download() {
+    let target = MockTarget::new();
+    target.fail_transfers(MAX_TRANSFER_ATTEMPTS);
+    let plan = registry_plan("10.0.0.42", &target);
+
+    let report = run_plan(&plan);
+
+    assert_eq!(1, report.failed());
+    assert_eq!(1, target.files().len());
+    assert_eq!(target.files(), retained_remote_files(&plan.computer));
+}
+
+#[test]
+fn run_plan_cleans_up_after_successful_download() {
+    let target = MockTarget::new();
+    let plan = registry_plan("10.0.0.43", &target);
+
+    let report = run_plan(&plan);
+
+    assert!(report.is_success());
+    assert!(target.files().is_empty());
+    assert!(retained_remote_files(&plan.computer).is_empty());
+}
+
+#[test]
+fn cancelled_acquirer_skips_remaining_artifacts() {
+    let store = temp_directory("cancelled");
+    let target = MockTarget::new();
+    let computer = Computer { address: "10.0.0.44".to_string(), ..computer() };
+    let connector = MockConnector::new(computer.clone(), &temp_directory("remote"), &target);
+    let acquirer = EvidenceAcquirer::new(&store, Box::new(connector), &Profile::built_in());
+    cancel_plan(&computer.address);
+
+    let outcomes = acquirer.run_all();
+
+    assert!(outcomes.is_empty());
+    assert!(target.commands().is_empty());
+}
+
+#[test]
+fn run_plan_forgets_cancellation_once_finished() {
+    let target = MockTarget::new();
+    let plan = registry_plan("10.0.0.45", &target);
+    cancel_plan(&plan.computer.address);
+
+    let report = run_plan(&plan);
+
+    assert!(report.results.is_empty());
+    assert!(target.commands().is_empty());
+    assert!(!is_cancelled(&plan.computer));
+    assert!(run_plan(&plan).is_success());
+    assert!(!target.commands().is_empty());
+}
@@ -0,0 +1,62 @@
+use gargamel::journal::{start_journal, journaled_step, StepKey, StepState, JOURNAL_JSON};
+use gargamel::memory_acquirer::{MemoryAcquirer, LinuxMemoryTool};
+use gargamel::remote::{Computer, Compression, MockConnector, MockTarget};
+use std::fs;
+use std::path::PathBuf;
+
+// The journal is global, so its tests live in their own test binary.
+
+fn temp_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("gargamel-{}-{}", name, uuid::Uuid::new_v4()));
+    fs::create_dir_all(&directory).unwrap();
+    dunce::canonicalize(directory).unwrap()
+}
+
+#[test]
+fn resumed_memory_image_continues_with_next_part() {
+    let store = temp_directory("resume");
+    let remote_storage = temp_directory("remote");
+    let image = remote_storage.join("Mock-mem-image-10-0-0-1-Jano.aff4");
+    let journal = format!(r#"{{"steps": [{{
+        "computer": "10.0.0.1",
+        "method": "Mock",
+        "name": "mem-image",
+        "state": "command-run",
+        "command": "winpmem.exe",
+        "remote_file": {:?},
+        "local_file": null,
+        "downloaded_parts": [1, 2],
+        "deleted_parts": [1],
+        "updated": "2020-10-10T10:10:10Z"
+    }}]}}"#, image.to_string_lossy());
+    fs::write(store.join(JOURNAL_JSON), journal).unwrap();
+    start_journal(&store, true).unwrap();
+    let target = MockTarget::new();
+    let part = |number: usize| PathBuf::from(format!("{}.7z.00{}", image.display(), number));
+    target.add_file(&part(2), &"second part ".repeat(10).into_bytes());
+    target.add_file(&part(3), &"third part ".repeat(10).into_bytes());
+    let computer = Computer {
+        address: "10.0.0.1".to_string(),
+        username: "Jano".to_string(),
+        domain: None,
+        password: None,
+    };
+    let acquirer = MemoryAcquirer {
+        local_store_directory: &store,
+        connector: Box::new(MockConnector::new(computer.clone(), &remote_storage, &target)),
+        image_timeout: None,
+        compress_timeout: None,
+        compression: Compression::YesSplit,
+        linux_tool: LinuxMemoryTool::default(),
+    };
+
+    acquirer.image_memory().unwrap();
+    acquirer.image_memory().unwrap();
+
+    assert!(target.commands().is_empty());
+    assert_eq!(vec![part(2), part(3)], target.downloads());
+    assert!(target.files().is_empty());
+    let step = journaled_step(&StepKey::new(&computer, "Mock", "mem-image")).unwrap();
+    assert_eq!(StepState::Completed, step.state);
+    assert_eq!(vec![1, 2, 3], step.deleted_parts);
+}